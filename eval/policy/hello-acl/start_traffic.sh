@@ -16,7 +16,7 @@ cargo rr --bin launcher -- --output-dir ${OD} --timeout=120 --benchmark ./rpc_be
 sleep 30
 
 LIST_OUTPUT="${OD}"/policy/list.json
-cargo rr --bin list -- --dump "${LIST_OUTPUT}" # Need to specifiy PHOENIX_PREFIX
+cargo rr --bin phoenixctl -- list --dump "${LIST_OUTPUT}" # Need to specifiy PHOENIX_PREFIX
 cat "${LIST_OUTPUT}"
 ARG_PID=$(cat "${LIST_OUTPUT}" | jq '.[] | select(.service == "Mrpc") | .pid')
 ARG_SID=$(cat "${LIST_OUTPUT}" | jq '.[] | select(.service == "Mrpc") | .sid')
@@ -24,6 +24,6 @@ echo $ARG_SID
 
 sleep 1
 
-cargo run --bin addonctl -- --config ./attach.toml --pid ${ARG_PID} --sid ${ARG_SID} # Need to specifiy PHOENIX_PREFIX
+cargo run --bin phoenixctl -- attach --config ./attach.toml --pid ${ARG_PID} --sid ${ARG_SID} # Need to specifiy PHOENIX_PREFIX
 
 wait
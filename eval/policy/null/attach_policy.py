@@ -22,7 +22,7 @@ env = {**os.environ, **config['env']}
 os.chdir(workdir)
 os.makedirs(f"{OD}/policy/null", exist_ok=True)
 
-list_cmd = f"cargo run --release --bin list -- --dump {OD}/policy/list.json"
+list_cmd = f"cargo run --release --bin phoenixctl -- list --dump {OD}/policy/list.json"
 subprocess.run(list_cmd.split(), env=env)
 
 with open(f"{OD}/policy/list.json", "r") as fin:
@@ -34,13 +34,13 @@ mrpc_sid = None
 for subscription in data:
     pid = subscription["pid"]
     sid = subscription["sid"]
-    engines = [x[1] for x in subscription["engines"]]
+    engines = [x["engine_type"] for x in subscription["engines"]]
     if "MrpcEngine" in engines:
         mrpc_pid = pid
         mrpc_sid = sid
 
 attach_config = os.path.join(SCRIPTDIR, "attach.toml")
-attach_cmd = f"cargo run --release --bin addonctl -- --config {attach_config} --pid {mrpc_pid} --sid {mrpc_sid}"
+attach_cmd = f"cargo run --release --bin phoenixctl -- attach --config {attach_config} --pid {mrpc_pid} --sid {mrpc_sid}"
 subprocess.run(attach_cmd.split(), env=env)
 time.sleep(1)
 
@@ -42,8 +42,9 @@ subprocess.run([
     "run",
     "--release",
     "--bin",
-    "list",
+    "phoenixctl",
     "--",
+    "list",
     "--dump",
     OD+"/policy/list.json"
 ])
@@ -54,7 +55,7 @@ mrpc_sid = None
 for subscription in data:
     pid = subscription["pid"]
     sid = subscription["sid"]
-    engines = [x[1] for x in subscription["engines"]]
+    engines = [x["engine_type"] for x in subscription["engines"]]
     if "MrpcEngine" in engines:
         mrpc_pid = pid
         mrpc_sid = sid
@@ -65,8 +66,9 @@ subprocess.run([
     "run",
     "--release",
     "--bin",
-    "addonctl",
+    "phoenixctl",
     "--",
+    "attach",
     "--config",
     attach_config,
     "--pid",
@@ -80,8 +82,9 @@ subprocess.run([
     "run",
     "--release",
     "--bin",
-    "list",
+    "phoenixctl",
     "--",
+    "list",
     "--dump",
     OD+"/policy/list.json"
 ])
@@ -44,8 +44,9 @@ subprocess.run([
     "run",
     "--release",
     "--bin",
-    "list",
+    "phoenixctl",
     "--",
+    "list",
     "--dump",
     OD+"/policy/qos/list.json"
 ])
@@ -57,7 +58,7 @@ mrpc_sid_lat = None
 for subscription in data:
     pid = subscription["pid"]
     sid = subscription["sid"]
-    engines = [x[1] for x in subscription["engines"]]
+    engines = [x["engine_type"] for x in subscription["engines"]]
     if "MrpcEngine" in engines:
         mrpc_pid_lat = pid
         mrpc_sid_lat = sid
@@ -67,8 +68,9 @@ subprocess.run([
     "run",
     "--release",
     "--bin",
-    "addonctl",
+    "phoenixctl",
     "--",
+    "attach",
     "--config",
     attach_config,
     "--pid",
@@ -98,8 +100,9 @@ subprocess.run([
     "run",
     "--release",
     "--bin",
-    "list",
+    "phoenixctl",
     "--",
+    "list",
     "--dump",
     OD+"/policy/qos/list.json"
 ])
@@ -111,7 +114,7 @@ mrpc_sid_bd = None
 for subscription in data:
     pid = subscription["pid"]
     sid = subscription["sid"]
-    engines = [x[1] for x in subscription["engines"]]
+    engines = [x["engine_type"] for x in subscription["engines"]]
     if "MrpcEngine" in engines and pid != mrpc_pid_lat:
         mrpc_pid_bd = pid
         mrpc_sid_bd = sid
@@ -121,8 +124,9 @@ subprocess.run([
     "run",
     "--release",
     "--bin",
-    "addonctl",
+    "phoenixctl",
     "--",
+    "attach",
     "--config",
     attach_config,
     "--pid",
@@ -1,6 +1,14 @@
 const PROTO: &str = "../proto/rpc_hello/rpc_hello.proto";
+// has map and oneof fields, used by the tests
+const LABELS_PROTO: &str = "../proto/rpc_labels/rpc_labels.proto";
+// has server-streaming and client-streaming methods, used by the tests
+const STREAM_PROTO: &str = "../proto/rpc_stream/rpc_stream.proto";
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed={PROTO}");
+    println!("cargo:rerun-if-changed={LABELS_PROTO}");
+    println!("cargo:rerun-if-changed={STREAM_PROTO}");
     mrpc_build::compile_protos(PROTO)?;
+    mrpc_build::compile_protos(LABELS_PROTO)?;
+    mrpc_build::compile_protos(STREAM_PROTO)?;
     Ok(())
 }
@@ -0,0 +1,55 @@
+//! Makes a call to an echo server through the phoenix embedded in the test process.
+use std::sync::mpsc;
+use std::thread;
+
+pub mod rpc_echo {
+    // The string specified here must match the proto package name
+    mrpc::include_proto!("rpc_hello");
+}
+
+use rpc_echo::greeter_client::GreeterClient;
+use rpc_echo::greeter_server::{Greeter, GreeterServer};
+use rpc_echo::{HelloReply, HelloRequest};
+
+use mrpc::{RRef, WRef};
+
+const SERVER_ADDR: &str = "127.0.0.1:5115";
+
+#[derive(Debug, Default)]
+struct MyGreeter;
+
+#[mrpc::async_trait]
+impl Greeter for MyGreeter {
+    async fn say_hello(
+        &self,
+        request: RRef<HelloRequest>,
+    ) -> Result<WRef<HelloReply>, mrpc::Status> {
+        let message = format!("Hello {}!", String::from_utf8_lossy(&request.name));
+        let reply = WRef::new(HelloReply {
+            message: message.as_bytes().into(),
+        });
+        Ok(reply)
+    }
+}
+
+#[test]
+fn unary_call() {
+    let (bound_tx, bound_rx) = mpsc::channel();
+    // the server runs until the test process exits
+    thread::spawn(move || {
+        smol::block_on(async {
+            let mut server = mrpc::stub::LocalServer::bind(SERVER_ADDR).unwrap();
+            server.add_service(GreeterServer::new(MyGreeter::default()));
+            bound_tx.send(()).unwrap();
+            server.serve().await.unwrap();
+        })
+    });
+    bound_rx.recv().unwrap();
+
+    let client = GreeterClient::connect(SERVER_ADDR).unwrap();
+    let req = HelloRequest {
+        name: "mRPC".into(),
+    };
+    let reply = smol::block_on(client.say_hello(req)).unwrap();
+    assert_eq!(reply.message.as_slice(), b"Hello mRPC!");
+}
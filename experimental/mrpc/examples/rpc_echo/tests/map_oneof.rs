@@ -0,0 +1,96 @@
+//! Makes calls with map and oneof fields through the phoenix embedded in the test process.
+use std::sync::mpsc;
+use std::thread;
+
+pub mod rpc_labels {
+    // The string specified here must match the proto package name
+    mrpc::include_proto!("rpc_labels");
+}
+
+use rpc_labels::labeler_client::LabelerClient;
+use rpc_labels::labeler_server::{Labeler, LabelerServer};
+use rpc_labels::lookup_request::Key;
+use rpc_labels::{Label, LookupReply, LookupRequest};
+
+use mrpc::alloc::BTreeMap;
+use mrpc::{RRef, WRef};
+
+const SERVER_ADDR: &str = "127.0.0.1:5116";
+
+#[derive(Debug, Default)]
+struct MyLabeler;
+
+#[mrpc::async_trait]
+impl Labeler for MyLabeler {
+    async fn lookup(
+        &self,
+        request: RRef<LookupRequest>,
+    ) -> Result<WRef<LookupReply>, mrpc::Status> {
+        let mut labels = BTreeMap::new();
+        for (index, (name, value)) in request.labels.iter().enumerate() {
+            let selected = match request.key {
+                Some(Key::Name(ref key)) => key.as_str() == name.as_str(),
+                Some(Key::Index(key)) => key == index as u64,
+                None => true,
+            };
+            if selected {
+                let label = Label {
+                    name: name.as_str().into(),
+                    value: value.as_str().into(),
+                };
+                labels.insert(index as u64, label);
+            }
+        }
+
+        if labels.is_empty() {
+            return Err(mrpc::Status::not_found("no label is selected"));
+        }
+        Ok(WRef::new(LookupReply { labels }))
+    }
+}
+
+fn request(key: Option<Key>) -> LookupRequest {
+    let mut labels = BTreeMap::new();
+    labels.insert("zone".into(), "us-west".into());
+    labels.insert("app".into(), "echo".into());
+    LookupRequest { labels, key }
+}
+
+/// Returns the index, name and value of each label in the reply.
+fn labels(reply: &LookupReply) -> Vec<(u64, &str, &str)> {
+    reply
+        .labels
+        .iter()
+        .map(|(index, label)| (*index, label.name.as_str(), label.value.as_str()))
+        .collect()
+}
+
+#[test]
+fn lookup() {
+    let (bound_tx, bound_rx) = mpsc::channel();
+    // the server runs until the test process exits
+    thread::spawn(move || {
+        smol::block_on(async {
+            let mut server = mrpc::stub::LocalServer::bind(SERVER_ADDR).unwrap();
+            server.add_service(LabelerServer::new(MyLabeler::default()));
+            bound_tx.send(()).unwrap();
+            server.serve().await.unwrap();
+        })
+    });
+    bound_rx.recv().unwrap();
+
+    let client = LabelerClient::connect(SERVER_ADDR).unwrap();
+
+    // the labels are ordered by name
+    let reply = smol::block_on(client.lookup(request(None))).unwrap();
+    assert_eq!(labels(&reply), [(0, "app", "echo"), (1, "zone", "us-west")]);
+
+    let reply = smol::block_on(client.lookup(request(Some(Key::Name("zone".into()))))).unwrap();
+    assert_eq!(labels(&reply), [(1, "zone", "us-west")]);
+
+    let reply = smol::block_on(client.lookup(request(Some(Key::Index(0))))).unwrap();
+    assert_eq!(labels(&reply), [(0, "app", "echo")]);
+
+    let status = smol::block_on(client.lookup(request(Some(Key::Index(2))))).unwrap_err();
+    assert_eq!(status.code(), mrpc::Code::NotFound);
+}
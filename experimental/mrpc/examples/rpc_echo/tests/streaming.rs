@@ -0,0 +1,105 @@
+//! Makes server-streaming and client-streaming calls through the phoenix embedded in the test
+//! process.
+use std::sync::mpsc;
+use std::thread;
+
+use futures::StreamExt;
+
+pub mod rpc_stream {
+    // The string specified here must match the proto package name
+    mrpc::include_proto!("rpc_stream");
+}
+
+use rpc_stream::counter_client::CounterClient;
+use rpc_stream::counter_server::{Counter, CounterServer};
+use rpc_stream::{CountRequest, Number};
+
+use mrpc::stub::{ReplyStream, RequestStream};
+use mrpc::{RRef, WRef};
+
+const SERVER_ADDR: &str = "127.0.0.1:5117";
+
+/// The most numbers a server stream replies with.
+const MAX_COUNT: u64 = 16;
+
+#[derive(Debug, Default)]
+struct MyCounter;
+
+#[mrpc::async_trait]
+impl Counter for MyCounter {
+    async fn count(
+        &self,
+        request: RRef<CountRequest>,
+    ) -> Result<ReplyStream<Number>, mrpc::Status> {
+        let count = request.count;
+        let numbers = (0..count.min(MAX_COUNT)).map(|value| Ok(WRef::new(Number { value })));
+        // the stream breaks after the most numbers it can reply with
+        let end = (count > MAX_COUNT).then(|| Err(mrpc::Status::out_of_range("count too large")));
+        Ok(futures::stream::iter(numbers.chain(end)).boxed())
+    }
+
+    async fn sum(&self, mut request: RequestStream<Number>) -> Result<WRef<Number>, mrpc::Status> {
+        let mut value = 0u64;
+        while let Some(number) = request.next().await {
+            value = value
+                .checked_add(number?.value)
+                .ok_or_else(|| mrpc::Status::out_of_range("sum overflows"))?;
+        }
+        Ok(WRef::new(Number { value }))
+    }
+}
+
+fn numbers(values: Vec<u64>) -> impl mrpc::stub::Stream<Item = WRef<Number>> {
+    futures::stream::iter(values).map(|value| WRef::new(Number { value }))
+}
+
+#[test]
+fn streaming() {
+    let (bound_tx, bound_rx) = mpsc::channel();
+    // the server runs until the test process exits
+    thread::spawn(move || {
+        smol::block_on(async {
+            let mut server = mrpc::stub::LocalServer::bind(SERVER_ADDR).unwrap();
+            server.add_service(CounterServer::new(MyCounter::default()));
+            bound_tx.send(()).unwrap();
+            server.serve().await.unwrap();
+        })
+    });
+    bound_rx.recv().unwrap();
+
+    let client = CounterClient::connect(SERVER_ADDR).unwrap();
+
+    // server streaming, the replies arrive in order
+    let replies: Vec<_> = smol::block_on(client.count(CountRequest { count: 5 }).collect());
+    let values: Vec<_> = replies
+        .iter()
+        .map(|reply| reply.as_ref().unwrap().value)
+        .collect();
+    assert_eq!(values, [0, 1, 2, 3, 4]);
+
+    let replies: Vec<_> = smol::block_on(client.count(CountRequest { count: 0 }).collect());
+    assert!(replies.is_empty());
+
+    // the stream ends with the status of the server
+    let replies: Vec<_> = smol::block_on(
+        client
+            .count(CountRequest {
+                count: MAX_COUNT + 1,
+            })
+            .collect(),
+    );
+    assert_eq!(replies.len() as u64, MAX_COUNT + 1);
+    assert!(replies[..MAX_COUNT as usize].iter().all(Result::is_ok));
+    let status = replies.last().unwrap().as_ref().unwrap_err();
+    assert_eq!(status.code(), mrpc::Code::OutOfRange);
+
+    // client streaming, the server replies once the stream ends
+    let reply = smol::block_on(client.sum(numbers(vec![1, 2, 3, 4]))).unwrap();
+    assert_eq!(reply.value, 10);
+
+    let reply = smol::block_on(client.sum(numbers(Vec::new()))).unwrap();
+    assert_eq!(reply.value, 0);
+
+    let status = smol::block_on(client.sum(numbers(vec![u64::MAX, 1]))).unwrap_err();
+    assert_eq!(status.code(), mrpc::Code::OutOfRange);
+}
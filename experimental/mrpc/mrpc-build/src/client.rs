@@ -9,7 +9,8 @@ use crate::{
 /// Generate service for client.
 ///
 /// This takes some `Service` and will generate a `TokenStream` that contains
-/// a public module with the generated client.
+/// a public module with the generated client. A bidirectional streaming method,
+/// which mRPC does not support, is generated as a `compile_error!`.
 pub fn generate<T: Service>(
     service: &T,
     emit_package: bool,
@@ -62,11 +63,20 @@ pub fn generate<T: Service>(
                     // request/response to/from the rpc engine rather than the transport engine.
                     // let stub = phoenix_syscalls::mrpc::cm::MrpcStub::set_transport(phoenix_syscalls::mrpc::cm::TransportType::Rdma)?;
                     Self::update_protos()?;
-                    let stub = ClientStub::connect(dst).unwrap();
+                    let stub = ClientStub::connect(dst)?;
                     Ok(Self {
                         stub,
                     })
                 }
+                /// Re-establishes the connection according to `policy` once it is lost.
+                pub fn with_reconnect(mut self, policy: ::mrpc::stub::ReconnectPolicy) -> Self {
+                    self.stub.set_reconnect(Some(policy));
+                    self
+                }
+                /// Returns the state of the connection.
+                pub fn connectivity_state(&self) -> ::mrpc::stub::ConnectivityState {
+                    self.stub.connectivity_state()
+                }
                 pub fn multi_connect<A: std::net::ToSocketAddrs>(dsts: impl IntoIterator<Item=A>) -> Result<Self, ::mrpc::Error> {
                     // use the cmid builder to create a CmId.
                     // no you shouldn't rely on cmid here anymore. you should have your own rpc endpoint
@@ -108,23 +118,20 @@ fn generate_methods<T: Service>(
 
         stream.extend(generate_doc_comments(method.comment()));
 
-        // mRPC current doesn't not support streaming
-        // Generate unary
-        let ident = quote::format_ident!("{}", method.name());
-
         let (request, response) =
             method.request_response_name(proto_path, compile_well_known_types);
 
-        let method = quote::quote! {
-            pub fn #ident(
-                &self,
-                req: impl ::mrpc::IntoWRef<#request>
-            ) -> impl std::future::Future<
-                Output = Result<::mrpc::RRef<#response>, ::mrpc::Status>
-            > + '_ {
-                let call_id = self.stub.initiate_call();
-
-                self.stub.unary(#service_id, #func_id, call_id, req.into_wref())
+        let method = match (method.client_streaming(), method.server_streaming()) {
+            (false, false) => generate_unary(method, service_id, func_id, request, response),
+            (false, true) => {
+                generate_server_streaming(method, service_id, func_id, request, response)
+            }
+            (true, false) => {
+                generate_client_streaming(method, service_id, func_id, request, response)
+            }
+            (true, true) => {
+                let error = format!("mRPC does not support bidirectional streaming: {}", path);
+                quote::quote! { compile_error!(#error); }
             }
         };
 
@@ -133,3 +140,89 @@ fn generate_methods<T: Service>(
 
     stream
 }
+
+fn generate_unary<T: Method>(
+    method: &T,
+    service_id: u32,
+    func_id: u32,
+    request: TokenStream,
+    response: TokenStream,
+) -> TokenStream {
+    let ident = quote::format_ident!("{}", method.name());
+    let ident_with_timeout = quote::format_ident!("{}_with_timeout", method.name());
+    let timeout_doc = format!(
+        " Same as [`Self::{}`], but the call fails with `deadline_exceeded` if it does not \
+        complete within `timeout`.",
+        method.name()
+    );
+
+    quote::quote! {
+        pub fn #ident(
+            &self,
+            req: impl ::mrpc::IntoWRef<#request>
+        ) -> impl std::future::Future<
+            Output = Result<::mrpc::RRef<#response>, ::mrpc::Status>
+        > + '_ {
+            let call_id = self.stub.initiate_call();
+
+            self.stub.unary(#service_id, #func_id, call_id, req.into_wref())
+        }
+
+        #[doc = #timeout_doc]
+        pub fn #ident_with_timeout(
+            &self,
+            req: impl ::mrpc::IntoWRef<#request>,
+            timeout: std::time::Duration,
+        ) -> impl std::future::Future<
+            Output = Result<::mrpc::RRef<#response>, ::mrpc::Status>
+        > + '_ {
+            let call_id = self.stub.initiate_call();
+
+            self.stub.unary_with_timeout(#service_id, #func_id, call_id, req.into_wref(), timeout)
+        }
+    }
+}
+
+fn generate_server_streaming<T: Method>(
+    method: &T,
+    service_id: u32,
+    func_id: u32,
+    request: TokenStream,
+    response: TokenStream,
+) -> TokenStream {
+    let ident = quote::format_ident!("{}", method.name());
+
+    quote::quote! {
+        pub fn #ident(
+            &self,
+            req: impl ::mrpc::IntoWRef<#request>
+        ) -> ::mrpc::stub::ResponseStream<'_, #response> {
+            let call_id = self.stub.initiate_call();
+
+            self.stub.server_streaming(#service_id, #func_id, call_id, req.into_wref())
+        }
+    }
+}
+
+fn generate_client_streaming<T: Method>(
+    method: &T,
+    service_id: u32,
+    func_id: u32,
+    request: TokenStream,
+    response: TokenStream,
+) -> TokenStream {
+    let ident = quote::format_ident!("{}", method.name());
+
+    quote::quote! {
+        pub fn #ident<'a>(
+            &'a self,
+            requests: impl ::mrpc::stub::Stream<Item = ::mrpc::WRef<#request>> + 'a
+        ) -> impl std::future::Future<
+            Output = Result<::mrpc::RRef<#response>, ::mrpc::Status>
+        > + 'a {
+            let call_id = self.stub.initiate_call();
+
+            self.stub.client_streaming(#service_id, #func_id, call_id, requests)
+        }
+    }
+}
@@ -107,6 +107,10 @@ pub trait Method {
     ) -> (TokenStream, TokenStream);
     /// Proto pacakge name of request and response
     fn request_response_package(&self, proto_path: &str) -> (Option<String>, Option<String>);
+    /// Method is streamed by client.
+    fn client_streaming(&self) -> bool;
+    /// Method is streamed by server.
+    fn server_streaming(&self) -> bool;
 }
 
 // Returns a full path of a service compatible to gRPC.
@@ -1,6 +1,7 @@
 use std::ffi::OsString;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use proc_macro2::TokenStream;
 use prost_build::Config;
@@ -83,6 +84,9 @@ impl Builder {
 
     /// Compile the .proto files and execute code generation using a
     /// custom `prost_build::Config`.
+    ///
+    /// Returns an error of kind `InvalidInput` if a service has a bidirectional streaming
+    /// method, which mRPC does not support.
     pub fn compile_with_config(
         self,
         mut config: Config,
@@ -96,6 +100,8 @@ impl Builder {
         };
 
         config.out_dir(out_dir);
+        // map fields are held by the shared-memory `BTreeMap`
+        config.btree_map(["."]);
         if let Some(path) = self.file_descriptor_set_path.as_ref() {
             config.file_descriptor_set_path(path);
         }
@@ -119,17 +125,31 @@ impl Builder {
             config.protoc_arg(arg);
         }
 
-        config.service_generator(self.service_generator());
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        config.service_generator(Box::new(ServiceGenerator::new(self, Arc::clone(&errors))));
 
         config.compile_protos_mrpc_frontend(protos, includes)?;
 
+        let errors = errors.lock().unwrap();
+        if !errors.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                errors.join("\n"),
+            ));
+        }
         Ok(())
     }
 
     /// Turn the builder into a `ServiceGenerator` ready to be passed to `prost-build`s
     /// `Config::service_generator`.
+    ///
+    /// A service with a bidirectional streaming method is not generated, and the generated
+    /// code fails to compile with the reason instead.
     pub fn service_generator(self) -> Box<dyn prost_build::ServiceGenerator> {
-        Box::new(ServiceGenerator::new(self))
+        Box::new(ServiceGenerator::new(
+            self,
+            Arc::new(Mutex::new(Vec::new())),
+        ))
     }
 
     /// Enable or disable gRPC client code generation.
@@ -276,20 +296,45 @@ struct ServiceGenerator {
     builder: Builder,
     clients: TokenStream,
     servers: TokenStream,
+    // why services are not generated, returned by `Builder::compile_with_config`
+    errors: Arc<Mutex<Vec<String>>>,
 }
 
 impl ServiceGenerator {
-    fn new(builder: Builder) -> Self {
+    fn new(builder: Builder, errors: Arc<Mutex<Vec<String>>>) -> Self {
         ServiceGenerator {
             builder,
             clients: TokenStream::default(),
             servers: TokenStream::default(),
+            errors,
         }
     }
 }
 
 impl prost_build::ServiceGenerator for ServiceGenerator {
-    fn generate(&mut self, service: prost_build::Service, _buf: &mut String) {
+    fn generate(&mut self, service: prost_build::Service, buf: &mut String) {
+        use crate::{Method, Service};
+
+        let bidi_streaming: Vec<_> = service
+            .methods()
+            .iter()
+            .filter(|method| method.client_streaming() && method.server_streaming())
+            .map(|method| method.name())
+            .collect();
+        if !bidi_streaming.is_empty() {
+            let error = format!(
+                "mRPC does not support bidirectional streaming, found in {}.{}: {}",
+                service.package(),
+                service.identifier(),
+                bidi_streaming.join(", ")
+            );
+            let code = quote! { compile_error!(#error); };
+            buf.push_str(&code.to_string());
+            buf.push('\n');
+            self.errors.lock().unwrap().push(error);
+            return;
+        }
+
         if self.builder.build_server {
             let server = server::generate(
                 &service,
@@ -405,6 +450,14 @@ impl crate::Method for prost_build::Method {
         &self.comments.leading[..]
     }
 
+    fn client_streaming(&self) -> bool {
+        self.client_streaming
+    }
+
+    fn server_streaming(&self) -> bool {
+        self.server_streaming
+    }
+
     fn request_response_name(
         &self,
         proto_path: &str,
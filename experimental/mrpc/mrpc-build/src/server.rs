@@ -45,6 +45,8 @@ pub fn generate<T: Service>(
         .into_iter()
         .map(|x| syn::parse_str::<syn::Path>(&x).unwrap());
 
+    let method_names = service.methods().iter().map(|method| method.identifier());
+
     quote::quote! {
         /// Generated server implementations.
         #(#mod_attributes)*
@@ -64,7 +66,7 @@ pub fn generate<T: Service>(
 
             impl<T: #server_trait> #server_service<T> {
                 fn update_protos() -> Result<(), ::mrpc::Error> {
-                    let srcs = [#(#proto_srcs),*].concat();
+                    let srcs = <Self as NamedService>::protos();
                     ::mrpc::stub::update_protos(srcs.as_slice())
                 }
 
@@ -78,6 +80,11 @@ pub fn generate<T: Service>(
             impl<T: #server_trait> NamedService for #server_service<T> {
                 const SERVICE_ID: u32 = #service_id;
                 const NAME: &'static str = #path;
+                const METHODS: &'static [&'static str] = &[#(#method_names),*];
+
+                fn protos() -> Vec<&'static str> {
+                    [#(#proto_srcs),*].concat()
+                }
             }
 
             #[mrpc::async_trait]
@@ -92,7 +99,12 @@ pub fn generate<T: Service>(
                     match func_id {
                         #methods
                         _ => {
-                            todo!("error handling for unknown func_id: {}", func_id);
+                            // release the receive buffer of the request
+                            drop(::mrpc::stub::service_pre_handler::<()>(&req_opaque, read_heap));
+                            let status = ::mrpc::Status::unimplemented(
+                                format!("unknown func_id: {}", func_id)
+                            );
+                            ::mrpc::stub::service_error_handler(status, &req_opaque)
                         }
                     }
                 }
@@ -132,13 +144,23 @@ fn generate_trait_methods<T: Service>(
 
         let method_doc = generate_doc_comments(method.comment());
 
-        // mRPC does not support streaming
+        let request = if method.client_streaming() {
+            quote::quote! { ::mrpc::stub::RequestStream<#req_type> }
+        } else {
+            quote::quote! { ::mrpc::RRef<#req_type> }
+        };
+        let reply = if method.server_streaming() {
+            quote::quote! { ::mrpc::stub::ReplyStream<#res_type> }
+        } else {
+            quote::quote! { ::mrpc::WRef<#res_type> }
+        };
+
         let method = quote::quote! {
             #method_doc
             async fn #name(
                 &self,
-                request: ::mrpc::RRef<#req_type>
-            ) -> Result<::mrpc::WRef<#res_type>, ::mrpc::Status>;
+                request: #request
+            ) -> Result<#reply, ::mrpc::Status>;
         };
 
         stream.extend(method);
@@ -162,17 +184,36 @@ fn generate_methods<T: Service>(
         let (_req_type, _res_type) =
             method.request_response_name(proto_path, compile_well_known_types);
 
+        let req = if method.client_streaming() {
+            quote::quote! {
+                ::mrpc::stub::service_stream_pre_handler(&req_opaque, read_heap)
+            }
+        } else {
+            quote::quote! {
+                ::mrpc::RRef::new(&req_opaque, read_heap)
+            }
+        };
+        let post_handler = if method.server_streaming() {
+            quote::quote! {
+                ::mrpc::stub::service_stream_post_handler(reply, req_opaque).await
+            }
+        } else {
+            quote::quote! {
+                ::mrpc::stub::service_post_handler(reply, &req_opaque)
+            }
+        };
+
         let match_branch = quote::quote! {
             #func_id => {
                 // let req_view = ::mrpc::stub::service_pre_handler(&req, reclaim_buffer);
-                let req = ::mrpc::RRef::new(&req_opaque, read_heap);
+                let req = #req;
                 let res = self.inner.#func_ident(req).await;
                 match res {
                     Ok(reply) => {
-                        ::mrpc::stub::service_post_handler(reply, &req_opaque)
+                        #post_handler
                     }
-                    Err(_status) => {
-                        todo!();
+                    Err(status) => {
+                        ::mrpc::stub::service_error_handler(status, &req_opaque)
                     }
                 }
             },
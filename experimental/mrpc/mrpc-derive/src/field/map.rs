@@ -0,0 +1,133 @@
+use anyhow::{bail, Error};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Lit, Meta, MetaNameValue};
+
+use crate::field::{scalar, set_option, tag_attr};
+
+/// The value type of a map field.
+#[derive(Clone, Debug)]
+pub enum ValueTy {
+    Scalar(scalar::Ty),
+    Message,
+}
+
+impl ValueTy {
+    fn from_str(s: &str) -> Result<ValueTy, Error> {
+        if s.trim() == "message" {
+            Ok(ValueTy::Message)
+        } else {
+            Ok(ValueTy::Scalar(scalar::Ty::from_str(s)?))
+        }
+    }
+
+    /// Returns closures that emplace, excavate and evaluate the extent of a key or a value of
+    /// this type. Numeric types have nothing beyond their representation in the entry.
+    fn emplacement(&self) -> (TokenStream, TokenStream, TokenStream) {
+        let module = match *self {
+            ValueTy::Scalar(ref ty) if ty.is_numeric() => {
+                return (quote!(|_, _| Ok(())), quote!(|_, _| Ok(())), quote!(|_| 0));
+            }
+            ValueTy::Scalar(ref ty) => ty.module(),
+            ValueTy::Message => syn::Ident::new("message", proc_macro2::Span::call_site()),
+        };
+        (
+            quote!(|v, sgl| ::mrpc_marshal::emplacement::#module::emplace(v, sgl)),
+            quote!(|v, ctx| ::mrpc_marshal::emplacement::#module::excavate(v, ctx)),
+            quote!(|v| ::mrpc_marshal::emplacement::#module::extent(v)),
+        )
+    }
+}
+
+/// A protobuf map field.
+#[derive(Clone)]
+pub struct Field {
+    pub key_ty: scalar::Ty,
+    pub value_ty: ValueTy,
+    pub tag: u32,
+}
+
+impl Field {
+    pub fn new(attrs: &[Meta], inferred_tag: Option<u32>) -> Result<Option<Field>, Error> {
+        let mut types = None;
+        let mut tag = None;
+
+        for attr in attrs {
+            if let Some(t) = tag_attr(attr)? {
+                set_option(&mut tag, t, "duplicate tag attributes")?;
+            } else if let Some(map_ty) = map_attr(attr)? {
+                set_option(&mut types, map_ty, "duplicate map type attribute")?;
+            } else {
+                return Ok(None);
+            }
+        }
+
+        Ok(match (types, tag.or(inferred_tag)) {
+            (Some((key_ty, value_ty)), Some(tag)) => Some(Field {
+                key_ty,
+                value_ty,
+                tag,
+            }),
+            _ => None,
+        })
+    }
+
+    pub fn emplace(&self, ident: TokenStream) -> TokenStream {
+        let (emplace_key, _, _) = ValueTy::Scalar(self.key_ty.clone()).emplacement();
+        let (emplace_value, _, _) = self.value_ty.emplacement();
+        quote! {
+            ::mrpc_marshal::emplacement::map::emplace(&#ident, sgl, #emplace_key, #emplace_value)?;
+        }
+    }
+
+    pub fn excavate(&self, ident: TokenStream) -> TokenStream {
+        let (_, excavate_key, _) = ValueTy::Scalar(self.key_ty.clone()).emplacement();
+        let (_, excavate_value, _) = self.value_ty.emplacement();
+        quote! {
+            ::mrpc_marshal::emplacement::map::excavate(&mut #ident, ctx, #excavate_key, #excavate_value)?;
+        }
+    }
+
+    pub fn extent(&self, ident: TokenStream) -> TokenStream {
+        let (_, _, extent_key) = ValueTy::Scalar(self.key_ty.clone()).emplacement();
+        let (_, _, extent_value) = self.value_ty.emplacement();
+        quote! {
+            ::mrpc_marshal::emplacement::map::extent(&#ident, #extent_key, #extent_value)
+        }
+    }
+}
+
+/// Parses `btree_map = "key, value"`. Maps are only represented by the shared-memory `BTreeMap`,
+/// so `map` and `hash_map` fields are rejected.
+fn map_attr(attr: &Meta) -> Result<Option<(scalar::Ty, ValueTy)>, Error> {
+    if attr.path().is_ident("map") || attr.path().is_ident("hash_map") {
+        bail!("invalid map attribute: map fields must be generated as `btree_map`");
+    }
+    if !attr.path().is_ident("btree_map") {
+        return Ok(None);
+    }
+    let value = match *attr {
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(ref lit),
+            ..
+        }) => lit.value(),
+        _ => bail!("invalid map attribute: {:?}", attr),
+    };
+    let (key, value) = match value.split_once(',') {
+        Some(types) => types,
+        None => bail!("invalid map attribute: must have key and value types"),
+    };
+
+    let key_ty = scalar::Ty::from_str(key)?;
+    match key_ty {
+        scalar::Ty::Float
+        | scalar::Ty::Double
+        | scalar::Ty::Bytes(..)
+        | scalar::Ty::Enumeration(..) => {
+            bail!("invalid map attribute: key type cannot be {}", key_ty)
+        }
+        _ => (),
+    }
+
+    Ok(Some((key_ty, ValueTy::from_str(value)?)))
+}
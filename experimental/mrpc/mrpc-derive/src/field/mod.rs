@@ -1,4 +1,6 @@
+mod map;
 mod message;
+mod oneof;
 mod scalar;
 
 use std::fmt;
@@ -14,6 +16,10 @@ pub enum Field {
     Scalar(scalar::Field),
     /// A message field.
     Message(message::Field),
+    /// A map field.
+    Map(map::Field),
+    /// A oneof field.
+    Oneof(oneof::Field),
 }
 
 // dispatcher
@@ -28,6 +34,10 @@ impl Field {
             Field::Scalar(field)
         } else if let Some(field) = message::Field::new(&attrs, inferred_tag)? {
             Field::Message(field)
+        } else if let Some(field) = map::Field::new(&attrs, inferred_tag)? {
+            Field::Map(field)
+        } else if let Some(field) = oneof::Field::new(&attrs)? {
+            Field::Oneof(field)
         } else {
             bail!("no type attribute");
         };
@@ -35,10 +45,48 @@ impl Field {
         Ok(Some(field))
     }
 
+    /// Creates a new oneof `Field` from an iterator of field attributes.
+    ///
+    /// If the meta items are invalid, an error will be returned.
+    /// If the field should be ignored, `None` is returned.
+    pub fn new_oneof(attrs: Vec<Attribute>) -> Result<Option<Field>, Error> {
+        let attrs = prost_attrs(attrs);
+        // the variants of a oneof hold their values directly, without an `Option`
+        let field = if let Some(field) = scalar::Field::new(&attrs, None)? {
+            Field::Scalar(field)
+        } else if let Some(mut field) = message::Field::new(&attrs, None)? {
+            field.label = Label::Required;
+            Field::Message(field)
+        } else {
+            bail!("invalid oneof variant: oneof variants may only be scalars or messages");
+        };
+
+        Ok(Some(field))
+    }
+
     pub fn tags(&self) -> Vec<u32> {
         match *self {
             Field::Scalar(ref scalar) => vec![scalar.tag],
             Field::Message(ref message) => vec![message.tag],
+            Field::Map(ref map) => vec![map.tag],
+            Field::Oneof(ref oneof) => oneof.tags.clone(),
+        }
+    }
+
+    /// Returns true if the field holds at most one value, which can be read with `Reflect`.
+    /// Repeated fields, maps and oneofs cannot be read by name.
+    pub fn is_reflectable(&self) -> bool {
+        match *self {
+            Field::Scalar(ref scalar) => {
+                matches!(
+                    scalar.kind,
+                    scalar::Kind::Plain(..)
+                        | scalar::Kind::Optional(..)
+                        | scalar::Kind::Required(..)
+                ) && scalar.ty != scalar::Ty::Bytes(scalar::BytesTy::Bytes)
+            }
+            Field::Message(ref message) => message.label != Label::Repeated,
+            Field::Map(..) | Field::Oneof(..) => false,
         }
     }
 
@@ -50,6 +98,8 @@ impl Field {
         match *self {
             Field::Scalar(ref scalar) => scalar.emplace(ident),
             Field::Message(ref message) => message.emplace(ident),
+            Field::Map(ref map) => map.emplace(ident),
+            Field::Oneof(ref oneof) => oneof.emplace(ident),
         }
     }
 
@@ -58,6 +108,8 @@ impl Field {
         match *self {
             Field::Scalar(ref scalar) => scalar.excavate(ident),
             Field::Message(ref message) => message.excavate(ident),
+            Field::Map(ref map) => map.excavate(ident),
+            Field::Oneof(ref oneof) => oneof.excavate(ident),
         }
     }
     /// Returns an expression that evaluates how many SgEs that this field requires
@@ -67,6 +119,8 @@ impl Field {
         match *self {
             Field::Scalar(ref scalar) => scalar.extent(ident),
             Field::Message(ref message) => message.extent(ident),
+            Field::Map(ref map) => map.extent(ident),
+            Field::Oneof(ref oneof) => oneof.extent(ident),
         }
     }
 }
@@ -227,3 +281,33 @@ pub(super) fn tag_attr(attr: &Meta) -> Result<Option<u32>, Error> {
         _ => bail!("invalid tag attribute: {:?}", attr),
     }
 }
+
+pub(super) fn tags_attr(attr: &Meta) -> Result<Option<Vec<u32>>, Error> {
+    // parse the tags of a oneof field
+    if !attr.path().is_ident("tags") {
+        return Ok(None);
+    }
+    match *attr {
+        Meta::List(ref meta_list) => {
+            let mut tags = Vec::with_capacity(meta_list.nested.len());
+            for item in &meta_list.nested {
+                if let NestedMeta::Lit(Lit::Int(ref lit)) = *item {
+                    tags.push(lit.base10_parse()?);
+                } else {
+                    bail!("invalid tag attribute: {:?}", attr);
+                }
+            }
+            Ok(Some(tags))
+        }
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(ref lit),
+            ..
+        }) => lit
+            .value()
+            .split(',')
+            .map(|s| s.trim().parse::<u32>().map_err(Error::from))
+            .collect::<Result<Vec<u32>, _>>()
+            .map(Some),
+        _ => bail!("invalid tag attribute: {:?}", attr),
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{bail, Error};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_str, Lit, Meta, MetaNameValue, NestedMeta, Path};
+
+use crate::field::{set_option, tags_attr};
+
+/// A protobuf oneof field. The field has type `Option<T>`, where `T` derives `Oneof`.
+#[derive(Clone)]
+pub struct Field {
+    pub tags: Vec<u32>,
+}
+
+impl Field {
+    pub fn new(attrs: &[Meta]) -> Result<Option<Field>, Error> {
+        let mut ty = None;
+        let mut tags = None;
+        let mut unknown_attrs = Vec::new();
+
+        for attr in attrs {
+            if attr.path().is_ident("oneof") {
+                let t: Path = match *attr {
+                    Meta::NameValue(MetaNameValue {
+                        lit: Lit::Str(ref lit),
+                        ..
+                    }) => parse_str::<Path>(&lit.value())?,
+                    Meta::List(ref list) if list.nested.len() == 1 => {
+                        // TODO(rustlang/rust#23121): slice pattern matching would make this much nicer.
+                        if let NestedMeta::Meta(Meta::Path(ref path)) = list.nested[0] {
+                            path.clone()
+                        } else {
+                            bail!("invalid oneof attribute: item must be a path");
+                        }
+                    }
+                    _ => bail!("invalid oneof attribute: {:?}", attr),
+                };
+                set_option(&mut ty, t, "duplicate oneof attribute")?;
+            } else if let Some(t) = tags_attr(attr)? {
+                set_option(&mut tags, t, "duplicate tags attributes")?;
+            } else {
+                unknown_attrs.push(attr);
+            }
+        }
+
+        if ty.is_none() {
+            return Ok(None);
+        }
+
+        match unknown_attrs.len() {
+            0 => (),
+            1 => bail!("unknown attribute for oneof field: {:?}", unknown_attrs[0]),
+            _ => bail!("unknown attributes for oneof field: {:?}", unknown_attrs),
+        }
+
+        let tags = match tags {
+            Some(tags) => tags,
+            None => bail!("oneof field is missing a tags attribute"),
+        };
+
+        Ok(Some(Field { tags }))
+    }
+
+    pub fn emplace(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            ::mrpc_marshal::emplacement::oneof::emplace_optional(&#ident, sgl)?;
+        }
+    }
+
+    pub fn excavate(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            ::mrpc_marshal::emplacement::oneof::excavate_optional(&mut #ident, ctx)?;
+        }
+    }
+
+    pub fn extent(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            ::mrpc_marshal::emplacement::oneof::extent_optional(&#ident)
+        }
+    }
+}
@@ -287,6 +287,40 @@ impl Ty {
         Ok(Some(ty))
     }
 
+    /// Parses a type as it appears in map field attributes, e.g., `string` or
+    /// `enumeration(Foo)`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Ty, Error> {
+        let s = s.trim();
+        let ty = match s {
+            "float" => Ty::Float,
+            "double" => Ty::Double,
+            "int32" => Ty::Int32,
+            "int64" => Ty::Int64,
+            "uint32" => Ty::Uint32,
+            "uint64" => Ty::Uint64,
+            "sint32" => Ty::Sint32,
+            "sint64" => Ty::Sint64,
+            "fixed32" => Ty::Fixed32,
+            "fixed64" => Ty::Fixed64,
+            "sfixed32" => Ty::Sfixed32,
+            "sfixed64" => Ty::Sfixed64,
+            "bool" => Ty::Bool,
+            "string" => Ty::String,
+            "bytes" => Ty::Bytes(BytesTy::Vec),
+            _ => match s.strip_prefix("enumeration").map(str::trim) {
+                Some(path)
+                    if (path.starts_with('(') && path.ends_with(')'))
+                        || (path.starts_with('<') && path.ends_with('>')) =>
+                {
+                    Ty::Enumeration(parse_str::<Path>(path[1..path.len() - 1].trim())?)
+                }
+                _ => bail!("invalid type: {}", s),
+            },
+        };
+        Ok(ty)
+    }
+
     /// Returns the type as it appears in protobuf field declarations.
     pub fn as_str(&self) -> &'static str {
         match *self {
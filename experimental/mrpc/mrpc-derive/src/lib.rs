@@ -89,7 +89,27 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         .iter()
         .map(|&(ref field_ident, ref field)| field.extent(quote!(self.#field_ident)));
 
+    let reflect = fields
+        .iter()
+        .filter(|&(_, ref field)| field.is_reflectable())
+        .map(|&(ref field_ident, _)| {
+            let name = field_ident.to_string();
+            let name = name.trim_start_matches("r#");
+            quote!(#name => ::mrpc_marshal::reflect::Reflect::reflect(&self.#field_ident, rest),)
+        });
+
     let expanded = quote! {
+        impl #impl_generics ::mrpc_marshal::reflect::Reflect for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn reflect(&self, path: &str) -> ::core::option::Option<::mrpc_marshal::reflect::FieldValue<'_>> {
+                let (name, rest) = path.split_once('.').unwrap_or((path, ""));
+                match name {
+                    #(#reflect)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+
         impl #impl_generics ::mrpc_marshal::RpcMessage for #ident #ty_generics #where_clause {
             fn marshal(&self) -> std::result::Result<::mrpc_marshal::SgList, mrpc_marshal::MarshalError> {
                 let cap = 1 + self.extent();
@@ -161,6 +181,121 @@ pub fn message(input: TokenStream) -> TokenStream {
     try_message(input).unwrap()
 }
 
+fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
+    let input: DeriveInput = syn::parse(input)?;
+    let ident = input.ident;
+
+    let variants = match input.data {
+        Data::Enum(DataEnum { variants, .. }) => variants,
+        Data::Struct(..) => bail!("Oneof can not be derived for a struct"),
+        Data::Union(..) => bail!("Oneof can not be derived for a union"),
+    };
+
+    let generics = &input.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    // Map the variants into 'fields'.
+    let mut fields: Vec<(Ident, Field)> = Vec::new();
+    for Variant {
+        attrs,
+        ident: variant_ident,
+        fields: variant_fields,
+        ..
+    } in variants
+    {
+        let variant_fields = match variant_fields {
+            Fields::Unit => Vec::new(),
+            Fields::Named(FieldsNamed { named: fields, .. })
+            | Fields::Unnamed(FieldsUnnamed {
+                unnamed: fields, ..
+            }) => fields.into_iter().collect(),
+        };
+        if variant_fields.len() != 1 {
+            bail!("Oneof enum variants must have a single field");
+        }
+        match Field::new_oneof(attrs)? {
+            Some(field) => fields.push((variant_ident, field)),
+            None => bail!("invalid oneof variant: oneof variants may not be ignored"),
+        }
+    }
+
+    let mut tags = fields
+        .iter()
+        .map(|&(ref variant_ident, ref field)| -> Result<u32, Error> {
+            if field.tags().len() > 1 {
+                bail!(
+                    "invalid oneof variant {}::{}: oneof variants may only have a single tag",
+                    ident,
+                    variant_ident
+                );
+            }
+            Ok(field.tags()[0])
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    tags.sort_unstable();
+    tags.dedup();
+    if tags.len() != fields.len() {
+        bail!("invalid oneof {}: variants have duplicate tags", ident);
+    }
+
+    let emplace = fields.iter().map(|&(ref variant_ident, ref field)| {
+        let emplace = field.emplace(quote!((*value)));
+        quote!(#ident::#variant_ident(ref value) => { #emplace })
+    });
+
+    let excavate = fields.iter().map(|&(ref variant_ident, ref field)| {
+        let excavate = field.excavate(quote!((*value)));
+        quote!(#ident::#variant_ident(ref mut value) => { #excavate })
+    });
+
+    let extent = fields.iter().map(|&(ref variant_ident, ref field)| {
+        let extent = field.extent(quote!((*value)));
+        quote!(#ident::#variant_ident(ref value) => #extent)
+    });
+
+    let expanded = quote! {
+        // numeric variants have nothing to emplace beyond the enum itself
+        #[allow(unused_variables)]
+        impl #impl_generics ::mrpc_marshal::RpcOneof for #ident #ty_generics #where_clause {
+            #[inline(always)]
+            fn emplace(
+                &self,
+                sgl: &mut ::mrpc_marshal::SgList
+            ) -> std::result::Result<(), ::mrpc_marshal::MarshalError> {
+                match *self {
+                    #(#emplace,)*
+                }
+                Ok(())
+            }
+
+            #[inline(always)]
+            unsafe fn excavate<'a, A: ::mrpc_marshal::AddressArbiter>(
+                &mut self,
+                ctx: &mut ::mrpc_marshal::ExcavateContext<'a, A>
+            ) -> std::result::Result<(), ::mrpc_marshal::UnmarshalError> {
+                match *self {
+                    #(#excavate,)*
+                }
+                Ok(())
+            }
+
+            #[inline(always)]
+            fn extent(&self) -> usize {
+                match *self {
+                    #(#extent,)*
+                }
+            }
+        }
+    };
+
+    Ok(expanded.into())
+}
+
+#[proc_macro_derive(Oneof, attributes(prost))]
+pub fn oneof(input: TokenStream) -> TokenStream {
+    try_oneof(input).unwrap()
+}
+
 fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
     let input: DeriveInput = syn::parse(input)?;
     let ident = input.ident;
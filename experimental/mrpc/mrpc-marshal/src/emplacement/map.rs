@@ -0,0 +1,103 @@
+//! A map field is laid out as the buffer of its entries, followed by the SgEs of the keys and
+//! values of each entry. The derived code passes in how to emplace a key and a value, as they can
+//! be of any scalar type or a message.
+use std::mem;
+
+use crate::shadow::BTreeMap;
+use crate::shadow::Vec;
+use crate::{AddressArbiter, ExcavateContext, MarshalError, SgE, SgList, UnmarshalError};
+
+#[inline]
+pub fn emplace<K, V, FK, FV>(
+    val: &BTreeMap<K, V>,
+    sgl: &mut SgList,
+    emplace_key: FK,
+    emplace_value: FV,
+) -> Result<(), MarshalError>
+where
+    FK: Fn(&K, &mut SgList) -> Result<(), MarshalError>,
+    FV: Fn(&V, &mut SgList) -> Result<(), MarshalError>,
+{
+    if val.is_empty() {
+        return Ok(());
+    }
+
+    let entries = val.as_entries();
+    let buf_ptr = entries.shm_non_null().as_ptr_backend().addr();
+    let buf_len = entries.len() * mem::size_of::<(K, V)>();
+    sgl.0.push(SgE {
+        ptr: buf_ptr,
+        len: buf_len,
+    });
+
+    for (k, v) in entries.iter() {
+        emplace_key(k, sgl)?;
+        emplace_value(v, sgl)?;
+    }
+
+    Ok(())
+}
+
+#[inline]
+pub unsafe fn excavate<'a, K, V, A, FK, FV>(
+    val: &mut BTreeMap<K, V>,
+    ctx: &mut ExcavateContext<'a, A>,
+    mut excavate_key: FK,
+    mut excavate_value: FV,
+) -> Result<(), UnmarshalError>
+where
+    A: AddressArbiter,
+    FK: FnMut(&mut K, &mut ExcavateContext<'a, A>) -> Result<(), UnmarshalError>,
+    FV: FnMut(&mut V, &mut ExcavateContext<'a, A>) -> Result<(), UnmarshalError>,
+{
+    // SAFETY: the entries are only replaced by the same entries in the receive buffer.
+    let entries = unsafe { val.as_mut_entries() };
+    if entries.is_empty() {
+        mem::forget(mem::replace(entries, Vec::new()));
+        return Ok(());
+    }
+
+    let len = entries.len();
+    let buf_sge = ctx.sgl.next().ok_or(UnmarshalError::SgListUnderflow)?;
+    let expected = len * mem::size_of::<(K, V)>();
+    if buf_sge.len != expected {
+        return Err(UnmarshalError::SgELengthMismatch {
+            expected,
+            actual: buf_sge.len,
+        });
+    }
+
+    let backend_addr = buf_sge.ptr;
+    let app_addr = ctx.addr_arbiter.query_app_addr(backend_addr)?;
+    mem::forget(mem::replace(entries, unsafe {
+        Vec::from_raw_parts(
+            app_addr as *mut (K, V),
+            backend_addr as *mut (K, V),
+            len,
+            len,
+        )
+    }));
+
+    for (k, v) in entries.iter_mut() {
+        excavate_key(k, ctx)?;
+        excavate_value(v, ctx)?;
+    }
+
+    Ok(())
+}
+
+#[inline]
+pub fn extent<K, V, FK, FV>(val: &BTreeMap<K, V>, extent_key: FK, extent_value: FV) -> usize
+where
+    FK: Fn(&K) -> usize,
+    FV: Fn(&V) -> usize,
+{
+    if !val.is_empty() {
+        1 + val
+            .iter()
+            .map(|(k, v)| extent_key(k) + extent_value(v))
+            .sum::<usize>()
+    } else {
+        0
+    }
+}
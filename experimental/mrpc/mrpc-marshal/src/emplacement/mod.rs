@@ -2,8 +2,10 @@
 #![allow(clippy::missing_safety_doc)]
 
 pub mod bytes;
+pub mod map;
 pub mod message;
 mod numeric;
+pub mod oneof;
 pub mod string;
 
 pub use numeric::{bool, double, float, int32, int64, uint32, uint64};
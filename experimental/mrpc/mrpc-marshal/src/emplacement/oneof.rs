@@ -0,0 +1,46 @@
+use crate::{AddressArbiter, ExcavateContext, MarshalError, RpcOneof, SgList, UnmarshalError};
+
+#[inline]
+pub fn emplace<O: RpcOneof>(val: &O, sgl: &mut SgList) -> Result<(), MarshalError> {
+    val.emplace(sgl)
+}
+
+#[inline]
+pub fn emplace_optional<O: RpcOneof>(
+    val: &Option<O>,
+    sgl: &mut SgList,
+) -> Result<(), MarshalError> {
+    if let Some(val) = val {
+        val.emplace(sgl)?;
+    }
+    Ok(())
+}
+
+#[inline]
+pub unsafe fn excavate<'a, O: RpcOneof, A: AddressArbiter>(
+    val: &mut O,
+    ctx: &mut ExcavateContext<'a, A>,
+) -> Result<(), UnmarshalError> {
+    val.excavate(ctx)
+}
+
+#[inline]
+pub unsafe fn excavate_optional<'a, O: RpcOneof, A: AddressArbiter>(
+    val: &mut Option<O>,
+    ctx: &mut ExcavateContext<'a, A>,
+) -> Result<(), UnmarshalError> {
+    if let Some(val) = val {
+        val.excavate(ctx)?;
+    }
+    Ok(())
+}
+
+#[inline]
+pub fn extent<O: RpcOneof>(val: &O) -> usize {
+    val.extent()
+}
+
+#[inline]
+pub fn extent_optional<O: RpcOneof>(val: &Option<O>) -> usize {
+    val.as_ref().map_or(0, extent)
+}
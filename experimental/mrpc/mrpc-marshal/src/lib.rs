@@ -14,11 +14,14 @@ use thiserror::Error;
 use shm::ptr::ShmPtr;
 
 pub mod emplacement;
+pub mod reflect;
+pub mod status;
 pub mod shadow {
     use crate::alloc::PrivateHeap;
 
     pub type String = shm::string::String<PrivateHeap>;
     pub type Vec<T> = shm::vec::Vec<T, PrivateHeap>;
+    pub type BTreeMap<K, V> = shm::collections::BTreeMap<K, V, PrivateHeap>;
 }
 
 pub mod alloc {
@@ -135,6 +138,22 @@ pub trait RpcMessage: Sized {
     fn extent(&self) -> usize;
 }
 
+/// The variants of a protobuf `oneof` field. Each variant holds exactly one field of the message.
+pub trait RpcOneof: Sized {
+    fn emplace(&self, sgl: &mut SgList) -> Result<(), MarshalError>;
+
+    /// # Safety
+    ///
+    /// This operation may be zero-copy. Thus, the user must ensure the underlying data remain
+    /// valid after excavating.
+    unsafe fn excavate<A: AddressArbiter>(
+        &mut self,
+        ctx: &mut ExcavateContext<A>,
+    ) -> Result<(), UnmarshalError>;
+
+    fn extent(&self) -> usize;
+}
+
 // Implementations of AddressMap
 
 #[derive(Error, Debug, Clone)]
@@ -0,0 +1,191 @@
+//! Reading the fields of a message on the shared memory heap by name, without its Rust type.
+//!
+//! Policies only see the address of a message and its `func_id`. The readers for the messages
+//! of an application are registered here by the code that knows their types, keyed by `func_id`.
+//! The dispatch library that the mRPC engine builds from the protos of an application exports
+//! such a reader, built on the [`Reflect`] impls generated for every message, together with the
+//! descriptors of its methods.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use spin::{Lazy, RwLock};
+
+use shm::alloc::ShmAllocator;
+
+/// The value of a scalar, string or bytes field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldValue<'a> {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(&'a str),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> FieldValue<'a> {
+    /// Returns true if the field equals `value`, which is parsed according to the field's type.
+    pub fn matches(&self, value: &str) -> bool {
+        match *self {
+            FieldValue::Bool(x) => value.parse() == Ok(x),
+            FieldValue::Int(x) => value.parse() == Ok(x),
+            FieldValue::UInt(x) => value.parse() == Ok(x),
+            FieldValue::Float(x) => value.parse() == Ok(x),
+            FieldValue::String(x) => x == value,
+            FieldValue::Bytes(x) => x == value.as_bytes(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for FieldValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Bool(x) => write!(f, "{}", x),
+            FieldValue::Int(x) => write!(f, "{}", x),
+            FieldValue::UInt(x) => write!(f, "{}", x),
+            FieldValue::Float(x) => write!(f, "{}", x),
+            FieldValue::String(x) => write!(f, "{}", x),
+            FieldValue::Bytes(x) => write!(f, "{}", String::from_utf8_lossy(x)),
+        }
+    }
+}
+
+/// Reads a field of a message by its name.
+pub trait Reflect {
+    /// Returns the value of the field at `path`, names separated by `.`. An empty path refers to
+    /// the value itself.
+    fn reflect(&self, path: &str) -> Option<FieldValue<'_>>;
+}
+
+macro_rules! impl_reflect_scalar {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl Reflect for $ty {
+                #[inline]
+                #[allow(clippy::useless_conversion)]
+                fn reflect(&self, path: &str) -> Option<FieldValue<'_>> {
+                    path.is_empty().then(|| FieldValue::$variant((*self).into()))
+                }
+            }
+        )*
+    };
+}
+
+impl_reflect_scalar! {
+    bool => Bool,
+    i32 => Int,
+    i64 => Int,
+    u32 => UInt,
+    u64 => UInt,
+    f32 => Float,
+    f64 => Float,
+}
+
+impl<A: ShmAllocator> Reflect for shm::string::String<A> {
+    #[inline]
+    fn reflect(&self, path: &str) -> Option<FieldValue<'_>> {
+        path.is_empty().then(|| FieldValue::String(self.as_str()))
+    }
+}
+
+impl<A: ShmAllocator> Reflect for shm::vec::Vec<u8, A> {
+    #[inline]
+    fn reflect(&self, path: &str) -> Option<FieldValue<'_>> {
+        path.is_empty().then(|| FieldValue::Bytes(self.as_slice()))
+    }
+}
+
+impl<T: Reflect> Reflect for Option<T> {
+    #[inline]
+    fn reflect(&self, path: &str) -> Option<FieldValue<'_>> {
+        self.as_ref()?.reflect(path)
+    }
+}
+
+/// Describes a method of a service, and the messages it takes and returns.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodDescriptor {
+    pub service_id: u32,
+    pub func_id: u32,
+    /// The fully qualified name of the service, e.g., `rpc_hello.Greeter`.
+    pub service: String,
+    pub method: String,
+    /// The fully qualified names of the request and the response messages.
+    pub request_type: String,
+    pub response_type: String,
+}
+
+/// Reads the fields of the request and the response messages of some methods.
+pub trait MessageReader: Send + Sync {
+    /// Reads the field at `path`, names separated by `.`, of the request (or the response)
+    /// message of `func_id` at `addr_backend`. Returns `None` if the message has no such field,
+    /// or a message on the path is not set.
+    ///
+    /// # Safety
+    ///
+    /// `addr_backend` must point to a live message of the method on the backend side, which
+    /// outlives the returned value.
+    unsafe fn read_field<'a>(
+        &self,
+        func_id: u32,
+        request: bool,
+        addr_backend: usize,
+        path: &str,
+    ) -> Option<FieldValue<'a>>;
+}
+
+static READERS: Lazy<RwLock<HashMap<u32, Arc<dyn MessageReader>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+static METHODS: Lazy<RwLock<HashMap<u32, MethodDescriptor>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Registers `reader` for the messages of the methods in `func_ids`, replacing the previous
+/// reader of these methods.
+pub fn register<I: IntoIterator<Item = u32>>(func_ids: I, reader: Arc<dyn MessageReader>) {
+    let mut readers = READERS.write();
+    for func_id in func_ids {
+        readers.insert(func_id, Arc::clone(&reader));
+    }
+}
+
+/// Returns true if the messages of `func_id` can be read.
+pub fn is_registered(func_id: u32) -> bool {
+    READERS.read().contains_key(&func_id)
+}
+
+/// Reads a field of a message with the reader registered for `func_id`.
+///
+/// # Safety
+///
+/// See [`MessageReader::read_field`].
+pub unsafe fn read_field<'a>(
+    func_id: u32,
+    request: bool,
+    addr_backend: usize,
+    path: &str,
+) -> Option<FieldValue<'a>> {
+    let reader = READERS.read().get(&func_id).cloned()?;
+    reader.read_field(func_id, request, addr_backend, path)
+}
+
+/// Publishes the descriptors of some methods, replacing the previous descriptors of these
+/// methods.
+pub fn register_methods<I: IntoIterator<Item = MethodDescriptor>>(methods: I) {
+    let mut registry = METHODS.write();
+    for method in methods {
+        registry.insert(method.func_id, method);
+    }
+}
+
+/// Returns the descriptor of `func_id`, if published.
+pub fn method(func_id: u32) -> Option<MethodDescriptor> {
+    METHODS.read().get(&func_id).cloned()
+}
+
+/// Returns the descriptors of all published methods.
+pub fn methods() -> Vec<MethodDescriptor> {
+    METHODS.read().values().cloned().collect()
+}
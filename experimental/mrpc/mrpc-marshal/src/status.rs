@@ -0,0 +1,99 @@
+//! The payload of a reply whose handler returned an error.
+//!
+//! The adapters marshal this type on their own when the message's status code is
+//! `StatusCode::ServerError`, so it does not go through the dispatch library generated
+//! from the user's protos.
+use std::mem;
+
+use shm::ptr::ShmPtr;
+
+use crate::emplacement::string;
+use crate::shadow::String;
+use crate::{
+    AddressArbiter, ExcavateContext, MarshalError, RpcMessage, SgE, SgList, UnmarshalError,
+};
+
+/// Shadow type of `mrpc::stub::RpcStatus`. The two must have identical memory layouts.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RpcStatus {
+    /// The gRPC status code.
+    pub code: i32,
+    /// The error message.
+    pub message: String,
+}
+
+impl RpcMessage for RpcStatus {
+    fn marshal(&self) -> Result<SgList, MarshalError> {
+        let cap = 1 + self.extent();
+        let mut sgl = SgList(std::vec::Vec::with_capacity(cap));
+        let self_sge = SgE {
+            ptr: self as *const _ as usize,
+            len: mem::size_of::<Self>(),
+        };
+        sgl.0.push(self_sge);
+        self.emplace(&mut sgl)?;
+        Ok(sgl)
+    }
+
+    unsafe fn unmarshal<A: AddressArbiter>(
+        ctx: &mut ExcavateContext<A>,
+    ) -> Result<ShmPtr<Self>, UnmarshalError> {
+        let self_sge = ctx.sgl.next().ok_or(UnmarshalError::SgListUnderflow)?;
+        if self_sge.len != mem::size_of::<Self>() {
+            return Err(UnmarshalError::SgELengthMismatch {
+                expected: mem::size_of::<Self>(),
+                actual: self_sge.len,
+            });
+        }
+
+        let backend_addr = self_sge.ptr;
+        let app_addr = ctx.addr_arbiter.query_app_addr(backend_addr)?;
+
+        let mut message = ShmPtr::new(app_addr as *mut Self, backend_addr as *mut Self).unwrap();
+        let this = message.as_mut_backend();
+        this.excavate(ctx)?;
+
+        Ok(message)
+    }
+
+    #[inline]
+    fn emplace(&self, sgl: &mut SgList) -> Result<(), MarshalError> {
+        string::emplace(&self.message, sgl)
+    }
+
+    #[inline]
+    unsafe fn excavate<A: AddressArbiter>(
+        &mut self,
+        ctx: &mut ExcavateContext<A>,
+    ) -> Result<(), UnmarshalError> {
+        string::excavate(&mut self.message, ctx)
+    }
+
+    #[inline]
+    fn extent(&self) -> usize {
+        string::extent(&self.message)
+    }
+}
+
+/// Marshals the [`RpcStatus`] located at `addr_backend`.
+pub fn marshal(addr_backend: usize) -> Result<SgList, MarshalError> {
+    let ptr_backend = addr_backend as *const RpcStatus;
+    assert_eq!(ptr_backend.align_offset(mem::align_of::<RpcStatus>()), 0);
+    let status = unsafe { &*ptr_backend };
+    status.marshal()
+}
+
+/// Unmarshals an [`RpcStatus`] from the SgList, returning its app and backend addresses.
+///
+/// # Safety
+///
+/// This operation is zero-copy. The caller must ensure the receive buffers remain valid
+/// while the returned message is in use.
+pub unsafe fn unmarshal<A: AddressArbiter>(
+    ctx: &mut ExcavateContext<A>,
+) -> Result<(usize, usize), UnmarshalError> {
+    let msg = RpcStatus::unmarshal(ctx)?;
+    let (ptr_app, ptr_backend) = msg.to_raw_parts();
+    Ok((ptr_app.addr().get(), ptr_backend.addr().get()))
+}
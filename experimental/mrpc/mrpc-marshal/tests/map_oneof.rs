@@ -0,0 +1,147 @@
+//! Marshals messages with map and oneof fields, and unmarshals them from a copy of their
+//! scatter-gather list, as the receiver of a message does.
+use mrpc_marshal::shadow::{BTreeMap, String};
+use mrpc_marshal::{ExcavateContext, NoopAddressMap, RpcMessage, SgE, SgList};
+
+#[repr(C)]
+#[derive(Debug, ::mrpc_derive::Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub value: String,
+}
+
+#[repr(C)]
+#[derive(Debug, ::mrpc_derive::Message)]
+pub struct LookupRequest {
+    #[prost(btree_map = "string, string", tag = "1")]
+    pub labels: BTreeMap<String, String>,
+    #[prost(btree_map = "uint64, message", tag = "2")]
+    pub indexed: BTreeMap<u64, Label>,
+    #[prost(oneof = "lookup_request::Key", tags = "3, 4, 5")]
+    pub key: ::core::option::Option<lookup_request::Key>,
+}
+
+pub mod lookup_request {
+    #[derive(Debug, ::mrpc_derive::Oneof)]
+    pub enum Key {
+        #[prost(string, tag = "3")]
+        Name(::mrpc_marshal::shadow::String),
+        #[prost(uint64, tag = "4")]
+        Index(u64),
+        #[prost(message, tag = "5")]
+        Label(super::Label),
+    }
+}
+
+/// Copies the buffers of a scatter-gather list, and returns the copies and the list pointing to
+/// them. The buffers are 8-byte aligned to hold the entries of any map.
+fn transfer(sgl: &SgList) -> (Vec<Vec<u64>>, Vec<SgE>) {
+    let mut bufs = Vec::new();
+    let mut sges = Vec::new();
+    for sge in &sgl.0 {
+        let mut buf = vec![0u64; (sge.len + 7) / 8];
+        unsafe {
+            std::ptr::copy_nonoverlapping(sge.ptr as *const u8, buf.as_mut_ptr().cast(), sge.len)
+        };
+        sges.push(SgE {
+            ptr: buf.as_ptr() as usize,
+            len: sge.len,
+        });
+        bufs.push(buf);
+    }
+    (bufs, sges)
+}
+
+/// Marshals `msg`, unmarshals a copy of it, and checks the copy with `check`.
+fn roundtrip(msg: &LookupRequest, check: impl FnOnce(&LookupRequest)) {
+    let sgl = msg.marshal().unwrap();
+    assert_eq!(sgl.0.len(), 1 + msg.extent());
+
+    let (bufs, sges) = transfer(&sgl);
+    let addr_map = NoopAddressMap::new();
+    let mut ctx = ExcavateContext {
+        sgl: sges.iter(),
+        addr_arbiter: &addr_map,
+    };
+    // the received message points into the copied buffers, and is never dropped
+    let received = unsafe { LookupRequest::unmarshal(&mut ctx) }.unwrap();
+    assert!(ctx.sgl.next().is_none());
+    check(unsafe { &*received.as_ptr_app() });
+    drop(bufs);
+}
+
+#[test]
+fn test_map_roundtrip() {
+    let mut msg = LookupRequest {
+        labels: BTreeMap::new(),
+        indexed: BTreeMap::new(),
+        key: None,
+    };
+    msg.labels
+        .insert(String::from("zone"), String::from("us-west"));
+    msg.labels.insert(String::from("app"), String::from("echo"));
+    msg.labels.insert(String::from("empty"), String::new());
+    msg.indexed.insert(
+        7,
+        Label {
+            value: String::from("seven"),
+        },
+    );
+    msg.indexed.insert(
+        2,
+        Label {
+            value: String::new(),
+        },
+    );
+
+    roundtrip(&msg, |received| {
+        let labels: Vec<_> = received
+            .labels
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            [("app", "echo"), ("empty", ""), ("zone", "us-west")]
+        );
+        assert_eq!(received.labels.get("zone").unwrap().as_str(), "us-west");
+
+        assert_eq!(received.indexed.keys().copied().collect::<Vec<_>>(), [2, 7]);
+        assert_eq!(received.indexed.get(&7).unwrap().value.as_str(), "seven");
+        assert!(received.indexed.get(&2).unwrap().value.is_empty());
+        assert!(received.key.is_none());
+    });
+}
+
+#[test]
+fn test_oneof_roundtrip() {
+    let variants = [
+        lookup_request::Key::Name(String::from("zone")),
+        lookup_request::Key::Index(3),
+        lookup_request::Key::Label(Label {
+            value: String::from("echo"),
+        }),
+    ];
+    for key in variants {
+        let msg = LookupRequest {
+            labels: BTreeMap::new(),
+            indexed: BTreeMap::new(),
+            key: Some(key),
+        };
+        roundtrip(&msg, |received| {
+            assert!(received.labels.is_empty());
+            match (msg.key.as_ref().unwrap(), received.key.as_ref().unwrap()) {
+                (lookup_request::Key::Name(sent), lookup_request::Key::Name(received)) => {
+                    assert_eq!(sent.as_str(), received.as_str())
+                }
+                (lookup_request::Key::Index(sent), lookup_request::Key::Index(received)) => {
+                    assert_eq!(sent, received)
+                }
+                (lookup_request::Key::Label(sent), lookup_request::Key::Label(received)) => {
+                    assert_eq!(sent.value.as_str(), received.value.as_str())
+                }
+                (sent, received) => panic!("sent {:?}, received {:?}", sent, received),
+            }
+        });
+    }
+}
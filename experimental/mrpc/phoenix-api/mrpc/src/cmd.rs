@@ -3,7 +3,7 @@ use std::{net::SocketAddr, os::unix::prelude::RawFd, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use super::control_plane::TransportType;
+use super::control_plane::{BufferSetting, TransportType};
 use phoenix_api::Handle;
 
 type IResult<T> = Result<T, phoenix_api::Error>;
@@ -20,6 +20,8 @@ pub enum Command {
     NewMappedAddrs(Handle, Vec<(Handle, usize)>),
     UpdateProtos(Vec<String>),
     UpdateProtosInner(PathBuf),
+    // The buffer setting of the connections, the backend does not reply to it
+    SetBuffersInner(BufferSetting),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +36,9 @@ pub struct ReadHeapRegion {
 pub struct ConnectResponse {
     pub conn_handle: Handle,
     pub read_regions: Vec<ReadHeapRegion>,
+    /// The receive buffers of the peer, exchanged when the connection is established. The
+    /// segments and messages sent on the connection fit in them.
+    pub peer_buffers: BufferSetting,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,4 +48,198 @@ pub struct Setting {
     pub core_id: Option<usize>,
 
     pub module_config: Option<String>,
+    /// The receive buffers and the maximum message size of each connection. [`None`] to use
+    /// the ones configured for the mRPC service.
+    #[serde(default)]
+    pub buffers: Option<BufferSetting>,
+}
+
+/// The smallest receive buffer, which must hold a message sent in one piece.
+pub const MIN_RECV_BUFFER_SIZE: usize = 16384;
+
+/// Receive buffers and the maximum message size of a connection.
+///
+/// Each connection posts `num_recv_buffers` buffers of `recv_buffer_size` bytes. Over TCP, a
+/// message larger than one buffer is split into segments. Over RDMA, messages are never split,
+/// so each buffer holds at least `max_message_size` bytes. A message larger than
+/// `max_message_size` is rejected with `resource_exhausted`.
+///
+/// The two ends of a connection exchange their settings when the connection is established,
+/// and each end sends no larger segments and messages than the other end receives.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BufferSetting {
+    /// The number of receive buffers posted on each connection.
+    #[serde(default = "default_num_recv_buffers")]
+    pub num_recv_buffers: usize,
+    /// The size of each receive buffer in bytes.
+    #[serde(default = "default_recv_buffer_size")]
+    pub recv_buffer_size: usize,
+    /// The maximum size of a message in bytes.
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+}
+
+impl Default for BufferSetting {
+    fn default() -> Self {
+        BufferSetting {
+            num_recv_buffers: default_num_recv_buffers(),
+            recv_buffer_size: default_recv_buffer_size(),
+            max_message_size: default_max_message_size(),
+        }
+    }
+}
+
+impl BufferSetting {
+    /// Checks that the buffers can receive any message.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.num_recv_buffers == 0 {
+            return Err("num_recv_buffers must be positive".to_owned());
+        }
+        if self.recv_buffer_size < MIN_RECV_BUFFER_SIZE {
+            return Err(format!(
+                "recv_buffer_size must be at least {MIN_RECV_BUFFER_SIZE}, got {}",
+                self.recv_buffer_size
+            ));
+        }
+        if self.max_message_size == 0 {
+            return Err("max_message_size must be positive".to_owned());
+        }
+        // a message in segments must leave buffers for the ones behind it
+        if self.max_message_size > self.num_recv_buffers.saturating_mul(self.recv_buffer_size) / 2 {
+            return Err(format!(
+                "max_message_size {} exceeds half of the receive buffers",
+                self.max_message_size
+            ));
+        }
+        Ok(())
+    }
+
+    /// The length of an encoded setting.
+    pub const ENCODED_LEN: usize = 24;
+
+    /// Encodes the setting to be sent to the peer of a connection.
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut buf = [0u8; Self::ENCODED_LEN];
+        buf[..8].copy_from_slice(&(self.num_recv_buffers as u64).to_le_bytes());
+        buf[8..16].copy_from_slice(&(self.recv_buffer_size as u64).to_le_bytes());
+        buf[16..].copy_from_slice(&(self.max_message_size as u64).to_le_bytes());
+        buf
+    }
+
+    /// Decodes a setting received from the peer of a connection. Returns [`None`] if the
+    /// setting is truncated or invalid. Bytes after the setting are ignored.
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::ENCODED_LEN {
+            return None;
+        }
+        let read = |off: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&buf[off..off + 8]);
+            usize::try_from(u64::from_le_bytes(word)).ok()
+        };
+        let setting = BufferSetting {
+            num_recv_buffers: read(0)?,
+            recv_buffer_size: read(8)?,
+            max_message_size: read(16)?,
+        };
+        setting.validate().ok()?;
+        Some(setting)
+    }
+}
+
+fn default_num_recv_buffers() -> usize {
+    128
+}
+
+fn default_recv_buffer_size() -> usize {
+    128 * 1024
+}
+
+fn default_max_message_size() -> usize {
+    8 * 1024 * 1024
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let setting = BufferSetting {
+            num_recv_buffers: 4,
+            recv_buffer_size: MIN_RECV_BUFFER_SIZE,
+            max_message_size: 2 * MIN_RECV_BUFFER_SIZE,
+        };
+        let buf = setting.encode();
+        assert_eq!(BufferSetting::decode(&buf), Some(setting));
+        assert_eq!(
+            BufferSetting::decode(&BufferSetting::default().encode()),
+            Some(BufferSetting::default())
+        );
+
+        // bytes after the setting are ignored
+        let mut longer = buf.to_vec();
+        longer.extend_from_slice(&[0xff; 8]);
+        assert_eq!(BufferSetting::decode(&longer), Some(setting));
+
+        // a truncated setting
+        assert_eq!(
+            BufferSetting::decode(&buf[..BufferSetting::ENCODED_LEN - 1]),
+            None
+        );
+        assert_eq!(BufferSetting::decode(&[]), None);
+
+        // an invalid setting from the peer
+        let invalid = BufferSetting {
+            num_recv_buffers: 0,
+            ..setting
+        };
+        assert_eq!(BufferSetting::decode(&invalid.encode()), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(BufferSetting::default().validate(), Ok(()));
+
+        // the largest message takes exactly half of the receive buffers
+        let setting = BufferSetting {
+            num_recv_buffers: 4,
+            recv_buffer_size: MIN_RECV_BUFFER_SIZE,
+            max_message_size: 2 * MIN_RECV_BUFFER_SIZE,
+        };
+        assert_eq!(setting.validate(), Ok(()));
+        let too_large = BufferSetting {
+            max_message_size: 2 * MIN_RECV_BUFFER_SIZE + 1,
+            ..setting
+        };
+        assert!(too_large.validate().is_err());
+
+        let too_small = BufferSetting {
+            recv_buffer_size: MIN_RECV_BUFFER_SIZE - 1,
+            max_message_size: 1,
+            ..setting
+        };
+        assert!(too_small.validate().is_err());
+
+        let no_buffer = BufferSetting {
+            num_recv_buffers: 0,
+            ..setting
+        };
+        assert!(no_buffer.validate().is_err());
+
+        let no_message = BufferSetting {
+            max_message_size: 0,
+            ..setting
+        };
+        assert!(no_message.validate().is_err());
+
+        // no overflow with huge buffers
+        let huge = BufferSetting {
+            num_recv_buffers: usize::MAX,
+            recv_buffer_size: usize::MAX,
+            max_message_size: usize::MAX / 2,
+        };
+        assert_eq!(huge.validate(), Ok(()));
+    }
 }
@@ -1,10 +1,10 @@
 //! mRPC data path operations.
 use serde::{Deserialize, Serialize};
 
-use phoenix_api::rpc::{CallId, MessageErased, RpcId, TransportStatus};
+use phoenix_api::rpc::{CallId, MessageErased, MessageMeta, RpcId, TransportStatus};
 use phoenix_api::Handle;
 
-pub type WorkRequestSlot = [u8; 64];
+pub type WorkRequestSlot = [u8; 128];
 
 pub const RECV_RECLAIM_BS: usize = 4;
 
@@ -16,9 +16,11 @@ pub enum WorkRequest {
     Reply(MessageErased),
     // conn_id and an array of call_id
     ReclaimRecvBuf(Handle, [CallId; RECV_RECLAIM_BS]),
+    // the meta of the call to cancel, with msg_type set to RpcMsgType::Cancel
+    Cancel(MessageMeta),
 }
 
-pub type CompletionSlot = [u8; 64];
+pub type CompletionSlot = [u8; 128];
 
 // Avoid using too much `Send`/`Recv` in the code.
 #[repr(C, align(64))]
@@ -28,6 +30,8 @@ pub enum Completion {
     Outgoing(RpcId, TransportStatus),
     // (conn_id, status)
     RecvError(Handle, TransportStatus),
+    // the peer cancelled an incoming call
+    Cancel(RpcId),
 }
 
 mod sa {
@@ -2,9 +2,28 @@ use serde::{Deserialize, Serialize};
 
 type IResult<T> = Result<T, String>;
 
+/// Names a token bucket, which must be of the kind the engine buckets requests by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Bucket {
+    /// The bucket of a method, e.g., `Method("rpc_hello.Greeter".into(), "SayHello".into())`.
+    Method(String, String),
+    /// The bucket of a connection.
+    Connection(u64),
+    /// The bucket of the client processes of a uid.
+    Tenant(u32),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Request {
+    /// Set the requests per second and the bucket size of the buckets without their own limits.
     NewConfig(u64, u64),
+    /// Set the requests per second and the bucket size of one bucket.
+    UpdateBucket(Bucket, u64, u64),
+    /// Remove the limits of one bucket, so that it takes the default ones again.
+    ResetBucket(Bucket),
+    /// Set the requests per second and the bucket size of the quota, for all the engines that
+    /// share it.
+    UpdateQuota(u64, u64),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -343,7 +343,9 @@ impl LoadBalancerEngine {
                                 .v2p
                                 .get(&conn_id)
                                 .ok_or(DatapathError::Resource(ResourceError::NotFound))?;
-                            let new_conn_id = rconns[call_id.0 as usize % rconns.len()];
+                            // all messages of a streaming call must go through the same connection
+                            let new_conn_id =
+                                rconns[call_id.without_stream_seq().0 as usize % rconns.len()];
 
                             unsafe {
                                 (*msg.meta_buf_ptr.as_meta_ptr()).conn_id = new_conn_id;
@@ -351,6 +353,19 @@ impl LoadBalancerEngine {
                         }
                         self.tx_outputs()[0].send(EngineTxMessage::RpcMessage(msg))?;
                     }
+                    EngineTxMessage::Cancel(mut meta) => {
+                        // route the cancel to the connection that carries the call.
+                        // cancels are not acknowledged, so there is nothing to remember.
+                        if meta.conn_id == Handle::MASTER {
+                            let rconns = self
+                                .v2p
+                                .get(&meta.conn_id)
+                                .ok_or(DatapathError::Resource(ResourceError::NotFound))?;
+                            meta.conn_id =
+                                rconns[meta.call_id.without_stream_seq().0 as usize % rconns.len()];
+                        }
+                        self.tx_outputs()[0].send(EngineTxMessage::Cancel(meta))?;
+                    }
                     m => self.tx_outputs()[0].send(m)?,
                 }
                 return Ok(Progress(1));
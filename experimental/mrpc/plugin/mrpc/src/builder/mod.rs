@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
 
@@ -98,6 +101,13 @@ pub struct RpcMethodInfo {
     pub input_type: String,
     // output type's path
     pub output_type: String,
+    // fully qualified proto name of the service, e.g., `foo.bar.Greeter`
+    pub service: String,
+    // proto name of the method
+    pub method: String,
+    // fully qualified proto names of the input and output messages, e.g., `foo.bar.HelloRequest`
+    pub input_proto_type: String,
+    pub output_proto_type: String,
 }
 
 #[derive(Debug, Error)]
@@ -110,9 +120,30 @@ pub enum Error {
     LibraryCompile(#[from] compiler::Error),
 }
 
+/// An exclusive lock on a build cache, so that the processes sharing the cache do not build
+/// into it at the same time. It is released when dropped.
+struct CacheLock {
+    _file: File,
+}
+
+impl CacheLock {
+    fn acquire(cache_dir: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(cache_dir.join(".lock"))?;
+        // SAFETY: the fd is valid as long as `file` is alive
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(CacheLock { _file: file })
+    }
+}
+
 pub fn build_serializer_lib(protos: Vec<String>, cache_dir: PathBuf) -> Result<PathBuf, Error> {
     // Create cache dir if it does not exists
     std::fs::create_dir_all(cache_dir.as_path())?;
+    let _lock = CacheLock::acquire(&cache_dir)?;
     let (identifier, cached) = cache::check_cache(&protos, &cache_dir, PROTO_DIR)?;
     if !cached {
         cache::write_protos_to_cache(&identifier, &protos, &cache_dir, PROTO_DIR)?;
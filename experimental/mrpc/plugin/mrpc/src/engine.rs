@@ -267,6 +267,7 @@ impl MrpcEngine {
                     }
                     EngineRxMessage::RpcMessage(_) => {}
                     EngineRxMessage::RecvError(..) => {}
+                    EngineRxMessage::Cancel(_) => {}
                 },
                 Err(TryRecvError::Disconnected) => return Ok(()),
                 Err(TryRecvError::Empty) => {}
@@ -343,6 +344,9 @@ impl MrpcEngine {
             Command::UpdateProtosInner(_) => {
                 panic!("UpdateProtosInner is only used in backend")
             }
+            Command::SetBuffersInner(_) => {
+                panic!("SetBuffersInner is only used in backend")
+            }
         }
     }
 
@@ -441,6 +445,7 @@ impl MrpcEngine {
                 let msg = RpcMessageTx {
                     meta_buf_ptr,
                     addr_backend: erased.shm_addr_backend,
+                    metadata_backend: erased.metadata_addr_backend,
                 };
 
                 // timer.tick();
@@ -462,6 +467,15 @@ impl MrpcEngine {
                 // timer.tick();
                 // log::info!("process_dp reclaim recv buf: {}", timer);
             }
+            WorkRequest::Cancel(meta) => {
+                // The meta is forwarded by value and no MetaBuffer is obtained, because the buffer
+                // of the call being cancelled may still be in use under the same rpc_id.
+                tracing::trace!(
+                    "mRPC engine got a cancel from App, call_id: {}",
+                    meta.call_id
+                );
+                self.tx_outputs()[0].send(EngineTxMessage::Cancel(*meta))?;
+            }
         }
         Ok(())
     }
@@ -483,6 +497,8 @@ impl MrpcEngine {
                             meta,
                             shm_addr_app: msg.addr_app,
                             shm_addr_backend: msg.addr_backend,
+                            metadata_addr_app: msg.metadata_app,
+                            metadata_addr_backend: msg.metadata_backend,
                         };
                         // timer.tick();
                         match meta.status_code {
@@ -512,7 +528,9 @@ impl MrpcEngine {
                             StatusCode::Unknown => {
                                 tracing::error!("Status code: Unknown error, meta={:?}", meta);
                             }
-                            StatusCode::Success => {
+                            // an error reply is delivered to the app as a normal message,
+                            // and the app reads the RpcStatus from its payload
+                            StatusCode::Success | StatusCode::ServerError => {
                                 // the following operation takes around 100ns
                                 let mut sent = false;
                                 while !sent {
@@ -556,6 +574,17 @@ impl MrpcEngine {
                             })?;
                         }
                     }
+                    EngineRxMessage::Cancel(rpc_id) => {
+                        let mut sent = false;
+                        while !sent {
+                            self.customer.enqueue_wc_with(|ptr, _count| unsafe {
+                                sent = true;
+                                ptr.cast::<dp::Completion>()
+                                    .write(dp::Completion::Cancel(rpc_id));
+                                1
+                            })?;
+                        }
+                    }
                 }
                 Ok(Progress(1))
             }
@@ -594,6 +623,11 @@ impl MrpcEngine {
                         self.customer.send_comp(cmd::Completion(c))?;
                         Ok(Status::Progress(1))
                     }
+                    // a failed command, e.g., the peer refused the connection
+                    Err(e) => {
+                        self.customer.send_comp(cmd::Completion(Err(e)))?;
+                        Ok(Status::Progress(1))
+                    }
                     other => panic!("unexpected: {:?}", other),
                 }
             }
@@ -70,7 +70,7 @@ impl<T> From<SendError<T>> for DatapathError {
 use crate::config::MrpcConfig;
 use crate::module::MrpcModule;
 
-#[no_mangle]
+#[cfg_attr(not(feature = "embedded"), no_mangle)]
 pub fn init_module(config_string: Option<&str>) -> InitFnResult<Box<dyn PhoenixModule>> {
     let config = MrpcConfig::new(config_string)?;
     let module = MrpcModule::new(config);
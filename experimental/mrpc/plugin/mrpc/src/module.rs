@@ -250,6 +250,7 @@ impl PhoenixModule for MrpcModule {
                     nic_index: self.config.nic_index,
                     core_id: None,
                     module_config: None,
+                    buffers: None,
                 }
             };
             log::debug!("mRPC service setting: {:?}", setting);
@@ -266,6 +267,13 @@ impl PhoenixModule for MrpcModule {
             let cmd_tx = shared.command_path.get_sender(&engine_type)?;
             let cmd_rx = shared.command_path.get_receiver(&engine_type)?;
 
+            // the connections use the buffers of the setting, or the configured ones
+            let buffers = setting.buffers.unwrap_or(self.config.buffers);
+            if let Err(e) = buffers.validate() {
+                bail!("invalid buffer setting: {}", e);
+            }
+            cmd_tx.send(cmd::Command::SetBuffersInner(buffers))?;
+
             let builder = MrpcEngineBuilder::new(
                 customer,
                 client_pid,
@@ -34,6 +34,41 @@ pub fn generate_unmarshal(method_id: &MethodIdentifier, ty: &str) -> Result<Toke
     Ok(unmarshal)
 }
 
+pub fn generate_read_field(method_id: &MethodIdentifier, ty: &str) -> Result<TokenStream> {
+    let func_id = method_id.1;
+    let rust_ty = syn::parse_str::<syn::Path>(&format!("codegen::{}", ty))?;
+    let read_field = quote! {
+        #func_id => {
+            let ptr_backend = addr_backend as *const #rust_ty;
+            assert_eq!(ptr_backend.align_offset(std::mem::align_of::<#rust_ty>()), 0);
+            (*ptr_backend).reflect(path)
+        },
+    };
+    Ok(read_field)
+}
+
+pub fn generate_method_descriptor(info: &RpcMethodInfo) -> TokenStream {
+    let RpcMethodInfo {
+        service_id,
+        func_id,
+        service,
+        method,
+        input_proto_type,
+        output_proto_type,
+        ..
+    } = info;
+    quote! {
+        MethodDescriptor {
+            service_id: #service_id,
+            func_id: #func_id,
+            service: #service.to_owned(),
+            method: #method.to_owned(),
+            request_type: #input_proto_type.to_owned(),
+            response_type: #output_proto_type.to_owned(),
+        },
+    }
+}
+
 pub fn generate(
     include_file: PathBuf,
     method_type_mapping: &HashMap<MethodIdentifier, RpcMethodInfo>,
@@ -60,12 +95,25 @@ pub fn generate(
         .map(|(id, info)| generate_unmarshal(id, &info.output_type))
         .collect::<Result<Vec<_>>>()?;
 
+    let requests_read_field = method_type_mapping
+        .iter()
+        .map(|(id, info)| generate_read_field(id, &info.input_type))
+        .collect::<Result<Vec<_>>>()?;
+
+    let responses_read_field = method_type_mapping
+        .iter()
+        .map(|(id, info)| generate_read_field(id, &info.output_type))
+        .collect::<Result<Vec<_>>>()?;
+
+    let method_descriptors = method_type_mapping.values().map(generate_method_descriptor);
+
     let dispatch = quote! {
         #![feature(strict_provenance)]
 
         use phoenix_api::rpc::{MessageMeta, RpcMsgType};
         use mrpc_marshal::{SgList, ExcavateContext, RpcMessage};
         use mrpc_marshal::{MarshalError, UnmarshalError, AddressMap};
+        use mrpc_marshal::reflect::{FieldValue, MethodDescriptor, Reflect};
 
         mod codegen {
             include!(#include_file);
@@ -77,18 +125,22 @@ pub fn generate(
             addr_backend: usize,
         ) -> Result<SgList, MarshalError> {
             match meta.msg_type {
-                RpcMsgType::Request => {
+                RpcMsgType::Request | RpcMsgType::StreamRequest => {
                     match meta.func_id {
                         #(#requests_marshal)*
                         _ => panic!("unknown func_id: {}, meta: {:?}", meta.func_id, meta),
                     }
                 },
-                RpcMsgType::Response => {
+                RpcMsgType::Response | RpcMsgType::StreamResponse => {
                     match meta.func_id {
                         #(#responses_marshal)*
                         _ => panic!("unknown func_id: {}, meta: {:?}", meta.func_id, meta),
                     }
                 }
+                RpcMsgType::Cancel => panic!("a cancel carries no payload, meta: {:?}", meta),
+                RpcMsgType::StreamRequestEnd | RpcMsgType::StreamResponseEnd => {
+                    panic!("the end of a stream carries an RPC status, meta: {:?}", meta)
+                }
             }
         }
 
@@ -98,22 +150,51 @@ pub fn generate(
             ctx: &mut ExcavateContext<AddressMap>,
         ) -> Result<(usize, usize), UnmarshalError> {
             let addr_shm = match meta.msg_type {
-                RpcMsgType::Request => {
+                RpcMsgType::Request | RpcMsgType::StreamRequest => {
                     match meta.func_id {
                         #(#requests_unmarshal)*
                         _ => panic!("unknown func_id: {}, meta: {:?}", meta.func_id, meta),
                     }
                 },
-                RpcMsgType::Response => {
+                RpcMsgType::Response | RpcMsgType::StreamResponse => {
                     match meta.func_id {
                         #(#response_unmarshal)*
                         _ => panic!("unknown func_id: {}, meta: {:?}", meta.func_id, meta),
                     }
                 }
+                RpcMsgType::Cancel => panic!("a cancel carries no payload, meta: {:?}", meta),
+                RpcMsgType::StreamRequestEnd | RpcMsgType::StreamResponseEnd => {
+                    panic!("the end of a stream carries an RPC status, meta: {:?}", meta)
+                }
             };
 
             Ok(addr_shm)
         }
+
+        #[no_mangle]
+        pub unsafe extern "Rust" fn read_field<'a>(
+            func_id: u32,
+            request: bool,
+            addr_backend: usize,
+            path: &str,
+        ) -> Option<FieldValue<'a>> {
+            if request {
+                match func_id {
+                    #(#requests_read_field)*
+                    _ => None,
+                }
+            } else {
+                match func_id {
+                    #(#responses_read_field)*
+                    _ => None,
+                }
+            }
+        }
+
+        #[no_mangle]
+        pub extern "Rust" fn methods() -> Vec<MethodDescriptor> {
+            vec![#(#method_descriptors)*]
+        }
     };
 
     Ok(dispatch)
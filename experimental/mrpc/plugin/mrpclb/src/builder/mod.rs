@@ -98,6 +98,13 @@ pub struct RpcMethodInfo {
     pub input_type: String,
     // output type's path
     pub output_type: String,
+    // fully qualified proto name of the service, e.g., `foo.bar.Greeter`
+    pub service: String,
+    // proto name of the method
+    pub method: String,
+    // fully qualified proto names of the input and output messages, e.g., `foo.bar.HelloRequest`
+    pub input_proto_type: String,
+    pub output_proto_type: String,
 }
 
 #[derive(Debug, Error)]
@@ -172,6 +172,8 @@ impl Builder {
         };
 
         config.out_dir(out_dir);
+        // map fields are held by the shared-memory `BTreeMap`
+        config.btree_map(["."]);
         if let Some(path) = self.file_descriptor_set_path.as_ref() {
             config.file_descriptor_set_path(path);
         }
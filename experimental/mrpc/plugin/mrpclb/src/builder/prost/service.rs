@@ -37,6 +37,10 @@ impl prost_build::ServiceGenerator for ServiceRecorder {
                 func_id,
                 input_type: input_type_canonical,
                 output_type: output_type_canonical,
+                service: service_path.clone(),
+                method: method.proto_name.clone(),
+                input_proto_type: method.input_proto_type.trim_start_matches('.').to_owned(),
+                output_proto_type: method.output_proto_type.trim_start_matches('.').to_owned(),
             };
             let method_id = MethodIdentifier(service_id, func_id);
             mapping.insert(method_id, method_info);
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use phoenix_api_mrpc::control_plane::TransportType;
+use phoenix_api_mrpc::control_plane::{BufferSetting, TransportType};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +20,10 @@ pub struct MrpcLBConfig {
     /// Use NIC 0 by default
     #[serde(default)]
     pub nic_index: usize,
+    /// The receive buffers and the maximum message size of each connection, unless the
+    /// application sets its own
+    #[serde(default)]
+    pub buffers: BufferSetting,
 }
 
 impl MrpcLBConfig {
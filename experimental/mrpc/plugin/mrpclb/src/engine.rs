@@ -268,6 +268,7 @@ impl MrpcLBEngine {
                     }
                     EngineRxMessage::RpcMessage(_) => {}
                     EngineRxMessage::RecvError(..) => {}
+                    EngineRxMessage::Cancel(_) => {}
                 },
                 Err(TryRecvError::Disconnected) => return Ok(()),
                 Err(TryRecvError::Empty) => {}
@@ -348,6 +349,9 @@ impl MrpcLBEngine {
             Command::UpdateProtosInner(_) => {
                 panic!("UpdateProtosInner is only used in backend")
             }
+            Command::SetBuffersInner(_) => {
+                panic!("SetBuffersInner is only used in backend")
+            }
         }
     }
 
@@ -447,6 +451,7 @@ impl MrpcLBEngine {
                 let msg = RpcMessageTx {
                     meta_buf_ptr,
                     addr_backend: erased.shm_addr_backend,
+                    metadata_backend: erased.metadata_addr_backend,
                 };
 
                 // timer.tick();
@@ -468,6 +473,15 @@ impl MrpcLBEngine {
                 // timer.tick();
                 // log::info!("process_dp reclaim recv buf: {}", timer);
             }
+            WorkRequest::Cancel(meta) => {
+                // The meta is forwarded by value and no MetaBuffer is obtained, because the buffer
+                // of the call being cancelled may still be in use under the same rpc_id.
+                tracing::trace!(
+                    "mRPC LB engine got a cancel from App, call_id: {:?}",
+                    meta.call_id
+                );
+                self.tx_outputs()[0].send(EngineTxMessage::Cancel(*meta))?;
+            }
         }
         Ok(())
     }
@@ -490,6 +504,8 @@ impl MrpcLBEngine {
                             meta,
                             shm_addr_app: msg.addr_app,
                             shm_addr_backend: msg.addr_backend,
+                            metadata_addr_app: msg.metadata_app,
+                            metadata_addr_backend: msg.metadata_backend,
                         };
                         // timer.tick();
                         match meta.status_code {
@@ -519,7 +535,9 @@ impl MrpcLBEngine {
                             StatusCode::Unknown => {
                                 tracing::error!("Status code: Unknown error, meta={:?}", meta);
                             }
-                            StatusCode::Success => {
+                            // an error reply is delivered to the app as a normal message,
+                            // and the app reads the RpcStatus from its payload
+                            StatusCode::Success | StatusCode::ServerError => {
                                 // the following operation takes around 100ns
                                 let mut sent = false;
                                 while !sent {
@@ -563,6 +581,17 @@ impl MrpcLBEngine {
                             })?;
                         }
                     }
+                    EngineRxMessage::Cancel(rpc_id) => {
+                        let mut sent = false;
+                        while !sent {
+                            self.customer.enqueue_wc_with(|ptr, _count| unsafe {
+                                sent = true;
+                                ptr.cast::<dp::Completion>()
+                                    .write(dp::Completion::Cancel(rpc_id));
+                                1
+                            })?;
+                        }
+                    }
                 }
                 Ok(Progress(1))
             }
@@ -606,6 +635,11 @@ impl MrpcLBEngine {
                         self.customer.send_comp(cmd::Completion(c))?;
                         Ok(Status::Progress(1))
                     }
+                    // a failed command, e.g., the peer refused the connection
+                    Err(e) => {
+                        self.customer.send_comp(cmd::Completion(Err(e)))?;
+                        Ok(Status::Progress(1))
+                    }
                     other => panic!("unexpected: {:?}", other),
                 }
             }
@@ -286,6 +286,7 @@ impl PhoenixModule for MrpcLBModule {
                     nic_index: self.config.nic_index,
                     core_id: None,
                     module_config: None,
+                    buffers: None,
                 }
             };
             log::debug!("mRPCLB service setting: {:?}", setting);
@@ -303,6 +304,13 @@ impl PhoenixModule for MrpcLBModule {
             let cmd_rx: tokio::sync::mpsc::UnboundedReceiver<cmd::Completion> =
                 shared.command_path.get_receiver(&MrpcLBModule::LB_ENGINE)?;
 
+            // the connections use the buffers of the setting, or the configured ones
+            let buffers = setting.buffers.unwrap_or(self.config.buffers);
+            if let Err(e) = buffers.validate() {
+                bail!("invalid buffer setting: {}", e);
+            }
+            cmd_tx.send(cmd::Command::SetBuffersInner(buffers))?;
+
             let builder = MrpcLBEngineBuilder::new(
                 customer,
                 client_pid,
@@ -0,0 +1,371 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use phoenix_api::rpc::{MessageMeta, RpcMetadata};
+use phoenix_common::log;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// A rule matches a request if all of its conditions hold. An unset condition always holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AclRule {
+    /// Fully qualified name of the service, e.g., `rpc_hello.Greeter`
+    pub service: Option<String>,
+    /// Name of the method in the service, e.g., `SayHello`
+    pub method: Option<String>,
+    /// Path of a field in the request message, e.g., `name` or `user.id`
+    pub field: Option<String>,
+    /// Key of an entry in the metadata of the request, e.g., `authorization`
+    pub header: Option<String>,
+    /// The value the field or the header must equal
+    pub value: Option<String>,
+    pub action: Action,
+    /// The gRPC status the client gets when the request is denied, e.g., `permission_denied`
+    #[serde(default = "AclRule::default_status")]
+    pub status: String,
+}
+
+impl AclRule {
+    fn default_status() -> String {
+        "permission_denied".to_owned()
+    }
+}
+
+/// The rules are checked in order, the first matching rule decides.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AclConfig {
+    /// The action for requests that match no rule
+    #[serde(default)]
+    pub default_action: Action,
+    /// The status for requests denied by `default_action`
+    #[serde(default = "AclRule::default_status")]
+    pub default_status: String,
+    #[serde(default)]
+    pub rules: Vec<AclRule>,
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        AclConfig {
+            default_action: Action::Allow,
+            default_status: AclRule::default_status(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl AclConfig {
+    /// Get config from toml file
+    pub fn new(config: Option<&str>) -> anyhow::Result<Self> {
+        let config: AclConfig = toml::from_str(config.unwrap_or(""))?;
+        config.compile()?;
+        Ok(config)
+    }
+
+    /// Checks the rules and resolves the names in them.
+    pub(crate) fn compile(&self) -> anyhow::Result<CompiledAcl> {
+        Ok(CompiledAcl {
+            rules: self
+                .rules
+                .iter()
+                .map(CompiledRule::new)
+                .collect::<anyhow::Result<_>>()?,
+            default_action: self.default_action,
+            default_code: status_code(&self.default_status)
+                .ok_or_else(|| anyhow!("unknown status {:?}", self.default_status))?,
+        })
+    }
+}
+
+/// The rules of an [`AclConfig`], ready to check requests against.
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledAcl {
+    rules: Vec<CompiledRule>,
+    default_action: Action,
+    default_code: u32,
+}
+
+impl CompiledAcl {
+    /// Returns the gRPC status code to reject the request with, or `None` if it is allowed.
+    ///
+    /// # Safety
+    ///
+    /// `addr_backend` must point to the request message of `meta`.
+    pub(crate) unsafe fn check(
+        &self,
+        meta: &MessageMeta,
+        addr_backend: usize,
+        metadata: Option<&RpcMetadata>,
+    ) -> Option<u32> {
+        let (action, code) = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(meta, addr_backend, metadata))
+            .map_or((self.default_action, self.default_code), |rule| {
+                (rule.action, rule.code)
+            });
+        match action {
+            Action::Allow => None,
+            Action::Deny => Some(code),
+        }
+    }
+}
+
+/// An [`AclRule`] with the names resolved to the identifiers in `MessageMeta`.
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledRule {
+    service_id: Option<u32>,
+    func_id: Option<u32>,
+    field: Option<(String, String)>,
+    header: Option<(String, String)>,
+    action: Action,
+    code: u32,
+}
+
+impl CompiledRule {
+    fn new(rule: &AclRule) -> anyhow::Result<Self> {
+        // the same as how mrpc-build computes SERVICE_ID and FUNC_ID
+        let service_id = rule
+            .service
+            .as_ref()
+            .map(|service| crc32fast::hash(service.as_bytes()));
+        let func_id = match (&rule.service, &rule.method) {
+            (Some(service), Some(method)) => Some(crc32fast::hash(
+                format!("/{}/{}", service, method).as_bytes(),
+            )),
+            (None, Some(method)) => bail!("method {:?} is given without its service", method),
+            _ => None,
+        };
+        let (field, header) = match (&rule.field, &rule.header, &rule.value) {
+            (Some(field), None, Some(value)) => (Some((field.clone(), value.clone())), None),
+            (None, Some(header), Some(value)) => (None, Some((header.clone(), value.clone()))),
+            (None, None, None) => (None, None),
+            _ => bail!("value must be given together with either field or header"),
+        };
+        let code =
+            status_code(&rule.status).ok_or_else(|| anyhow!("unknown status {:?}", rule.status))?;
+        Ok(CompiledRule {
+            service_id,
+            func_id,
+            field,
+            header,
+            action: rule.action,
+            code,
+        })
+    }
+
+    /// Returns true if the request matches the rule. A rule on a field of a message that cannot be
+    /// read matches only if it denies the request.
+    ///
+    /// # Safety
+    ///
+    /// `addr_backend` must point to the request message of `meta`.
+    unsafe fn matches(
+        &self,
+        meta: &MessageMeta,
+        addr_backend: usize,
+        metadata: Option<&RpcMetadata>,
+    ) -> bool {
+        if self.service_id.map_or(false, |id| id != meta.service_id)
+            || self.func_id.map_or(false, |id| id != meta.func_id)
+        {
+            return false;
+        }
+        if let Some((key, value)) = &self.header {
+            if metadata.and_then(|metadata| metadata.get(key)) != Some(value.as_bytes()) {
+                return false;
+            }
+        }
+        match &self.field {
+            Some((path, value)) => {
+                if !mrpc_marshal::reflect::is_registered(meta.func_id) {
+                    // the field cannot be read, fail closed
+                    log::warn!(
+                        "no reader for the messages of func_id {}, field {:?} is not checked",
+                        meta.func_id,
+                        path
+                    );
+                    return self.action == Action::Deny;
+                }
+                mrpc_marshal::reflect::read_field(meta.func_id, true, addr_backend, path)
+                    .map_or(false, |field| field.matches(value))
+            }
+            None => true,
+        }
+    }
+}
+
+/// Returns the gRPC status code of a status name in snake case.
+fn status_code(name: &str) -> Option<u32> {
+    const CODES: &[&str] = &[
+        "ok",
+        "cancelled",
+        "unknown",
+        "invalid_argument",
+        "deadline_exceeded",
+        "not_found",
+        "already_exists",
+        "permission_denied",
+        "resource_exhausted",
+        "failed_precondition",
+        "aborted",
+        "out_of_range",
+        "unimplemented",
+        "internal",
+        "unavailable",
+        "data_loss",
+        "unauthenticated",
+    ];
+    // a denied request must not look successful
+    CODES
+        .iter()
+        .skip(1)
+        .position(|x| *x == name)
+        .map(|i| i as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use phoenix_api::rpc::{CallId, RpcMsgType, StatusCode};
+    use phoenix_api::Handle;
+
+    use super::*;
+
+    fn rule(action: Action) -> AclRule {
+        AclRule {
+            service: None,
+            method: None,
+            field: None,
+            header: None,
+            value: None,
+            action,
+            status: AclRule::default_status(),
+        }
+    }
+
+    fn meta(service: &str, method: &str) -> MessageMeta {
+        MessageMeta {
+            conn_id: Handle(0),
+            service_id: crc32fast::hash(service.as_bytes()),
+            func_id: crc32fast::hash(format!("/{}/{}", service, method).as_bytes()),
+            call_id: CallId(0),
+            token: 0,
+            msg_type: RpcMsgType::Request,
+            status_code: StatusCode::Success,
+            has_metadata: false,
+            timeout_us: 0,
+        }
+    }
+
+    #[test]
+    fn test_compile_rule() {
+        let mut method_only = rule(Action::Deny);
+        method_only.method = Some("SayHello".to_owned());
+        assert!(CompiledRule::new(&method_only).is_err());
+
+        let mut value_only = rule(Action::Deny);
+        value_only.value = Some("admin".to_owned());
+        assert!(CompiledRule::new(&value_only).is_err());
+
+        let mut field_without_value = rule(Action::Deny);
+        field_without_value.field = Some("name".to_owned());
+        assert!(CompiledRule::new(&field_without_value).is_err());
+
+        let mut field_and_header = rule(Action::Deny);
+        field_and_header.field = Some("name".to_owned());
+        field_and_header.header = Some("authorization".to_owned());
+        field_and_header.value = Some("admin".to_owned());
+        assert!(CompiledRule::new(&field_and_header).is_err());
+
+        let mut unknown_status = rule(Action::Deny);
+        unknown_status.status = "denied".to_owned();
+        assert!(CompiledRule::new(&unknown_status).is_err());
+
+        let mut header = rule(Action::Deny);
+        header.service = Some("rpc_hello.Greeter".to_owned());
+        header.method = Some("SayHello".to_owned());
+        header.header = Some("authorization".to_owned());
+        header.value = Some("admin".to_owned());
+        let compiled = CompiledRule::new(&header).unwrap();
+        let meta = meta("rpc_hello.Greeter", "SayHello");
+        assert_eq!(compiled.service_id, Some(meta.service_id));
+        assert_eq!(compiled.func_id, Some(meta.func_id));
+        assert_eq!(compiled.code, 7);
+    }
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(status_code("cancelled"), Some(1));
+        assert_eq!(status_code("permission_denied"), Some(7));
+        assert_eq!(status_code("resource_exhausted"), Some(8));
+        assert_eq!(status_code("unauthenticated"), Some(16));
+        // a denied request must not look successful
+        assert_eq!(status_code("ok"), None);
+        assert_eq!(status_code("PermissionDenied"), None);
+        assert_eq!(status_code(""), None);
+    }
+
+    #[test]
+    fn test_first_match() {
+        let config: AclConfig = toml::from_str(
+            r#"
+            default_action = "deny"
+            default_status = "unauthenticated"
+
+            [[rules]]
+            service = "rpc_hello.Greeter"
+            method = "SayHello"
+            header = "authorization"
+            value = "admin"
+            action = "allow"
+
+            [[rules]]
+            service = "rpc_hello.Greeter"
+            action = "deny"
+            status = "not_found"
+
+            [[rules]]
+            service = "rpc_hello.Greeter"
+            method = "SayHello"
+            action = "allow"
+            "#,
+        )
+        .unwrap();
+        let acl = config.compile().unwrap();
+
+        let mut admin = RpcMetadata::new();
+        admin.insert("authorization", "admin").unwrap();
+        let say_hello = meta("rpc_hello.Greeter", "SayHello");
+        unsafe {
+            assert_eq!(acl.check(&say_hello, 0, Some(&admin)), None);
+            // the second rule decides before the third one allows the request
+            assert_eq!(acl.check(&say_hello, 0, None), Some(5));
+            let other = meta("rpc_hello.Other", "SayHello");
+            assert_eq!(acl.check(&other, 0, Some(&admin)), Some(16));
+        }
+    }
+
+    #[test]
+    fn test_unreadable_field() {
+        let mut deny = rule(Action::Deny);
+        deny.field = Some("name".to_owned());
+        deny.value = Some("admin".to_owned());
+        let mut allow = deny.clone();
+        allow.action = Action::Allow;
+
+        // no reader is registered in the test
+        let meta = meta("rpc_hello.Greeter", "SayHello");
+        unsafe {
+            assert!(CompiledRule::new(&deny).unwrap().matches(&meta, 0, None));
+            assert!(!CompiledRule::new(&allow).unwrap().matches(&meta, 0, None));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::os::unix::ucred::UCred;
+use std::pin::Pin;
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+
+use phoenix_api::rpc::{RpcId, RpcMsgType, TransportStatus};
+use phoenix_api_policy_acl::control_plane;
+
+use phoenix_common::engine::datapath::message::{EngineRxMessage, EngineTxMessage};
+use phoenix_common::engine::datapath::node::DataPathNode;
+use phoenix_common::engine::{future, Decompose, Engine, EngineResult, Indicator, Vertex};
+use phoenix_common::envelop::ResourceDowncast;
+use phoenix_common::impl_vertex_for_engine;
+use phoenix_common::module::Version;
+use phoenix_common::storage::{ResourceCollection, SharedStorage};
+
+use super::DatapathError;
+use crate::config::{AclConfig, CompiledAcl};
+
+pub(crate) struct AclEngine {
+    pub(crate) node: DataPathNode,
+    pub(crate) indicator: Indicator,
+    pub(crate) config: AclConfig,
+    // The rules of `config`, with the names resolved.
+    pub(crate) rules: CompiledAcl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Progress(usize),
+    Disconnected,
+}
+
+use Status::Progress;
+
+impl Engine for AclEngine {
+    fn activate<'a>(self: Pin<&'a mut Self>) -> BoxFuture<'a, EngineResult> {
+        Box::pin(async move { self.get_mut().mainloop().await })
+    }
+
+    fn description(self: Pin<&Self>) -> String {
+        "AclEngine".to_owned()
+    }
+
+    #[inline]
+    fn tracker(self: Pin<&mut Self>) -> &mut Indicator {
+        &mut self.get_mut().indicator
+    }
+
+    fn handle_request(&mut self, request: Vec<u8>, _cred: UCred) -> Result<()> {
+        let request: control_plane::Request = bincode::deserialize(&request[..])?;
+
+        match request {
+            control_plane::Request::NewConfig(config) => {
+                let config = AclConfig::new(Some(&config))?;
+                self.rules = config.compile()?;
+                self.config = config;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl_vertex_for_engine!(AclEngine, node);
+
+impl Decompose for AclEngine {
+    fn flush(&mut self) -> Result<usize> {
+        let mut work = 0;
+        while !self.tx_inputs()[0].is_empty() || !self.rx_inputs()[0].is_empty() {
+            if let Progress(n) = self.check_input_queue()? {
+                work += n;
+            }
+        }
+        Ok(work)
+    }
+
+    fn decompose(
+        self: Box<Self>,
+        _shared: &mut SharedStorage,
+        _global: &mut ResourceCollection,
+    ) -> (ResourceCollection, DataPathNode) {
+        let engine = *self;
+        let mut collections = ResourceCollection::with_capacity(1);
+        collections.insert("config".to_string(), Box::new(engine.config));
+        (collections, engine.node)
+    }
+}
+
+impl AclEngine {
+    pub(crate) fn restore(
+        mut local: ResourceCollection,
+        node: DataPathNode,
+        _prev_version: Version,
+    ) -> Result<Self> {
+        let config = *local
+            .remove("config")
+            .unwrap()
+            .downcast::<AclConfig>()
+            .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        let rules = config.compile()?;
+
+        let engine = AclEngine {
+            node,
+            indicator: Default::default(),
+            config,
+            rules,
+        };
+        Ok(engine)
+    }
+}
+
+impl AclEngine {
+    async fn mainloop(&mut self) -> EngineResult {
+        loop {
+            let mut work = 0;
+            loop {
+                match self.check_input_queue()? {
+                    Progress(0) => break,
+                    Progress(n) => work += n,
+                    Status::Disconnected => return Ok(()),
+                }
+            }
+            self.indicator.set_nwork(work);
+            future::yield_now().await;
+        }
+    }
+}
+
+impl AclEngine {
+    fn check_input_queue(&mut self) -> Result<Status, DatapathError> {
+        use phoenix_common::engine::datapath::TryRecvError;
+
+        match self.tx_inputs()[0].try_recv() {
+            Ok(msg) => {
+                match msg {
+                    EngineTxMessage::RpcMessage(msg) => {
+                        let meta = unsafe { &*msg.meta_buf_ptr.as_meta_ptr() };
+                        let rejected = match meta.msg_type {
+                            RpcMsgType::Request | RpcMsgType::StreamRequest => {
+                                // SAFETY: the request and its metadata stay on the heap until the
+                                // reply arrives
+                                unsafe {
+                                    let metadata = msg.metadata_ptr().map(|ptr| ptr.as_ref());
+                                    self.rules.check(meta, msg.addr_backend, metadata)
+                                }
+                            }
+                            _ => None,
+                        };
+                        match rejected {
+                            None => self.tx_outputs()[0].send(EngineTxMessage::RpcMessage(msg))?,
+                            Some(code) => {
+                                let rpc_id = RpcId::new(meta.conn_id, meta.call_id);
+                                let status = TransportStatus::rejected(code);
+                                self.rx_outputs()[0].send(EngineRxMessage::Ack(rpc_id, status))?;
+                            }
+                        }
+                    }
+                    m => self.tx_outputs()[0].send(m)?,
+                }
+                return Ok(Progress(1));
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => return Ok(Status::Disconnected),
+        }
+
+        match self.rx_inputs()[0].try_recv() {
+            Ok(msg) => {
+                self.rx_outputs()[0].send(msg)?;
+                return Ok(Progress(1));
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => return Ok(Status::Disconnected),
+        }
+
+        Ok(Progress(0))
+    }
+}
@@ -217,6 +217,7 @@ impl HelloAclEngine {
                                         let new_msg = RpcMessageTx {
                                             meta_buf_ptr: msg.meta_buf_ptr.clone(),
                                             addr_backend: raw_ptr.addr(),
+                                            metadata_backend: msg.metadata_backend,
                                         };
                                         log::debug!("tx input accept msg:{:?}", new_msg);
                                         RpcMessageGeneral::TxMessage(EngineTxMessage::RpcMessage(
@@ -184,6 +184,7 @@ impl FaultEngine {
                                     let new_msg = RpcMessageTx {
                                         meta_buf_ptr: msg.meta_buf_ptr.clone(),
                                         addr_backend: raw_ptr.addr(),
+                                        metadata_backend: msg.metadata_backend,
                                     };
                                     RpcMessageGeneral::TxMessage(EngineTxMessage::RpcMessage(
                                         new_msg,
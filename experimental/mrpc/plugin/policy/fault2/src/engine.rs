@@ -175,6 +175,7 @@ impl Fault2Engine {
                             let new_msg = RpcMessageTx {
                                 meta_buf_ptr: msg.meta_buf_ptr.clone(),
                                 addr_backend: raw_ptr.addr(),
+                                metadata_backend: msg.metadata_backend,
                             };
                             self.tx_outputs()[0].send(EngineTxMessage::RpcMessage(new_msg))?
                         }
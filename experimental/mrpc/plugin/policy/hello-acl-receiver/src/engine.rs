@@ -214,6 +214,7 @@ impl HelloAclReceiverEngine {
                             let rpc_msg = RpcMessageTx {
                                 meta_buf_ptr: meta_ptr,
                                 addr_backend: 0,
+                                metadata_backend: 0,
                             };
                             let new_msg = EngineTxMessage::RpcMessage(rpc_msg);
                             self.tx_outputs()[0]
@@ -229,7 +230,7 @@ impl HelloAclReceiverEngine {
                             self.rx_outputs()[0].send(EngineRxMessage::RpcMessage(msg))?;
                         }
                     }
-                    EngineRxMessage::RecvError(_, _) => {
+                    EngineRxMessage::RecvError(_, _) | EngineRxMessage::Cancel(_) => {
                         self.rx_outputs()[0].send(m)?;
                     }
                 }
@@ -222,6 +222,7 @@ impl HelloAclSenderEngine {
                                     let new_msg = RpcMessageTx {
                                         meta_buf_ptr: msg.meta_buf_ptr,
                                         addr_backend: raw_ptr.addr(),
+                                        metadata_backend: msg.metadata_backend,
                                     };
                                     self.tx_outputs()[0]
                                         .send(EngineTxMessage::RpcMessage(new_msg))?;
@@ -219,10 +219,11 @@ impl NofileLoggingEngine {
                             .iter()
                             .map(|req| {
                                 RpcMessageGeneral::TxMessage(EngineTxMessage::RpcMessage(
-                                    RpcMessageTx::new(
-                                        req.meta_buf_ptr.clone(),
-                                        req.addr_backend.clone(),
-                                    ),
+                                    RpcMessageTx {
+                                        meta_buf_ptr: req.meta_buf_ptr.clone(),
+                                        addr_backend: req.addr_backend.clone(),
+                                        metadata_backend: req.metadata_backend,
+                                    },
                                 ))
                             })
                             .collect::<Vec<_>>();
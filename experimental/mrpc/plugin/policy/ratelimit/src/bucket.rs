@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+
+use minstant::Instant;
+
+use phoenix_common::engine::datapath::message::RpcMessageTx;
+
+use crate::quota::SharedQuota;
+
+/// The key of a token bucket, see [`BucketBy`](crate::config::BucketBy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum BucketKey {
+    Engine,
+    /// `(service_id, func_id)`
+    Method(u32, u32),
+    Connection(u64),
+    Tenant(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    // Number of tokens to add for each seconds.
+    pub(crate) requests_per_sec: u64,
+    pub(crate) bucket_size: u64,
+}
+
+/// The tokens of the token bucket algorithm.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Tokens {
+    pub(crate) limits: Limits,
+    // The most recent timestamp we add the token to the bucket.
+    pub(crate) last_ts: Instant,
+    // The number of available tokens.
+    pub(crate) num_tokens: f64,
+}
+
+impl Tokens {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Tokens {
+            limits,
+            last_ts: Instant::now(),
+            num_tokens: limits.bucket_size as _,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn add_tokens(&mut self, now: Instant) {
+        let dura = now - self.last_ts;
+        let requests_per_sec = self.limits.requests_per_sec;
+        let bucket_size = self.limits.bucket_size as usize;
+        if dura.as_secs_f64() * requests_per_sec as f64 >= 1.0 {
+            self.num_tokens += dura.as_secs_f64() * requests_per_sec as f64;
+            if self.num_tokens > bucket_size as f64 {
+                self.num_tokens = bucket_size as f64;
+            }
+            self.last_ts = now;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn has_token(&self) -> bool {
+        self.num_tokens > 0.1
+    }
+
+    #[inline]
+    pub(crate) fn is_full(&self) -> bool {
+        self.num_tokens >= self.limits.bucket_size as f64
+    }
+}
+
+pub(crate) struct TokenBucket {
+    pub(crate) tokens: Tokens,
+    // The queue to buffer the requests that cannot be sent immediately.
+    // This is RPC buffer
+    pub(crate) queue: VecDeque<RpcMessageTx>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limits: Limits) -> Self {
+        TokenBucket {
+            tokens: Tokens::new(limits),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Takes a token if there is one and no request is waiting for it, and the quota shared with
+    /// the other engines, if any, has one as well.
+    #[inline]
+    pub(crate) fn try_acquire(&mut self, quota: Option<&SharedQuota>) -> bool {
+        if !self.queue.is_empty() || !self.tokens.has_token() {
+            return false;
+        }
+        if !quota.map_or(true, |quota| quota.try_acquire()) {
+            return false;
+        }
+        self.tokens.num_tokens -= 1.0;
+        true
+    }
+
+    /// Pops a queued request if there is a token for it.
+    #[inline]
+    pub(crate) fn pop_ready(&mut self, quota: Option<&SharedQuota>) -> Option<RpcMessageTx> {
+        if self.queue.is_empty()
+            || !self.tokens.has_token()
+            || !quota.map_or(true, |quota| quota.try_acquire())
+        {
+            return None;
+        }
+        self.tokens.num_tokens -= 1.0;
+        self.queue.pop_front()
+    }
+
+    /// Returns true if a request without a token can wait in the queue, which holds at most
+    /// `capacity` requests.
+    #[inline]
+    pub(crate) fn has_room(&self, capacity: Option<usize>) -> bool {
+        capacity.map_or(true, |cap| self.queue.len() < cap)
+    }
+
+    /// Returns true if the bucket is the same as a new one, hence can be dropped.
+    #[inline]
+    pub(crate) fn is_idle(&self) -> bool {
+        self.queue.is_empty() && self.tokens.is_full()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr::Unique;
+    use std::time::Duration;
+
+    use phoenix_common::engine::datapath::meta_pool::MetaBufferPtr;
+
+    use super::*;
+
+    /// Returns a request told apart by `id`, which the bucket never reads.
+    fn request(id: usize) -> RpcMessageTx {
+        RpcMessageTx {
+            meta_buf_ptr: MetaBufferPtr(Unique::dangling()),
+            addr_backend: id,
+            metadata_backend: 0,
+        }
+    }
+
+    fn new_bucket(bucket_size: u64) -> TokenBucket {
+        // no token is added over time
+        TokenBucket::new(Limits {
+            requests_per_sec: 0,
+            bucket_size,
+        })
+    }
+
+    #[test]
+    fn test_try_acquire() {
+        let mut bucket = new_bucket(2);
+        assert!(bucket.is_idle());
+        assert!(bucket.try_acquire(None));
+        assert!(!bucket.is_idle());
+        assert!(bucket.try_acquire(None));
+        assert!(!bucket.try_acquire(None));
+
+        // a queued request goes first
+        let mut bucket = new_bucket(2);
+        bucket.queue.push_back(request(1));
+        assert!(!bucket.try_acquire(None));
+        assert_eq!(bucket.tokens.num_tokens, 2.0);
+    }
+
+    #[test]
+    fn test_pop_ready() {
+        let mut bucket = new_bucket(2);
+        assert!(bucket.pop_ready(None).is_none());
+        // no token is taken without a request
+        assert_eq!(bucket.tokens.num_tokens, 2.0);
+
+        for id in 1..=3 {
+            bucket.queue.push_back(request(id));
+        }
+        assert_eq!(bucket.pop_ready(None).unwrap().addr_backend, 1);
+        assert_eq!(bucket.pop_ready(None).unwrap().addr_backend, 2);
+        assert!(bucket.pop_ready(None).is_none());
+        assert_eq!(bucket.queue.len(), 1);
+
+        // tokens are added over time
+        bucket.tokens.limits.requests_per_sec = 10;
+        let now = bucket.tokens.last_ts + Duration::from_millis(200);
+        bucket.tokens.add_tokens(now);
+        assert_eq!(bucket.pop_ready(None).unwrap().addr_backend, 3);
+        assert!(bucket.queue.is_empty());
+    }
+
+    #[test]
+    fn test_add_tokens() {
+        let mut tokens = Tokens::new(Limits {
+            requests_per_sec: 10,
+            bucket_size: 5,
+        });
+        tokens.num_tokens = 0.0;
+        let start = tokens.last_ts;
+
+        // less than a token has accrued, the time is not consumed
+        tokens.add_tokens(start + Duration::from_millis(50));
+        assert_eq!(tokens.num_tokens, 0.0);
+        assert!(tokens.last_ts == start);
+
+        tokens.add_tokens(start + Duration::from_millis(200));
+        assert!((tokens.num_tokens - 2.0).abs() < 1e-3);
+        assert!(tokens.has_token());
+
+        // the bucket never holds more than its size
+        tokens.add_tokens(start + Duration::from_secs(10));
+        assert!(tokens.is_full());
+        assert_eq!(tokens.num_tokens, 5.0);
+    }
+
+    #[test]
+    fn test_queue_capacity() {
+        let mut bucket = new_bucket(0);
+        assert!(bucket.has_room(None));
+        assert!(bucket.has_room(Some(2)));
+        assert!(!bucket.has_room(Some(0)));
+
+        bucket.queue.push_back(request(1));
+        bucket.queue.push_back(request(2));
+        assert!(!bucket.has_room(Some(2)));
+        assert!(bucket.has_room(Some(3)));
+        // an unlimited queue is never full
+        assert!(bucket.has_room(None));
+    }
+}
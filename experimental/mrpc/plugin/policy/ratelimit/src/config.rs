@@ -1,10 +1,105 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
 use serde::{Deserialize, Serialize};
 
+use phoenix_api_policy_ratelimit::control_plane::Bucket;
+
+use crate::bucket::{BucketKey, Limits};
+
+/// What the requests are bucketed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketBy {
+    /// A single bucket for all requests of the engine.
+    #[default]
+    Engine,
+    /// A bucket per method, i.e., `(service_id, func_id)`.
+    Method,
+    /// A bucket per connection.
+    Connection,
+    /// A bucket per uid of the client processes.
+    Tenant,
+}
+
+/// What client processes share a quota by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareBy {
+    Uid,
+    Cgroup,
+}
+
+/// A limit on the aggregate rate of all client processes with the same uid or cgroup.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
+pub struct QuotaConfig {
+    pub share_by: ShareBy,
+    pub requests_per_sec: u64,
+    pub bucket_size: u64,
+}
+
+impl QuotaConfig {
+    #[inline]
+    pub(crate) fn limits(&self) -> Limits {
+        Limits {
+            requests_per_sec: self.requests_per_sec,
+            bucket_size: self.bucket_size,
+        }
+    }
+}
+
+/// The limits of one bucket, instead of the default ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+    /// The fully qualified name of the service, e.g., `rpc_hello.Greeter`, with `method`.
+    #[serde(default)]
+    pub service: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub conn_id: Option<u64>,
+    #[serde(default)]
+    pub uid: Option<u32>,
+    pub requests_per_sec: u64,
+    pub bucket_size: u64,
+}
+
+impl BucketConfig {
+    fn bucket(&self) -> anyhow::Result<Bucket> {
+        let bucket = match (&self.service, &self.method, self.conn_id, self.uid) {
+            (Some(service), Some(method), None, None) => {
+                Bucket::Method(service.clone(), method.clone())
+            }
+            (None, None, Some(conn_id), None) => Bucket::Connection(conn_id),
+            (None, None, None, Some(uid)) => Bucket::Tenant(uid),
+            _ => bail!(
+                "a bucket must be given by either service and method, conn_id, or uid: {:?}",
+                self
+            ),
+        };
+        Ok(bucket)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct RateLimitConfig {
+    /// The limits of the buckets not listed in `buckets`.
     pub requests_per_sec: u64,
     pub bucket_size: u64,
+    #[serde(default)]
+    pub bucket_by: BucketBy,
+    /// The number of requests each bucket queues when it runs out of tokens. The requests beyond
+    /// it are rejected with `resource_exhausted`. Unlimited if not given.
+    #[serde(default)]
+    pub queue_capacity: Option<usize>,
+    #[serde(default)]
+    pub buckets: Vec<BucketConfig>,
+    /// The quota shared with the engines of the other client processes, on top of the buckets.
+    #[serde(default)]
+    pub quota: Option<QuotaConfig>,
 }
 
 impl Default for RateLimitConfig {
@@ -12,13 +107,216 @@ impl Default for RateLimitConfig {
         RateLimitConfig {
             requests_per_sec: 100000,
             bucket_size: 100000,
+            bucket_by: BucketBy::Engine,
+            queue_capacity: None,
+            buckets: Vec::new(),
+            quota: None,
         }
     }
 }
 
 impl RateLimitConfig {
     pub fn new(config: Option<&str>) -> anyhow::Result<Self> {
-        let config = toml::from_str(config.unwrap_or(""))?;
+        let config: RateLimitConfig = toml::from_str(config.unwrap_or(""))?;
+        config.bucket_limits()?;
         Ok(config)
     }
+
+    /// The limits of the buckets not configured on their own.
+    #[inline]
+    pub(crate) fn default_limits(&self) -> Limits {
+        Limits {
+            requests_per_sec: self.requests_per_sec,
+            bucket_size: self.bucket_size,
+        }
+    }
+
+    /// Returns the limits of the buckets configured on their own.
+    pub(crate) fn bucket_limits(&self) -> anyhow::Result<HashMap<BucketKey, Limits>> {
+        self.buckets
+            .iter()
+            .map(|bucket| {
+                let key = self.bucket_key(&bucket.bucket()?)?;
+                let limits = Limits {
+                    requests_per_sec: bucket.requests_per_sec,
+                    bucket_size: bucket.bucket_size,
+                };
+                Ok((key, limits))
+            })
+            .collect()
+    }
+
+    /// Resolves a bucket name to its key, which must be of the kind requests are bucketed by.
+    pub(crate) fn bucket_key(&self, bucket: &Bucket) -> anyhow::Result<BucketKey> {
+        let key = match (self.bucket_by, bucket) {
+            (BucketBy::Method, Bucket::Method(service, method)) => {
+                // the same as how mrpc-build computes SERVICE_ID and FUNC_ID
+                let service_id = crc32fast::hash(service.as_bytes());
+                let func_id = crc32fast::hash(format!("/{}/{}", service, method).as_bytes());
+                BucketKey::Method(service_id, func_id)
+            }
+            (BucketBy::Connection, Bucket::Connection(conn_id)) => BucketKey::Connection(*conn_id),
+            (BucketBy::Tenant, Bucket::Tenant(uid)) => BucketKey::Tenant(*uid),
+            (bucket_by, _) => bail!(
+                "bucket {:?} does not apply, requests are bucketed by {:?}",
+                bucket,
+                bucket_by
+            ),
+        };
+        Ok(key)
+    }
+
+    /// Sets the limits of a bucket, replacing its previous entry in `buckets`.
+    pub(crate) fn set_bucket(&mut self, bucket: Bucket, limits: Option<Limits>) {
+        let mut entry = BucketConfig {
+            service: None,
+            method: None,
+            conn_id: None,
+            uid: None,
+            requests_per_sec: 0,
+            bucket_size: 0,
+        };
+        match bucket {
+            Bucket::Method(service, method) => {
+                entry.service = Some(service);
+                entry.method = Some(method);
+            }
+            Bucket::Connection(conn_id) => entry.conn_id = Some(conn_id),
+            Bucket::Tenant(uid) => entry.uid = Some(uid),
+        }
+        self.buckets.retain(|b| {
+            (&b.service, &b.method, b.conn_id, b.uid)
+                != (&entry.service, &entry.method, entry.conn_id, entry.uid)
+        });
+        if let Some(limits) = limits {
+            entry.requests_per_sec = limits.requests_per_sec;
+            entry.bucket_size = limits.bucket_size;
+            self.buckets.push(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(bucket_by: BucketBy) -> RateLimitConfig {
+        RateLimitConfig {
+            bucket_by,
+            ..Default::default()
+        }
+    }
+
+    fn limits(requests_per_sec: u64) -> Limits {
+        Limits {
+            requests_per_sec,
+            bucket_size: requests_per_sec,
+        }
+    }
+
+    #[test]
+    fn test_bucket_key() {
+        // the SERVICE_ID and FUNC_ID that mrpc-build generates for rpc_hello.Greeter/SayHello
+        let method = Bucket::Method("rpc_hello.Greeter".to_owned(), "SayHello".to_owned());
+        assert_eq!(
+            config(BucketBy::Method).bucket_key(&method).unwrap(),
+            BucketKey::Method(4059748245, 3687134534)
+        );
+        assert_eq!(
+            config(BucketBy::Connection)
+                .bucket_key(&Bucket::Connection(3))
+                .unwrap(),
+            BucketKey::Connection(3)
+        );
+        assert_eq!(
+            config(BucketBy::Tenant)
+                .bucket_key(&Bucket::Tenant(1000))
+                .unwrap(),
+            BucketKey::Tenant(1000)
+        );
+
+        // a bucket of another kind than requests are bucketed by
+        assert!(config(BucketBy::Connection).bucket_key(&method).is_err());
+        assert!(config(BucketBy::Method)
+            .bucket_key(&Bucket::Tenant(1000))
+            .is_err());
+        assert!(config(BucketBy::Engine)
+            .bucket_key(&Bucket::Connection(3))
+            .is_err());
+    }
+
+    #[test]
+    fn test_bucket_limits() {
+        let config = RateLimitConfig::new(Some(
+            r#"
+            requests_per_sec = 1000
+            bucket_size = 1000
+            bucket_by = "tenant"
+            queue_capacity = 16
+
+            [[buckets]]
+            uid = 1000
+            requests_per_sec = 10
+            bucket_size = 20
+            "#,
+        ))
+        .unwrap();
+        assert_eq!(config.default_limits(), limits(1000));
+        assert_eq!(config.queue_capacity, Some(16));
+        let bucket_limits = config.bucket_limits().unwrap();
+        assert_eq!(bucket_limits.len(), 1);
+        assert_eq!(
+            bucket_limits[&BucketKey::Tenant(1000)],
+            Limits {
+                requests_per_sec: 10,
+                bucket_size: 20,
+            }
+        );
+
+        // a bucket must be given by exactly one of its names
+        let invalid = r#"
+            requests_per_sec = 1000
+            bucket_size = 1000
+            bucket_by = "tenant"
+
+            [[buckets]]
+            uid = 1000
+            conn_id = 3
+            requests_per_sec = 10
+            bucket_size = 20
+            "#;
+        assert!(RateLimitConfig::new(Some(invalid)).is_err());
+        // and apply to how requests are bucketed
+        let invalid = invalid.replace("uid = 1000\n", "");
+        assert!(RateLimitConfig::new(Some(&invalid)).is_err());
+    }
+
+    #[test]
+    fn test_set_bucket() {
+        let mut config = config(BucketBy::Method);
+        let method = || Bucket::Method("rpc_hello.Greeter".to_owned(), "SayHello".to_owned());
+        let other = || Bucket::Method("rpc_hello.Greeter".to_owned(), "SayBye".to_owned());
+
+        config.set_bucket(method(), Some(limits(10)));
+        config.set_bucket(other(), Some(limits(20)));
+        // the entry of a bucket is replaced
+        config.set_bucket(method(), Some(limits(30)));
+        assert_eq!(config.buckets.len(), 2);
+        let bucket_limits = config.bucket_limits().unwrap();
+        assert_eq!(
+            bucket_limits[&config.bucket_key(&method()).unwrap()],
+            limits(30)
+        );
+        assert_eq!(
+            bucket_limits[&config.bucket_key(&other()).unwrap()],
+            limits(20)
+        );
+
+        // and removed
+        config.set_bucket(method(), None);
+        assert_eq!(config.buckets.len(), 1);
+        assert_eq!(config.buckets[0].method.as_deref(), Some("SayBye"));
+        config.set_bucket(method(), None);
+        assert_eq!(config.buckets.len(), 1);
+    }
 }
@@ -1,14 +1,16 @@
-use std::collections::VecDeque;
+use std::collections::HashMap;
 use std::os::unix::ucred::UCred;
 use std::pin::Pin;
+use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use futures::future::BoxFuture;
 use minstant::Instant;
 
+use phoenix_api::rpc::{MessageMeta, RpcId, RpcMsgType, TransportStatus, RESOURCE_EXHAUSTED};
 use phoenix_api_policy_ratelimit::control_plane;
 
-use phoenix_common::engine::datapath::message::{EngineTxMessage, RpcMessageTx};
+use phoenix_common::engine::datapath::message::{EngineRxMessage, EngineTxMessage};
 use phoenix_common::engine::datapath::node::DataPathNode;
 use phoenix_common::engine::{future, Decompose, Engine, EngineResult, Indicator, Vertex};
 use phoenix_common::envelop::ResourceDowncast;
@@ -17,7 +19,9 @@ use phoenix_common::module::Version;
 use phoenix_common::storage::{ResourceCollection, SharedStorage};
 
 use super::DatapathError;
-use crate::config::RateLimitConfig;
+use crate::bucket::{BucketKey, Limits, TokenBucket};
+use crate::config::{BucketBy, RateLimitConfig};
+use crate::quota::SharedQuota;
 
 pub(crate) struct RateLimitEngine {
     pub(crate) node: DataPathNode,
@@ -27,15 +31,16 @@ pub(crate) struct RateLimitEngine {
     // A set of func_ids to apply the rate limit.
     // TODO(cjr): maybe put this filter in a separate engine like FilterEngine/ClassiferEngine.
     // pub(crate) filter: FnvHashSet<u32>,
-    // Number of tokens to add for each seconds.
     pub(crate) config: RateLimitConfig,
-    // The most recent timestamp we add the token to the bucket.
-    pub(crate) last_ts: Instant,
-    // The number of available tokens in the token bucket algorithm.
-    pub(crate) num_tokens: f64,
-    // The queue to buffer the requests that cannot be sent immediately.
-    // This is RPC buffer
-    pub(crate) queue: VecDeque<RpcMessageTx>,
+    // The uid of the client process, which names its bucket when bucketed by tenant.
+    pub(crate) uid: u32,
+    // The limits of the buckets configured on their own, resolved from `config`.
+    pub(crate) bucket_limits: HashMap<BucketKey, Limits>,
+    // The buckets with queued requests or missing tokens. A bucket is created full when the
+    // first request for it arrives, and dropped when it becomes full again.
+    pub(crate) buckets: HashMap<BucketKey, TokenBucket>,
+    // The quota shared with the engines of the other processes of the same uid or cgroup.
+    pub(crate) quota: Option<Arc<SharedQuota>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -65,10 +70,42 @@ impl Engine for RateLimitEngine {
 
         match request {
             control_plane::Request::NewConfig(requests_per_sec, bucket_size) => {
-                self.config = RateLimitConfig {
+                self.config.requests_per_sec = requests_per_sec;
+                self.config.bucket_size = bucket_size;
+                let limits = self.config.default_limits();
+                for (key, bucket) in self.buckets.iter_mut() {
+                    if !self.bucket_limits.contains_key(key) {
+                        bucket.tokens.limits = limits;
+                    }
+                }
+            }
+            control_plane::Request::UpdateBucket(bucket, requests_per_sec, bucket_size) => {
+                let key = self.config.bucket_key(&bucket)?;
+                let limits = Limits {
                     requests_per_sec,
                     bucket_size,
                 };
+                self.config.set_bucket(bucket, Some(limits));
+                self.bucket_limits.insert(key, limits);
+                if let Some(bucket) = self.buckets.get_mut(&key) {
+                    bucket.tokens.limits = limits;
+                }
+            }
+            control_plane::Request::ResetBucket(bucket) => {
+                let key = self.config.bucket_key(&bucket)?;
+                self.config.set_bucket(bucket, None);
+                self.bucket_limits.remove(&key);
+                if let Some(bucket) = self.buckets.get_mut(&key) {
+                    bucket.tokens.limits = self.config.default_limits();
+                }
+            }
+            control_plane::Request::UpdateQuota(requests_per_sec, bucket_size) => {
+                let (Some(quota), Some(quota_config)) = (&self.quota, &mut self.config.quota) else {
+                    bail!("RateLimitEngine has no quota");
+                };
+                quota_config.requests_per_sec = requests_per_sec;
+                quota_config.bucket_size = bucket_size;
+                quota.set_limits(quota_config.limits());
             }
         }
         Ok(())
@@ -80,15 +117,18 @@ impl_vertex_for_engine!(RateLimitEngine, node);
 impl Decompose for RateLimitEngine {
     fn flush(&mut self) -> Result<usize> {
         let mut work = 0;
-        while !self.tx_inputs()[0].is_empty() {
+        while !self.tx_inputs()[0].is_empty()
+            || self.rx_inputs().first().map_or(false, |rx| !rx.is_empty())
+        {
             if let Progress(n) = self.check_input_queue()? {
                 work += n;
             }
         }
-        while !self.queue.is_empty() {
-            let msg = self.queue.pop_front().unwrap();
-            self.tx_outputs()[0].send(EngineTxMessage::RpcMessage(msg))?;
-            work += 1;
+        for (_, mut bucket) in self.buckets.drain() {
+            while let Some(msg) = bucket.queue.pop_front() {
+                self.node.tx_outputs[0].send(EngineTxMessage::RpcMessage(msg))?;
+                work += 1;
+            }
         }
         Ok(work)
     }
@@ -102,9 +142,9 @@ impl Decompose for RateLimitEngine {
 
         let mut collections = ResourceCollection::with_capacity(4);
         collections.insert("config".to_string(), Box::new(engine.config));
-        collections.insert("last_ts".to_string(), Box::new(engine.last_ts));
-        collections.insert("num_tokens".to_string(), Box::new(engine.num_tokens));
-        collections.insert("queue".to_string(), Box::new(engine.queue));
+        collections.insert("uid".to_string(), Box::new(engine.uid));
+        collections.insert("buckets".to_string(), Box::new(engine.buckets));
+        collections.insert("quota".to_string(), Box::new(engine.quota));
         (collections, engine.node)
     }
 }
@@ -120,29 +160,31 @@ impl RateLimitEngine {
             .unwrap()
             .downcast::<RateLimitConfig>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
-        let last_ts = *local
-            .remove("last_ts")
+        let uid = *local
+            .remove("uid")
             .unwrap()
-            .downcast::<Instant>()
+            .downcast::<u32>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
-        let num_tokens = *local
-            .remove("num_tokens")
+        let buckets = *local
+            .remove("buckets")
             .unwrap()
-            .downcast::<f64>()
+            .downcast::<HashMap<BucketKey, TokenBucket>>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
-        let queue = *local
-            .remove("queue")
+        let quota = *local
+            .remove("quota")
             .unwrap()
-            .downcast::<VecDeque<RpcMessageTx>>()
+            .downcast::<Option<Arc<SharedQuota>>>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        let bucket_limits = config.bucket_limits()?;
 
         let engine = RateLimitEngine {
             node,
             indicator: Default::default(),
             config,
-            last_ts,
-            num_tokens,
-            queue,
+            uid,
+            bucket_limits,
+            buckets,
+            quota,
         };
         Ok(engine)
     }
@@ -151,8 +193,6 @@ impl RateLimitEngine {
 impl RateLimitEngine {
     async fn mainloop(&mut self) -> EngineResult {
         loop {
-            self.add_tokens();
-
             let mut work = 0;
             // check input queue, ~100ns
             loop {
@@ -163,9 +203,7 @@ impl RateLimitEngine {
                 }
             }
 
-            self.add_tokens();
-
-            self.leak_bucket()?;
+            self.leak_buckets()?;
 
             // If there's pending receives, there will always be future work to do.
             self.indicator.set_nwork(work);
@@ -177,28 +215,24 @@ impl RateLimitEngine {
 
 impl RateLimitEngine {
     #[inline]
-    fn add_tokens(&mut self) {
-        let now = Instant::now();
-        let dura = now - self.last_ts;
-        let config = &self.config;
-        let requests_per_sec = config.requests_per_sec;
-        let bucket_size = config.bucket_size as usize;
-        if dura.as_secs_f64() * requests_per_sec as f64 >= 1.0 {
-            self.num_tokens += dura.as_secs_f64() * requests_per_sec as f64;
-            if self.num_tokens > bucket_size as f64 {
-                self.num_tokens = bucket_size as f64;
-            }
-            self.last_ts = now;
+    fn bucket_key(&self, meta: &MessageMeta) -> BucketKey {
+        match self.config.bucket_by {
+            BucketBy::Engine => BucketKey::Engine,
+            BucketBy::Method => BucketKey::Method(meta.service_id, meta.func_id),
+            BucketBy::Connection => BucketKey::Connection(meta.conn_id.0),
+            BucketBy::Tenant => BucketKey::Tenant(self.uid),
         }
     }
 
-    fn leak_bucket(&mut self) -> Result<(), DatapathError> {
-        while self.num_tokens > 0.1 && !self.queue.is_empty() {
-            let msg = self.queue.pop_front().unwrap();
-            self.num_tokens -= 1.0;
-            self.tx_outputs()[0].send(EngineTxMessage::RpcMessage(msg))?;
+    fn leak_buckets(&mut self) -> Result<(), DatapathError> {
+        let now = Instant::now();
+        for bucket in self.buckets.values_mut() {
+            bucket.tokens.add_tokens(now);
+            while let Some(msg) = bucket.pop_ready(self.quota.as_deref()) {
+                self.node.tx_outputs[0].send(EngineTxMessage::RpcMessage(msg))?;
+            }
         }
-
+        self.buckets.retain(|_, bucket| !bucket.is_idle());
         Ok(())
     }
 
@@ -209,11 +243,32 @@ impl RateLimitEngine {
             Ok(msg) => {
                 match msg {
                     EngineTxMessage::RpcMessage(msg) => {
-                        if !self.queue.is_empty() || self.num_tokens < 0.1 {
-                            self.queue.push_back(msg);
+                        let meta = unsafe { &*msg.meta_buf_ptr.as_meta_ptr() };
+                        let key = self.bucket_key(meta);
+                        let limits = self
+                            .bucket_limits
+                            .get(&key)
+                            .copied()
+                            .unwrap_or_else(|| self.config.default_limits());
+                        let bucket = self
+                            .buckets
+                            .entry(key)
+                            .or_insert_with(|| TokenBucket::new(limits));
+                        bucket.tokens.add_tokens(Instant::now());
+
+                        // Only requests can be rejected, the responses are always queued.
+                        let rejectable = matches!(
+                            meta.msg_type,
+                            RpcMsgType::Request | RpcMsgType::StreamRequest
+                        );
+                        if bucket.try_acquire(self.quota.as_deref()) {
+                            self.node.tx_outputs[0].send(EngineTxMessage::RpcMessage(msg))?;
+                        } else if !rejectable || bucket.has_room(self.config.queue_capacity) {
+                            bucket.queue.push_back(msg);
                         } else {
-                            self.num_tokens -= 1.0;
-                            self.tx_outputs()[0].send(EngineTxMessage::RpcMessage(msg))?;
+                            let rpc_id = RpcId::new(meta.conn_id, meta.call_id);
+                            let status = TransportStatus::rejected(RESOURCE_EXHAUSTED);
+                            self.node.rx_outputs[0].send(EngineRxMessage::Ack(rpc_id, status))?;
                         }
                     }
                     // XXX TODO(cjr): it is best not to reorder the message
@@ -225,6 +280,18 @@ impl RateLimitEngine {
             Err(TryRecvError::Disconnected) => return Ok(Status::Disconnected),
         }
 
+        // The engine is also attached on the rx direction when it rejects requests.
+        if !self.rx_inputs().is_empty() {
+            match self.rx_inputs()[0].try_recv() {
+                Ok(msg) => {
+                    self.rx_outputs()[0].send(msg)?;
+                    return Ok(Progress(1));
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return Ok(Status::Disconnected),
+            }
+        }
+
         Ok(Progress(0))
     }
 }
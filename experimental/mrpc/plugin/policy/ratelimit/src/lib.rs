@@ -1,12 +1,15 @@
 #![feature(peer_credentials_unix_socket)]
+#![cfg_attr(test, feature(ptr_internals))]
 
 use thiserror::Error;
 
 pub use phoenix_common::{InitFnResult, PhoenixAddon};
 
+pub(crate) mod bucket;
 pub mod config;
 pub(crate) mod engine;
 pub mod module;
+pub(crate) mod quota;
 
 #[derive(Error, Debug)]
 pub(crate) enum DatapathError {
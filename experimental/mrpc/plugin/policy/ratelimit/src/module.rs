@@ -1,41 +1,62 @@
-use std::collections::VecDeque;
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+use std::sync::Arc;
 
 use anyhow::{bail, Result};
-use minstant::Instant;
 use nix::unistd::Pid;
 
-use phoenix_common::addon::{PhoenixAddon, Version};
+use phoenix_common::addon::{AddonDowncast, PhoenixAddon, Version};
 use phoenix_common::engine::datapath::DataPathNode;
 use phoenix_common::engine::{Engine, EngineType};
 use phoenix_common::storage::ResourceCollection;
 
 use super::engine::RateLimitEngine;
 use crate::config::RateLimitConfig;
+use crate::quota::{QuotaKey, QuotaManager, SharedQuota};
 
 pub(crate) struct RateLimitEngineBuilder {
     node: DataPathNode,
     config: RateLimitConfig,
+    uid: u32,
+    quota: Option<Arc<SharedQuota>>,
 }
 
 impl RateLimitEngineBuilder {
-    fn new(node: DataPathNode, config: RateLimitConfig) -> Self {
-        RateLimitEngineBuilder { node, config }
+    fn new(
+        node: DataPathNode,
+        config: RateLimitConfig,
+        uid: u32,
+        quota: Option<Arc<SharedQuota>>,
+    ) -> Self {
+        RateLimitEngineBuilder {
+            node,
+            config,
+            uid,
+            quota,
+        }
     }
 
     fn build(self) -> Result<RateLimitEngine> {
+        if self.config.queue_capacity.is_some() && self.node.rx_outputs.is_empty() {
+            bail!("RateLimitEngine must be attached on the rx direction to reject requests");
+        }
+        let bucket_limits = self.config.bucket_limits()?;
         Ok(RateLimitEngine {
             node: self.node,
             indicator: Default::default(),
             config: self.config,
-            last_ts: Instant::now(),
-            num_tokens: self.config.bucket_size as _,
-            queue: VecDeque::new(),
+            uid: self.uid,
+            bucket_limits,
+            buckets: HashMap::new(),
+            quota: self.quota,
         })
     }
 }
 
 pub struct RateLimitAddon {
     config: RateLimitConfig,
+    // The quotas shared by the engines of the client processes with the same uid or cgroup.
+    quotas: QuotaManager,
 }
 
 impl RateLimitAddon {
@@ -45,7 +66,10 @@ impl RateLimitAddon {
 
 impl RateLimitAddon {
     pub fn new(config: RateLimitConfig) -> Self {
-        RateLimitAddon { config }
+        RateLimitAddon {
+            config,
+            quotas: QuotaManager::new(),
+        }
     }
 }
 
@@ -58,32 +82,49 @@ impl PhoenixAddon for RateLimitAddon {
         let addon = *self;
         let mut collections = ResourceCollection::new();
         collections.insert("config".to_string(), Box::new(addon.config));
+        collections.insert("quotas".to_string(), Box::new(addon.quotas));
         collections
     }
 
-    #[inline]
-    fn migrate(&mut self, _prev_addon: Box<dyn PhoenixAddon>) {}
+    fn migrate(&mut self, prev_addon: Box<dyn PhoenixAddon>) {
+        // Keep sharing the quotas with the engines created by the previous version.
+        if let Ok(prev) = prev_addon.downcast::<Self>() {
+            self.quotas = prev.quotas;
+        }
+    }
 
     fn engines(&self) -> &[EngineType] {
         RateLimitAddon::ENGINES
     }
 
     fn update_config(&mut self, config: &str) -> Result<()> {
-        self.config = toml::from_str(config)?;
+        self.config = RateLimitConfig::new(Some(config))?;
         Ok(())
     }
 
     fn create_engine(
         &mut self,
         ty: EngineType,
-        _pid: Pid,
+        pid: Pid,
         node: DataPathNode,
     ) -> Result<Box<dyn Engine>> {
         if ty != RateLimitAddon::RATE_LIMIT_ENGINE {
             bail!("invalid engine type {:?}", ty)
         }
 
-        let builder = RateLimitEngineBuilder::new(node, self.config);
+        let uid = std::fs::metadata(format!("/proc/{}", pid))?.uid();
+        let quota = match self.config.quota {
+            Some(quota) => {
+                // The limits of an existing quota are not changed by joining it.
+                let key = QuotaKey::of_process(pid, quota.share_by)?;
+                let shared = self
+                    .quotas
+                    .get_or_insert_with(key, || SharedQuota::new(quota.limits()));
+                Some(shared)
+            }
+            None => None,
+        };
+        let builder = RateLimitEngineBuilder::new(node, self.config.clone(), uid, quota);
         let engine = builder.build()?;
         Ok(Box::new(engine))
     }
@@ -0,0 +1,136 @@
+use std::os::unix::fs::MetadataExt;
+
+use anyhow::anyhow;
+use minstant::Instant;
+use nix::unistd::Pid;
+
+use phoenix_common::state_mgr::SharedStateManager;
+
+use crate::bucket::{Limits, Tokens};
+use crate::config::ShareBy;
+
+/// The group of client processes that share a quota.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum QuotaKey {
+    Uid(u32),
+    /// The path of a cgroup, e.g., `/user.slice/user-1000.slice`.
+    Cgroup(String),
+}
+
+impl QuotaKey {
+    /// Returns the key of the quota that a client process takes its tokens from.
+    pub(crate) fn of_process(pid: Pid, share_by: ShareBy) -> anyhow::Result<Self> {
+        let key = match share_by {
+            ShareBy::Uid => QuotaKey::Uid(std::fs::metadata(format!("/proc/{}", pid))?.uid()),
+            ShareBy::Cgroup => {
+                let cgroups = std::fs::read_to_string(format!("/proc/{}/cgroup", pid))?;
+                // Each line is `hierarchy-ID:controller-list:cgroup-path`, the unified hierarchy
+                // of cgroup v2 has the ID 0.
+                let path = cgroups
+                    .lines()
+                    .find(|line| line.starts_with("0::"))
+                    .or_else(|| cgroups.lines().next())
+                    .and_then(|line| line.splitn(3, ':').nth(2))
+                    .ok_or_else(|| anyhow!("no cgroup found for process {}", pid))?;
+                QuotaKey::Cgroup(path.to_owned())
+            }
+        };
+        Ok(key)
+    }
+}
+
+/// The tokens shared by the engines of all processes with the same [`QuotaKey`]. A request takes
+/// a token from both its bucket in the engine and the quota.
+pub(crate) struct SharedQuota {
+    tokens: spin::Mutex<Tokens>,
+}
+
+impl SharedQuota {
+    pub(crate) fn new(limits: Limits) -> Self {
+        SharedQuota {
+            tokens: spin::Mutex::new(Tokens::new(limits)),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn try_acquire(&self) -> bool {
+        let mut tokens = self.tokens.lock();
+        tokens.add_tokens(Instant::now());
+        if tokens.has_token() {
+            tokens.num_tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn set_limits(&self, limits: Limits) {
+        self.tokens.lock().limits = limits;
+    }
+}
+
+/// The quotas of the addon, each is dropped once no engine uses it.
+pub(crate) type QuotaManager = SharedStateManager<SharedQuota, QuotaKey>;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bucket::TokenBucket;
+
+    use super::*;
+
+    fn limits(requests_per_sec: u64, bucket_size: u64) -> Limits {
+        Limits {
+            requests_per_sec,
+            bucket_size,
+        }
+    }
+
+    #[test]
+    fn test_shared_quota() {
+        // no token is added over time
+        let quota = SharedQuota::new(limits(0, 3));
+        let mut first = TokenBucket::new(limits(0, 2));
+        let mut second = TokenBucket::new(limits(0, 2));
+
+        assert!(first.try_acquire(Some(&quota)));
+        assert!(first.try_acquire(Some(&quota)));
+        assert!(second.try_acquire(Some(&quota)));
+        // the quota has run out, though the second bucket has a token
+        assert!(!second.try_acquire(Some(&quota)));
+        assert_eq!(second.tokens.num_tokens, 1.0);
+        assert!(second.try_acquire(None));
+    }
+
+    #[test]
+    fn test_set_limits() {
+        let quota = SharedQuota::new(limits(0, 1));
+        assert!(quota.try_acquire());
+        assert!(!quota.try_acquire());
+
+        // tokens are added at the new rate
+        quota.set_limits(limits(1_000_000_000, 1));
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(quota.try_acquire());
+
+        quota.set_limits(limits(0, 1));
+        assert!(!quota.try_acquire());
+    }
+
+    #[test]
+    fn test_quota_key() {
+        let pid = Pid::this();
+        assert_eq!(
+            QuotaKey::of_process(pid, ShareBy::Uid).unwrap(),
+            QuotaKey::Uid(nix::unistd::geteuid().as_raw())
+        );
+        match QuotaKey::of_process(pid, ShareBy::Cgroup).unwrap() {
+            QuotaKey::Cgroup(path) => assert!(path.starts_with('/')),
+            key => panic!("unexpected quota key: {:?}", key),
+        }
+
+        // the process has gone
+        assert!(QuotaKey::of_process(Pid::from_raw(i32::MAX), ShareBy::Uid).is_err());
+    }
+}
@@ -12,13 +12,16 @@ use fnv::FnvHashMap;
 use futures::future::BoxFuture;
 use slab::Slab;
 
-use mrpc_marshal::{ExcavateContext, SgE, SgList};
+use mrpc_marshal::{AddressArbiter, ExcavateContext, SgE, SgList, UnmarshalError};
 use phoenix_api::engine::SchedulingMode;
 use phoenix_api::net;
-use phoenix_api::rpc::{MessageMeta, RpcId, RpcMsgType, TransportStatus};
+use phoenix_api::rpc::{
+    MessageMeta, RpcId, RpcMetadata, RpcMsgType, StatusCode, TransportStatus, RESOURCE_EXHAUSTED,
+};
 use phoenix_api::{AsHandle, Handle};
 use phoenix_api_mrpc::cmd;
 use phoenix_api_mrpc::cmd::{ConnectResponse, ReadHeapRegion};
+use phoenix_api_mrpc::control_plane::BufferSetting;
 use phoenix_api_rpc_adapter::control_plane;
 use phoenix_mrpc::unpack::UnpackFromSgE;
 use phoenix_salloc::state::State as SallocState;
@@ -27,7 +30,7 @@ use transport_rdma::ops::Ops;
 use phoenix_common::engine::datapath::message::{
     EngineRxMessage, EngineTxMessage, RpcMessageRx, RpcMessageTx,
 };
-use phoenix_common::engine::datapath::meta_pool::{MetaBuffer, MetaBufferPtr};
+use phoenix_common::engine::datapath::meta_pool::{MetaBuffer, MetaBufferPtr, MetaHeader};
 use phoenix_common::engine::datapath::DataPathNode;
 use phoenix_common::engine::{future, Decompose, Engine, EngineResult, Indicator, Vertex};
 use phoenix_common::envelop::ResourceDowncast;
@@ -36,14 +39,17 @@ use phoenix_common::module::{ModuleCollection, Version};
 use phoenix_common::storage::{ResourceCollection, SharedStorage};
 use phoenix_common::{log, tracing};
 
-use super::pool::BufferSlab;
+use super::pool::{BufferSlab, RECV_BUFFER_ALIGN};
 use super::serialization::SerializationEngine;
-use super::state::{ConnectionContext, ReqContext, State, WrContext};
+use super::state::{ConnectionContext, State, WrContext};
 use super::ulib;
 use super::{ControlPathError, DatapathError};
 
 pub(crate) const MAX_INLINE_DATA: usize = 128;
 
+/// The wr_id of a cancel. Cancels are sent inline and nobody waits for their completions.
+const CANCEL_WR_ID: u64 = u64::MAX;
+
 thread_local! {
     /// To emulate a thread local storage (TLS). This should be called engine-local-storage (ELS).
     pub(crate) static ELS: RefCell<Option<&'static TlStorage>> = RefCell::new(None);
@@ -62,6 +68,8 @@ pub(crate) struct RpcAdapterEngine {
 
     // shared completion queue model
     pub(crate) local_buffer: VecDeque<RpcMessageTx>,
+    // the cancels waiting for a credit
+    pub(crate) cancel_buffer: VecDeque<MessageMeta>,
 
     // the number of pending receives that are going on. this can avoid the runtime from sleeping
     pub(crate) pending_recv: usize,
@@ -89,6 +97,9 @@ pub(crate) struct RpcAdapterEngine {
 
     // NOTE: Hold salloc State to prevent early dropping of send heap.
     pub(crate) salloc: SallocState,
+
+    // the receive buffers and the maximum message size of the connections
+    pub(crate) buffer_setting: BufferSetting,
 }
 
 impl_vertex_for_engine!(RpcAdapterEngine, node);
@@ -127,6 +138,10 @@ impl Decompose for RpcAdapterEngine {
                 "local_buffer".to_string(),
                 Box::new(ptr::read(&engine.local_buffer)),
             );
+            collections.insert(
+                "cancel_buffer".to_string(),
+                Box::new(ptr::read(&engine.cancel_buffer)),
+            );
             collections.insert(
                 "pending_recv".to_string(),
                 Box::new(ptr::read(&engine.pending_recv)),
@@ -147,6 +162,10 @@ impl Decompose for RpcAdapterEngine {
                 Box::new(ptr::read(&engine.wc_read_buffer)),
             );
             collections.insert("salloc".to_string(), Box::new(ptr::read(&engine.salloc)));
+            collections.insert(
+                "buffer_setting".to_string(),
+                Box::new(ptr::read(&engine.buffer_setting)),
+            );
             // don't call the drop function
             ptr::read(&engine.node)
         };
@@ -192,6 +211,13 @@ impl RpcAdapterEngine {
             .unwrap()
             .downcast::<VecDeque<RpcMessageTx>>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        // absent when upgrading from a version that sent cancels without credits
+        let cancel_buffer = match local.remove("cancel_buffer") {
+            Some(cancel_buffer) => *cancel_buffer
+                .downcast::<VecDeque<MessageMeta>>()
+                .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?,
+            None => VecDeque::new(),
+        };
         let recv_mr_usage = *local
             .remove("recv_mr_usage")
             .unwrap()
@@ -232,12 +258,18 @@ impl RpcAdapterEngine {
             .unwrap()
             .downcast::<SallocState>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        let buffer_setting = *local
+            .remove("buffer_setting")
+            .unwrap()
+            .downcast::<BufferSetting>()
+            .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
 
         let engine = RpcAdapterEngine {
             state,
             odp_mr,
             tls,
             local_buffer,
+            cancel_buffer,
             pending_recv,
             recv_mr_usage,
             serialization_engine,
@@ -250,6 +282,7 @@ impl RpcAdapterEngine {
             rpc_ctx,
             wc_read_buffer,
             salloc,
+            buffer_setting,
         };
         Ok(engine)
     }
@@ -403,6 +436,22 @@ impl RpcAdapterEngine {
     }
 }
 
+/// Takes the [`RpcMetadata`] that goes before the message off the SgList, returning its app and
+/// backend addresses.
+fn unmarshal_metadata<A: AddressArbiter>(
+    ctx: &mut ExcavateContext<A>,
+) -> Result<(usize, usize), UnmarshalError> {
+    let sge = ctx.sgl.next().ok_or(UnmarshalError::SgListUnderflow)?;
+    if sge.len != mem::size_of::<RpcMetadata>() {
+        return Err(UnmarshalError::SgELengthMismatch {
+            expected: mem::size_of::<RpcMetadata>(),
+            actual: sge.len,
+        });
+    }
+    let addr_app = ctx.addr_arbiter.query_app_addr(sge.ptr)?;
+    Ok((addr_app, sge.ptr))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum RpcStrategy {
     /// The entire message is encapuslated into one message, transmitted with one send/recv
@@ -411,6 +460,26 @@ enum RpcStrategy {
     Standard,
 }
 
+/// Returns `true` if the client sends the message. Such a message takes credits for the receive
+/// buffers it uses on the server, and the message that [finishes its call][finishes_call] returns
+/// the credits taken by all messages of the call.
+#[inline]
+fn takes_credits(meta: &MessageMeta) -> bool {
+    matches!(
+        meta.msg_type,
+        RpcMsgType::Request | RpcMsgType::StreamRequest | RpcMsgType::StreamRequestEnd
+    )
+}
+
+/// Returns `true` if the message is the last one the server sends for a call.
+#[inline]
+fn finishes_call(meta: &MessageMeta) -> bool {
+    matches!(
+        meta.msg_type,
+        RpcMsgType::Response | RpcMsgType::StreamResponseEnd
+    )
+}
+
 impl RpcAdapterEngine {
     fn get_or_init_odp_mr(
         &mut self,
@@ -448,20 +517,16 @@ impl RpcAdapterEngine {
     ) -> Result<Status, DatapathError> {
         use ulib::uverbs::SendFlags;
 
-        let call_id = unsafe { &*meta_buf_ptr.as_meta_ptr() }.call_id;
-        let msg_type = unsafe { &*meta_buf_ptr.as_meta_ptr() }.msg_type;
+        let meta_ref = unsafe { &*meta_buf_ptr.as_meta_ptr() };
+        let call_id = meta_ref.call_id;
+        let takes_credits = takes_credits(meta_ref);
         let cmid = &conn_ctx.cmid;
         // let ctx = RpcId::new(cmid.as_handle(), call_id).encode_u64();
         let ctx = self.rpc_ctx.insert(RpcId::new(cmid.as_handle(), call_id));
 
-        // TODO(cjr): XXX, this credit implementation has big flaws
-        if msg_type == RpcMsgType::Request {
-            conn_ctx.credit.fetch_sub(1, Ordering::AcqRel);
+        if takes_credits {
+            conn_ctx.take_credits(call_id.without_stream_seq(), 1);
             self.pending_recv += 1;
-            conn_ctx
-                .outstanding_req
-                .lock()
-                .push_back(ReqContext { call_id, sg_len: 1 });
         }
 
         let off = meta_buf_ptr.0.as_ptr().expose_addr();
@@ -521,16 +586,9 @@ impl RpcAdapterEngine {
         let call_id = meta_ref.call_id;
         let cmid = &conn_ctx.cmid;
 
-        // TODO(cjr): XXX, this credit implementation has some issues
-        if meta_ref.msg_type == RpcMsgType::Request {
-            conn_ctx
-                .credit
-                .fetch_sub(sglist.0.len() + 1, Ordering::AcqRel);
+        if takes_credits(meta_ref) {
+            conn_ctx.take_credits(call_id.without_stream_seq(), sglist.0.len() + 1);
             self.pending_recv += sglist.0.len() + 1;
-            conn_ctx.outstanding_req.lock().push_back(ReqContext {
-                call_id,
-                sg_len: sglist.0.len() + 1,
-            });
         }
 
         // Sender posts send requests from the SgList
@@ -582,6 +640,49 @@ impl RpcAdapterEngine {
         Ok(Progress(1))
     }
 
+    /// Sends a cancel, which takes a credit for the receive buffer it uses on the server. The
+    /// credit is returned along with those of the call by the `cancelled` reply of the server.
+    /// Returns `Progress(0)` if the cancel has to wait for a credit.
+    fn send_cancel(&mut self, meta: MessageMeta) -> Result<Status, DatapathError> {
+        use ulib::uverbs::SendFlags;
+
+        let conn_ctx = match self.state.local_resource().cmid_table.get(&meta.conn_id) {
+            Ok(conn_ctx) => conn_ctx,
+            Err(_) => {
+                // the connection has gone, and so has the call
+                return Ok(Progress(1));
+            }
+        };
+
+        if !conn_ctx.outstanding_req.lock().contains_key(&meta.call_id) {
+            // the call has finished, there is nothing to cancel
+            return Ok(Progress(1));
+        }
+        // cancels may use the credits that are held back from new messages, as they help to
+        // free the receive buffers of the server
+        if conn_ctx.credit.load(Ordering::Acquire) == 0 {
+            self.cancel_buffer.push_front(meta);
+            return Ok(Progress(0));
+        }
+        conn_ctx.take_credits(meta.call_id, 1);
+        self.pending_recv += 1;
+
+        let header = MetaHeader::new(meta);
+        let off = (&header as *const MetaHeader).expose_addr();
+        let odp_mr = self.odp_mr.as_mut().unwrap();
+        // the header is copied at posting time as it is sent inline
+        unsafe {
+            conn_ctx.cmid.post_send_with_imm(
+                odp_mr,
+                off..off + MetaHeader::len(),
+                CANCEL_WR_ID,
+                SendFlags::INLINE | SendFlags::SIGNALED,
+                0,
+            )?;
+        }
+        Ok(Progress(1))
+    }
+
     fn check_input_queue(&mut self) -> Result<Status, DatapathError> {
         use phoenix_common::engine::datapath::TryRecvError;
 
@@ -606,6 +707,7 @@ impl RpcAdapterEngine {
                         // timer.tick();
                         // log::info!("ReclaimRecvBuf: {}", timer);
                     }
+                    EngineTxMessage::Cancel(meta) => self.cancel_buffer.push_back(meta),
                 }
                 return Ok(Progress(1));
             }
@@ -613,7 +715,14 @@ impl RpcAdapterEngine {
             Err(TryRecvError::Disconnected) => return Ok(Status::Disconnected),
         }
 
+        // the cancels go before the messages
+        if let Some(meta) = self.cancel_buffer.pop_front() {
+            return self.send_cancel(meta);
+        }
+
         if let Some(msg) = self.local_buffer.pop_front() {
+            // tell the peer whether the metadata goes before the message
+            unsafe { (*msg.meta_buf_ptr.as_meta_ptr()).has_metadata = msg.metadata_backend != 0 };
             // SAFETY: don't know what kind of UB can be triggered
             let meta_ref = unsafe { &*msg.meta_buf_ptr.as_meta_ptr() };
             let cmid_handle = meta_ref.conn_id;
@@ -628,13 +737,48 @@ impl RpcAdapterEngine {
             }
             // let mut timer = crate::timer::Timer::new();
 
-            let sglist = if let Some(ref module) = self.serialization_engine {
+            let mut sglist = if meta_ref.status_code == StatusCode::ServerError
+                || meta_ref.msg_type.is_stream_end()
+            {
+                mrpc_marshal::status::marshal(msg.addr_backend).unwrap()
+            } else if let Some(ref module) = self.serialization_engine {
                 module.marshal(meta_ref, msg.addr_backend).unwrap()
             } else {
                 panic!("dispatch module not loaded");
             };
+            if let Some(metadata) = msg.metadata_ptr() {
+                sglist.0.insert(
+                    0,
+                    SgE {
+                        ptr: metadata.as_ptr().expose_addr(),
+                        len: mem::size_of::<RpcMetadata>(),
+                    },
+                );
+            }
             // timer.tick();
 
+            // the sges are not split, each of them must fit in a receive buffer of the peer
+            let peer_buffers = &conn_ctx.peer_buffers;
+            let msg_size: usize = sglist.0.iter().map(|sge| sge.len).sum();
+            if msg_size > self.buffer_setting.max_message_size
+                || msg_size > peer_buffers.max_message_size
+                || sglist
+                    .0
+                    .iter()
+                    .any(|sge| sge.len > peer_buffers.recv_buffer_size)
+            {
+                log::warn!(
+                    "message of {} bytes does not fit the receive buffers",
+                    msg_size
+                );
+                let rpc_id = RpcId::new(meta_ref.conn_id, meta_ref.call_id);
+                let status = TransportStatus::rejected(RESOURCE_EXHAUSTED);
+                self.rx_outputs()[0]
+                    .send(EngineRxMessage::Ack(rpc_id, status))
+                    .unwrap();
+                return Ok(Progress(1));
+            }
+
             // TODO(cjr): Examine the SgList and optimize for small messages
             let status = match Self::choose_strategy(&sglist) {
                 RpcStrategy::Fused => self.send_fused(&conn_ctx, msg.meta_buf_ptr, &sglist)?,
@@ -685,7 +829,7 @@ impl RpcAdapterEngine {
         &mut self,
         sgl: SgList,
         conn_ctx: Arc<ConnectionContext>,
-    ) -> Result<RpcId, DatapathError> {
+    ) -> Result<Option<RpcId>, DatapathError> {
         // log::debug!("unmarshal_and_deliver_up, sgl: {:0x?}", sgl);
 
         // let mut timer = crate::timer::Timer::new();
@@ -695,17 +839,19 @@ impl RpcAdapterEngine {
         meta.conn_id = conn_ctx.cmid.as_handle();
 
         let recv_id = RpcId(meta.conn_id, meta.call_id);
+        if meta.msg_type == RpcMsgType::Cancel {
+            self.rx_outputs()[0]
+                .send(EngineRxMessage::Cancel(recv_id))
+                .unwrap();
+            return Ok(None);
+        }
 
         // timer.tick();
         // replenish the credits
-        if meta.msg_type == RpcMsgType::Response {
-            let call_id = meta.call_id;
-            let mut outstanding_req = conn_ctx.outstanding_req.lock();
-            let req_ctx = outstanding_req.pop_front().unwrap();
-            assert_eq!(call_id, req_ctx.call_id);
-            conn_ctx.credit.fetch_add(req_ctx.sg_len, Ordering::AcqRel);
-            self.pending_recv -= req_ctx.sg_len;
-            drop(outstanding_req);
+        if finishes_call(meta) {
+            // calls finish in any order, e.g., a unary call issued during a server stream
+            // finishes first
+            self.pending_recv -= conn_ctx.return_credits(meta.call_id.without_stream_seq());
         }
         // timer.tick();
 
@@ -714,17 +860,28 @@ impl RpcAdapterEngine {
             addr_arbiter: &self.state.local_resource().addr_map,
         };
 
-        let (addr_app, addr_backend) = if let Some(ref module) = self.serialization_engine {
-            module.unmarshal(meta, &mut excavate_ctx).unwrap()
+        let (metadata_app, metadata_backend) = if meta.has_metadata {
+            unmarshal_metadata(&mut excavate_ctx).unwrap()
         } else {
-            panic!("dispatch module not loaded");
+            (0, 0)
         };
+
+        let (addr_app, addr_backend) =
+            if meta.status_code == StatusCode::ServerError || meta.msg_type.is_stream_end() {
+                unsafe { mrpc_marshal::status::unmarshal(&mut excavate_ctx) }.unwrap()
+            } else if let Some(ref module) = self.serialization_engine {
+                module.unmarshal(meta, &mut excavate_ctx).unwrap()
+            } else {
+                panic!("dispatch module not loaded");
+            };
         // timer.tick();
 
         let msg = RpcMessageRx {
             meta: meta_ptr,
             addr_backend,
             addr_app,
+            metadata_app,
+            metadata_backend,
         };
 
         self.rx_outputs()[0]
@@ -734,7 +891,7 @@ impl RpcAdapterEngine {
         // timer.tick();
         // log::info!("unmarshal_and_deliver_up {}", timer);
 
-        Ok(recv_id)
+        Ok(Some(recv_id))
     }
 
     fn check_transport_service(&mut self) -> Result<Status, DatapathError> {
@@ -763,7 +920,9 @@ impl RpcAdapterEngine {
                     match wc.opcode {
                         WcOpcode::Send => {
                             // send completed,  do nothing
-                            if wc.wc_flags.contains(WcFlags::WITH_IMM) {
+                            if wc.wr_id == CANCEL_WR_ID {
+                                // a cancel has been sent, nobody waits for its ack
+                            } else if wc.wc_flags.contains(WcFlags::WITH_IMM) {
                                 tracing::trace!("post_send_imm completed, wr_id={}", wc.wr_id);
                                 // let rpc_id = RpcId::decode_u64(wc.wr_id);
                                 let rpc_id = self.rpc_ctx.remove(wc.wr_id as usize);
@@ -819,9 +978,20 @@ impl RpcAdapterEngine {
                                 // timer.tick();
 
                                 // 60-70ns
-                                // keep them outstanding because they will be used by the user
-                                self.recv_mr_usage
-                                    .insert(recv_id, recv_ctx.recv_buffer_handles);
+                                match recv_id {
+                                    Some(recv_id) => {
+                                        // keep them outstanding because they will be used by the user
+                                        self.recv_mr_usage
+                                            .insert(recv_id, recv_ctx.recv_buffer_handles);
+                                    }
+                                    None => {
+                                        // a cancel has been consumed, repost the buffers right away
+                                        self.reclaim_recv_buffers(
+                                            &conn_ctx.cmid,
+                                            &recv_ctx.recv_buffer_handles,
+                                        )?;
+                                    }
+                                }
                                 // timer.tick();
                                 // log::info!("check_transport_service: {}", timer);
                             }
@@ -842,6 +1012,9 @@ impl RpcAdapterEngine {
                         // this is a recv operation. don't know the rpc_id
                         let conn_id = wr_ctx.conn_id;
                         EngineRxMessage::RecvError(conn_id, TransportStatus::Error(code))
+                    } else if wc.wr_id == CANCEL_WR_ID {
+                        // nobody waits for the result of a cancel
+                        continue;
                     } else {
                         // let rpc_id = RpcId::decode_u64(wc.wr_id);
                         let rpc_id = self.rpc_ctx.remove(wc.wr_id as usize);
@@ -892,12 +1065,20 @@ impl RpcAdapterEngine {
         match ret {
             None => Ok(Status::Progress(0)),
             Some(mut builder) => {
+                let peer_buffers = match BufferSetting::decode(builder.private_data()) {
+                    Some(peer_buffers) => peer_buffers,
+                    None => {
+                        log::warn!("rejecting a connect request without valid receive buffers");
+                        builder.destroy();
+                        return Ok(Status::Progress(1));
+                    }
+                };
                 let cq = self.state.get_or_init_cq(2048, 0, &builder)?;
                 let mut pre_id = builder
                     .set_send_cq(cq)
                     .set_recv_cq(cq)
                     .set_max_send_wr(128)
-                    .set_max_recv_wr(128)
+                    .set_max_recv_wr(self.buffer_setting.num_recv_buffers as _)
                     .set_max_inline_data(MAX_INLINE_DATA as _)
                     .build()?;
 
@@ -908,11 +1089,12 @@ impl RpcAdapterEngine {
                 self.state
                     .resource()
                     .staging_pre_cmid_table
-                    .insert(handle, pre_id)?;
+                    .insert(handle, (pre_id, peer_buffers))?;
                 // pass these resources back to the user
                 let conn_resp = ConnectResponse {
                     conn_handle: handle,
                     read_regions,
+                    peer_buffers,
                 };
                 let comp = cmd::Completion(Ok(cmd::CompletionKind::NewConnectionInternal(
                     conn_resp, fds,
@@ -923,23 +1105,36 @@ impl RpcAdapterEngine {
         }
     }
 
+    /// Returns the receive buffers of a new connection. The messages are not split into
+    /// segments, so a receive buffer holds the largest message.
+    fn local_buffers(&self) -> BufferSetting {
+        BufferSetting {
+            recv_buffer_size: self
+                .buffer_setting
+                .recv_buffer_size
+                .max(self.buffer_setting.max_message_size),
+            ..self.buffer_setting
+        }
+    }
+
     fn prepare_recv_buffers(
         &mut self,
         pre_id: &mut ulib::ucm::PreparedCmId,
     ) -> Result<(Vec<ReadHeapRegion>, Vec<RawFd>), ControlPathError> {
-        // create 128 receive mrs, post recv requests
+        // create receive mrs, post recv requests
+        let setting = self.local_buffers();
         let slab = BufferSlab::new(
-            128,
-            8 * 1024 * 1024,
-            8 * 1024 * 1024,
+            setting.num_recv_buffers,
+            setting.recv_buffer_size,
+            RECV_BUFFER_ALIGN,
             &self.salloc.addr_mediator,
         )?;
 
         // post receives
-        for _ in 0..128 {
+        for _ in 0..setting.num_recv_buffers {
             let odp_mr = self.get_or_init_odp_mr(pre_id);
 
-            // This is fine because we just allocated 128 buffers there
+            // This is fine because we just allocated these buffers there
             let recv_buffer = slab.obtain().unwrap();
 
             let handle = recv_buffer.as_handle();
@@ -983,6 +1178,11 @@ impl RpcAdapterEngine {
     async fn check_input_cmd_queue(&mut self) -> Result<Status, ControlPathError> {
         use tokio::sync::mpsc::error::TryRecvError;
         match self.cmd_rx.try_recv() {
+            Ok(cmd::Command::SetBuffersInner(setting)) => {
+                // the service does not wait for a reply
+                self.buffer_setting = setting;
+                Ok(Progress(1))
+            }
             Ok(req) => {
                 let result = self.process_cmd(&req).await;
                 match result {
@@ -1009,7 +1209,7 @@ impl RpcAdapterEngine {
                 // create CmIdBuilder
                 let mut builder = ulib::ucm::CmIdBuilder::new()
                     .set_max_send_wr(128)
-                    .set_max_recv_wr(128)
+                    .set_max_recv_wr(self.buffer_setting.num_recv_buffers as _)
                     .set_max_inline_data(MAX_INLINE_DATA as u32)
                     .resolve_route(addr)
                     .await?;
@@ -1022,15 +1222,20 @@ impl RpcAdapterEngine {
 
                 // prepare and post receive buffers
                 let (read_regions, fds) = self.prepare_recv_buffers(&mut pre_id)?;
-                // connect
-                let id = pre_id.connect(None).await?;
+                // connect, the two ends exchange their receive buffers
+                let local_buffers = self.local_buffers().encode();
+                let conn_param = ulib::uverbs::ConnParam::with_private_data(&local_buffers);
+                let (id, private_data) = pre_id.connect(Some(&conn_param)).await?;
                 let handle = id.as_handle();
+                let peer_buffers =
+                    BufferSetting::decode(&private_data).ok_or(ControlPathError::PeerBuffers)?;
 
                 // insert resources after connection establishment
-                self.state.local_resource().insert_cmid(id, 128)?;
+                self.state.local_resource().insert_cmid(id, peer_buffers)?;
                 let conn_resp = ConnectResponse {
                     conn_handle: handle,
                     read_regions,
+                    peer_buffers,
                 };
                 Ok(cmd::CompletionKind::ConnectInternal(conn_resp, fds))
             }
@@ -1059,16 +1264,20 @@ impl RpcAdapterEngine {
                         .insert_addr_map(mr_local_addr, mr_remote_mapped)?;
                 }
                 // finish the last step to establish a connection
-                if let Ok(Some(pre_id)) = self
+                if let Ok(Some(staging)) = self
                     .state
                     .resource()
                     .staging_pre_cmid_table
                     .close_resource(conn_handle)
                 {
-                    // accept connection after we get the AddrMap updated
-                    let id = Arc::try_unwrap(pre_id).unwrap().accept(None).await?;
+                    let (pre_id, peer_buffers) = Arc::try_unwrap(staging).unwrap();
+                    // accept connection after we get the AddrMap updated, and tell the peer
+                    // about the receive buffers
+                    let local_buffers = self.local_buffers().encode();
+                    let conn_param = ulib::uverbs::ConnParam::with_private_data(&local_buffers);
+                    let id = pre_id.accept(Some(&conn_param)).await?;
                     // insert resources after connection establishment
-                    self.state.local_resource().insert_cmid(id, 128)?;
+                    self.state.local_resource().insert_cmid(id, peer_buffers)?;
                 }
                 Ok(cmd::CompletionKind::NewMappedAddrs)
             }
@@ -1084,6 +1293,9 @@ impl RpcAdapterEngine {
             cmd::Command::MultiConnect(_) => {
                 unreachable!();
             }
+            cmd::Command::SetBuffersInner(_) => {
+                unreachable!();
+            }
         }
     }
 }
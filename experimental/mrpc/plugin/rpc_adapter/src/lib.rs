@@ -40,6 +40,8 @@ pub(crate) enum ControlPathError {
     SharedRegion(#[from] region::Error),
     #[error("{0}")]
     InsertAddrMap(#[from] mrpc_marshal::AddressExists),
+    #[error("The peer did not send valid receive buffers")]
+    PeerBuffers,
 
     // Below are errors that does not return to the user.
     #[error("Send command error")]
@@ -101,6 +101,7 @@ impl RpcAdapterEngineBuilder {
             tls: Box::new(TlStorage { ops: self.ops }),
             pending_recv: 0,
             local_buffer: VecDeque::new(),
+            cancel_buffer: VecDeque::new(),
             cmd_tx: self.cmd_tx,
             cmd_rx: self.cmd_rx,
             node: self.node,
@@ -111,6 +112,7 @@ impl RpcAdapterEngineBuilder {
             rpc_ctx: slab::Slab::with_capacity(128),
             wc_read_buffer: Vec::with_capacity(BUF_LEN),
             salloc: salloc_state,
+            buffer_setting: Default::default(),
         })
     }
 }
@@ -6,6 +6,7 @@ use bitvec::bitvec;
 use bitvec::vec::BitVec;
 
 use phoenix_api::{AsHandle, Handle};
+use phoenix_api_mrpc::control_plane::BufferSetting;
 
 use phoenix_salloc::region::{AddressMediator, SharedRegion};
 
@@ -13,6 +14,9 @@ use phoenix_common::resource::Error as ResourceError;
 
 use super::ControlPathError;
 
+/// The alignment of receive buffers.
+pub(crate) const RECV_BUFFER_ALIGN: usize = 4096;
+
 /// A reference handed by `BufferPool`, pointed to one particular memory segment in one of the
 /// backing storage of `BufferPool`. Multiple `RecvBuffer`s cannot overlap with each other.
 pub(crate) struct RecvBuffer {
@@ -76,7 +80,8 @@ impl BufferSlab {
         );
         assert!(buffer_align % 4096 == 0, "buffer_align: {buffer_align}");
 
-        let buffer_size = buffer_size.max(buffer_align);
+        // round up so that every buffer is aligned
+        let buffer_size = (buffer_size + buffer_align - 1) & !(buffer_align - 1);
         let total_size = num_buffers * buffer_size;
 
         // allocate a SharedRegion
@@ -139,7 +144,7 @@ impl BufferPool {
         self.slabs.lock().push(slab);
     }
 
-    pub(crate) fn obtain(&self) -> RecvBuffer {
+    pub(crate) fn obtain(&self, setting: &BufferSetting) -> RecvBuffer {
         for slab in self.slabs.lock().iter() {
             if let Some(ret) = slab.obtain() {
                 return ret;
@@ -148,9 +153,24 @@ impl BufferPool {
 
         // replenish a slab
         self.replenish(
-            BufferSlab::new(128, 8 * 1024 * 1024, 8 * 1024 * 1024, &self.addr_mediator).unwrap(),
+            BufferSlab::new(
+                setting.num_recv_buffers,
+                setting.recv_buffer_size,
+                RECV_BUFFER_ALIGN,
+                &self.addr_mediator,
+            )
+            .unwrap(),
         );
-        self.obtain()
+        self.obtain(setting)
+    }
+
+    /// Obtains a buffer from the slab whose backing storage is `storage`.
+    pub(crate) fn obtain_from(&self, storage: &Handle) -> Option<RecvBuffer> {
+        self.slabs
+            .lock()
+            .iter()
+            .find(|s| &s.storage.as_handle() == storage)
+            .and_then(|s| s.obtain())
     }
 
     pub(crate) fn release(&self, recv_buf: RecvBuffer) {
@@ -4,12 +4,13 @@ use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use dashmap::DashMap;
-use fnv::FnvBuildHasher;
+use fnv::{FnvBuildHasher, FnvHashMap};
 use nix::unistd::Pid;
 
 use mrpc_marshal::SgList;
 use phoenix_api::rpc::CallId;
 use phoenix_api::AsHandle;
+use phoenix_api_mrpc::control_plane::BufferSetting;
 
 use phoenix_salloc::region::AddressMediator;
 
@@ -82,13 +83,6 @@ pub(crate) struct WrContext {
     pub(crate) buffer_addr: usize,
 }
 
-#[derive(Debug)]
-
-pub(crate) struct ReqContext {
-    pub(crate) call_id: CallId,
-    pub(crate) sg_len: usize,
-}
-
 #[derive(Debug, Default)]
 pub(crate) struct RecvContext {
     // buffer for recevied sges
@@ -100,21 +94,39 @@ pub(crate) struct RecvContext {
 #[derive(Debug)]
 pub(crate) struct ConnectionContext {
     pub(crate) cmid: ulib::ucm::CmId,
+    // the receive buffers of the peer
+    pub(crate) peer_buffers: BufferSetting,
     pub(crate) credit: AtomicUsize,
-    // call_id, sg_len
-    pub(crate) outstanding_req: spin::Mutex<VecDeque<ReqContext>>,
+    // call_id -> the credits taken by the messages of the call
+    pub(crate) outstanding_req: spin::Mutex<FnvHashMap<CallId, usize>>,
     pub(crate) receiving_ctx: spin::Mutex<RecvContext>,
 }
 
 impl ConnectionContext {
-    pub(crate) fn new(cmid: ulib::ucm::CmId, credit: usize) -> Self {
+    pub(crate) fn new(cmid: ulib::ucm::CmId, peer_buffers: BufferSetting) -> Self {
         Self {
             cmid,
-            credit: AtomicUsize::new(credit),
-            outstanding_req: spin::Mutex::new(VecDeque::new()),
+            peer_buffers,
+            // each credit is a receive buffer of the peer
+            credit: AtomicUsize::new(peer_buffers.num_recv_buffers),
+            outstanding_req: spin::Mutex::new(FnvHashMap::default()),
             receiving_ctx: spin::Mutex::new(RecvContext::default()),
         }
     }
+
+    /// Takes `n` credits for a message of the call `call_id` that uses `n` receive buffers of
+    /// the peer.
+    pub(crate) fn take_credits(&self, call_id: CallId, n: usize) {
+        self.credit.fetch_sub(n, Ordering::AcqRel);
+        *self.outstanding_req.lock().entry(call_id).or_insert(0) += n;
+    }
+
+    /// Returns the credits taken by the call once it finishes. Returns the number of credits.
+    pub(crate) fn return_credits(&self, call_id: CallId) -> usize {
+        let n = self.outstanding_req.lock().remove(&call_id).unwrap_or(0);
+        self.credit.fetch_add(n, Ordering::AcqRel);
+        n
+    }
 }
 
 pub struct LocalResource {
@@ -144,10 +156,10 @@ impl LocalResource {
     pub(crate) fn insert_cmid(
         &self,
         cmid: ulib::ucm::CmId,
-        credit: usize,
+        peer_buffers: BufferSetting,
     ) -> Result<(), ResourceError> {
         self.cmid_table
-            .insert(cmid.as_handle(), ConnectionContext::new(cmid, credit))
+            .insert(cmid.as_handle(), ConnectionContext::new(cmid, peer_buffers))
     }
 }
 
@@ -159,7 +171,9 @@ pub struct Resource {
         VecDeque<ulib::ucm::CmIdBuilder<'static, 'static, 'static, 'static, 'static>>,
         FnvBuildHasher,
     >,
-    pub(crate) staging_pre_cmid_table: ResourceTable<ulib::ucm::PreparedCmId>,
+    // the accepted connections and the receive buffers of their peers, until the address
+    // mappings are updated
+    pub(crate) staging_pre_cmid_table: ResourceTable<(ulib::ucm::PreparedCmId, BufferSetting)>,
     // (rpc_adapter_id, CmIdListener)
     pub(crate) listener_table: ResourceTable<(usize, ulib::ucm::CmIdListener)>,
 
@@ -22,6 +22,8 @@ pub(crate) struct CmIdBuilder<'pd, 'ctx, 'scq, 'rcq, 'srq> {
     pd: Option<&'pd ProtectionDomain>,
     qp_init_attr: QpInitAttr<'ctx, 'scq, 'rcq, 'srq>,
     tos: Option<u8>,
+    // the private data of the connect request, empty on the active side
+    private_data: Vec<u8>,
 }
 
 impl<'pd, 'ctx, 'scq, 'rcq, 'srq> Default for CmIdBuilder<'pd, 'ctx, 'scq, 'rcq, 'srq> {
@@ -38,9 +40,20 @@ impl<'pd, 'ctx, 'scq, 'rcq, 'srq> CmIdBuilder<'pd, 'ctx, 'scq, 'rcq, 'srq> {
             pd: None,
             qp_init_attr: Default::default(),
             tos: None,
+            private_data: Vec::new(),
         }
     }
 
+    /// Returns the private data that the peer sent with the connect request.
+    pub(crate) fn private_data(&self) -> &[u8] {
+        &self.private_data
+    }
+
+    /// Destroys the CmId of a connect request instead of accepting it.
+    pub(crate) fn destroy(self) {
+        let _ = DropCmId(self.handle);
+    }
+
     pub(crate) fn set_pd(&mut self, pd: &'pd ProtectionDomain) -> &mut Self {
         self.pd = Some(pd);
         self
@@ -284,10 +297,11 @@ impl CmIdListener {
     pub(crate) async fn get_request<'pd, 'ctx, 'scq, 'rcq, 'srq>(
         &self,
     ) -> Result<CmIdBuilder<'pd, 'ctx, 'scq, 'rcq, 'srq>, Error> {
-        let cmid = get_ops().get_request(self.handle.0).await?;
+        let (cmid, private_data) = get_ops().get_request(self.handle.0).await?;
         assert!(cmid.qp.is_none());
         let mut builder = CmIdBuilder::new();
         builder.handle = cmid.handle;
+        builder.private_data = private_data;
         Ok(builder)
     }
 
@@ -295,10 +309,11 @@ impl CmIdListener {
         &self,
     ) -> Result<Option<CmIdBuilder<'pd, 'ctx, 'scq, 'rcq, 'srq>>, Error> {
         let maybe_cmid = get_ops().try_get_request(self.handle.0)?;
-        if let Some(cmid) = maybe_cmid.as_ref() {
+        if let Some((cmid, private_data)) = maybe_cmid {
             assert!(cmid.qp.is_none());
             let mut builder = CmIdBuilder::new();
             builder.handle = cmid.handle;
+            builder.private_data = private_data;
             Ok(Some(builder))
         } else {
             Ok(None)
@@ -331,17 +346,19 @@ impl PreparedCmId {
         Ok(CmId { inner: self.inner })
     }
 
+    /// Connects to the peer. Returns the connected CmId and the private data that the peer
+    /// accepts the connection with.
     pub(crate) async fn connect<'a>(
         self,
         conn_param: Option<&'a ConnParam<'a>>,
-    ) -> Result<CmId, Error> {
+    ) -> Result<(CmId, Vec<u8>), Error> {
         let conn_param = conn_param.map(|param| net::ConnParam::from_borrow(&param));
-        get_ops()
+        let private_data = get_ops()
             .connect(self.inner.handle.0, conn_param.as_ref())
             .await
             .map_err(Error::Connect)?;
         get_ops().set_rnr_timeout(self.inner.handle.0, 1)?;
-        Ok(CmId { inner: self.inner })
+        Ok((CmId { inner: self.inner }, private_data))
     }
 }
 
@@ -313,6 +313,24 @@ pub(crate) struct ConnParam<'priv_data> {
     pub(crate) qp_num: u32,
 }
 
+impl<'priv_data> ConnParam<'priv_data> {
+    /// The parameters that rdma_cm uses by default, plus the private data to send to the peer.
+    pub(crate) fn with_private_data(private_data: &'priv_data [u8]) -> Self {
+        ConnParam {
+            private_data: Some(private_data),
+            // the largest ones that the device supports
+            responder_resources: u8::MAX,
+            initiator_depth: u8::MAX,
+            flow_control: 1,
+            retry_count: 7,
+            // retry infinitely
+            rnr_retry_count: 7,
+            srq: 0,
+            qp_num: 0,
+        }
+    }
+}
+
 impl<'priv_data> FromBorrow<ConnParam<'priv_data>> for net::ConnParam {
     fn from_borrow<T: Borrow<ConnParam<'priv_data>>>(borrow: &T) -> Self {
         let b = borrow.borrow();
@@ -11,24 +11,28 @@ use fnv::FnvHashMap;
 use futures::future::BoxFuture;
 use slab::Slab;
 
-use mrpc_marshal::{ExcavateContext, SgE, SgList};
+use mrpc_marshal::status::RpcStatus;
+use mrpc_marshal::{AddressArbiter, ExcavateContext, RpcMessage, SgE, SgList, UnmarshalError};
 use phoenix_api::buf::Range;
 use phoenix_api::engine::SchedulingMode;
 use phoenix_api::net::{MappedAddrStatus, WcOpcode, WcStatus};
-use phoenix_api::rpc::{MessageMeta, RpcId, StatusCode, TransportStatus};
+use phoenix_api::rpc::{
+    MessageMeta, RpcId, RpcMetadata, RpcMsgType, StatusCode, TransportStatus, RESOURCE_EXHAUSTED,
+};
 use phoenix_api::transport::tcp::dp::Completion;
 use phoenix_api::{AsHandle, Handle};
 use phoenix_api_mrpc::cmd::{ConnectResponse, ReadHeapRegion};
+use phoenix_api_mrpc::control_plane::BufferSetting;
 use phoenix_api_tcp_rpc_adapter::control_plane;
 use phoenix_mrpc::unpack::UnpackFromSgE;
 use phoenix_salloc::state::State as SallocState;
 use transport_tcp::ops::Ops;
-use transport_tcp::ApiError;
+use transport_tcp::{ApiError, TransportError};
 
 use phoenix_common::engine::datapath::message::{
     EngineRxMessage, EngineTxMessage, RpcMessageRx, RpcMessageTx,
 };
-use phoenix_common::engine::datapath::meta_pool::{MetaBuffer, MetaBufferPtr};
+use phoenix_common::engine::datapath::meta_pool::{MetaBuffer, MetaBufferPtr, MetaHeader};
 use phoenix_common::engine::datapath::DataPathNode;
 use phoenix_common::engine::{future, Decompose, Engine, EngineResult, Indicator, Vertex};
 use phoenix_common::envelop::ResourceDowncast;
@@ -39,9 +43,9 @@ use phoenix_common::resource::Error as ResourceError;
 use phoenix_common::storage::{ResourceCollection, SharedStorage};
 
 use super::get_ops;
-use super::pool::BufferSlab;
+use super::pool::{BufferSlab, RecvBuffer, RECV_BUFFER_ALIGN};
 use super::serialization::SerializationEngine;
-use super::state::{ConnectionContext, State};
+use super::state::{ConnectionContext, PendingConnection, RecvContext, State};
 use super::{ControlPathError, DatapathError};
 
 thread_local! {
@@ -53,6 +57,22 @@ pub(crate) struct TlStorage {
     pub(crate) ops: Ops,
 }
 
+/// The wr_id of a cancel has this bit set, and the rest of the bits index into `cancel_ctx`.
+const CANCEL_WR_ID_FLAG: u64 = 1 << 63;
+/// The wr_id of a hello has this bit set, and the rest of the bits index into `hello_ctx`.
+const HELLO_WR_ID_FLAG: u64 = 1 << 62;
+
+/// The imm of the last send of a message.
+const IMM_LAST: u32 = 1;
+/// The imm of a send that carries a segment of an SgE, which continues in the next send.
+const IMM_SEGMENT: u32 = 2;
+/// The imm of the hello, the first send of each end of a connection. It carries the receive
+/// buffers of the end.
+const IMM_HELLO: u32 = 3;
+
+/// The number of reassembly buffers of a connection, each holds the segmented SgEs of a message.
+const NUM_REASSEMBLY_BUFFERS: usize = 2;
+
 pub(crate) struct TcpRpcAdapterEngine {
     // NOTE(cjr): The drop order here is important. objects in ulib first, objects in transport later.
     pub(crate) state: State,
@@ -80,6 +100,15 @@ pub(crate) struct TcpRpcAdapterEngine {
     pub(crate) indicator: Indicator,
     // pub(crate) start: std::time::Instant,
     pub(crate) rpc_ctx: Slab<RpcId>,
+    // the headers of the cancels being sent, they must stay in place until the send completes
+    pub(crate) cancel_ctx: Slab<Box<MetaHeader>>,
+    // the hellos being sent, they must stay in place until the send completes
+    pub(crate) hello_ctx: Slab<Box<[u8; BufferSetting::ENCODED_LEN]>>,
+    // the receive buffers and the maximum message size of the connections
+    pub(crate) buffer_setting: BufferSetting,
+    // the status sent in place of a reply larger than the maximum message size, it must stay in
+    // place while such replies are being sent
+    pub(crate) exhausted_status: Box<RpcStatus>,
 }
 
 impl_vertex_for_engine!(TcpRpcAdapterEngine, node);
@@ -129,6 +158,22 @@ impl Decompose for TcpRpcAdapterEngine {
             collections.insert("cmd_rx".to_string(), Box::new(ptr::read(&engine.cmd_rx)));
             collections.insert("salloc".to_string(), Box::new(ptr::read(&engine.salloc)));
             collections.insert("rpc_ctx".to_string(), Box::new(ptr::read(&engine.rpc_ctx)));
+            collections.insert(
+                "cancel_ctx".to_string(),
+                Box::new(ptr::read(&engine.cancel_ctx)),
+            );
+            collections.insert(
+                "hello_ctx".to_string(),
+                Box::new(ptr::read(&engine.hello_ctx)),
+            );
+            collections.insert(
+                "buffer_setting".to_string(),
+                Box::new(ptr::read(&engine.buffer_setting)),
+            );
+            collections.insert(
+                "exhausted_status".to_string(),
+                Box::new(ptr::read(&engine.exhausted_status)),
+            );
             // don't call the drop function
             ptr::read(&engine.node)
         };
@@ -199,6 +244,28 @@ impl TcpRpcAdapterEngine {
             .unwrap()
             .downcast::<Slab<RpcId>>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        let cancel_ctx = *local
+            .remove("cancel_ctx")
+            .unwrap()
+            .downcast::<Slab<Box<MetaHeader>>>()
+            .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        let hello_ctx = match local.remove("hello_ctx") {
+            Some(hello_ctx) => *hello_ctx
+                .downcast::<Slab<Box<[u8; BufferSetting::ENCODED_LEN]>>>()
+                .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?,
+            None => Slab::new(),
+        };
+        let buffer_setting = *local
+            .remove("buffer_setting")
+            .unwrap()
+            .downcast::<BufferSetting>()
+            .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        let exhausted_status = match local.remove("exhausted_status") {
+            Some(exhausted_status) => *exhausted_status
+                .downcast::<Box<RpcStatus>>()
+                .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?,
+            None => exhausted_status(),
+        };
 
         let engine = TcpRpcAdapterEngine {
             state,
@@ -214,11 +281,23 @@ impl TcpRpcAdapterEngine {
             salloc,
             // start: std::time::Instant::now(),
             rpc_ctx,
+            cancel_ctx,
+            hello_ctx,
+            buffer_setting,
+            exhausted_status,
         };
         Ok(engine)
     }
 }
 
+/// Returns the status the peer receives in place of a reply larger than the maximum message size.
+pub(crate) fn exhausted_status() -> Box<RpcStatus> {
+    Box::new(RpcStatus {
+        code: RESOURCE_EXHAUSTED as i32,
+        message: "the reply exceeds the maximum message size".into(),
+    })
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Status {
     Progress(usize),
@@ -338,6 +417,22 @@ impl TcpRpcAdapterEngine {
     }
 }
 
+/// Takes the [`RpcMetadata`] that goes before the message off the SgList, returning its app and
+/// backend addresses.
+fn unmarshal_metadata<A: AddressArbiter>(
+    ctx: &mut ExcavateContext<A>,
+) -> Result<(usize, usize), UnmarshalError> {
+    let sge = ctx.sgl.next().ok_or(UnmarshalError::SgListUnderflow)?;
+    if sge.len != mem::size_of::<RpcMetadata>() {
+        return Err(UnmarshalError::SgELengthMismatch {
+            expected: mem::size_of::<RpcMetadata>(),
+            actual: sge.len,
+        });
+    }
+    let addr_app = ctx.addr_arbiter.query_app_addr(sge.ptr)?;
+    Ok((addr_app, sge.ptr))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum RpcStrategy {
     /// The entire message is encapuslated into one message, transmitted with one send/recv
@@ -407,7 +502,7 @@ impl TcpRpcAdapterEngine {
                 offset: off as _,
                 len: meta_buf.len() as _,
             },
-            1,
+            IMM_LAST,
         )?;
 
         Ok(Progress(1))
@@ -447,23 +542,75 @@ impl TcpRpcAdapterEngine {
             0,
         )?;
 
-        // post the remaining data
-        for (i, &sge) in sglist.0.iter().enumerate() {
-            let off = sge.ptr;
+        // post the remaining data, an sge larger than a receive buffer of the peer is sent in
+        // segments
+        let segment_size = conn_ctx.peer_buffers.recv_buffer_size;
+        for (segment, imm) in Self::segments(sglist, segment_size) {
             get_ops().post_send(
                 sock_handle,
                 ctx as u64,
                 Range {
-                    offset: off as _,
-                    len: sge.len as _,
+                    offset: segment.ptr as _,
+                    len: segment.len as _,
                 },
-                (i + 1 == sglist.0.len()) as _,
+                imm,
             )?;
         }
         log::debug!("send_standard finish!");
         Ok(Progress(1))
     }
 
+    /// Splits the sges of a message into the sends that carry them, each of at most
+    /// `segment_size` bytes, and returns the sends with their imms.
+    fn segments(sglist: &SgList, segment_size: usize) -> Vec<(SgE, u32)> {
+        let mut segments = Vec::with_capacity(sglist.0.len());
+        for (i, &sge) in sglist.0.iter().enumerate() {
+            let end = sge.ptr + sge.len;
+            let mut off = sge.ptr;
+            loop {
+                let len = (end - off).min(segment_size);
+                let imm = if off + len < end {
+                    IMM_SEGMENT
+                } else if i + 1 == sglist.0.len() {
+                    IMM_LAST
+                } else {
+                    0
+                };
+                segments.push((SgE { ptr: off, len }, imm));
+                off += len;
+                if off == end {
+                    break;
+                }
+            }
+        }
+        segments
+    }
+
+    fn send_cancel(&mut self, meta: MessageMeta) -> Result<(), DatapathError> {
+        let sock_handle = match self.state.conn_table.borrow().get(&meta.conn_id) {
+            Some(conn_ctx) => conn_ctx.sock_handle,
+            None => {
+                // the connection has gone, and so has the call
+                return Ok(());
+            }
+        };
+
+        let header = Box::new(MetaHeader::new(meta));
+        let off = (header.as_ref() as *const MetaHeader).expose_addr();
+        let ctx = self.cancel_ctx.insert(header) as u64 | CANCEL_WR_ID_FLAG;
+
+        get_ops().post_send(
+            sock_handle,
+            ctx,
+            Range {
+                offset: off as _,
+                len: MetaHeader::len() as _,
+            },
+            IMM_LAST,
+        )?;
+        Ok(())
+    }
+
     fn check_input_queue(&mut self) -> Result<Status, DatapathError> {
         use phoenix_common::engine::datapath::TryRecvError;
 
@@ -485,12 +632,15 @@ impl TcpRpcAdapterEngine {
                         self.reclaim_recv_buffers(sock_handle, &recv_mrs[..])?;
                     }
                 }
+                EngineTxMessage::Cancel(meta) => self.send_cancel(meta)?,
             },
             Err(TryRecvError::Empty) => {}
             Err(TryRecvError::Disconnected) => return Ok(Status::Disconnected),
         }
 
         if let Some(msg) = self.local_buffer.pop_front() {
+            // tell the peer whether the metadata goes before the message
+            unsafe { (*msg.meta_buf_ptr.as_meta_ptr()).has_metadata = msg.metadata_backend != 0 };
             // SAFETY: don't know what kind of UB can be triggered
             let meta_ref = unsafe { &*msg.meta_buf_ptr.as_meta_ptr() };
             // let table = self.state.conn_table.borrow_mut();
@@ -498,8 +648,22 @@ impl TcpRpcAdapterEngine {
             //     .get(&meta_ref.conn_id)
             //     .ok_or(ResourceError::NotFound)?;
             // log::info!("dispatching message: {:?}", meta_ref);
-            let sglist = match meta_ref.status_code {
+            let mut sglist = match meta_ref.status_code {
                 StatusCode::AccessDenied => SgList { 0: Vec::new() },
+                StatusCode::Success if meta_ref.msg_type.is_stream_end() => {
+                    match mrpc_marshal::status::marshal(msg.addr_backend) {
+                        Ok(sglist) => sglist,
+                        Err(e) => {
+                            panic!("marshal error: {:?}", e);
+                        }
+                    }
+                }
+                StatusCode::ServerError => match mrpc_marshal::status::marshal(msg.addr_backend) {
+                    Ok(sglist) => sglist,
+                    Err(e) => {
+                        panic!("marshal error: {:?}", e);
+                    }
+                },
                 StatusCode::Success => {
                     if let Some(ref module) = self.serialization_engine {
                         match module.marshal(meta_ref, msg.addr_backend) {
@@ -516,6 +680,62 @@ impl TcpRpcAdapterEngine {
                     panic!("unexpected status code: {:?}", meta_ref.status_code);
                 }
             };
+            if let Some(metadata) = msg.metadata_ptr() {
+                sglist.0.insert(
+                    0,
+                    SgE {
+                        ptr: metadata.as_ptr().expose_addr(),
+                        len: mem::size_of::<RpcMetadata>(),
+                    },
+                );
+            }
+
+            // neither end takes a message larger than its maximum message size
+            let max_message_size = match self.state.conn_table.borrow().get(&meta_ref.conn_id) {
+                Some(conn_ctx) => conn_ctx
+                    .peer_buffers
+                    .max_message_size
+                    .min(self.buffer_setting.max_message_size),
+                None => self.buffer_setting.max_message_size,
+            };
+            let msg_size: usize = sglist.0.iter().map(|sge| sge.len).sum();
+            if msg_size > max_message_size {
+                log::warn!(
+                    "message of {} bytes exceeds the maximum message size {}",
+                    msg_size,
+                    max_message_size
+                );
+                match meta_ref.msg_type {
+                    RpcMsgType::Response
+                    | RpcMsgType::StreamResponse
+                    | RpcMsgType::StreamResponseEnd => {
+                        // the client is waiting for the reply, tell it the reply is too large
+                        // through a server error, which ends a server stream
+                        let meta = unsafe { &mut *msg.meta_buf_ptr.as_meta_ptr() };
+                        if meta.msg_type == RpcMsgType::StreamResponse {
+                            meta.msg_type = RpcMsgType::StreamResponseEnd;
+                        }
+                        meta.status_code = StatusCode::ServerError;
+                        meta.has_metadata = false;
+                        sglist = match self.exhausted_status.marshal() {
+                            Ok(sglist) => sglist,
+                            Err(e) => {
+                                panic!("marshal error: {:?}", e);
+                            }
+                        };
+                    }
+                    _ => {
+                        let rpc_id = RpcId::new(meta_ref.conn_id, meta_ref.call_id);
+                        let status = TransportStatus::rejected(RESOURCE_EXHAUSTED);
+                        self.rx_outputs()[0]
+                            .send(EngineRxMessage::Ack(rpc_id, status))
+                            .unwrap();
+                        return Ok(Progress(1));
+                    }
+                }
+            }
+            // the meta of an oversized reply has been rewritten
+            let meta_ref = unsafe { &*msg.meta_buf_ptr.as_meta_ptr() };
 
             let status = match Self::choose_strategy(&sglist) {
                 RpcStrategy::Fused => self.send_fused(msg.meta_buf_ptr, &sglist)?,
@@ -556,18 +776,34 @@ impl TcpRpcAdapterEngine {
         // tracing::trace!("reshape_fused_sg_list: sg_list: {:?}", sg_list);
     }
 
-    fn unmarshal_and_deliver_up(&mut self, sgl: SgList, sock_handle: Handle) -> RpcId {
+    /// Returns `None` if the message is a cancel, which is not held by anyone.
+    fn unmarshal_and_deliver_up(&mut self, sgl: SgList, sock_handle: Handle) -> Option<RpcId> {
         let mut meta_ptr = unsafe { MessageMeta::unpack(&sgl.0[0]) }.unwrap();
         let meta = unsafe { meta_ptr.as_mut() };
         meta.conn_id = sock_handle;
 
         let recv_id = RpcId::new(meta.conn_id, meta.call_id);
+        if meta.msg_type == RpcMsgType::Cancel {
+            self.rx_outputs()[0]
+                .send(EngineRxMessage::Cancel(recv_id))
+                .unwrap();
+            return None;
+        }
         let mut excavate_ctx = ExcavateContext {
             sgl: sgl.0[1..].iter(),
             addr_arbiter: &self.state.resource().addr_map,
         };
 
+        let (metadata_app, metadata_backend) = if meta.has_metadata {
+            unmarshal_metadata(&mut excavate_ctx).unwrap()
+        } else {
+            (0, 0)
+        };
+
         let (addr_app, addr_backend) = match meta.status_code {
+            StatusCode::Success if meta.msg_type.is_stream_end() => {
+                unsafe { mrpc_marshal::status::unmarshal(&mut excavate_ctx) }.unwrap()
+            }
             StatusCode::Success => {
                 if let Some(ref module) = self.serialization_engine {
                     module.unmarshal(meta, &mut excavate_ctx).unwrap()
@@ -575,6 +811,9 @@ impl TcpRpcAdapterEngine {
                     panic!("dispatch module not loaded");
                 }
             }
+            StatusCode::ServerError => {
+                unsafe { mrpc_marshal::status::unmarshal(&mut excavate_ctx) }.unwrap()
+            }
             StatusCode::AccessDenied => (0usize, 0usize),
             _ => {
                 panic!("unexpected status code: {:?}", meta.status_code);
@@ -585,37 +824,121 @@ impl TcpRpcAdapterEngine {
             meta: meta_ptr,
             addr_backend,
             addr_app,
+            metadata_app,
+            metadata_backend,
         };
 
         self.rx_outputs()[0]
             .send(EngineRxMessage::RpcMessage(msg))
             .unwrap();
 
-        recv_id
+        Some(recv_id)
     }
 
+    /// Posts the receive buffers of an accepted connection. The user learns about the
+    /// connection once the hello of the peer arrives.
     fn process_new_connection(&mut self, handle: &Handle) -> usize {
         (|| -> Result<(), ControlPathError> {
             let (read_regions, fds) = self.prepare_recv_buffers(*handle)?;
-            let conn_resp = ConnectResponse {
-                conn_handle: *handle,
+            // The peer sends nothing but its hello until it receives the hello of this end,
+            // which goes after the addresses are mapped, so the socket can be read now.
+            let mut table = get_ops().state.sock_table.borrow_mut();
+            let value = table.get_mut(handle).ok_or(ApiError::NotFound)?;
+            value.1 = MappedAddrStatus::Mapped;
+            drop(table);
+            let pending = PendingConnection {
                 read_regions,
+                fds,
+                active: false,
             };
-            let comp = phoenix_api_mrpc::cmd::Completion(Ok(
-                phoenix_api_mrpc::cmd::CompletionKind::NewConnectionInternal(conn_resp, fds),
-            ));
-            self.cmd_tx.send(comp)?;
+            self.state
+                .pending_conns
+                .borrow_mut()
+                .insert(*handle, pending);
             Ok(())
         })()
         .is_ok() as usize
     }
 
+    /// Tells the peer about the receive buffers of this end.
+    fn send_hello(&mut self, sock_handle: Handle) -> Result<(), TransportError> {
+        let hello = Box::new(self.buffer_setting.encode());
+        let off = hello.as_ptr().expose_addr();
+        let ctx = self.hello_ctx.insert(hello) as u64 | HELLO_WR_ID_FLAG;
+        get_ops().post_send(
+            sock_handle,
+            ctx,
+            Range {
+                offset: off as _,
+                len: BufferSetting::ENCODED_LEN as _,
+            },
+            IMM_HELLO,
+        )
+    }
+
+    /// Completes a pending connection with the receive buffers of the peer in the hello.
+    fn process_hello(&mut self, wc: &Completion) -> Result<(), DatapathError> {
+        let sock_handle = Handle(wc.conn_id);
+        let hello =
+            unsafe { std::slice::from_raw_parts(wc.buf.offset as *const u8, wc.byte_len as usize) };
+        let peer_buffers = BufferSetting::decode(hello);
+        // the hello is consumed, the buffer can receive again
+        self.reclaim_recv_buffers(sock_handle, &[Handle(wc.wr_id)])?;
+
+        let pending = match self.state.pending_conns.borrow_mut().remove(&sock_handle) {
+            Some(pending) => pending,
+            None => {
+                log::warn!("unexpected hello on {:?}", sock_handle);
+                return Ok(());
+            }
+        };
+        let peer_buffers = match peer_buffers {
+            Some(peer_buffers) => peer_buffers,
+            None => {
+                log::warn!("the peer of {:?} sent invalid receive buffers", sock_handle);
+                if pending.active {
+                    let err = ControlPathError::PeerBuffers;
+                    self.cmd_tx
+                        .send(phoenix_api_mrpc::cmd::Completion(Err(err.into())))
+                        .unwrap();
+                }
+                return Ok(());
+            }
+        };
+
+        self.state.conn_table.borrow_mut().insert(
+            sock_handle,
+            ConnectionContext::new(sock_handle, peer_buffers),
+        );
+        let conn_resp = ConnectResponse {
+            conn_handle: sock_handle,
+            read_regions: pending.read_regions,
+            peer_buffers,
+        };
+        let comp = if pending.active {
+            phoenix_api_mrpc::cmd::CompletionKind::ConnectInternal(conn_resp, pending.fds)
+        } else {
+            phoenix_api_mrpc::cmd::CompletionKind::NewConnectionInternal(conn_resp, pending.fds)
+        };
+        self.cmd_tx
+            .send(phoenix_api_mrpc::cmd::Completion(Ok(comp)))
+            .unwrap();
+        Ok(())
+    }
+
     fn process_completion(&mut self, wc: &Completion) -> usize {
         match wc.status {
             WcStatus::Success => {
                 match wc.opcode {
                     WcOpcode::Send => {
-                        if wc.imm != 0 {
+                        if wc.wr_id & HELLO_WR_ID_FLAG != 0 {
+                            self.hello_ctx
+                                .remove((wc.wr_id & !HELLO_WR_ID_FLAG) as usize);
+                        } else if wc.wr_id & CANCEL_WR_ID_FLAG != 0 {
+                            // a cancel has been sent, nobody waits for its ack
+                            self.cancel_ctx
+                                .remove((wc.wr_id & !CANCEL_WR_ID_FLAG) as usize);
+                        } else if wc.imm == IMM_LAST {
                             // let rpc_id = RpcId::decode_u64(wc.wr_id);
                             let rpc_id = self.rpc_ctx.remove(wc.wr_id as usize);
                             self.rx_outputs()[0]
@@ -623,6 +946,11 @@ impl TcpRpcAdapterEngine {
                                 .unwrap();
                         }
                     }
+                    WcOpcode::Recv if wc.imm == IMM_HELLO => {
+                        if let Err(e) = self.process_hello(wc) {
+                            log::warn!("failed to process the hello: {}", e);
+                        }
+                    }
                     WcOpcode::Recv => {
                         let mut table = self.state.conn_table.borrow_mut();
                         let conn_ctx = table.get_mut(&Handle(wc.conn_id));
@@ -636,26 +964,20 @@ impl TcpRpcAdapterEngine {
                             ptr: wc.buf.offset as _,
                             len: wc.byte_len as _,
                         };
-                        conn_ctx.receiving_ctx.sg_list.0.push(sge);
-                        conn_ctx.receiving_ctx.recv_mrs.push(Handle(wc.wr_id));
+                        conn_ctx
+                            .receiving_ctx
+                            .push(sge, Handle(wc.wr_id), wc.imm == IMM_SEGMENT);
 
-                        if wc.imm != 0 {
+                        if wc.imm == IMM_LAST {
                             // received an entire RPC message
                             let sock_handle = conn_ctx.sock_handle;
-                            let mut recv_ctx = mem::take(&mut conn_ctx.receiving_ctx);
+                            let recv_ctx = mem::take(&mut conn_ctx.receiving_ctx);
+                            conn_ctx.received.push_back(recv_ctx);
                             drop(table);
 
-                            // check if it is an eager message
-                            if recv_ctx.sg_list.0.len() == 1 {
-                                // got an eager message
-                                Self::reshape_fused_sg_list(&mut recv_ctx.sg_list);
+                            if let Err(e) = self.deliver_received(sock_handle) {
+                                log::warn!("failed to deliver received messages: {}", e);
                             }
-
-                            let recv_id =
-                                self.unmarshal_and_deliver_up(recv_ctx.sg_list, sock_handle);
-
-                            // keep them outstanding because they will be used by the user
-                            self.recv_mr_usage.insert(recv_id, recv_ctx.recv_mrs);
                         }
                     }
                     // The below two are probably errors in impl logic, so assert them
@@ -669,7 +991,30 @@ impl TcpRpcAdapterEngine {
                 get_ops().state.sock_table.borrow_mut().remove(&handle);
                 get_ops().state.cq_table.borrow_mut().remove(&handle);
                 self.state.conn_table.borrow_mut().remove(&handle);
+                self.state.reassembly_regions.borrow_mut().remove(&handle);
+                if let Some(pending) = self.state.pending_conns.borrow_mut().remove(&handle) {
+                    // the user is waiting for the connection
+                    if pending.active {
+                        let err = ControlPathError::TransportError(TransportError::General(
+                            format!("connection failed: {:?}", code),
+                        ));
+                        self.cmd_tx
+                            .send(phoenix_api_mrpc::cmd::Completion(Err(err.into())))
+                            .unwrap();
+                    }
+                }
                 let msg = if wc.opcode == WcOpcode::Send {
+                    if wc.wr_id & HELLO_WR_ID_FLAG != 0 {
+                        self.hello_ctx
+                            .remove((wc.wr_id & !HELLO_WR_ID_FLAG) as usize);
+                        return 1;
+                    }
+                    if wc.wr_id & CANCEL_WR_ID_FLAG != 0 {
+                        // nobody waits for the result of a cancel
+                        self.cancel_ctx
+                            .remove((wc.wr_id & !CANCEL_WR_ID_FLAG) as usize);
+                        return 1;
+                    }
                     // let rpc_id = RpcId::decode_u64(wc.wr_id);
                     let rpc_id = self.rpc_ctx.remove(wc.wr_id as usize);
                     EngineRxMessage::Ack(rpc_id, TransportStatus::Error(code))
@@ -709,7 +1054,14 @@ impl TcpRpcAdapterEngine {
         sock_handle: Handle,
         mr_handles: &[Handle],
     ) -> Result<(), DatapathError> {
+        let mut reassembly_released = false;
         for handle in mr_handles {
+            // a reassembly buffer goes back to its slab rather than receiving new data
+            if let Some(buffer) = self.state.reassembly_table.borrow_mut().remove(handle) {
+                self.state.resource().recv_buffer_pool.release(buffer);
+                reassembly_released = true;
+                continue;
+            }
             let table = self.state.recv_buffer_table.borrow();
             let recv_buffer = table.get(handle).ok_or(ResourceError::NotFound)?;
 
@@ -722,6 +1074,116 @@ impl TcpRpcAdapterEngine {
                 },
             )?;
         }
+        if reassembly_released {
+            // the messages waiting for a reassembly buffer can go now
+            self.deliver_received(sock_handle)?;
+        }
+        Ok(())
+    }
+
+    /// Delivers the received messages of a connection in order. A message with sges received in
+    /// segments waits until a reassembly buffer of the connection is free.
+    fn deliver_received(&mut self, sock_handle: Handle) -> Result<(), DatapathError> {
+        loop {
+            let recv_ctx = match self.state.conn_table.borrow_mut().get_mut(&sock_handle) {
+                Some(conn_ctx) => conn_ctx.received.pop_front(),
+                None => None,
+            };
+            let mut recv_ctx = match recv_ctx {
+                Some(recv_ctx) => recv_ctx,
+                None => return Ok(()),
+            };
+
+            if !recv_ctx.segmented_sges.is_empty() {
+                let region = self
+                    .state
+                    .reassembly_regions
+                    .borrow()
+                    .get(&sock_handle)
+                    .copied();
+                let segmented_len: usize = recv_ctx
+                    .segmented_sges
+                    .iter()
+                    .map(|segmented| recv_ctx.sg_list.0[segmented.index].len)
+                    .sum();
+                let region = match region {
+                    Some(region) if segmented_len <= self.buffer_setting.max_message_size => region,
+                    _ => {
+                        // the peer sent a message larger than the maximum message size
+                        log::warn!(
+                            "dropping a received message of more than {} bytes",
+                            self.buffer_setting.max_message_size
+                        );
+                        for segmented in &recv_ctx.segmented_sges {
+                            self.reclaim_recv_buffers(sock_handle, &segmented.recv_mrs)?;
+                        }
+                        self.reclaim_recv_buffers(sock_handle, &recv_ctx.recv_mrs)?;
+                        let status = TransportStatus::rejected(RESOURCE_EXHAUSTED);
+                        self.rx_outputs()[0]
+                            .send(EngineRxMessage::RecvError(sock_handle, status))
+                            .unwrap();
+                        continue;
+                    }
+                };
+                match self.state.resource().recv_buffer_pool.obtain_from(&region) {
+                    Some(buffer) => self.reassemble(sock_handle, &mut recv_ctx, buffer)?,
+                    None => {
+                        // wait for a reassembly buffer to be released
+                        if let Some(conn_ctx) =
+                            self.state.conn_table.borrow_mut().get_mut(&sock_handle)
+                        {
+                            conn_ctx.received.push_front(recv_ctx);
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+
+            // check if it is an eager message
+            if recv_ctx.sg_list.0.len() == 1 {
+                // got an eager message
+                Self::reshape_fused_sg_list(&mut recv_ctx.sg_list);
+            }
+
+            match self.unmarshal_and_deliver_up(recv_ctx.sg_list, sock_handle) {
+                Some(recv_id) => {
+                    // keep them outstanding because they will be used by the user
+                    self.recv_mr_usage.insert(recv_id, recv_ctx.recv_mrs);
+                }
+                None => {
+                    // a cancel has been consumed, repost the buffers right away
+                    self.reclaim_recv_buffers(sock_handle, &recv_ctx.recv_mrs)?;
+                }
+            }
+        }
+    }
+
+    /// Copies the segmented sges of a message into a reassembly buffer, each into one piece.
+    fn reassemble(
+        &mut self,
+        sock_handle: Handle,
+        recv_ctx: &mut RecvContext,
+        buffer: RecvBuffer,
+    ) -> Result<(), DatapathError> {
+        let mut dst = buffer.addr();
+        for segmented in mem::take(&mut recv_ctx.segmented_sges) {
+            recv_ctx.sg_list.0[segmented.index].ptr = dst;
+            for segment in &segmented.segments.0 {
+                unsafe {
+                    ptr::copy_nonoverlapping(segment.ptr as *const u8, dst as *mut u8, segment.len);
+                }
+                dst += segment.len;
+            }
+            // the segments have been copied, their buffers can receive again
+            self.reclaim_recv_buffers(sock_handle, &segmented.recv_mrs)?;
+        }
+
+        let handle = buffer.as_handle();
+        recv_ctx.recv_mrs.push(handle);
+        self.state
+            .reassembly_table
+            .borrow_mut()
+            .insert(handle, buffer);
         Ok(())
     }
 
@@ -729,14 +1191,15 @@ impl TcpRpcAdapterEngine {
         &mut self,
         sock_handle: Handle,
     ) -> Result<(Vec<ReadHeapRegion>, Vec<RawFd>), ControlPathError> {
+        let setting = self.buffer_setting;
         let slab = BufferSlab::new(
-            128,
-            8 * 1024 * 1024,
-            8 * 1024 * 1024,
+            setting.num_recv_buffers,
+            setting.recv_buffer_size,
+            RECV_BUFFER_ALIGN,
             &self.salloc.addr_mediator,
         )?;
-        // create 128 receive mrs and post recv requests
-        for _ in 0..128 {
+        // create receive mrs and post recv requests
+        for _ in 0..setting.num_recv_buffers {
             let recv_buffer = slab.obtain().unwrap();
             let wr_id = recv_buffer.as_handle().0 as u64;
             let offset = recv_buffer.addr() as u64;
@@ -748,23 +1211,59 @@ impl TcpRpcAdapterEngine {
                 .insert(recv_buffer.as_handle(), recv_buffer);
         }
 
-        let region = slab.storage();
-        let read_regions = vec![ReadHeapRegion {
-            handle: region.as_handle(),
-            addr: region.as_ptr().addr(),
-            len: region.len(),
-            file_off: 0,
-        }];
-        let fds = vec![region.memfd().as_raw_fd()];
-
+        let mut regions = vec![slab.storage()];
         // don't forget this
         self.state.resource().recv_buffer_pool.replenish(slab);
+
+        // the sges larger than a receive buffer are reassembled in these buffers
+        if setting.max_message_size > setting.recv_buffer_size {
+            let reassembly = BufferSlab::new(
+                NUM_REASSEMBLY_BUFFERS,
+                setting.max_message_size,
+                RECV_BUFFER_ALIGN,
+                &self.salloc.addr_mediator,
+            )?;
+            let region = reassembly.storage();
+            self.state
+                .reassembly_regions
+                .borrow_mut()
+                .insert(sock_handle, region.as_handle());
+            regions.push(region);
+            self.state.resource().recv_buffer_pool.replenish(reassembly);
+        }
+
+        let read_regions = regions
+            .iter()
+            .map(|region| ReadHeapRegion {
+                handle: region.as_handle(),
+                addr: region.as_ptr().addr(),
+                len: region.len(),
+                file_off: 0,
+            })
+            .collect();
+        let fds = regions
+            .iter()
+            .map(|region| region.memfd().as_raw_fd())
+            .collect();
         Ok((read_regions, fds))
     }
 
     fn check_input_cmd_queue(&mut self) -> Result<Status, ControlPathError> {
         use tokio::sync::mpsc::error::TryRecvError;
         match self.cmd_rx.try_recv() {
+            Ok(phoenix_api_mrpc::cmd::Command::SetBuffersInner(setting)) => {
+                // the service does not wait for a reply
+                self.buffer_setting = setting;
+                Ok(Progress(1))
+            }
+            Ok(phoenix_api_mrpc::cmd::Command::Connect(addr)) => {
+                // the connection completes once the hello of the peer arrives
+                if let Err(e) = self.connect(&addr) {
+                    self.cmd_tx
+                        .send(phoenix_api_mrpc::cmd::Completion(Err(e.into())))?;
+                }
+                Ok(Progress(1))
+            }
             Ok(req) => {
                 let result = self.process_cmd(&req);
                 match result {
@@ -782,6 +1281,25 @@ impl TcpRpcAdapterEngine {
         }
     }
 
+    /// Connects to `addr`, posts the receive buffers and tells the peer about them. The
+    /// connection completes in [`Self::process_hello`].
+    fn connect(&mut self, addr: &std::net::SocketAddr) -> Result<(), ControlPathError> {
+        log::debug!("Connect, addr: {:?}", addr);
+        let sock_handle = get_ops().connect(addr)?;
+        let (read_regions, fds) = self.prepare_recv_buffers(sock_handle)?;
+        self.send_hello(sock_handle)?;
+        let pending = PendingConnection {
+            read_regions,
+            fds,
+            active: true,
+        };
+        self.state
+            .pending_conns
+            .borrow_mut()
+            .insert(sock_handle, pending);
+        Ok(())
+    }
+
     fn process_cmd(
         &mut self,
         req: &phoenix_api_mrpc::cmd::Command,
@@ -806,31 +1324,13 @@ impl TcpRpcAdapterEngine {
                         .insert_addr_map(mr_local_addr, mr_remote_mapped)?;
                 }
 
-                //Marked socket as addresses mapped
-                let mut table = get_ops().state.sock_table.borrow_mut();
-                let value = table.get_mut(sock_handle).ok_or(ApiError::NotFound)?;
-                value.1 = MappedAddrStatus::Mapped;
-                // insert resources after connection establishment
-                self.state
-                    .conn_table
-                    .borrow_mut()
-                    .insert(*sock_handle, ConnectionContext::new(*sock_handle));
+                // the peer starts sending once it receives the hello
+                self.send_hello(*sock_handle)?;
 
                 Ok(CompletionKind::NewMappedAddrs)
             }
-            Command::Connect(addr) => {
-                log::debug!("Connect, addr: {:?}", addr);
-                let sock_handle = get_ops().connect(addr)?;
-                let (read_regions, fds) = self.prepare_recv_buffers(sock_handle)?;
-                self.state
-                    .conn_table
-                    .borrow_mut()
-                    .insert(sock_handle, ConnectionContext::new(sock_handle));
-                let conn_resp = ConnectResponse {
-                    conn_handle: sock_handle,
-                    read_regions,
-                };
-                Ok(CompletionKind::ConnectInternal(conn_resp, fds))
+            Command::Connect(_) => {
+                unreachable!();
             }
 
             Command::Bind(addr) => {
@@ -850,6 +1350,95 @@ impl TcpRpcAdapterEngine {
             Command::MultiConnect(_) => {
                 unreachable!();
             }
+            Command::SetBuffersInner(_) => {
+                unreachable!();
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::SegmentedSgE;
+
+    const SEGMENT_SIZE: usize = 1024;
+
+    /// Splits the sges of `lens` bytes of `data` into sends, and receives the sends as the peer
+    /// does. Returns the imms of the sends and the received message.
+    fn transfer(data: &[u8], lens: &[usize]) -> (Vec<u32>, RecvContext) {
+        let mut sglist = SgList(Vec::new());
+        let mut off = 0;
+        for &len in lens {
+            sglist.0.push(SgE {
+                ptr: data[off..].as_ptr().expose_addr(),
+                len,
+            });
+            off += len;
+        }
+
+        let segments = TcpRpcAdapterEngine::segments(&sglist, SEGMENT_SIZE);
+        assert!(segments.iter().all(|(sge, _)| sge.len <= SEGMENT_SIZE));
+
+        let mut recv_ctx = RecvContext::default();
+        for (i, &(sge, imm)) in segments.iter().enumerate() {
+            recv_ctx.push(sge, Handle(i as u64), imm == IMM_SEGMENT);
+        }
+        assert!(!recv_ctx.in_segments);
+        let imms = segments.iter().map(|&(_, imm)| imm).collect();
+        (imms, recv_ctx)
+    }
+
+    /// Returns the bytes of a segmented sge, as they are copied into a reassembly buffer.
+    fn reassemble(segmented: &SegmentedSgE) -> Vec<u8> {
+        segmented
+            .segments
+            .0
+            .iter()
+            .flat_map(|sge| unsafe { std::slice::from_raw_parts(sge.ptr as *const u8, sge.len) })
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_segments() {
+        let data: Vec<u8> = (0..4 * SEGMENT_SIZE).map(|i| i as u8).collect();
+        let lens = [100, 2 * SEGMENT_SIZE + 300, SEGMENT_SIZE];
+        let (imms, recv_ctx) = transfer(&data, &lens);
+
+        // an sge of exactly a receive buffer is not split
+        assert_eq!(imms, [0, IMM_SEGMENT, IMM_SEGMENT, 0, IMM_LAST]);
+        let received: Vec<_> = recv_ctx.sg_list.0.iter().map(|sge| sge.len).collect();
+        assert_eq!(received, lens);
+        assert_eq!(recv_ctx.recv_mrs, [Handle(0), Handle(4)]);
+
+        assert_eq!(recv_ctx.segmented_sges.len(), 1);
+        let segmented = &recv_ctx.segmented_sges[0];
+        assert_eq!(segmented.index, 1);
+        assert_eq!(segmented.recv_mrs, [Handle(1), Handle(2), Handle(3)]);
+        assert_eq!(reassemble(segmented), &data[100..100 + lens[1]]);
+    }
+
+    #[test]
+    fn test_segments_at_the_end() {
+        let data: Vec<u8> = (0..4 * SEGMENT_SIZE).map(|i| (i * 7) as u8).collect();
+        let lens = [SEGMENT_SIZE + 1, 2 * SEGMENT_SIZE];
+        let (imms, recv_ctx) = transfer(&data, &lens);
+
+        // the last segment of a message is marked as the last send
+        assert_eq!(imms, [IMM_SEGMENT, 0, IMM_SEGMENT, IMM_LAST]);
+        let received: Vec<_> = recv_ctx.sg_list.0.iter().map(|sge| sge.len).collect();
+        assert_eq!(received, lens);
+        assert!(recv_ctx.recv_mrs.is_empty());
+
+        // two adjacent segmented sges are not merged
+        assert_eq!(recv_ctx.segmented_sges.len(), 2);
+        assert_eq!(recv_ctx.segmented_sges[0].index, 0);
+        assert_eq!(recv_ctx.segmented_sges[1].index, 1);
+        assert_eq!(reassemble(&recv_ctx.segmented_sges[0]), &data[..lens[0]]);
+        assert_eq!(
+            reassemble(&recv_ctx.segmented_sges[1]),
+            &data[lens[0]..lens[0] + lens[1]]
+        );
+    }
+}
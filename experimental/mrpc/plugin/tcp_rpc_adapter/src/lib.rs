@@ -45,6 +45,8 @@ pub(crate) enum ControlPathError {
     SharedRegion(#[from] region::Error),
     #[error("{0}")]
     InsertAddrMap(#[from] mrpc_marshal::AddressExists),
+    #[error("The peer did not send valid receive buffers")]
+    PeerBuffers,
 
     // Below are errors that does not return to the user.
     #[error("Send command error")]
@@ -79,7 +81,7 @@ pub(crate) enum DatapathError {
 
 use crate::module::TcpRpcAdapterModule;
 
-#[no_mangle]
+#[cfg_attr(not(feature = "embedded"), no_mangle)]
 pub fn init_module(_config_string: Option<&str>) -> InitFnResult<Box<dyn PhoenixModule>> {
     let module = TcpRpcAdapterModule::new();
     Ok(Box::new(module))
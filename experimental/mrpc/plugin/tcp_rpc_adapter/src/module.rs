@@ -21,7 +21,7 @@ use phoenix_common::module::{
 use phoenix_common::state_mgr::SharedStateManager;
 use phoenix_common::storage::{ResourceCollection, SharedStorage};
 
-use crate::engine::{TcpRpcAdapterEngine, TlStorage};
+use crate::engine::{exhausted_status, TcpRpcAdapterEngine, TlStorage};
 use crate::state::{Shared, State};
 
 pub(crate) struct RpcAdapterEngineBuilder {
@@ -80,6 +80,10 @@ impl RpcAdapterEngineBuilder {
             salloc: salloc_state,
             // start: std::time::Instant::now(),
             rpc_ctx: Default::default(),
+            cancel_ctx: Default::default(),
+            hello_ctx: Default::default(),
+            buffer_setting: Default::default(),
+            exhausted_status: exhausted_status(),
         })
     }
 }
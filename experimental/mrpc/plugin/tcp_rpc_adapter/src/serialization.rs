@@ -1,14 +1,19 @@
 use std::ffi::OsStr;
+use std::sync::Arc;
 
+use mrpc_marshal::reflect::{FieldValue, MessageReader, MethodDescriptor};
 use mrpc_marshal::{ExcavateContext, SgList};
 use mrpc_marshal::{MarshalError, UnmarshalError};
 use phoenix_api::rpc::MessageMeta;
+use phoenix_common::log;
 
 pub(crate) use mrpc_marshal::AddressMap;
 
 pub(crate) type MarshalFn = fn(&MessageMeta, usize) -> Result<SgList, MarshalError>;
 pub(crate) type UnmarshalFn =
     fn(&MessageMeta, &mut ExcavateContext<AddressMap>) -> Result<(usize, usize), UnmarshalError>;
+pub(crate) type ReadFieldFn = unsafe fn(u32, bool, usize, &str) -> Option<FieldValue<'static>>;
+pub(crate) type MethodsFn = fn() -> Vec<MethodDescriptor>;
 
 pub(crate) struct SerializationEngine {
     _library: libloading::Library,
@@ -25,6 +30,7 @@ pub(crate) struct SerializationEngine {
 
 impl SerializationEngine {
     pub(crate) fn new<P: AsRef<OsStr>>(lib: P) -> Result<Self, libloading::Error> {
+        publish_reflection(lib.as_ref())?;
         let library = unsafe { libloading::Library::new(lib) }?;
 
         let marshal_fn = unsafe {
@@ -63,3 +69,66 @@ impl SerializationEngine {
         (self.unmarshal_fn)(meta, ctx)
     }
 }
+
+/// Reads the fields of messages with the `read_field` exported by a dispatch library.
+struct DispatchReader {
+    _library: libloading::Library,
+    // NOTE: Symbol here shall not outlive library.
+    #[cfg(unix)]
+    read_field_fn: libloading::os::unix::Symbol<ReadFieldFn>,
+    #[cfg(windows)]
+    read_field_fn: libloading::os::windows::Symbol<ReadFieldFn>,
+}
+
+impl MessageReader for DispatchReader {
+    #[inline]
+    unsafe fn read_field<'a>(
+        &self,
+        func_id: u32,
+        request: bool,
+        addr_backend: usize,
+        path: &str,
+    ) -> Option<FieldValue<'a>> {
+        (self.read_field_fn)(func_id, request, addr_backend, path)
+    }
+}
+
+/// Publishes the method descriptors and the message reader of a dispatch library to
+/// `mrpc_marshal::reflect`, so that policies can read the fields of its messages.
+/// Libraries built before these were exported are loaded without them.
+fn publish_reflection(lib: &OsStr) -> Result<(), libloading::Error> {
+    let library = unsafe { libloading::Library::new(lib) }?;
+
+    let symbols = unsafe {
+        match (
+            library.get::<ReadFieldFn>(b"read_field"),
+            library.get::<MethodsFn>(b"methods"),
+        ) {
+            (Ok(read_field), Ok(methods)) => Ok((read_field.into_raw(), methods.into_raw())),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    };
+    let (read_field_fn, methods_fn) = match symbols {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            log::warn!(
+                "Dispatch library {:?} does not export message readers: {}, \
+                 clear its cache to regenerate it",
+                lib,
+                e
+            );
+            return Ok(());
+        }
+    };
+
+    let methods = methods_fn();
+    let func_ids = methods.iter().map(|m| m.func_id).collect::<Vec<_>>();
+    mrpc_marshal::reflect::register_methods(methods);
+
+    let reader = DispatchReader {
+        _library: library,
+        read_field_fn,
+    };
+    mrpc_marshal::reflect::register(func_ids, Arc::new(reader));
+    Ok(())
+}
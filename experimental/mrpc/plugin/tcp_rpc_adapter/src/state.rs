@@ -1,12 +1,16 @@
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::io;
+use std::os::unix::prelude::RawFd;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use fnv::FnvHashMap as HashMap;
-use mrpc_marshal::SgList;
+use mrpc_marshal::{SgE, SgList};
 use nix::unistd::Pid;
 use phoenix_api::Handle;
+use phoenix_api_mrpc::cmd::ReadHeapRegion;
+use phoenix_api_mrpc::control_plane::BufferSetting;
 use phoenix_salloc::region::AddressMediator;
 
 use phoenix_common::state_mgr::ProcessShared;
@@ -20,7 +24,13 @@ pub(crate) struct State {
     // shared among all engines of a user process
     pub(crate) shared: Arc<Shared>,
     pub(crate) conn_table: RefCell<HashMap<Handle, ConnectionContext>>,
+    // the new connections waiting for the hello of the peer
+    pub(crate) pending_conns: RefCell<HashMap<Handle, PendingConnection>>,
     pub(crate) recv_buffer_table: RefCell<HashMap<Handle, RecvBuffer>>,
+    // the storage of the reassembly buffers of each connection
+    pub(crate) reassembly_regions: RefCell<HashMap<Handle, Handle>>,
+    // the reassembly buffers held by received messages
+    pub(crate) reassembly_table: RefCell<HashMap<Handle, RecvBuffer>>,
 }
 // SAFETY: State in tcp will not be shared by multiple threads
 // It is owned and used by a single thread/runtime
@@ -33,7 +43,10 @@ impl State {
             _rpc_adapter_id: rpc_adapter_id,
             shared,
             conn_table: RefCell::new(HashMap::default()),
+            pending_conns: RefCell::new(HashMap::default()),
             recv_buffer_table: RefCell::new(HashMap::default()),
+            reassembly_regions: RefCell::new(HashMap::default()),
+            reassembly_table: RefCell::new(HashMap::default()),
         }
     }
 }
@@ -45,7 +58,10 @@ impl Clone for State {
             _rpc_adapter_id,
             shared: Arc::clone(&self.shared),
             conn_table: RefCell::new(HashMap::default()),
+            pending_conns: RefCell::new(HashMap::default()),
             recv_buffer_table: RefCell::new(HashMap::default()),
+            reassembly_regions: RefCell::new(HashMap::default()),
+            reassembly_table: RefCell::new(HashMap::default()),
         }
     }
 }
@@ -100,25 +116,82 @@ impl Shared {
     }
 }
 
+/// An SgE that is larger than a receive buffer, received in segments.
+#[derive(Debug, Default)]
+pub(crate) struct SegmentedSgE {
+    // the index of the SgE in the message
+    pub(crate) index: usize,
+    pub(crate) segments: SgList,
+    // recv mrs that the segments are on
+    pub(crate) recv_mrs: Vec<Handle>,
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct RecvContext {
     // buffer for recevied sges
     pub(crate) sg_list: SgList,
     // recv mrs that received sges are on
     pub(crate) recv_mrs: Vec<Handle>,
+    // the sges received in segments, they are copied into a reassembly buffer before the
+    // message is delivered
+    pub(crate) segmented_sges: Vec<SegmentedSgE>,
+    // whether the last segment of the last segmented sge is yet to come
+    pub(crate) in_segments: bool,
+}
+
+impl RecvContext {
+    /// Adds an sge received on `recv_mr` to the message. `segment` tells whether the sge is a
+    /// segment that continues in the next sge.
+    pub(crate) fn push(&mut self, sge: SgE, recv_mr: Handle, segment: bool) {
+        if segment && !self.in_segments {
+            // the first segment of an sge larger than a receive buffer
+            self.segmented_sges.push(SegmentedSgE {
+                index: self.sg_list.0.len(),
+                ..Default::default()
+            });
+            self.sg_list.0.push(SgE { ptr: 0, len: 0 });
+            self.in_segments = true;
+        }
+        if self.in_segments {
+            let segmented = self.segmented_sges.last_mut().unwrap();
+            segmented.segments.0.push(sge);
+            segmented.recv_mrs.push(recv_mr);
+            self.sg_list.0[segmented.index].len += sge.len;
+            self.in_segments = segment;
+        } else {
+            self.sg_list.0.push(sge);
+            self.recv_mrs.push(recv_mr);
+        }
+    }
+}
+
+/// A new connection whose receive buffers are posted, but whose peer has not told about its
+/// receive buffers yet.
+#[derive(Debug)]
+pub(crate) struct PendingConnection {
+    pub(crate) read_regions: Vec<ReadHeapRegion>,
+    pub(crate) fds: Vec<RawFd>,
+    // whether this end initiated the connection
+    pub(crate) active: bool,
 }
 
 #[derive(Debug)]
 pub(crate) struct ConnectionContext {
     pub(crate) sock_handle: Handle,
+    // the receive buffers of the peer
+    pub(crate) peer_buffers: BufferSetting,
     pub(crate) receiving_ctx: RecvContext,
+    // the received messages waiting for a reassembly buffer, in order
+    pub(crate) received: VecDeque<RecvContext>,
 }
 
 impl ConnectionContext {
-    pub(crate) fn new(sock_handle: Handle) -> Self {
+    pub(crate) fn new(sock_handle: Handle, peer_buffers: BufferSetting) -> Self {
         Self {
             sock_handle,
+            peer_buffers,
             receiving_ctx: RecvContext::default(),
+            received: VecDeque::new(),
         }
     }
 }
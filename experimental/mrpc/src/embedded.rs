@@ -0,0 +1,89 @@
+//! Runs the mRPC service on a thread of the application instead of in a phoenixos daemon.
+//!
+//! With the `embedded` feature, the mRPC, TCP adapter, TCP transport and shared memory allocator
+//! engines are linked into the application, and an embedded phoenix is started on the first use
+//! of mRPC. Its sockets are in a directory of its own under [`std::env::temp_dir`], so several
+//! test processes can run at once. The directory is removed when the process exits. Only the TCP
+//! transport is supported.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use phoenixos::embedded::{self, EmbeddedPhoenix, StaticModule};
+
+const CONTROL_SOCK: &str = "control.sock";
+
+struct Embedded {
+    _phoenix: EmbeddedPhoenix,
+    prefix: PathBuf,
+}
+
+lazy_static::lazy_static! {
+    static ref EMBEDDED: Embedded = start();
+}
+
+/// Starts the embedded phoenix unless it is running.
+///
+/// mRPC calls this before it registers with phoenix. An application that allocates shared memory
+/// before using any other mRPC API must call it first, as the allocator registers with phoenix
+/// too.
+pub fn launch() {
+    lazy_static::initialize(&EMBEDDED);
+}
+
+/// Returns the prefix and the control socket of the embedded phoenix. Starts it unless it is
+/// running.
+pub(crate) fn control_path() -> (&'static Path, &'static Path) {
+    (&EMBEDDED.prefix, Path::new(CONTROL_SOCK))
+}
+
+extern "C" fn remove_prefix() {
+    let _ = fs::remove_dir_all(&EMBEDDED.prefix);
+}
+
+fn start() -> Embedded {
+    let prefix = env::temp_dir().join(format!("phoenix-embedded-{}", std::process::id()));
+    // the marshal libraries built for the protos are reused across test runs
+    let build_cache = env::temp_dir().join("phoenix-embedded-build-cache");
+    let mrpc_config = format!(
+        "build_cache = '{}'\ntransport = \"Tcp\"\n",
+        build_cache.display()
+    );
+
+    let modules = vec![
+        StaticModule {
+            name: "TcpTransport".to_owned(),
+            init: transport_tcp::init_module,
+            config_string: None,
+        },
+        StaticModule {
+            name: "Salloc".to_owned(),
+            init: phoenix_salloc::init_module,
+            config_string: None,
+        },
+        StaticModule {
+            name: "TcpRpcAdapter".to_owned(),
+            init: phoenix_tcp_rpc_adapter::init_module,
+            config_string: None,
+        },
+        StaticModule {
+            name: "Mrpc".to_owned(),
+            init: phoenix_mrpc::init_module,
+            config_string: Some(mrpc_config),
+        },
+    ];
+
+    let phoenix = EmbeddedPhoenix::start(embedded::config(&prefix), modules)
+        .unwrap_or_else(|e| panic!("failed to start the embedded phoenix: {:#}", e));
+    // SAFETY: `remove_prefix` does not unwind
+    unsafe {
+        libc::atexit(remove_prefix);
+    }
+
+    // the allocator registers before mRPC, point it to the embedded phoenix
+    shmalloc::backend::set_control_path(prefix.clone(), CONTROL_SOCK.into());
+    Embedded {
+        _phoenix: phoenix,
+        prefix,
+    }
+}
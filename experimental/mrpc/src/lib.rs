@@ -20,14 +20,25 @@
 //!
 //! Follow the instructions in the [`mrpc-tutorial`] to learn how to write applications.
 //!
+//! For tests, the `embedded` feature runs the mRPC service inside the application, so no
+//! phoenixos daemon is needed.
+//!
 //! # Structure
 //!
 #![doc = include_str!("../rpc.svg")]
 //!
 //! # Max Message Size
 //!
-//! Currently, both servers and clients are using a fixed `8MB` as the limit for maximal message size.
-//! This fixed limit will be removed or made configurable in the future.
+//! Each connection receives into `num_recv_buffers` buffers of `recv_buffer_size` bytes, and
+//! messages larger than `max_message_size` (`8MB` by default) fail with `resource_exhausted`.
+//! The mRPC service sets these in the `buffers` of its config, and an application can override
+//! them with the `buffers` of its [`Setting`] before calling other mRPC APIs (see [`set`]).
+//!
+//! The two ends of a connection tell each other about their receive buffers when they connect,
+//! and neither end sends a message larger than the `max_message_size` of the other. Over TCP, a
+//! message larger than a receive buffer of the peer is split into segments and put back together
+//! by the peer. Over RDMA, messages are not split, so each receive buffer holds at least
+//! `max_message_size` bytes.
 //!
 //! [`mRPC`]: https://github.com/phoenix-dataplane/phoenix/tree/main/experimental/mrpc
 //! [`Phoenix`]: https://github.com/phoenix-dataplane/phoenix
@@ -53,6 +64,7 @@
 use std::cell::RefCell;
 use std::collections::BTreeSet;
 use std::io;
+use std::path::Path;
 
 use thiserror::Error;
 
@@ -61,6 +73,7 @@ pub use phoenix_api::engine::SchedulingHint;
 use phoenix_api_mrpc::control_plane::Setting;
 use phoenix_api_mrpc::{cmd, dp};
 use phoenix_syscalls::_rx_recv_impl as rx_recv_impl;
+#[cfg(not(feature = "embedded"))]
 use phoenix_syscalls::{PHOENIX_CONTROL_SOCK, PHOENIX_PREFIX};
 
 pub use phoenix_syscalls;
@@ -104,10 +117,17 @@ thread_local! {
     // Initialization is dynamically performed on the first call to with within a thread.
     pub(crate) static SCHEDULING_HINT: RefCell<SchedulingHint> = RefCell::new(Default::default());
     pub(crate) static MRPC_CTX: Context = {
+        #[cfg(feature = "embedded")]
+        embedded::launch();
         SA_CTX.with(|_ctx| {
             // do nothing, just to ensure SA_CTX is initialized before MRPC_CTX
         });
-        Context::register(&current_setting()).expect("phoenix mRPC register failed")
+        #[cfg(feature = "embedded")]
+        let (prefix, control) = embedded::control_path();
+        #[cfg(not(feature = "embedded"))]
+        let (prefix, control) = (PHOENIX_PREFIX.as_path(), PHOENIX_CONTROL_SOCK.as_path());
+        Context::register(&current_setting(), prefix, control)
+            .expect("phoenix mRPC register failed")
     }
 }
 
@@ -117,7 +137,8 @@ pub(crate) struct Context {
 }
 
 impl Context {
-    fn register(setting: &Setting) -> Result<Context, Error> {
+    /// Registers at the phoenix whose control socket is `control` under `prefix`.
+    fn register(setting: &Setting, prefix: &Path, control: &Path) -> Result<Context, Error> {
         let protos = RefCell::new(BTreeSet::new());
         println!("mrpc register: {:?}", setting);
         let setting_str = serde_json::to_string(setting)?;
@@ -126,8 +147,8 @@ impl Context {
             service = name.clone();
         }
         let service = ShmService::register(
-            &*PHOENIX_PREFIX,
-            &*PHOENIX_CONTROL_SOCK,
+            prefix,
+            control,
             service,
             SCHEDULING_HINT.with_borrow(|h| *h),
             Some(&setting_str),
@@ -162,16 +183,24 @@ pub mod alloc {
     pub type Vec<T> = shm::vec::Vec<T, SharedHeapAllocator>;
     /// Shared memory String whose memory is managed by [`SharedHeapAllocator`].
     pub type String = shm::string::String<SharedHeapAllocator>;
+    /// Shared memory BTreeMap whose memory is managed by [`SharedHeapAllocator`].
+    pub type BTreeMap<K, V> = shm::collections::BTreeMap<K, V, SharedHeapAllocator>;
 }
 
 pub mod stub;
 
+#[cfg(feature = "embedded")]
+pub mod embedded;
+
 #[macro_use]
 mod macros;
 
 #[doc(inline)]
 pub use phoenix_api::rpc::Token;
 
+#[doc(inline)]
+pub use phoenix_api::rpc::{MetadataFull, RpcMetadata};
+
 #[doc(hidden)]
 pub use phoenix_api::rpc::MessageErased;
 
@@ -182,6 +211,7 @@ mod wref;
 pub use wref::{IntoWRef, WRef, WRefOpaque};
 
 mod status;
+pub(crate) use status::RpcStatus;
 #[doc(inline)]
 pub use status::{Code, Status};
 
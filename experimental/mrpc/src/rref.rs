@@ -6,7 +6,7 @@ use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::sync::Arc;
 
-use phoenix_api::rpc::{CallId, MessageErased, RpcId, Token};
+use phoenix_api::rpc::{CallId, MessageErased, RpcId, RpcMetadata, Token};
 use phoenix_api_mrpc::dp::{WorkRequest, RECV_RECLAIM_BS};
 use shm::ptr::ShmPtr;
 
@@ -20,6 +20,8 @@ struct RRefInner<T> {
     token: Token,
     read_heap: Arc<ReadHeap>,
     data: ShmPtr<T>,
+    /// The metadata that came with the message, in the same receive buffers.
+    metadata: Option<ShmPtr<RpcMetadata>>,
 }
 
 /// A thread-safe reference-counting pointer to objects on the read-only shared memory heap.
@@ -62,6 +64,12 @@ impl<T> RRef<T> {
         let ptr_backend = ptr_app.with_addr(msg.shm_addr_backend);
         let backend_owned = ShmPtr::new(ptr_app, ptr_backend).unwrap();
 
+        let metadata_app = msg.metadata_addr_app as *mut RpcMetadata;
+        let metadata = ShmPtr::new(
+            metadata_app,
+            metadata_app.with_addr(msg.metadata_addr_backend),
+        );
+
         let rpc_id = RpcId::new(msg.meta.conn_id, msg.meta.call_id);
 
         // increase refcnt on the heap
@@ -72,6 +80,7 @@ impl<T> RRef<T> {
             token: Token(msg.meta.token as usize),
             read_heap,
             data: backend_owned,
+            metadata,
         }))
     }
 
@@ -81,6 +90,18 @@ impl<T> RRef<T> {
     pub fn token(&self) -> Token {
         self.0.token
     }
+
+    /// Returns the metadata that came with the message, if any.
+    #[must_use]
+    #[inline]
+    pub fn metadata(&self) -> Option<&RpcMetadata> {
+        // SAFETY: the metadata lives in the receive buffers of the message, which are not
+        // reclaimed until the last RRef is dropped.
+        self.0
+            .metadata
+            .as_ref()
+            .map(|metadata| unsafe { metadata.as_ref_app() })
+    }
 }
 
 impl<T> Clone for RRef<T> {
@@ -134,6 +134,26 @@ impl std::fmt::Display for Code {
     }
 }
 
+/// The payload of a reply whose handler returned an error.
+///
+/// This type has an identical memory layout as `mrpc_marshal::status::RpcStatus`, which is
+/// used by the backend to marshal the error.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct RpcStatus {
+    code: i32,
+    message: crate::alloc::String,
+}
+
+impl RpcStatus {
+    pub(crate) fn new(status: &Status) -> Self {
+        RpcStatus {
+            code: status.code().into(),
+            message: status.message().into(),
+        }
+    }
+}
+
 // ===== impl Status =====
 
 impl Status {
@@ -313,10 +333,15 @@ impl Status {
     pub(crate) fn from_incoming_transport(transport_status: TransportStatus) -> Status {
         match transport_status {
             TransportStatus::Success => Status::ok(""),
-            TransportStatus::Error(code) => match code.get() {
-                402 => Status::permission_denied("Access Denied from server ACL engine"),
-                _ => Status::data_loss(format!("receiving wc error: {code}")),
-            },
+            TransportStatus::Error(code) => {
+                if let Some(rejected) = transport_status.rejected_code() {
+                    return Status::new(Code::from(rejected as i32), "rejected by policy");
+                }
+                match code.get() {
+                    402 => Status::permission_denied("Access Denied from server ACL engine"),
+                    _ => Status::data_loss(format!("receiving wc error: {code}")),
+                }
+            }
         }
     }
 
@@ -337,6 +362,15 @@ impl Status {
         Status::from_error(err)
     }
 
+    pub(crate) fn from_rpc_status(rpc_status: &RpcStatus) -> Status {
+        // the message comes from the peer, so it is not trusted to be UTF-8
+        Status::from_parts(rpc_status.code, rpc_status.message.as_bytes())
+    }
+
+    fn from_parts(code: i32, message: &[u8]) -> Status {
+        Status::new(Code::from(code), String::from_utf8_lossy(message))
+    }
+
     /// Get the gRPC `Code` of this `Status`.
     pub fn code(&self) -> Code {
         self.code
@@ -421,7 +455,7 @@ impl From<crate::Error> for Status {
             Serde(..) => Code::InvalidArgument,
             NoAddrResolved => Code::NotFound,
             Connect(..) => Code::Unavailable,
-            ConnectionClosed => Code::Cancelled,
+            ConnectionClosed => Code::Unavailable,
         };
         Status::new(code, err.to_string())
     }
@@ -613,4 +647,31 @@ mod tests {
         assert_eq!(Status::data_loss("").code(), Code::DataLoss);
         assert_eq!(Status::unauthenticated("").code(), Code::Unauthenticated);
     }
+
+    #[test]
+    fn from_rpc_status_empty_message() {
+        let rpc_status = RpcStatus {
+            code: Code::NotFound as i32,
+            message: crate::alloc::String::new(),
+        };
+        let status = Status::from_rpc_status(&rpc_status);
+
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "");
+    }
+
+    #[test]
+    fn from_rpc_status_parts() {
+        let status = Status::from_parts(Code::Internal as i32, b"oops");
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(status.message(), "oops");
+
+        // an invalid code from the peer
+        assert_eq!(Status::from_parts(42, b"").code(), Code::Unknown);
+
+        // invalid UTF-8 from the peer is replaced rather than trusted
+        let status = Status::from_parts(Code::DataLoss as i32, b"bad \xF0\x90 bytes");
+        assert_eq!(status.code(), Code::DataLoss);
+        assert_eq!(status.message(), "bad \u{FFFD} bytes");
+    }
 }
@@ -1,5 +1,6 @@
 //! Client implementation.
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::future::Future;
 use std::hash::Hash;
 use std::marker::PhantomData;
@@ -7,19 +8,23 @@ use std::net::{SocketAddr, ToSocketAddrs};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use futures::{Stream, StreamExt};
 use ipc::channel::{Receiver, TryRecvError};
-use phoenix_api::rpc::{CallId, MessageErased, MessageMeta, RpcId, RpcMsgType, TransportStatus};
+use phoenix_api::rpc::{
+    CallId, MessageErased, MessageMeta, RpcId, RpcMsgType, StatusCode, TransportStatus,
+};
 use phoenix_api::{AsHandle, Handle};
 use phoenix_api_mrpc::cmd::{Command, CompletionKind};
 use phoenix_api_mrpc::dp;
 use phoenix_syscalls::_rx_recv_impl as rx_recv_impl;
 
 use super::conn::Connection;
-use super::reply_cache::ReplyCache;
+use super::reply_cache::{ReplyCache, ReplyError};
 use super::RpcData;
 use super::LOCAL_REACTOR;
-use crate::{Error, RRef, ReadHeap, Status, WRef, MRPC_CTX};
+use crate::{Code, Error, RRef, ReadHeap, RpcStatus, Status, WRef, MRPC_CTX};
 
 #[cfg(feature = "timing")]
 use crate::timing::{SampleKind, Timer};
@@ -31,8 +36,13 @@ thread_local! {
 
 /// Future that represents an ongoing RPC. Resolves to a read-only [`RRef<T>`] on success.
 /// Resolves to a [`Status`] on failure.
+///
+/// Dropping the future before it resolves cancels the RPC.
 pub struct ReqFuture<'a, T> {
-    rpc_id: RpcId,
+    meta: MessageMeta,
+    deadline: Option<Instant>,
+    // whether the RPC has resolved, either by a reply or by cancellation
+    done: bool,
     client: &'a ClientStub,
     _marker: PhantomData<T>,
 }
@@ -47,39 +57,217 @@ impl<'a, T: Unpin> Future for ReqFuture<'a, T> {
 
         this.client.dispatch()?;
         // let inner = this.client.inner.borrow();
-        let inner = this.client.inner.lock();
+        let mut inner = this.client.inner.lock();
+
+        let call_id = this.meta.call_id;
+        let rpc_id = RpcId::new(this.meta.conn_id, call_id);
 
         // Poll::Pending
-        if let Some(reply) = inner
-            .reply_cache
-            .get(this.rpc_id.1)
-            .expect("Expect an entry")
-        {
+        let reply = inner.reply_cache.pop(call_id).expect("Expect an entry");
+        if let Some(reply) = reply {
+            let rest = inner.reply_cache.remove(call_id).unwrap();
+            drop(inner);
+            this.done = true;
+            this.client.release_replies(rest);
+
             let ret = match reply {
                 Ok(reply) => {
                     tracing::trace!(
                         "ReqFuture receive reply from mRPC engine, rpc_id={:?}",
-                        this.rpc_id
+                        rpc_id
                     );
-                    let read_heap = this
-                        .client
-                        .conns
-                        .get(&reply.meta.conn_id)
-                        .unwrap()
-                        .map_alive(|alive| Arc::clone(&alive.read_heap))
-                        .expect("TODO: return an error when connection is dead rather than panic");
-                    Ok(RRef::new(reply, read_heap))
+                    let read_heap = this.client.read_heap(&reply)?;
+                    if reply.meta.status_code == StatusCode::ServerError {
+                        // the handler on the server returned an error
+                        let rpc_status = RRef::<RpcStatus>::new(&reply, read_heap);
+                        Err(Status::from_rpc_status(&rpc_status))
+                    } else {
+                        Ok(RRef::new(&reply, read_heap))
+                    }
                 }
-                Err(status) => Err(Status::from_incoming_transport(*status)),
+                Err(err) => Err(err.into_status()),
             };
             return Poll::Ready(ret);
         }
+        drop(inner);
+
+        if let Some(deadline) = this.deadline {
+            if Instant::now() >= deadline {
+                this.done = true;
+                this.client.cancel_call(&this.meta)?;
+                return Poll::Ready(Err(Status::deadline_exceeded(format!(
+                    "no reply within the deadline, rpc_id={:?}",
+                    rpc_id
+                ))));
+            }
+        }
 
         cx.waker().wake_by_ref();
         Poll::Pending
     }
 }
 
+impl<'a, T> Drop for ReqFuture<'a, T> {
+    fn drop(&mut self) {
+        if !self.done {
+            // the caller has given up the RPC
+            if let Err(e) = self.client.cancel_call(&self.meta) {
+                log::warn!("failed to cancel call {}: {}", self.meta.call_id, e);
+            }
+        }
+    }
+}
+
+/// Stream that represents an ongoing server-streaming RPC. Yields read-only [`RRef<T>`]s as the
+/// replies arrive, and ends when the server finishes the stream. Yields a [`Status`] and then ends
+/// on failure.
+///
+/// Dropping the stream before it ends cancels the RPC.
+pub struct ResponseStream<'a, T> {
+    meta: MessageMeta,
+    // whether the stream has ended, either by the server or by an error
+    done: bool,
+    client: &'a ClientStub,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T: Unpin> Stream for ResponseStream<'a, T> {
+    type Item = Result<RRef<T>, Status>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        futures::ready!(LOCAL_REACTOR.with_borrow_mut(|r| r.poll(cx)))?;
+
+        this.client.dispatch()?;
+        let mut inner = this.client.inner.lock();
+
+        let call_id = this.meta.call_id;
+        let reply = match inner.reply_cache.pop(call_id).expect("Expect an entry") {
+            Some(reply) => reply,
+            None => {
+                drop(inner);
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        };
+
+        if let Ok(msg) = reply {
+            if msg.meta.msg_type == RpcMsgType::StreamResponse {
+                drop(inner);
+                return match this.client.read_heap(&msg) {
+                    Ok(read_heap) => Poll::Ready(Some(Ok(RRef::new(&msg, read_heap)))),
+                    Err(status) => {
+                        this.done = true;
+                        this.client.cancel_call(&this.meta)?;
+                        Poll::Ready(Some(Err(status)))
+                    }
+                };
+            }
+        }
+
+        // the stream has ended
+        let rest = inner.reply_cache.remove(call_id).unwrap();
+        drop(inner);
+        this.done = true;
+        this.client.release_replies(rest);
+
+        let item = match reply {
+            // either the end of the stream or an error for the entire call, both carry an RPC
+            // status
+            Ok(msg) => match this.client.read_heap(&msg) {
+                Ok(read_heap) => {
+                    let rpc_status = RRef::<RpcStatus>::new(&msg, read_heap);
+                    let status = Status::from_rpc_status(&rpc_status);
+                    (status.code() != Code::Ok).then_some(Err(status))
+                }
+                Err(status) => Some(Err(status)),
+            },
+            Err(err) => Some(Err(err.into_status())),
+        };
+        Poll::Ready(item)
+    }
+}
+
+impl<'a, T> Drop for ResponseStream<'a, T> {
+    fn drop(&mut self) {
+        if !self.done {
+            // the caller has given up the RPC
+            if let Err(e) = self.client.cancel_call(&self.meta) {
+                log::warn!("failed to cancel call {}: {}", self.meta.call_id, e);
+            }
+        }
+    }
+}
+
+/// The state of the connection of a [`ClientStub`], after the gRPC channel connectivity states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectivityState {
+    /// The connection is lost, and the next call re-establishes it.
+    Connecting,
+    /// The connection is established.
+    Ready,
+    /// The connection is lost, and re-establishing it waits for the backoff to expire. Calls
+    /// fail with [`Status::unavailable`] in the meantime.
+    TransientFailure,
+    /// The connection is lost, and it is not going to be re-established.
+    Shutdown,
+}
+
+/// How a [`ClientStub`] re-establishes a lost connection.
+///
+/// The connection is re-established when a call is initiated. The first attempt is made right
+/// away, and each failed attempt delays the next one by an exponentially growing backoff.
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// The delay after the first failed attempt.
+    pub initial_backoff: Duration,
+    /// The upper bound of the delay between two attempts.
+    pub max_backoff: Duration,
+    /// The factor the delay grows by after each failed attempt.
+    pub multiplier: f64,
+    /// The number of failed attempts before giving up, or `None` to never give up.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 1.6,
+            max_attempts: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Backoff {
+    // failed attempts since the connection was lost
+    failures: u32,
+    // the delay after the last failed attempt
+    delay: Duration,
+    // no attempt is made before this instant
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    fn fail(&mut self, policy: &ReconnectPolicy) {
+        self.delay = if self.failures == 0 {
+            policy.initial_backoff
+        } else {
+            self.delay
+                .mul_f64(policy.multiplier)
+                .min(policy.max_backoff)
+        };
+        self.failures += 1;
+        self.next_attempt = Instant::now().checked_add(self.delay);
+    }
+}
+
 impl !Send for ClientStub {}
 impl !Sync for ClientStub {}
 
@@ -91,9 +279,16 @@ pub struct ClientStub {
     vconn: Connection,
     // A connection could go into error state, in that case, all subsequent operations over this
     // connection would return an error.
-    conns: HashMap<Handle, Connection>,
+    conns: RefCell<HashMap<Handle, Connection>>,
     // inner: RefCell<Inner>,
     inner: spin::Mutex<Inner>,
+    // The id of the stub in the reactor.
+    stub_id: usize,
+    // The address to re-establish the connection with. Only a stub with a single connection
+    // reconnects.
+    addr: Option<SocketAddr>,
+    reconnect: Option<ReconnectPolicy>,
+    backoff: RefCell<Backoff>,
 }
 
 #[derive(Debug)]
@@ -118,36 +313,210 @@ impl ClientStub {
         Req: RpcData,
         Res: Unpin + RpcData,
     {
-        let conn_id = self.master_conn().handle();
+        self.unary_with_deadline(service_id, func_id, call_id, req, None)
+    }
+
+    /// Issue a single unary RPC request that must complete within `timeout`.
+    ///
+    /// The future resolves to [`Status::deadline_exceeded`] when the timeout expires, and the
+    /// server is asked to abandon the call. The timeout is also carried in the
+    /// [`MessageMeta`] of the request.
+    pub fn unary_with_timeout<Req, Res>(
+        &self,
+        service_id: u32,
+        func_id: u32,
+        call_id: CallId,
+        req: WRef<Req>,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<RRef<Res>, Status>> + '_
+    where
+        Req: RpcData,
+        Res: Unpin + RpcData,
+    {
+        self.unary_with_deadline(service_id, func_id, call_id, req, Some(timeout))
+    }
+
+    fn unary_with_deadline<Req, Res>(
+        &self,
+        service_id: u32,
+        func_id: u32,
+        call_id: CallId,
+        req: WRef<Req>,
+        timeout: Option<Duration>,
+    ) -> ReqFuture<'_, Res>
+    where
+        Req: RpcData,
+        Res: Unpin + RpcData,
+    {
+        // A timeout that does not fit in the meta is not told to the server, but it is still
+        // enforced by the client.
+        let timeout_us = timeout.map_or(0, |timeout| {
+            u32::try_from(timeout.as_micros()).map_or(0, |us| us.max(1))
+        });
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
 
-        // construct meta
         let meta = MessageMeta {
-            conn_id,
-            service_id,
-            func_id,
-            call_id,
             token: req.token().0 as u64,
-            msg_type: RpcMsgType::Request,
-            status_code: phoenix_api::rpc::StatusCode::Success,
+            timeout_us,
+            ..self.new_meta(service_id, func_id, call_id, RpcMsgType::Request)
         };
 
-        self.post_request(req, meta).unwrap();
+        self.start_call(req, meta);
 
         ReqFuture {
-            rpc_id: RpcId(conn_id, call_id),
+            meta,
+            deadline,
+            done: false,
+            client: self,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Issue a server-streaming RPC request. The returned stream yields the replies of the server.
+    pub fn server_streaming<Req, Res>(
+        &self,
+        service_id: u32,
+        func_id: u32,
+        call_id: CallId,
+        req: WRef<Req>,
+    ) -> ResponseStream<'_, Res>
+    where
+        Req: RpcData,
+        Res: Unpin + RpcData,
+    {
+        let meta = MessageMeta {
+            token: req.token().0 as u64,
+            ..self.new_meta(service_id, func_id, call_id, RpcMsgType::Request)
+        };
+
+        self.start_call(req, meta);
+
+        ResponseStream {
+            meta,
+            done: false,
             client: self,
             _marker: PhantomData,
         }
     }
 
+    /// Issue a client-streaming RPC. Each request yielded by `requests` is sent as soon as it is
+    /// available. The future resolves to the single reply of the server.
+    ///
+    /// A stream carries at most `u16::MAX` requests.
+    pub fn client_streaming<'a, Req, Res, S>(
+        &'a self,
+        service_id: u32,
+        func_id: u32,
+        call_id: CallId,
+        requests: S,
+    ) -> impl Future<Output = Result<RRef<Res>, Status>> + 'a
+    where
+        Req: RpcData,
+        Res: Unpin + RpcData,
+        S: Stream<Item = WRef<Req>> + 'a,
+    {
+        async move {
+            futures::pin_mut!(requests);
+            let mut meta = self.new_meta(service_id, func_id, call_id, RpcMsgType::StreamRequest);
+
+            // from now on, giving up the future cancels the call
+            let reply = ReqFuture {
+                meta,
+                deadline: None,
+                done: false,
+                client: self,
+                _marker: PhantomData,
+            };
+
+            let mut seq = 0u16;
+            while let Some(req) = requests.next().await {
+                meta.call_id = call_id.with_stream_seq(seq);
+                meta.token = req.token().0 as u64;
+                self.post_request(req, meta)?;
+                seq = seq
+                    .checked_add(1)
+                    .ok_or_else(|| Status::out_of_range("too many requests in a stream"))?;
+            }
+
+            // mark the end of the stream
+            let end = WRef::new(RpcStatus::new(&Status::ok("")));
+            meta.msg_type = RpcMsgType::StreamRequestEnd;
+            meta.call_id = call_id.with_stream_seq(seq);
+            meta.token = end.token().0 as u64;
+            self.post_request(end, meta)?;
+
+            reply.await
+        }
+    }
+
+    fn new_meta(
+        &self,
+        service_id: u32,
+        func_id: u32,
+        call_id: CallId,
+        msg_type: RpcMsgType,
+    ) -> MessageMeta {
+        MessageMeta {
+            conn_id: self.with_master_conn(Connection::handle),
+            service_id,
+            func_id,
+            call_id,
+            token: 0,
+            msg_type,
+            status_code: StatusCode::Success,
+            has_metadata: false,
+            timeout_us: 0,
+        }
+    }
+
     /// Prepare to make an RPC.
     ///
-    /// Allocating an entry to the ongoing RPC slab.
+    /// Allocating an entry to the ongoing RPC slab. A lost connection is re-established first if
+    /// the [`ReconnectPolicy`] allows.
     #[inline]
     pub fn initiate_call(&self) -> CallId {
+        if self.connectivity_state() == ConnectivityState::Connecting {
+            self.reconnect();
+        }
         // self.inner.borrow_mut().reply_cache.initiate_call()
         self.inner.lock().reply_cache.initiate_call()
     }
+
+    /// Sets how a lost connection is re-established. A lost connection stays dead with `None`,
+    /// which is the default.
+    ///
+    /// Only a stub created by [`ClientStub::connect`] reconnects.
+    pub fn set_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect = policy;
+    }
+
+    /// Returns the state of the connection. The completions from the backend are processed
+    /// first, so a lost connection is noticed without making a call.
+    pub fn connectivity_state(&self) -> ConnectivityState {
+        if let Err(e) = self.poll_completions() {
+            log::warn!("failed to poll completions: {}", e);
+        }
+
+        if self.with_master_conn(Connection::is_alive) {
+            return ConnectivityState::Ready;
+        }
+        let backoff = self.backoff.borrow();
+        match (self.addr, &self.reconnect) {
+            (Some(_), Some(policy))
+                if policy
+                    .max_attempts
+                    .map_or(true, |max| backoff.failures < max) =>
+            {
+                match backoff.next_attempt {
+                    Some(next_attempt) if Instant::now() < next_attempt => {
+                        ConnectivityState::TransientFailure
+                    }
+                    _ => ConnectivityState::Connecting,
+                }
+            }
+            _ => ConnectivityState::Shutdown,
+        }
+    }
 }
 
 impl ClientStub {
@@ -157,13 +526,21 @@ impl ClientStub {
             dp::Completion::Incoming(msg) => {
                 let call_id = msg.meta.call_id;
                 match msg.meta.msg_type {
-                    RpcMsgType::Request => {
+                    RpcMsgType::Request
+                    | RpcMsgType::Cancel
+                    | RpcMsgType::StreamRequest
+                    | RpcMsgType::StreamRequestEnd => {
                         // server receives requests
                         panic!("impossible, something is wrong")
                     }
-                    RpcMsgType::Response => {
+                    RpcMsgType::Response
+                    | RpcMsgType::StreamResponse
+                    | RpcMsgType::StreamResponseEnd => {
                         // client receives responses, update the ReplyCache
-                        inner.reply_cache.update(call_id, Ok(msg)).unwrap();
+                        if inner.reply_cache.push(call_id, Ok(msg)).is_err() {
+                            // the call has been cancelled, drop the late reply
+                            self.release_reply(&msg);
+                        }
                     }
                 }
             }
@@ -178,20 +555,17 @@ impl ClientStub {
                 match status {
                     TransportStatus::Error(code) => match code.get() {
                         402 => {}
-                        _ => {
-                            self.master_conn()
-                                .map_alive(|alive| alive.pending.remove(&rpc_id))?;
-                        }
+                        _ => self.remove_pending(&rpc_id),
                     },
-                    _ => {
-                        self.master_conn()
-                            .map_alive(|alive| alive.pending.remove(&rpc_id))?;
-                    }
+                    _ => self.remove_pending(&rpc_id),
                 }
 
                 if let TransportStatus::Error(_) = status {
-                    // Update the ReplyCache with error
-                    inner.reply_cache.update(rpc_id.1, Err(status)).unwrap();
+                    // Update the ReplyCache with error, unless the call has been cancelled
+                    let err = Err(ReplyError::Transport(status));
+                    if inner.reply_cache.push(rpc_id.1, err).is_err() {
+                        log::debug!("{:?} failed after cancelled, status: {:?}", rpc_id, status);
+                    }
                 }
             }
             dp::Completion::RecvError(conn_id, status) => {
@@ -203,13 +577,97 @@ impl ClientStub {
                     conn_id,
                     status
                 );
-                self.master_conn().close();
+                // a connection replaced by a reconnect has nothing left to fail
+                if conn_id == self.vconn.handle() || self.conns.borrow().contains_key(&conn_id) {
+                    self.with_master_conn(Connection::close);
+                    // no reply is coming for the outstanding calls
+                    inner.reply_cache.push_all(Err(ReplyError::Disconnected));
+                }
+            }
+            dp::Completion::Cancel(rpc_id) => {
+                log::warn!("Unexpected cancel for {:?} on a client", rpc_id);
             }
         }
 
         Ok(())
     }
 
+    /// Abandons an ongoing call. Frees its entry in the ReplyCache, and asks the server to stop
+    /// working on it if the reply has not arrived.
+    fn cancel_call(&self, meta: &MessageMeta) -> Result<(), Error> {
+        let replies = match self.inner.lock().reply_cache.remove(meta.call_id) {
+            Ok(replies) => replies,
+            // the call has been removed, nothing is in flight
+            Err(_) => return Ok(()),
+        };
+
+        // the call has failed or the last reply has arrived
+        let finished = replies.iter().any(|reply| match reply {
+            Ok(reply) => reply.meta.msg_type != RpcMsgType::StreamResponse,
+            Err(_) => true,
+        });
+        self.release_replies(replies);
+        // the server of a lost connection is not working on the call anymore
+        let connected =
+            self.with_master_conn(|conn| conn.is_alive() && conn.handle() == meta.conn_id);
+        if finished || !connected {
+            return Ok(());
+        }
+
+        let cancel = dp::WorkRequest::Cancel(MessageMeta {
+            call_id: meta.call_id.without_stream_seq(),
+            msg_type: RpcMsgType::Cancel,
+            has_metadata: false,
+            ..*meta
+        });
+        MRPC_CTX.with(|ctx| {
+            let mut sent = false;
+            while !sent {
+                ctx.service.enqueue_wr_with(|ptr, _count| unsafe {
+                    ptr.cast::<dp::WorkRequest>().write(cancel);
+                    sent = true;
+                    1
+                })?;
+            }
+            Ok(())
+        })
+    }
+
+    fn read_heap(&self, reply: &MessageErased) -> Result<Arc<ReadHeap>, Status> {
+        self.conns
+            .borrow()
+            .get(&reply.meta.conn_id)
+            .and_then(|conn| conn.map_alive(|alive| Arc::clone(&alive.read_heap)).ok())
+            .ok_or_else(|| Status::unavailable("connection lost"))
+    }
+
+    /// Releases the receive buffers of the replies nobody waits for.
+    fn release_replies(&self, replies: VecDeque<Result<MessageErased, ReplyError>>) {
+        for reply in replies.into_iter().flatten() {
+            self.release_reply(&reply);
+        }
+    }
+
+    /// Releases the receive buffers of a reply nobody waits for.
+    fn release_reply(&self, reply: &MessageErased) {
+        if let Some(conn) = self.conns.borrow().get(&reply.meta.conn_id) {
+            // nothing to release if the connection is dead
+            if let Ok(read_heap) = conn.map_alive(|alive| Arc::clone(&alive.read_heap)) {
+                drop(RRef::<()>::new(reply, read_heap));
+            }
+        }
+    }
+
+    /// Fetches the completions from the backend and dispatches those of this stub.
+    fn poll_completions(&self) -> Result<(), Error> {
+        // the reactor never waits, so the context is not used
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        if let Poll::Ready(Err(e)) = LOCAL_REACTOR.with_borrow_mut(|r| r.poll(&mut cx)) {
+            return Err(e);
+        }
+        self.dispatch()
+    }
+
     /// Dispatch completions from the Receiver.
     pub(crate) fn dispatch(&self) -> Result<(), Error> {
         // Because for client, each stub only has one connection, there is no real dispatch here.
@@ -244,19 +702,27 @@ impl ClientStub {
         // self.conn
         //     .hold_rpc(RpcId::new(meta.conn_id, meta.call_id), WRef::clone(&msg))?;
 
-        self.master_conn()
-            .map_alive(|alive: &crate::stub::conn::AliveConnection| {
+        self.with_master_conn(|conn| {
+            conn.map_alive(|alive: &crate::stub::conn::AliveConnection| {
                 alive
                     .pending
                     .insert(RpcId::new(meta.conn_id, meta.call_id), WRef::clone(&msg))
-            })?;
+            })
+        })?;
 
         // construct the request
+        let (metadata_addr_app, metadata_addr_backend) = msg.metadata_addrs();
+        let meta = MessageMeta {
+            has_metadata: metadata_addr_backend != 0,
+            ..meta
+        };
         let (ptr_app, ptr_backend) = msg.into_shmptr().to_raw_parts();
         let erased = MessageErased {
             meta,
             shm_addr_app: ptr_app.addr().get(),
             shm_addr_backend: ptr_backend.addr().get(),
+            metadata_addr_app,
+            metadata_addr_backend,
         };
 
         let req = dp::WorkRequest::Call(erased);
@@ -283,13 +749,128 @@ impl ClientStub {
         })
     }
 
-    fn master_conn(&self) -> &Connection {
+    /// Issues a request. The call fails right away if the connection is lost.
+    fn start_call<T: RpcData>(&self, msg: WRef<T>, meta: MessageMeta) {
+        match self.post_request(msg, meta) {
+            Ok(()) => {}
+            Err(Error::ConnectionClosed) => {
+                let err = Err(ReplyError::Disconnected);
+                // the call is fresh, it cannot have been removed
+                self.inner
+                    .lock()
+                    .reply_cache
+                    .push(meta.call_id, err)
+                    .unwrap();
+            }
+            Err(e) => panic!("failed to post request: {}", e),
+        }
+    }
+
+    /// Stops holding a request that the backend has finished with.
+    fn remove_pending(&self, rpc_id: &RpcId) {
+        self.with_master_conn(|conn| {
+            // the requests are no longer held once the connection is lost
+            let _ = conn.map_alive(|alive| alive.pending.remove(rpc_id));
+        });
+    }
+
+    fn with_master_conn<T, F: FnOnce(&Connection) -> T>(&self, f: F) -> T {
         if self.vconn.handle().is_master() {
-            &self.vconn
+            f(&self.vconn)
         } else {
-            self.conns.get(&self.vconn.handle()).unwrap()
+            f(&self.conns.borrow()[&self.vconn.handle()])
+        }
+    }
+
+    /// Attempts to re-establish the lost connection, and backs off on failure.
+    fn reconnect(&self) {
+        let (addr, policy) = match (self.addr, &self.reconnect) {
+            (Some(addr), Some(policy)) => (addr, policy),
+            _ => return,
+        };
+        match Self::establish(addr) {
+            Ok(conn) => {
+                let conn_handle = conn.handle();
+                LOCAL_REACTOR.with_borrow_mut(|r| r.register_connection(self.stub_id, &conn));
+                let mut conns = self.conns.borrow_mut();
+                conns.remove(&self.vconn.handle());
+                conns.insert(conn_handle, conn);
+                drop(conns);
+                self.vconn.revive(conn_handle, ReadHeap::default());
+                *self.backoff.borrow_mut() = Backoff::default();
+                log::info!("Reconnected to {}, conn_id: {:?}", addr, conn_handle);
+            }
+            Err(e) => {
+                let mut backoff = self.backoff.borrow_mut();
+                backoff.fail(policy);
+                log::warn!(
+                    "Failed to reconnect to {} ({} attempts): {}",
+                    addr,
+                    backoff.failures,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Connects to a given socket address, and maps the receive buffers of the new connection.
+    fn establish(addr: SocketAddr) -> Result<Connection, Error> {
+        MRPC_CTX.with(|ctx| {
+            ctx.service.send_cmd(Command::Connect(addr))?;
+            // the backend only passes the receive buffers of a successful connect
+            let conn_resp = match ctx.service.recv_comp()?.0 {
+                Ok(CompletionKind::Connect(conn_resp)) => conn_resp,
+                Err(e) => return Err(Error::Connect(e)),
+                otherwise => panic!("Expect Connect, found {:?}", otherwise),
+            };
+            let fds = ctx.service.recv_fd()?;
+            // use memfd::Memfd;
+            assert_eq!(fds.len(), conn_resp.read_regions.len());
+
+            let conn_handle = conn_resp.conn_handle;
+
+            let read_heap = ReadHeap::new(&conn_resp, &fds);
+            let vaddrs = read_heap
+                .rbufs
+                .iter()
+                .map(|rbuf| (rbuf.as_handle(), rbuf.as_ptr().expose_addr()))
+                .collect();
+
+            // return the mapped addr back
+            let req = Command::NewMappedAddrs(conn_handle, vaddrs);
+            ctx.service.send_cmd(req)?;
+            // wait for the reply!
+            rx_recv_impl!(ctx.service, CompletionKind::NewMappedAddrs)?;
+
+            Ok(Connection::new(conn_handle, read_heap))
+        })
+    }
+
+    /// Registers the stub and its connections with the reactor.
+    fn new(vconn: Connection, conns: Vec<Connection>, addr: Option<SocketAddr>) -> Self {
+        let (stub_id, receiver) = LOCAL_REACTOR.with_borrow_mut(|r| r.register_stub());
+        for conn in conns.iter().chain([&vconn]) {
+            LOCAL_REACTOR.with_borrow_mut(|r| r.register_connection(stub_id, conn));
+        }
+        let conns = conns
+            .into_iter()
+            .map(|conn| (conn.handle(), conn))
+            .collect();
+        Self {
+            vconn,
+            conns: RefCell::new(conns),
+            // inner: RefCell::new(Inner {
+            inner: spin::Mutex::new(Inner {
+                receiver,
+                reply_cache: ReplyCache::new(),
+            }),
+            stub_id,
+            addr,
+            reconnect: None,
+            backoff: RefCell::new(Backoff::default()),
         }
     }
+
     /// Creates an RPC client by connecting to a given socket address.
     // TODO(cjr): Change this to async too
     pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, Error> {
@@ -297,48 +878,9 @@ impl ClientStub {
             .to_socket_addrs()?
             .next()
             .ok_or(Error::NoAddrResolved)?;
-        let req = Command::Connect(connect_addr);
-
-        MRPC_CTX.with(|ctx| {
-            ctx.service.send_cmd(req)?;
-            let fds = ctx.service.recv_fd()?;
-            rx_recv_impl!(ctx.service, CompletionKind::Connect, conn_resp, {
-                // use memfd::Memfd;
-                assert_eq!(fds.len(), conn_resp.read_regions.len());
-
-                let conn_handle = conn_resp.conn_handle;
-
-                let read_heap = ReadHeap::new(&conn_resp, &fds);
-                let vaddrs = read_heap
-                    .rbufs
-                    .iter()
-                    .map(|rbuf| (rbuf.as_handle(), rbuf.as_ptr().expose_addr()))
-                    .collect();
-
-                // return the mapped addr back
-                let req = Command::NewMappedAddrs(conn_handle, vaddrs);
-                ctx.service.send_cmd(req)?;
-                // wait for the reply!
-                rx_recv_impl!(ctx.service, CompletionKind::NewMappedAddrs)?;
-
-                // register the stub with the reactor
-                let conn = Connection::new(conn_handle, read_heap);
-                let (stub_id, receiver) = LOCAL_REACTOR.with_borrow_mut(|r| r.register_stub());
-                LOCAL_REACTOR.with_borrow_mut(|r| r.register_connection(stub_id, &conn));
-
-                let mut conns = HashMap::new();
-                conns.insert(conn.handle().clone(), conn);
-                Ok(Self {
-                    vconn: Connection::vconn(conn_handle),
-                    conns: conns,
-                    // inner: RefCell::new(Inner {
-                    inner: spin::Mutex::new(Inner {
-                        receiver,
-                        reply_cache: ReplyCache::new(),
-                    }),
-                })
-            })
-        })
+        let conn = Self::establish(connect_addr)?;
+        let vconn = Connection::vconn(conn.handle());
+        Ok(Self::new(vconn, vec![conn], Some(connect_addr)))
     }
 
     /// Creates an RPC client by connecting to multiple socket address.
@@ -350,78 +892,18 @@ impl ClientStub {
             .into_iter()
             .flatten()
             .collect();
-        let mut conns = Vec::new();
-        let mut handles = Vec::new();
-        let mut vconn = None;
-        for addr in connect_addrs {
-            let cmd = Command::Connect(addr);
-            MRPC_CTX.with(|ctx| {
-                ctx.service.send_cmd(cmd).unwrap();
-                let fds = ctx.service.recv_fd().unwrap();
-                match ctx.service.recv_comp().unwrap().0 {
-                    Ok(CompletionKind::Connect(conn_resp)) => {
-                        assert_eq!(fds.len(), conn_resp.read_regions.len());
-
-                        let conn_handle = conn_resp.conn_handle;
-
-                        let read_heap = ReadHeap::new(&conn_resp, &fds);
-                        let vaddrs = read_heap
-                            .rbufs
-                            .iter()
-                            .map(|rbuf| (rbuf.as_handle(), rbuf.as_ptr().expose_addr()))
-                            .collect();
-
-                        // return the mapped addr back
-                        let req = Command::NewMappedAddrs(conn_handle, vaddrs);
-                        ctx.service.send_cmd(req).unwrap();
-                        // wait for the reply!
-                        match ctx.service.recv_comp().unwrap().0 {
-                            Ok(CompletionKind::NewMappedAddrs) => {}
-                            Err(e) => panic!("{:?}", e),
-                            _ => panic!("unmatched branch"),
-                        }
-
-                        // register the stub with the reactor
-                        let conn = Connection::new(conn_handle, read_heap);
-                        handles.push(conn.handle().clone());
-                        conns.push(conn);
-                    }
-                    Err(e) => {
-                        panic!("{:?}", e)
-                    }
-                    _ => panic!("unmatched branch"),
-                }
-            });
-        }
-        MRPC_CTX.with(|ctx| {
+        let conns = connect_addrs
+            .into_iter()
+            .map(Self::establish)
+            .collect::<Result<Vec<_>, _>>()?;
+        let handles = conns.iter().map(Connection::handle).collect();
+        let vconn = MRPC_CTX.with(|ctx| {
             let cmd = Command::MultiConnect(handles);
-            ctx.service.send_cmd(cmd).unwrap();
-            match ctx.service.recv_comp().unwrap().0 {
-                Ok(CompletionKind::MultiConnect(handle)) => {
-                    //assert!(handle == Handle::MASTER);
-                    _ = vconn.insert(Connection::vconn(handle));
-                }
-                Err(e) => panic!("{:?}", e),
-                _ => panic!("unmatched branch"),
-            }
-        });
-        let (stub_id, receiver) = LOCAL_REACTOR.with_borrow_mut(|r| r.register_stub());
-        for conn in &conns {
-            LOCAL_REACTOR.with_borrow_mut(|r| r.register_connection(stub_id, conn));
-        }
-        let rv = vconn.as_ref().unwrap();
-        LOCAL_REACTOR.with_borrow_mut(|r| r.register_connection(stub_id, rv));
-        let mut conn_map = HashMap::new();
-        for conn in conns {
-            conn_map.insert(conn.handle().clone(), conn);
-        }
-        Ok(Self {
-            vconn: vconn.unwrap(),
-            conns: conn_map,
-            inner: spin::Mutex::new(Inner {
-                receiver,
-                reply_cache: ReplyCache::new(),
-            }),
-        })
+            ctx.service.send_cmd(cmd)?;
+            rx_recv_impl!(ctx.service, CompletionKind::MultiConnect, handle, {
+                Ok(Connection::vconn(handle))
+            })
+        })?;
+        Ok(Self::new(vconn, conns, None))
     }
 }
@@ -63,14 +63,25 @@ impl Connection {
         }
     }
 
+    /// Marks the connection as dead. Closing a dead connection does nothing, as several
+    /// connections may report the loss of the same virtual connection.
     pub(crate) fn close(&self) {
         let mut inner = self.inner.borrow_mut();
-        match &mut *inner {
-            Inner::Alive(alive) => *inner = Inner::Dead(alive.close()),
-            Inner::Dead(_dead) => panic!("Double close on {:?}", self.handle()),
+        if let Inner::Alive(alive) = &mut *inner {
+            *inner = Inner::Dead(alive.close());
         }
     }
 
+    /// Replaces the connection with a newly established one.
+    pub(crate) fn revive(&self, handle: Handle, read_heap: ReadHeap) {
+        *self.inner.borrow_mut() = Inner::Alive(AliveConnection::new(handle, read_heap));
+    }
+
+    #[inline]
+    pub(crate) fn is_alive(&self) -> bool {
+        matches!(&*self.inner.borrow(), Inner::Alive(_))
+    }
+
     #[inline]
     pub(crate) fn handle(&self) -> Handle {
         let inner = self.inner.borrow();
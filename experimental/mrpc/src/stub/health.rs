@@ -0,0 +1,235 @@
+//! A health service in the style of the gRPC health checking protocol.
+//!
+//! [`LocalServer::add_health_service`] serves it next to the services of the server, and returns
+//! a [`HealthReporter`] that changes the status of the services at runtime. Load balancers probe
+//! the server with a [`HealthClient`]. The protocol is defined in `proto/health.proto`.
+//!
+//! [`LocalServer::add_health_service`]: super::LocalServer::add_health_service
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+
+use fnv::FnvHashMap as HashMap;
+
+use phoenix_api::rpc::MessageErased;
+
+use super::service::{service_error_handler, service_post_handler, service_pre_handler};
+use super::{update_protos, ClientStub, NamedService, Service};
+use crate::{Error, IntoWRef, RRef, ReadHeap, Status, WRef, WRefOpaque};
+
+const PROTO_SRCS: &[&str] = &[include_str!("../../proto/health.proto")];
+
+const CHECK_FUNC_ID: u32 = 2239138428;
+
+/// The serving status of a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum ServingStatus {
+    /// The status is not known.
+    Unknown = 0,
+    /// The service accepts calls.
+    Serving = 1,
+    /// The service does not accept calls, e.g., it is being drained.
+    NotServing = 2,
+    /// The service is not registered at the server.
+    ServiceUnknown = 3,
+}
+
+impl ServingStatus {
+    /// Converts the value of a [`HealthCheckResponse::status`] field.
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(ServingStatus::Unknown),
+            1 => Some(ServingStatus::Serving),
+            2 => Some(ServingStatus::NotServing),
+            3 => Some(ServingStatus::ServiceUnknown),
+            _ => None,
+        }
+    }
+}
+
+/// The request of `Check`.
+#[derive(Debug, Default)]
+pub struct HealthCheckRequest {
+    /// The fully qualified name of a service, e.g., `rpc_hello.Greeter`. Empty for the server.
+    pub service: crate::alloc::String,
+}
+
+/// The reply of `Check`.
+#[derive(Debug, Default)]
+pub struct HealthCheckResponse {
+    /// A [`ServingStatus`].
+    pub status: i32,
+}
+
+impl HealthCheckResponse {
+    /// Returns the status, or [`ServingStatus::Unknown`] for an invalid value.
+    pub fn status(&self) -> ServingStatus {
+        ServingStatus::from_i32(self.status).unwrap_or(ServingStatus::Unknown)
+    }
+}
+
+/// Changes the statuses reported by the health service of a server.
+///
+/// The statuses are shared by all clones of a reporter, so a reporter can be moved to another
+/// thread, e.g., to mark the server as [`NotServing`] before it shuts down.
+///
+/// [`NotServing`]: ServingStatus::NotServing
+#[derive(Debug, Clone)]
+pub struct HealthReporter {
+    statuses: Arc<spin::Mutex<HashMap<String, ServingStatus>>>,
+}
+
+impl HealthReporter {
+    pub(crate) fn new() -> Self {
+        let mut statuses = HashMap::default();
+        // the status of the whole server
+        statuses.insert(String::new(), ServingStatus::Serving);
+        HealthReporter {
+            statuses: Arc::new(spin::Mutex::new(statuses)),
+        }
+    }
+
+    /// Marks the service `S` as serving.
+    pub fn set_serving<S: NamedService>(&self) {
+        self.set_service_status(S::NAME, ServingStatus::Serving);
+    }
+
+    /// Marks the service `S` as not serving.
+    pub fn set_not_serving<S: NamedService>(&self) {
+        self.set_service_status(S::NAME, ServingStatus::NotServing);
+    }
+
+    /// Sets the status of the service named `service`. An empty name sets the status of the
+    /// whole server.
+    pub fn set_service_status(&self, service: &str, status: ServingStatus) {
+        self.statuses.lock().insert(service.to_owned(), status);
+    }
+
+    /// Starts reporting a service that is added to the server. It is serving unless its status
+    /// has been set.
+    pub(crate) fn register(&self, service: &str) {
+        self.statuses
+            .lock()
+            .entry(service.to_owned())
+            .or_insert(ServingStatus::Serving);
+    }
+
+    fn status(&self, service: &str) -> Option<ServingStatus> {
+        self.statuses.lock().get(service).copied()
+    }
+}
+
+/// Serves the health service of a server.
+#[derive(Debug)]
+pub(crate) struct HealthServer {
+    reporter: HealthReporter,
+}
+
+impl HealthServer {
+    pub(crate) fn new(reporter: HealthReporter) -> Result<Self, Error> {
+        update_protos(PROTO_SRCS)?;
+        Ok(HealthServer { reporter })
+    }
+}
+
+impl NamedService for HealthServer {
+    const SERVICE_ID: u32 = 2594391577;
+    const NAME: &'static str = "mrpc.health.v1.Health";
+    const METHODS: &'static [&'static str] = &["Check"];
+
+    fn protos() -> Vec<&'static str> {
+        PROTO_SRCS.to_vec()
+    }
+}
+
+#[crate::async_trait]
+impl Service for HealthServer {
+    async fn call(
+        &self,
+        req_opaque: MessageErased,
+        read_heap: Arc<ReadHeap>,
+    ) -> (WRefOpaque, MessageErased) {
+        match req_opaque.meta.func_id {
+            CHECK_FUNC_ID => {
+                let req = RRef::<HealthCheckRequest>::new(&req_opaque, read_heap);
+                match self.reporter.status(&req.service) {
+                    Some(status) => {
+                        let reply = WRef::new(HealthCheckResponse {
+                            status: status as i32,
+                        });
+                        service_post_handler(reply, &req_opaque)
+                    }
+                    None => {
+                        let status =
+                            Status::not_found(format!("unknown service: {}", req.service.as_str()));
+                        service_error_handler(status, &req_opaque)
+                    }
+                }
+            }
+            func_id => {
+                // release the receive buffer of the request
+                drop(service_pre_handler::<()>(&req_opaque, read_heap));
+                let status = Status::unimplemented(format!("unknown func_id: {}", func_id));
+                service_error_handler(status, &req_opaque)
+            }
+        }
+    }
+}
+
+/// A client of the health service.
+#[derive(Debug)]
+pub struct HealthClient {
+    stub: ClientStub,
+}
+
+impl HealthClient {
+    /// Connects to the server at `dst`.
+    pub fn connect<A: ToSocketAddrs>(dst: A) -> Result<Self, Error> {
+        update_protos(PROTO_SRCS)?;
+        let stub = ClientStub::connect(dst)?;
+        Ok(HealthClient { stub })
+    }
+
+    /// Returns the status of a service. Fails with `not_found` if the server does not know the
+    /// service.
+    pub fn check(
+        &self,
+        req: impl IntoWRef<HealthCheckRequest>,
+    ) -> impl std::future::Future<Output = Result<RRef<HealthCheckResponse>, Status>> + '_ {
+        let call_id = self.stub.initiate_call();
+
+        self.stub
+            .unary(Self::SERVICE_ID, CHECK_FUNC_ID, call_id, req.into_wref())
+    }
+}
+
+impl NamedService for HealthClient {
+    const SERVICE_ID: u32 = HealthServer::SERVICE_ID;
+    const NAME: &'static str = HealthServer::NAME;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_keeps_status() {
+        let reporter = HealthReporter::new();
+        assert_eq!(reporter.status(""), Some(ServingStatus::Serving));
+        assert_eq!(reporter.status("rpc_hello.Greeter"), None);
+
+        // the status set before the service is added is kept
+        reporter.set_service_status("rpc_hello.Greeter", ServingStatus::NotServing);
+        reporter.register("rpc_hello.Greeter");
+        assert_eq!(
+            reporter.status("rpc_hello.Greeter"),
+            Some(ServingStatus::NotServing)
+        );
+
+        // clones share the statuses
+        reporter
+            .clone()
+            .set_service_status("", ServingStatus::NotServing);
+        assert_eq!(reporter.status(""), Some(ServingStatus::NotServing));
+    }
+}
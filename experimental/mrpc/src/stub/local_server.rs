@@ -6,7 +6,7 @@ use std::sync::Arc;
 use std::task::Poll;
 
 use fnv::FnvHashMap as HashMap;
-use futures::future::poll_fn;
+use futures::future::{self, poll_fn, AbortHandle, Abortable, Aborted};
 use futures::select;
 use futures::stream::{FuturesUnordered, StreamExt};
 use futures::task::LocalFutureObj;
@@ -20,10 +20,13 @@ use phoenix_api_mrpc::dp;
 use phoenix_syscalls::_rx_recv_impl as rx_recv_impl;
 
 use super::conn::Connection;
-use super::service::{NamedService, Service};
+use super::health::{HealthReporter, HealthServer};
+use super::reflection::{ReflectionServer, ServiceDescriptor, ServiceRegistry};
+use super::service::{service_error_handler, service_pre_handler, NamedService, Service};
+use super::stream;
 use super::LOCAL_REACTOR;
 use crate::wref::WRefOpaque;
-use crate::{Error, ReadHeap, MRPC_CTX};
+use crate::{Error, ReadHeap, Status, MRPC_CTX};
 
 #[cfg(feature = "timing")]
 use crate::timing::{SampleKind, Timer};
@@ -33,11 +36,19 @@ thread_local! {
     static TIMER: std::cell::RefCell<Timer> = std::cell::RefCell::new(Timer::new());
 }
 
+// A call being served. Resolves to `Aborted` if the client cancels the call, in which case a
+// `cancelled` reply is sent instead.
+type RunningCall<'s> = LocalFutureObj<'s, Result<(WRefOpaque, MessageErased), Aborted>>;
+
 /// An RPC server that accepts connections to a TCP port.
 pub struct LocalServer {
     stub_id: usize,
     listener_handle: Handle,
     routes: HashMap<u32, Box<dyn Service>>,
+    // The services added to the server, in the order they were added.
+    registry: ServiceRegistry,
+    // Present once the health service is added.
+    health: Option<HealthReporter>,
     inner: RefCell<Inner>,
 }
 
@@ -58,6 +69,8 @@ pub(crate) struct Inner {
     receiver: Receiver<dp::Completion>,
     // Connections.
     connections: HashMap<Handle, Connection>,
+    // Handles to abort the calls being served, along with their requests.
+    abort_handles: HashMap<RpcId, (AbortHandle, MessageErased)>,
 }
 
 impl Inner {
@@ -92,9 +105,12 @@ impl LocalServer {
                     stub_id,
                     listener_handle,
                     routes: HashMap::default(),
+                    registry: ServiceRegistry::default(),
+                    health: None,
                     inner: RefCell::new(Inner {
                         connections: HashMap::default(),
                         receiver,
+                        abort_handles: HashMap::default(),
                     }),
                 })
             })
@@ -110,9 +126,41 @@ impl LocalServer {
         if self.routes.insert(S::SERVICE_ID, Box::new(svc)).is_some() {
             panic!("Hash collisions in func_id: {}", S::SERVICE_ID);
         }
+        self.registry.lock().push(ServiceDescriptor::of::<S>());
+        if let Some(reporter) = self.health.as_ref() {
+            reporter.register(S::NAME);
+        }
         self
     }
 
+    /// Add the health service, which reports whether the server and each of its services are
+    /// serving. All of them are serving until their status is changed through the returned
+    /// [`HealthReporter`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the health service has been added.
+    pub fn add_health_service(&mut self) -> Result<HealthReporter, Error> {
+        let reporter = HealthReporter::new();
+        for desc in self.registry.lock().iter() {
+            reporter.register(desc.name());
+        }
+        self.health = Some(reporter.clone());
+        self.add_service(HealthServer::new(reporter.clone())?);
+        Ok(reporter)
+    }
+
+    /// Add the reflection service, which lists the services of the server with their methods and
+    /// protos.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reflection service has been added.
+    pub fn add_reflection_service(&mut self) -> Result<&mut Self, Error> {
+        let svc = ReflectionServer::new(Arc::clone(&self.registry))?;
+        Ok(self.add_service(svc))
+    }
+
     /// Receive data from read shared heap and look up the routes and dispatch the erased message.
     ///
     /// Returns an [`Future`] that should be run by an `Executor`. The [`Future`] resolves to a
@@ -129,8 +177,13 @@ impl LocalServer {
             loop {
                 select! {
                     reply_erased = running.next() => {
-                        if reply_erased.is_none() { continue; }
-                        reply_buffer.push(reply_erased.unwrap());
+                        // the replies streamed by the call go before its last reply
+                        stream::take_outgoing(&mut reply_buffer);
+                        // skip the handlers of cancelled calls, their replies are queued by the cancel
+                        if let Some(Ok(reply_erased)) = reply_erased {
+                            self.finish_call(&reply_erased.1);
+                            reply_buffer.push(reply_erased);
+                        }
                     }
                     // has_queue_event = poll_fn(|cx| LOCAL_REACTOR.with_borrow_mut(|r| r.poll(cx))) => {
                     //     self.dispatch_requests(&mut running)?;
@@ -140,6 +193,7 @@ impl LocalServer {
                     }
                     default => {
                         // TODO(cjr): Having the default branch is not cpu efficient
+                        stream::take_outgoing(&mut reply_buffer);
                         if !reply_buffer.is_empty() {
                             self.post_replies(&mut reply_buffer)?;
                         }
@@ -180,8 +234,13 @@ impl LocalServer {
             loop {
                 select! {
                     reply_erased = running.next() => {
-                        if reply_erased.is_none() { continue; }
-                        reply_buffer.push(reply_erased.unwrap());
+                        // the replies streamed by the call go before its last reply
+                        stream::take_outgoing(&mut reply_buffer);
+                        // skip the handlers of cancelled calls, their replies are queued by the cancel
+                        if let Some(Ok(reply_erased)) = reply_erased {
+                            self.finish_call(&reply_erased.1);
+                            reply_buffer.push(reply_erased);
+                        }
                     }
                     // has_queue_event = poll_fn(|cx| LOCAL_REACTOR.with_borrow_mut(|r| r.poll(cx))) => {
                     //     self.dispatch_requests(&mut running)?;
@@ -194,6 +253,7 @@ impl LocalServer {
                     }
                     default => {
                         // TODO(cjr): Having the default branch is not cpu efficient
+                        stream::take_outgoing(&mut reply_buffer);
                         if !reply_buffer.is_empty() {
                             self.post_replies(&mut reply_buffer)?;
                         }
@@ -276,6 +336,13 @@ impl LocalServer {
         })
     }
 
+    /// Forgets the abort handle and the client stream of a call whose reply is ready.
+    fn finish_call(&self, reply: &MessageErased) {
+        let rpc_id = RpcId::new(reply.meta.conn_id, reply.meta.call_id.without_stream_seq());
+        self.inner.borrow_mut().abort_handles.remove(&rpc_id);
+        stream::remove_incoming(rpc_id);
+    }
+
     fn post_replies(&self, msg_buffer: &mut Vec<(WRefOpaque, MessageErased)>) -> Result<(), Error> {
         // track the msg as pending
 
@@ -311,35 +378,82 @@ impl LocalServer {
         Ok(())
     }
 
+    /// Routes a request to its service, and runs the handler.
+    fn start_call<'s>(
+        &'s self,
+        request: MessageErased,
+        inner: &mut Inner,
+        running: &mut FuturesUnordered<RunningCall<'s>>,
+    ) -> Result<(), Error> {
+        let service_id = request.meta.service_id;
+        match self.routes.get(&service_id) {
+            Some(s) => {
+                let conn = inner.get_connection(request.meta.conn_id)?;
+                // the connection has disappeared, do nothing
+
+                let read_heap = conn.map_alive(|alive| Arc::clone(&alive.read_heap))?;
+                let (abort_handle, abort_registration) = AbortHandle::new_pair();
+                let task = Abortable::new(s.call(request, read_heap), abort_registration);
+                inner.abort_handles.insert(
+                    RpcId::new(request.meta.conn_id, request.meta.call_id),
+                    (abort_handle, request),
+                );
+                running.push(LocalFutureObj::new(Box::new(task)));
+            }
+            None => {
+                log::warn!("unrecognized request: {:?}", request);
+                let conn = inner.get_connection(request.meta.conn_id)?;
+                let read_heap = conn.map_alive(|alive| Arc::clone(&alive.read_heap))?;
+                // release the receive buffer of the request
+                drop(service_pre_handler::<()>(&request, read_heap));
+                let status = Status::unimplemented(format!("unknown service_id: {}", service_id));
+                let reply = service_error_handler(status, &request);
+                running.push(LocalFutureObj::new(Box::new(future::ready(Ok(reply)))));
+            }
+        }
+        Ok(())
+    }
+
     fn dispatch_one_request<'s>(
         &'s self,
         comp: &dp::Completion,
         inner: &mut Inner,
-        running: &mut FuturesUnordered<LocalFutureObj<'s, (WRefOpaque, MessageErased)>>,
+        running: &mut FuturesUnordered<RunningCall<'s>>,
     ) -> Result<(), Error> {
         match *comp {
             dp::Completion::Incoming(request) => {
                 match request.meta.msg_type {
                     RpcMsgType::Request => {
                         // server receives requests
-                        // todo!("do something with the request");
-                        let service_id = request.meta.service_id;
-                        match self.routes.get(&service_id) {
-                            Some(s) => {
-                                let conn = inner.get_connection(request.meta.conn_id)?;
-                                // the connection has disappeared, do nothing
-
-                                let read_heap =
-                                    conn.map_alive(|alive| Arc::clone(&alive.read_heap))?;
-                                let task = LocalFutureObj::new(s.call(request, read_heap));
-                                running.push(task);
-                            }
-                            None => {
-                                log::warn!("unrecognized request: {:?}", request);
+                        self.start_call(request, inner, running)?;
+                    }
+                    RpcMsgType::StreamRequest | RpcMsgType::StreamRequestEnd => {
+                        let conn_id = request.meta.conn_id;
+                        let call_id = request.meta.call_id;
+                        let rpc_id = RpcId::new(conn_id, call_id.without_stream_seq());
+                        let read_heap = inner
+                            .get_connection(conn_id)?
+                            .map_alive(|alive| Arc::clone(&alive.read_heap))?;
+                        if call_id.stream_seq() == 0 {
+                            // the first message of a client stream starts the call
+                            if self.routes.contains_key(&request.meta.service_id) {
+                                stream::open_incoming(rpc_id);
                             }
+                            self.start_call(request, inner, running)?;
+                        } else if request.meta.msg_type == RpcMsgType::StreamRequest {
+                            stream::push_incoming(rpc_id, request, read_heap);
+                        } else {
+                            // the end of a stream carries nothing for the handler
+                            drop(service_pre_handler::<()>(&request, read_heap));
+                        }
+                        if request.meta.msg_type == RpcMsgType::StreamRequestEnd {
+                            stream::close_incoming(rpc_id, Ok(()));
                         }
                     }
-                    RpcMsgType::Response => {
+                    RpcMsgType::Response
+                    | RpcMsgType::StreamResponse
+                    | RpcMsgType::StreamResponseEnd
+                    | RpcMsgType::Cancel => {
                         // client receives responses, update the ReplyCache
                         panic!("impossible, something is wrong")
                     }
@@ -369,6 +483,23 @@ impl LocalServer {
                     status
                 );
                 inner.close_connection(conn_id);
+                stream::close_incoming_on(conn_id);
+            }
+            dp::Completion::Cancel(rpc_id) => {
+                // The cancel may find nothing, either because the reply has been sent, or because
+                // the cancel overtook its request in the backend. The reply will be dropped by
+                // the client in both cases.
+                if let Some((abort_handle, request)) = inner.abort_handles.remove(&rpc_id) {
+                    log::debug!("Call {:?} cancelled by the client", rpc_id);
+                    // dropping the handler also releases the receive buffer of the request
+                    abort_handle.abort();
+                    // Every call ends with a reply, which tells the transport of the client that
+                    // the receive buffers of the call have been released.
+                    let status = Status::cancelled("cancelled by the client");
+                    let reply = service_error_handler(status, &request);
+                    running.push(LocalFutureObj::new(Box::new(future::ready(Ok(reply)))));
+                }
+                stream::remove_incoming(rpc_id);
             }
         }
 
@@ -377,7 +508,7 @@ impl LocalServer {
 
     fn dispatch_requests<'s>(
         &'s self,
-        running: &mut FuturesUnordered<LocalFutureObj<'s, (WRefOpaque, MessageErased)>>,
+        running: &mut FuturesUnordered<RunningCall<'s>>,
     ) -> Result<(), Error> {
         let mut inner = self.inner.borrow_mut();
         loop {
@@ -4,19 +4,29 @@ use std::cell::RefCell;
 use crate::{Error, MRPC_CTX};
 
 // Re-exports
+pub use futures::Stream;
 pub use phoenix_api::rpc::{MessageErased, MessageMeta, RpcMsgType};
 pub use phoenix_api_mrpc::control_plane::TransportType;
 
 mod service;
-pub use service::{service_post_handler, service_pre_handler, NamedService, Service};
+pub use service::{
+    service_error_handler, service_post_handler, service_pre_handler, service_stream_post_handler,
+    service_stream_pre_handler, NamedService, Service,
+};
+
+mod stream;
+pub use stream::{ReplyStream, RequestStream};
 
 mod client;
-pub use client::{ClientStub, ReqFuture};
+pub use client::{ClientStub, ConnectivityState, ReconnectPolicy, ReqFuture, ResponseStream};
 
 mod local_server;
 pub mod server;
 pub use local_server::LocalServer;
 
+pub mod health;
+pub mod reflection;
+
 pub(crate) mod conn;
 pub(crate) mod pending;
 pub(crate) mod reply_cache;
@@ -84,6 +84,7 @@ impl Reactor {
                     dp::Completion::Incoming(msg) => msg.meta.conn_id,
                     dp::Completion::Outgoing(rpc_id, _status) => rpc_id.0,
                     dp::Completion::RecvError(conn_id, _status) => *conn_id,
+                    dp::Completion::Cancel(rpc_id) => rpc_id.0,
                 };
 
                 // find the stub and push the completion to that stub
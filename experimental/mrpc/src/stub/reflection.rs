@@ -0,0 +1,186 @@
+//! A reflection service that lists the services of a server.
+//!
+//! [`LocalServer::add_reflection_service`] serves it next to the services of the server. Tools
+//! discover the names, methods and protos of the services with a [`ReflectionClient`]. The
+//! protocol is defined in `proto/reflection.proto`.
+//!
+//! [`LocalServer::add_reflection_service`]: super::LocalServer::add_reflection_service
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+
+use phoenix_api::rpc::MessageErased;
+
+use super::service::{service_error_handler, service_post_handler, service_pre_handler};
+use super::{update_protos, ClientStub, NamedService, Service};
+use crate::{Error, IntoWRef, RRef, ReadHeap, Status, WRef, WRefOpaque};
+
+const PROTO_SRCS: &[&str] = &[include_str!("../../proto/reflection.proto")];
+
+const LIST_SERVICES_FUNC_ID: u32 = 2339897219;
+
+/// The request of `ListServices`.
+#[derive(Debug, Default)]
+pub struct ListServicesRequest {
+    /// The fully qualified name of a service, e.g., `rpc_hello.Greeter`. Empty for all services.
+    pub service: crate::alloc::String,
+}
+
+/// A service registered at the server.
+#[derive(Debug, Default)]
+pub struct ServiceInfo {
+    /// The fully qualified name of the service.
+    pub name: crate::alloc::String,
+    /// The [`NamedService::SERVICE_ID`] of the service.
+    pub service_id: u32,
+    /// The names of the methods, e.g., `SayHello`.
+    pub methods: crate::alloc::Vec<crate::alloc::String>,
+    /// The sources of the proto files that define the service and its messages.
+    pub protos: crate::alloc::Vec<crate::alloc::String>,
+}
+
+/// The reply of `ListServices`.
+#[derive(Debug, Default)]
+pub struct ListServicesResponse {
+    /// The services, in the order they were added to the server.
+    pub services: crate::alloc::Vec<ServiceInfo>,
+}
+
+/// Describes a service added to a server.
+#[derive(Debug, Clone)]
+pub(crate) struct ServiceDescriptor {
+    name: &'static str,
+    service_id: u32,
+    methods: &'static [&'static str],
+    protos: Vec<&'static str>,
+}
+
+impl ServiceDescriptor {
+    pub(crate) fn of<S: NamedService>() -> Self {
+        ServiceDescriptor {
+            name: S::NAME,
+            service_id: S::SERVICE_ID,
+            methods: S::METHODS,
+            protos: S::protos(),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn to_info(&self) -> ServiceInfo {
+        let mut methods = crate::alloc::Vec::with_capacity(self.methods.len());
+        for method in self.methods {
+            methods.push(crate::alloc::String::from(*method));
+        }
+        let mut protos = crate::alloc::Vec::with_capacity(self.protos.len());
+        for proto in &self.protos {
+            protos.push(crate::alloc::String::from(*proto));
+        }
+        ServiceInfo {
+            name: self.name.into(),
+            service_id: self.service_id,
+            methods,
+            protos,
+        }
+    }
+}
+
+/// The services added to a server, shared with its reflection service.
+pub(crate) type ServiceRegistry = Arc<spin::Mutex<Vec<ServiceDescriptor>>>;
+
+/// Serves the reflection service of a server.
+#[derive(Debug)]
+pub(crate) struct ReflectionServer {
+    registry: ServiceRegistry,
+}
+
+impl ReflectionServer {
+    pub(crate) fn new(registry: ServiceRegistry) -> Result<Self, Error> {
+        update_protos(PROTO_SRCS)?;
+        Ok(ReflectionServer { registry })
+    }
+}
+
+impl NamedService for ReflectionServer {
+    const SERVICE_ID: u32 = 2719820367;
+    const NAME: &'static str = "mrpc.reflection.v1.ServerReflection";
+    const METHODS: &'static [&'static str] = &["ListServices"];
+
+    fn protos() -> Vec<&'static str> {
+        PROTO_SRCS.to_vec()
+    }
+}
+
+#[crate::async_trait]
+impl Service for ReflectionServer {
+    async fn call(
+        &self,
+        req_opaque: MessageErased,
+        read_heap: Arc<ReadHeap>,
+    ) -> (WRefOpaque, MessageErased) {
+        match req_opaque.meta.func_id {
+            LIST_SERVICES_FUNC_ID => {
+                let req = RRef::<ListServicesRequest>::new(&req_opaque, read_heap);
+                let registry = self.registry.lock();
+                let mut services = crate::alloc::Vec::new();
+                for desc in registry.iter() {
+                    if req.service.is_empty() || req.service.as_str() == desc.name {
+                        services.push(desc.to_info());
+                    }
+                }
+                drop(registry);
+
+                if services.is_empty() && !req.service.is_empty() {
+                    let status =
+                        Status::not_found(format!("unknown service: {}", req.service.as_str()));
+                    return service_error_handler(status, &req_opaque);
+                }
+                let reply = WRef::new(ListServicesResponse { services });
+                service_post_handler(reply, &req_opaque)
+            }
+            func_id => {
+                // release the receive buffer of the request
+                drop(service_pre_handler::<()>(&req_opaque, read_heap));
+                let status = Status::unimplemented(format!("unknown func_id: {}", func_id));
+                service_error_handler(status, &req_opaque)
+            }
+        }
+    }
+}
+
+/// A client of the reflection service.
+#[derive(Debug)]
+pub struct ReflectionClient {
+    stub: ClientStub,
+}
+
+impl ReflectionClient {
+    /// Connects to the server at `dst`.
+    pub fn connect<A: ToSocketAddrs>(dst: A) -> Result<Self, Error> {
+        update_protos(PROTO_SRCS)?;
+        let stub = ClientStub::connect(dst)?;
+        Ok(ReflectionClient { stub })
+    }
+
+    /// Lists the services of the server. Fails with `not_found` if the request names a service
+    /// the server does not know.
+    pub fn list_services(
+        &self,
+        req: impl IntoWRef<ListServicesRequest>,
+    ) -> impl std::future::Future<Output = Result<RRef<ListServicesResponse>, Status>> + '_ {
+        let call_id = self.stub.initiate_call();
+
+        self.stub.unary(
+            Self::SERVICE_ID,
+            LIST_SERVICES_FUNC_ID,
+            call_id,
+            req.into_wref(),
+        )
+    }
+}
+
+impl NamedService for ReflectionClient {
+    const SERVICE_ID: u32 = ReflectionServer::SERVICE_ID;
+    const NAME: &'static str = ReflectionServer::NAME;
+}
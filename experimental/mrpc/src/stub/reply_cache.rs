@@ -1,18 +1,33 @@
+use std::collections::VecDeque;
+
 use phoenix_api::rpc::{CallId, MessageErased, TransportStatus};
 use thiserror::Error;
 
 use slab::Slab;
 
+use crate::Status;
+
 #[derive(Error, Debug)]
 pub(crate) enum Error {
     #[error("CallId {0} not found")]
     NotFound(CallId),
 }
 
+#[derive(Debug)]
+struct Entry<T> {
+    call_id: CallId,
+    // A server stream may deliver several replies before they are taken.
+    vals: VecDeque<T>,
+}
+
 #[derive(Debug)]
 pub(crate) struct ReplyCacheT<T> {
-    // Each RPC identified by a call_id resolves to a Result<MessageErased, TransportStatus>
-    slab: Slab<Option<T>>,
+    // Each RPC identified by a call_id resolves to a Result<MessageErased, ReplyError>
+    slab: Slab<Entry<T>>,
+    // The lower 32 bits of a call_id index into the slab, and the next 16 bits hold the
+    // generation of the call. A late reply to a removed call thus never hits a reused slot.
+    // The upper 16 bits are left for the sequence number of a stream message.
+    generation: u16,
 }
 
 impl<T> Default for ReplyCacheT<T> {
@@ -23,31 +38,136 @@ impl<T> Default for ReplyCacheT<T> {
 
 impl<T> ReplyCacheT<T> {
     pub(crate) fn new() -> Self {
-        ReplyCacheT { slab: Slab::new() }
+        ReplyCacheT {
+            slab: Slab::new(),
+            generation: 0,
+        }
     }
 
     #[inline]
     pub(crate) fn initiate_call(&mut self) -> CallId {
-        self.slab.insert(None).into()
+        let entry = self.slab.vacant_entry();
+        let call_id = CallId(((self.generation as u64) << 32) | entry.key() as u64);
+        self.generation = self.generation.wrapping_add(1);
+        entry.insert(Entry {
+            call_id,
+            vals: VecDeque::new(),
+        });
+        call_id
+    }
+
+    /// Looks up the entry of a call. The sequence number of a stream message in `call_id` is
+    /// ignored.
+    #[inline]
+    fn entry_mut(&mut self, call_id: CallId) -> Result<&mut Entry<T>, Error> {
+        let call_id = call_id.without_stream_seq();
+        match self.slab.get_mut(call_id.0 as u32 as usize) {
+            Some(entry) if entry.call_id == call_id => Ok(entry),
+            _ => Err(Error::NotFound(call_id)),
+        }
     }
 
+    /// Appends a reply to the call.
     #[inline]
-    pub(crate) fn update(&mut self, call_id: CallId, val: T) -> Result<(), Error> {
-        match self.slab.get_mut(call_id.0 as usize) {
-            Some(entry) => {
-                entry.replace(val);
-                Ok(())
-            }
-            None => Err(Error::NotFound(call_id)),
+    pub(crate) fn push(&mut self, call_id: CallId, val: T) -> Result<(), Error> {
+        self.entry_mut(call_id)?.vals.push_back(val);
+        Ok(())
+    }
+
+    /// Appends a reply to every ongoing call.
+    pub(crate) fn push_all(&mut self, val: T)
+    where
+        T: Clone,
+    {
+        for (_, entry) in self.slab.iter_mut() {
+            entry.vals.push_back(val.clone());
         }
     }
 
+    /// Takes the earliest reply of the call that has not been taken.
     #[inline]
-    pub(crate) fn get(&self, call_id: CallId) -> Result<&Option<T>, Error> {
-        self.slab
-            .get(call_id.0 as usize)
-            .ok_or(Error::NotFound(call_id))
+    pub(crate) fn pop(&mut self, call_id: CallId) -> Result<Option<T>, Error> {
+        Ok(self.entry_mut(call_id)?.vals.pop_front())
     }
+
+    /// Removes the entry of a finished or abandoned call, returning the replies not yet taken.
+    #[inline]
+    pub(crate) fn remove(&mut self, call_id: CallId) -> Result<VecDeque<T>, Error> {
+        let call_id = self.entry_mut(call_id)?.call_id;
+        Ok(self.slab.remove(call_id.0 as u32 as usize).vals)
+    }
+}
+
+/// Why a call fails before a reply from the server arrives.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ReplyError {
+    /// The backend reports an error for the call.
+    Transport(TransportStatus),
+    /// The connection is lost while the call is in flight.
+    Disconnected,
 }
 
-pub(crate) type ReplyCache = ReplyCacheT<Result<MessageErased, TransportStatus>>;
+impl ReplyError {
+    pub(crate) fn into_status(self) -> Status {
+        match self {
+            ReplyError::Transport(status) => Status::from_incoming_transport(status),
+            ReplyError::Disconnected => Status::unavailable("connection lost"),
+        }
+    }
+}
+
+pub(crate) type ReplyCache = ReplyCacheT<Result<MessageErased, ReplyError>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_late_reply_to_removed_call() {
+        let mut cache = ReplyCacheT::<u32>::new();
+        let old = cache.initiate_call();
+        assert!(cache.remove(old).unwrap().is_empty());
+
+        // the slot is reused by a new call
+        let new = cache.initiate_call();
+        assert_eq!(old.0 as u32, new.0 as u32);
+        assert_ne!(old, new);
+
+        // the late reply to the old call must not be delivered to the new call
+        assert!(cache.push(old, 1).is_err());
+        assert_eq!(cache.pop(new).unwrap(), None);
+
+        cache.push(new, 2).unwrap();
+        assert_eq!(cache.remove(new).unwrap(), [2]);
+        assert!(cache.pop(new).is_err());
+    }
+
+    #[test]
+    fn test_stream_replies() {
+        let mut cache = ReplyCacheT::<u32>::new();
+        let call_id = cache.initiate_call();
+        for seq in 0..3 {
+            cache
+                .push(call_id.with_stream_seq(seq), seq as u32)
+                .unwrap();
+        }
+        assert_eq!(cache.pop(call_id).unwrap(), Some(0));
+        assert_eq!(cache.remove(call_id).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn test_fail_all_calls() {
+        let mut cache = ReplyCacheT::<u32>::new();
+        let first = cache.initiate_call();
+        let second = cache.initiate_call();
+        cache.push(first, 1).unwrap();
+
+        cache.push_all(0);
+        assert_eq!(cache.remove(first).unwrap(), [1, 0]);
+        assert_eq!(cache.pop(second).unwrap(), Some(0));
+
+        // a call made afterwards is not affected
+        let third = cache.initiate_call();
+        assert_eq!(cache.pop(third).unwrap(), None);
+    }
+}
@@ -1,9 +1,11 @@
 use std::sync::Arc;
 
-use phoenix_api::rpc::{MessageErased, MessageMeta, RpcMsgType};
+use futures::StreamExt;
+use phoenix_api::rpc::{MessageErased, MessageMeta, RpcMsgType, StatusCode};
 
+use super::stream::{self, ReplyStream, RequestStream};
 use super::RpcData;
-use crate::{RRef, ReadHeap, WRef, WRefOpaque};
+use crate::{RRef, ReadHeap, RpcStatus, Status, WRef, WRefOpaque};
 
 /// A trait to provide a static reference to the service's name and ID.
 /// This is used for routing requests to service within the server.
@@ -16,6 +18,13 @@ pub trait NamedService {
     ///
     /// [here]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#requests
     const NAME: &'static str = "";
+    /// The names of the methods of the service, e.g., `SayHello`.
+    const METHODS: &'static [&'static str] = &[];
+
+    /// Returns the sources of the proto files that define the service and its messages.
+    fn protos() -> Vec<&'static str> {
+        Vec::new()
+    }
 }
 
 /// A trait implemented by generated code.
@@ -34,6 +43,14 @@ pub fn service_pre_handler<T: Unpin>(req: &MessageErased, read_heap: Arc<ReadHea
     RRef::new(req, read_heap)
 }
 
+#[doc(hidden)]
+pub fn service_stream_pre_handler<T: Unpin>(
+    req: &MessageErased,
+    read_heap: Arc<ReadHeap>,
+) -> RequestStream<T> {
+    RequestStream::new(req, read_heap)
+}
+
 #[doc(hidden)]
 pub fn service_post_handler<T: RpcData>(
     reply: WRef<T>,
@@ -42,16 +59,81 @@ pub fn service_post_handler<T: RpcData>(
     // construct meta
     let meta = MessageMeta {
         msg_type: RpcMsgType::Response,
+        call_id: req_opaque.meta.call_id.without_stream_seq(),
+        ..req_opaque.meta
+    };
+
+    erase_reply(reply, meta)
+}
+
+/// Sends the replies of a server stream as they are produced. Resolves to the end of the stream.
+#[doc(hidden)]
+pub async fn service_stream_post_handler<T: RpcData>(
+    mut replies: ReplyStream<T>,
+    req_opaque: MessageErased,
+) -> (WRefOpaque, MessageErased) {
+    let call_id = req_opaque.meta.call_id;
+    let mut seq = 0u16;
+    let status = loop {
+        match replies.next().await {
+            Some(Ok(reply)) if seq == u16::MAX => {
+                drop(reply);
+                break Status::out_of_range("too many replies in a stream");
+            }
+            Some(Ok(reply)) => {
+                let meta = MessageMeta {
+                    msg_type: RpcMsgType::StreamResponse,
+                    call_id: call_id.with_stream_seq(seq),
+                    ..req_opaque.meta
+                };
+                stream::push_outgoing(erase_reply(reply, meta));
+                seq += 1;
+            }
+            Some(Err(status)) => break status,
+            None => break Status::ok(""),
+        }
+    };
+
+    let meta = MessageMeta {
+        msg_type: RpcMsgType::StreamResponseEnd,
+        call_id: call_id.with_stream_seq(seq),
+        ..req_opaque.meta
+    };
+    erase_reply(WRef::new(RpcStatus::new(&status)), meta)
+}
+
+#[doc(hidden)]
+pub fn service_error_handler(
+    status: Status,
+    req_opaque: &MessageErased,
+) -> (WRefOpaque, MessageErased) {
+    // construct meta
+    let meta = MessageMeta {
+        msg_type: RpcMsgType::Response,
+        status_code: StatusCode::ServerError,
+        call_id: req_opaque.meta.call_id.without_stream_seq(),
         ..req_opaque.meta
     };
 
+    erase_reply(WRef::new(RpcStatus::new(&status)), meta)
+}
+
+fn erase_reply<T: RpcData>(reply: WRef<T>, meta: MessageMeta) -> (WRefOpaque, MessageErased) {
     let reply_opaque = WRef::clone(&reply).into_opaque();
 
+    // the reply does not inherit the metadata of the request
+    let (metadata_addr_app, metadata_addr_backend) = reply.metadata_addrs();
+    let meta = MessageMeta {
+        has_metadata: metadata_addr_backend != 0,
+        ..meta
+    };
     let (ptr_app, ptr_backend) = reply.into_shmptr().to_raw_parts();
     let erased = MessageErased {
         meta,
         shm_addr_app: ptr_app.addr().get(),
         shm_addr_backend: ptr_backend.addr().get(),
+        metadata_addr_app,
+        metadata_addr_backend,
     };
 
     (reply_opaque, erased)
@@ -0,0 +1,199 @@
+//! Streams of messages within a single call on the server.
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use fnv::FnvHashMap as HashMap;
+use futures::stream::BoxStream;
+use futures::Stream;
+
+use phoenix_api::rpc::{MessageErased, RpcId};
+use phoenix_api::Handle;
+
+use crate::{RRef, ReadHeap, Status, WRef, WRefOpaque};
+
+/// The replies of a server-streaming call, returned by the server handler.
+pub type ReplyStream<T> = BoxStream<'static, Result<WRef<T>, Status>>;
+
+#[derive(Default)]
+struct Incoming {
+    // The messages that have arrived but have not been taken by the handler.
+    messages: VecDeque<(MessageErased, Arc<ReadHeap>)>,
+    // Set when the stream ends. Carries an error if the stream is broken.
+    end: Option<Result<(), Status>>,
+    waker: Option<Waker>,
+}
+
+impl Incoming {
+    fn finish(&mut self, end: Result<(), Status>) {
+        self.end.get_or_insert(end);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Incoming {
+    fn drop(&mut self) {
+        // release the receive buffers of the messages nobody takes
+        for (msg, read_heap) in self.messages.drain(..) {
+            drop(RRef::<()>::new(&msg, read_heap));
+        }
+    }
+}
+
+thread_local! {
+    // The client streams of the calls being served on this thread, keyed by the RpcId of the
+    // call, i.e., without the stream sequence number.
+    static INCOMING_STREAMS: RefCell<HashMap<RpcId, Arc<spin::Mutex<Incoming>>>> =
+        RefCell::new(HashMap::default());
+    // The replies of server streams that are ready to send. Posted by the `LocalServer` running
+    // on this thread.
+    static OUTGOING_REPLIES: RefCell<Vec<(WRefOpaque, MessageErased)>> = RefCell::new(Vec::new());
+}
+
+/// Starts to receive the client stream of the call `rpc_id`.
+pub(crate) fn open_incoming(rpc_id: RpcId) {
+    INCOMING_STREAMS.with_borrow_mut(|streams| streams.insert(rpc_id, Default::default()));
+}
+
+/// Delivers a message of the client stream of the call `rpc_id` to the server handler.
+///
+/// The message is released if the call is no longer served.
+pub(crate) fn push_incoming(rpc_id: RpcId, msg: MessageErased, read_heap: Arc<ReadHeap>) {
+    let incoming = INCOMING_STREAMS.with_borrow(|streams| streams.get(&rpc_id).cloned());
+    match incoming {
+        Some(incoming) => {
+            let mut incoming = incoming.lock();
+            incoming.messages.push_back((msg, read_heap));
+            if let Some(waker) = incoming.waker.take() {
+                waker.wake();
+            }
+        }
+        None => drop(RRef::<()>::new(&msg, read_heap)),
+    }
+}
+
+/// Ends the client stream of the call `rpc_id`.
+pub(crate) fn close_incoming(rpc_id: RpcId, end: Result<(), Status>) {
+    INCOMING_STREAMS.with_borrow_mut(|streams| {
+        if let Some(incoming) = streams.get(&rpc_id) {
+            incoming.lock().finish(end);
+            // keep the stream until the handler picks it up
+            if Arc::strong_count(incoming) > 1 {
+                streams.remove(&rpc_id);
+            }
+        }
+    });
+}
+
+/// Forgets the client stream of a call that is finished or cancelled. The messages not taken by
+/// the handler are released.
+pub(crate) fn remove_incoming(rpc_id: RpcId) {
+    let incoming = INCOMING_STREAMS.with_borrow_mut(|streams| streams.remove(&rpc_id));
+    drop(incoming);
+}
+
+/// Ends the client streams of all calls on a closed connection.
+pub(crate) fn close_incoming_on(conn_id: Handle) {
+    let closed: Vec<_> = INCOMING_STREAMS.with_borrow_mut(|streams| {
+        let rpc_ids: Vec<_> = streams
+            .keys()
+            .filter(|rpc_id| rpc_id.0 == conn_id)
+            .copied()
+            .collect();
+        rpc_ids
+            .iter()
+            .filter_map(|rpc_id| streams.remove(rpc_id))
+            .collect()
+    });
+    for incoming in closed {
+        incoming
+            .lock()
+            .finish(Err(Status::unavailable("connection closed")));
+    }
+}
+
+/// Queues a reply of a server stream to send.
+pub(crate) fn push_outgoing(reply: (WRefOpaque, MessageErased)) {
+    OUTGOING_REPLIES.with_borrow_mut(|replies| replies.push(reply));
+}
+
+/// Moves the queued replies of server streams to `reply_buffer`.
+pub(crate) fn take_outgoing(reply_buffer: &mut Vec<(WRefOpaque, MessageErased)>) {
+    OUTGOING_REPLIES.with_borrow_mut(|replies| reply_buffer.append(replies));
+}
+
+/// The requests of a client-streaming call, handed to the server handler.
+///
+/// Yields read-only [`RRef<T>`]s in the order the client sends them, and ends when the client
+/// finishes the stream. Yields a [`Status`] and then ends if the stream breaks.
+pub struct RequestStream<T> {
+    incoming: Arc<spin::Mutex<Incoming>>,
+    done: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RequestStream<T> {
+    /// Picks up the client stream of the call that `first` starts.
+    pub(crate) fn new(first: &MessageErased, read_heap: Arc<ReadHeap>) -> Self {
+        let rpc_id = RpcId::new(first.meta.conn_id, first.meta.call_id.without_stream_seq());
+        let incoming = INCOMING_STREAMS.with_borrow_mut(|streams| {
+            let incoming = streams.get(&rpc_id).cloned()?;
+            if incoming.lock().end.is_some() {
+                // the stream ended before the handler runs
+                streams.remove(&rpc_id);
+            }
+            Some(incoming)
+        });
+        let incoming = incoming.unwrap_or_else(|| {
+            // the connection has been closed before the handler runs
+            let mut incoming = Incoming::default();
+            incoming.end = Some(Err(Status::unavailable("connection closed")));
+            Arc::new(spin::Mutex::new(incoming))
+        });
+
+        if first.meta.msg_type.is_stream_end() {
+            // an empty stream, the end carries no request
+            drop(RRef::<()>::new(first, read_heap));
+        } else {
+            incoming.lock().messages.push_front((*first, read_heap));
+        }
+
+        RequestStream {
+            incoming,
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Unpin> Stream for RequestStream<T> {
+    type Item = Result<RRef<T>, Status>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        let mut incoming = this.incoming.lock();
+        if let Some((msg, read_heap)) = incoming.messages.pop_front() {
+            return Poll::Ready(Some(Ok(RRef::new(&msg, read_heap))));
+        }
+
+        match incoming.end.take() {
+            Some(end) => {
+                this.done = true;
+                Poll::Ready(end.err().map(Err))
+            }
+            None => {
+                incoming.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::mem;
 use std::ops::Deref;
 use std::sync::Arc;
 
-use phoenix_api::rpc::Token;
+use phoenix_api::rpc::{RpcMetadata, Token};
 use shm::ptr::ShmNonNull;
 
 use crate::alloc::Box as ShmBox;
@@ -158,6 +158,8 @@ impl<T: RpcData> IntoWRef<T> for &WRef<T> {
 #[derive(Debug)]
 struct WRefInner<T> {
     ptr: ShmBox<T>,
+    /// Allocated on the shared memory heap on the first write.
+    metadata: Option<ShmBox<RpcMetadata>>,
 }
 
 // TODO(cjr): consider moving refcnt to ShmBox.
@@ -186,6 +188,7 @@ impl<T: RpcData> WRef<T> {
             token,
             inner: Arc::new(WRefInner {
                 ptr: ShmBox::new(msg),
+                metadata: None,
             }),
         }
     }
@@ -203,6 +206,41 @@ impl<T: RpcData> WRef<T> {
         self.token = token;
     }
 
+    /// Returns the metadata sent along with the message, if any.
+    #[must_use]
+    #[inline]
+    pub fn metadata(&self) -> Option<&RpcMetadata> {
+        self.inner.metadata.as_deref()
+    }
+
+    /// Returns a mutable reference to the metadata sent along with the message, which is empty
+    /// if not set before, if there are no other `WRef` pointers to the same allocation.
+    ///
+    /// Returns [`None`] otherwise, because it is not safe to mutable a shared value.
+    #[inline]
+    pub fn metadata_mut(this: &mut Self) -> Option<&mut RpcMetadata> {
+        let inner = Arc::get_mut(&mut this.inner)?;
+        Some(
+            inner
+                .metadata
+                .get_or_insert_with(|| ShmBox::new(RpcMetadata::new()))
+                .as_mut(),
+        )
+    }
+
+    /// Returns the addresses of the metadata on the app side and the backend side, or zeros if
+    /// there is no metadata.
+    #[inline]
+    pub(crate) fn metadata_addrs(&self) -> (usize, usize) {
+        match self.inner.metadata {
+            Some(ref metadata) => {
+                let (ptr_app, ptr_backend) = ShmBox::to_raw_parts(metadata);
+                (ptr_app.addr().get(), ptr_backend.addr().get())
+            }
+            None => (0, 0),
+        }
+    }
+
     #[inline]
     pub(crate) fn into_opaque(self) -> WRefOpaque {
         WRefOpaque::from_wref(self)
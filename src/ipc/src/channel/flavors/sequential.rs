@@ -46,6 +46,10 @@ impl<T> Sender<T> {
         drop(inner);
         Ok(())
     }
+
+    pub(crate) fn len(&self) -> usize {
+        self.shared.inner.borrow().queue.len()
+    }
 }
 
 impl<T> Receiver<T> {
@@ -64,6 +68,10 @@ impl<T> Receiver<T> {
         let inner = self.shared.inner.borrow_mut();
         inner.queue.is_empty()
     }
+
+    pub(crate) fn len(&self) -> usize {
+        self.shared.inner.borrow().queue.len()
+    }
 }
 
 pub(crate) fn create_channel<T>() -> (Sender<T>, Receiver<T>) {
@@ -9,6 +9,8 @@ pub type TryRecvError = crossbeam::channel::TryRecvError;
 #[derive(Debug)]
 pub struct Sender<T> {
     flavor: SenderFlavor<T>,
+    /// Number of messages sent through this end.
+    sent: u64,
 }
 
 #[derive(Debug)]
@@ -32,7 +34,26 @@ macro_rules! choose_sender_flavor {
 impl<T> Sender<T> {
     #[inline]
     pub fn send(&mut self, t: T) -> Result<(), SendError<T>> {
-        choose_sender_flavor!(&mut self.flavor, send, t)
+        choose_sender_flavor!(&mut self.flavor, send, t)?;
+        self.sent += 1;
+        Ok(())
+    }
+
+    /// Returns the number of messages sent through this end.
+    #[inline]
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Returns the number of messages in the channel.
+    #[inline]
+    pub fn len(&self) -> usize {
+        choose_sender_flavor!(&self.flavor, len)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
@@ -40,6 +61,8 @@ impl<T> Sender<T> {
 #[derive(Debug)]
 pub struct Receiver<T> {
     flavor: ReceiverFlavor<T>,
+    /// Number of messages received from this end.
+    received: u64,
 }
 
 #[derive(Debug)]
@@ -63,7 +86,21 @@ macro_rules! choose_receiver_flavor {
 impl<T> Receiver<T> {
     #[inline]
     pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
-        choose_receiver_flavor!(&mut self.flavor, try_recv)
+        let t = choose_receiver_flavor!(&mut self.flavor, try_recv)?;
+        self.received += 1;
+        Ok(t)
+    }
+
+    /// Returns the number of messages received from this end.
+    #[inline]
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Returns the number of messages in the channel.
+    #[inline]
+    pub fn len(&self) -> usize {
+        choose_receiver_flavor!(&self.flavor, len)
     }
 
     #[inline]
@@ -85,9 +122,11 @@ pub fn create_channel<T>(flavor: ChannelFlavor) -> (Sender<T>, Receiver<T>) {
             (
                 Sender {
                     flavor: SenderFlavor::Concurrent(sender),
+                    sent: 0,
                 },
                 Receiver {
                     flavor: ReceiverFlavor::Concurrent(receiver),
+                    received: 0,
                 },
             )
         }
@@ -96,9 +135,11 @@ pub fn create_channel<T>(flavor: ChannelFlavor) -> (Sender<T>, Receiver<T>) {
             (
                 Sender {
                     flavor: SenderFlavor::Sequential(sender),
+                    sent: 0,
                 },
                 Receiver {
                     flavor: ReceiverFlavor::Sequential(receiver),
+                    received: 0,
                 },
             )
         }
@@ -48,6 +48,49 @@ pub struct UpgradeRequest {
     /// whether to suspend all engines
     /// within the same service subscription
     pub detach_subscription: bool,
+    /// which clients to upgrade, only applies to modules
+    #[serde(default)]
+    pub scope: UpgradeScope,
+    /// roll back a staged upgrade instead
+    #[serde(default)]
+    pub rollback: bool,
+}
+
+/// Request for unloading plugins. A plugin can only be unloaded when none of its engines
+/// is running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnloadRequest {
+    /// Names of the plugins, as in their `PluginDescriptor`
+    pub plugins: Vec<String>,
+    pub ty: PluginType,
+}
+
+/// Which clients a module upgrade applies to.
+///
+/// Upgrading a subset of the clients stages the upgrade: the old and the new versions of the
+/// modules stay loaded side by side, the other clients keep running the old version. The staged
+/// upgrade is rolled forward by upgrading the same modules again, for the next clients or
+/// for `All`, and the old version is unloaded once no client runs it. It is rolled back by an
+/// `UpgradeRequest` with `rollback` set, which restores all the upgraded clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpgradeScope {
+    /// Every client
+    #[default]
+    All,
+    /// All service subscriptions of these client processes
+    Pids(Vec<pid_t>),
+    /// These service subscriptions, in (pid, sid)
+    Subscriptions(Vec<(pid_t, u64)>),
+}
+
+impl UpgradeScope {
+    pub fn contains(&self, pid: pid_t, sid: u64) -> bool {
+        match self {
+            UpgradeScope::All => true,
+            UpgradeScope::Pids(pids) => pids.contains(&pid),
+            UpgradeScope::Subscriptions(subscriptions) => subscriptions.contains(&(pid, sid)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +99,36 @@ pub enum PluginType {
     Addon,
 }
 
+/// Where an addon is inserted into the data path, named by the engines on the tx direction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InsertionPoint {
+    /// On the channel from the first engine to the second one
+    Between(String, String),
+    /// On the only tx input channel of the engine, e.g., before the transport
+    Before(String),
+    /// On the only tx output channel of the engine
+    After(String),
+}
+
+/// Attach an addon at an insertion point, phoenix computes the channel replacements.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddonInsertion {
+    pub at: InsertionPoint,
+    /// Whether to insert the addon on the tx direction
+    #[serde(default = "AddonInsertion::default_tx")]
+    pub tx: bool,
+    /// Whether to also insert the addon on the reverse channel on the rx direction
+    #[serde(default)]
+    pub rx: bool,
+}
+
+impl AddonInsertion {
+    fn default_tx() -> bool {
+        true
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddonRequest {
     /// Ttarget user process
@@ -68,6 +141,9 @@ pub struct AddonRequest {
     pub tx_channels_replacements: Vec<(String, String, usize, usize)>,
     /// replacement for data path rx edges
     pub rx_channels_replacements: Vec<(String, String, usize, usize)>,
+    /// Where to attach the addon, used instead of the replacements when they are empty.
+    /// Detaching an addon with empty replacements connects its peers back directly.
+    pub insertion: Option<AddonInsertion>,
     /// Which scheduling group should the addon belongs when attaching an addon,
     /// the group is identified as a set engines
     pub group: Vec<String>,
@@ -83,14 +159,51 @@ pub enum Request {
     NewClient(SchedulingHint, String, Option<String>),
     /// Send a request to a specified engine, identified by the EngineId
     EngineRequest(u64, Vec<u8>),
-    /// List all service subscriptions
-    ListSubscription,
+    /// List the service subscriptions that match the filter
+    ListSubscription(SubscriptionFilter),
     /// Attach an addon to a service subscription
     AttachAddon(SchedulingMode, AddonRequest),
     /// Detach an addon from a service subscription
     DetachAddon(AddonRequest),
     /// Upgrade modules or plugins
     Upgrade(UpgradeRequest),
+    /// Unload modules or addons
+    Unload(UnloadRequest),
+    /// Collect the metrics of all runtimes and the engines running on them
+    Metrics,
+}
+
+/// Selects service subscriptions in `Request::ListSubscription`. An unset field matches all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubscriptionFilter {
+    pub pid: Option<pid_t>,
+    pub service: Option<String>,
+}
+
+impl SubscriptionFilter {
+    pub fn matches(&self, pid: pid_t, service: &str) -> bool {
+        self.pid.map_or(true, |p| p == pid) && self.service.as_ref().map_or(true, |s| s == service)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineInfo {
+    pub eid: u64,
+    pub engine_type: String,
+    /// The runtime the engine is running on
+    pub rid: u64,
+    /// The scheduling group the engine belongs to
+    pub gid: u64,
+    pub scheduling_mode: SchedulingMode,
+    /// Version of the module or addon that the engine comes from
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddonInfo {
+    pub engine_type: String,
+    /// The configuration string given when the addon was attached
+    pub config: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,8 +211,110 @@ pub struct ServiceSubscriptionInfo {
     pub pid: pid_t,
     pub sid: u64,
     pub service: String,
-    pub engines: Vec<(u64, String)>,
-    pub addons: Vec<String>,
+    pub engines: Vec<EngineInfo>,
+    pub addons: Vec<AddonInfo>,
+}
+
+/// The outcome of a plugin in an upgrade or addon request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginStatus {
+    /// Name of the plugin, or the addon engine type for addon requests.
+    pub name: String,
+    /// Why the plugin failed, `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+/// Report for `Request::Upgrade`, `Request::Unload`, `Request::AttachAddon` and
+/// `Request::DetachAddon`. It is sent back after the operation finishes on every affected client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpgradeReport {
+    pub plugins: Vec<PluginStatus>,
+    /// Engines that were (re)submitted to the runtimes, in (pid, sid, engine_id, engine_type).
+    pub engines: Vec<(pid_t, u64, u64, String)>,
+}
+
+impl UpgradeReport {
+    /// Returns true if all plugins succeeded.
+    pub fn is_success(&self) -> bool {
+        self.plugins.iter().all(|p| p.error.is_none())
+    }
+}
+
+/// Which data path queue of an engine a `QueueMetrics` refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueueKind {
+    TxInput,
+    TxOutput,
+    RxInput,
+    RxOutput,
+}
+
+impl QueueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueKind::TxInput => "tx_input",
+            QueueKind::TxOutput => "tx_output",
+            QueueKind::RxInput => "rx_input",
+            QueueKind::RxOutput => "rx_output",
+        }
+    }
+}
+
+/// Counters of one of the queues in an engine's `DataPathNode`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueMetrics {
+    pub kind: QueueKind,
+    /// Index of the queue in the `DataPathNode`
+    pub index: usize,
+    /// Messages received from an input queue, or sent to an output queue, by this engine
+    pub messages: u64,
+    /// Messages currently waiting in the queue
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineMetrics {
+    pub eid: u64,
+    pub engine_type: String,
+    /// Number of times the engine's future has been polled
+    pub polls: u64,
+    /// Number of polls in which the engine made progress
+    pub busy_polls: u64,
+    /// Total time spent in polling the engine's future, i.e., inside `activate`, in nanoseconds
+    pub poll_time_ns: u64,
+    pub queues: Vec<QueueMetrics>,
+}
+
+impl EngineMetrics {
+    /// Fraction of polls in which the engine made progress.
+    pub fn busy_ratio(&self) -> f64 {
+        if self.polls == 0 {
+            0.0
+        } else {
+            self.busy_polls as f64 / self.polls as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeMetrics {
+    pub rid: u64,
+    /// Number of iterations of the runtime's mainloop
+    pub iterations: u64,
+    /// Number of iterations in which any engine made progress
+    pub busy_iterations: u64,
+    pub engines: Vec<EngineMetrics>,
+}
+
+impl RuntimeMetrics {
+    /// Fraction of mainloop iterations in which any engine made progress.
+    pub fn busy_ratio(&self) -> f64 {
+        if self.iterations == 0 {
+            0.0
+        } else {
+            self.busy_iterations as f64 / self.iterations as f64
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -116,6 +331,11 @@ pub enum ResponseKind {
         wq_cap: usize,
         cq_cap: usize,
     },
+    Upgrade(UpgradeReport),
+    Unload(UpgradeReport),
+    AttachAddon(UpgradeReport),
+    DetachAddon(UpgradeReport),
+    Metrics(Vec<RuntimeMetrics>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,11 +25,29 @@ impl From<Token> for usize {
 }
 
 /// Indicates the direction of an RPC message.
-#[repr(C)]
+#[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RpcMsgType {
     Request,
     Response,
+    /// The client abandons an ongoing call. The message carries no payload.
+    Cancel,
+    /// A message of a client stream.
+    StreamRequest,
+    /// A message of a server stream.
+    StreamResponse,
+    /// Marks the end of a client stream. The payload is an RPC status instead of a message.
+    StreamRequestEnd,
+    /// Marks the end of a server stream. The payload is an RPC status instead of a message.
+    StreamResponseEnd,
+}
+
+impl RpcMsgType {
+    /// Returns `true` if the message marks the end of a stream.
+    #[inline]
+    pub fn is_stream_end(self) -> bool {
+        matches!(self, Self::StreamRequestEnd | Self::StreamResponseEnd)
+    }
 }
 
 /// An `u64` associated with an RPC.
@@ -45,6 +63,31 @@ impl From<usize> for CallId {
     }
 }
 
+impl CallId {
+    const STREAM_SEQ_SHIFT: u32 = 48;
+
+    /// Returns the `CallId` of the `seq`-th message of a streaming call.
+    ///
+    /// The upper 16 bits of a `CallId` hold the sequence number of the message within a stream,
+    /// so that each message of the call is uniquely identified.
+    #[inline]
+    pub fn with_stream_seq(self, seq: u16) -> Self {
+        CallId(self.without_stream_seq().0 | (seq as u64) << Self::STREAM_SEQ_SHIFT)
+    }
+
+    /// Returns the sequence number of the message within a stream. It is always 0 for unary calls.
+    #[inline]
+    pub fn stream_seq(self) -> u16 {
+        (self.0 >> Self::STREAM_SEQ_SHIFT) as u16
+    }
+
+    /// Returns the `CallId` shared by all messages of the call.
+    #[inline]
+    pub fn without_stream_seq(self) -> Self {
+        CallId(self.0 & ((1 << Self::STREAM_SEQ_SHIFT) - 1))
+    }
+}
+
 impl fmt::Display for CallId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "CallId({})", self.0)
@@ -91,15 +134,39 @@ impl TransportStatus {
             Self::Error(code) => code.get(),
         }
     }
+
+    /// The status of an RPC rejected by a policy engine, which the client sees as
+    /// `mrpc::Status` with the gRPC `code`.
+    #[inline]
+    pub fn rejected(code: u32) -> Self {
+        Self::Error(NonZeroU32::new(POLICY_REJECTED_BASE + code).unwrap())
+    }
+
+    /// Returns the gRPC code if the RPC was rejected by a policy engine.
+    #[inline]
+    pub fn rejected_code(self) -> Option<u32> {
+        self.code().checked_sub(POLICY_REJECTED_BASE)
+    }
 }
 
+/// Error codes from this value up are set by policy engines that reject an RPC,
+/// the gRPC status code is added to it.
+pub const POLICY_REJECTED_BASE: u32 = 1000;
+
+/// The gRPC status code of the RPCs that exceed a limit, e.g., a full queue or the maximum
+/// message size.
+pub const RESOURCE_EXHAUSTED: u32 = 8;
+
 /// The metadata prepended to each RPC message.
-#[repr(C)]
+#[repr(u8)]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum StatusCode {
     Success = 0,
     AccessDenied = 1,
     Unknown = 2,
+    /// The server handler failed. The payload of the message is an RPC status
+    /// (a gRPC code and an error message) instead of the method's reply type.
+    ServerError = 3,
 }
 
 #[repr(C)]
@@ -121,6 +188,11 @@ pub struct MessageMeta {
     pub msg_type: RpcMsgType,
     /// Plugin specific status code.
     pub status_code: StatusCode,
+    /// Whether an [`RpcMetadata`] travels along with the message.
+    pub has_metadata: bool,
+    /// The time budget of the call in microseconds, counting from when the client issued it.
+    /// 0 means the call has no deadline.
+    pub timeout_us: u32,
 }
 
 /// An RPC descriptor.
@@ -134,6 +206,198 @@ pub struct MessageErased {
     // It is fine to use usize here because these two addresses are machine-local.
     pub shm_addr_app: usize,
     pub shm_addr_backend: usize,
+    /// The addresses of the [`RpcMetadata`] of the message, or 0 if it has none.
+    pub metadata_addr_app: usize,
+    pub metadata_addr_backend: usize,
+}
+
+/// The number of bytes the entries of an [`RpcMetadata`] can take.
+pub const RPC_METADATA_CAPACITY: usize = 1022;
+
+/// The number of bytes an entry takes in addition to its key and value.
+const METADATA_ENTRY_HEADER: usize = 4;
+
+/// Returned when an [`RpcMetadata`] has no room for an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("RPC metadata is full")]
+pub struct MetadataFull;
+
+/// Key/value pairs attached to an RPC, e.g., authentication tokens or tracing contexts.
+///
+/// The entries are stored inline, so the struct can be placed on the shared memory heap and sent
+/// as is. Each entry is encoded as
+/// ```text
+/// | key_len | value_len | key | value |
+/// |    2    |     2     |     |       |
+/// ```
+/// with lengths in little endian. Keys are strings, values are arbitrary bytes.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RpcMetadata {
+    /// The number of bytes of `buf` in use, in little endian.
+    len: [u8; 2],
+    buf: [u8; RPC_METADATA_CAPACITY],
+}
+
+impl Default for RpcMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RpcMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(k, v)| (k, String::from_utf8_lossy(v).into_owned())),
+            )
+            .finish()
+    }
+}
+
+impl RpcMetadata {
+    /// Constructs an empty `RpcMetadata`.
+    #[inline]
+    pub const fn new() -> Self {
+        RpcMetadata {
+            len: [0; 2],
+            buf: [0; RPC_METADATA_CAPACITY],
+        }
+    }
+
+    /// Returns the number of bytes the entries take.
+    #[inline]
+    fn used(&self) -> usize {
+        // the bytes may come from the wire, never trust them
+        (u16::from_le_bytes(self.len) as usize).min(RPC_METADATA_CAPACITY)
+    }
+
+    /// Returns `true` if there is no entry.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.used() == 0
+    }
+
+    /// Returns an iterator over the entries, in the order they were inserted.
+    #[inline]
+    pub fn iter(&self) -> MetadataIter<'_> {
+        MetadataIter {
+            buf: &self.buf[..self.used()],
+        }
+    }
+
+    /// Returns the value of `key`.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Returns the value of `key` if it is a valid UTF-8 string.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|v| std::str::from_utf8(v).ok())
+    }
+
+    /// Sets the value of `key`, replacing its previous value.
+    ///
+    /// Returns an error and leaves the metadata unchanged if there is no room for the entry.
+    pub fn insert<V: AsRef<[u8]>>(&mut self, key: &str, value: V) -> Result<(), MetadataFull> {
+        let value = value.as_ref();
+        let entry_len = METADATA_ENTRY_HEADER + key.len() + value.len();
+        let old_len = self.entry_range(key).map_or(0, |(start, end)| end - start);
+        if self.used() - old_len + entry_len > RPC_METADATA_CAPACITY {
+            return Err(MetadataFull);
+        }
+
+        self.remove(key);
+        let start = self.used();
+        let entry = &mut self.buf[start..start + entry_len];
+        // both fit in u16 since the capacity does
+        entry[0..2].copy_from_slice(&(key.len() as u16).to_le_bytes());
+        entry[2..4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        entry[4..4 + key.len()].copy_from_slice(key.as_bytes());
+        entry[4 + key.len()..].copy_from_slice(value);
+        self.set_used(start + entry_len);
+        Ok(())
+    }
+
+    /// Removes `key`. Returns `true` if it was present.
+    pub fn remove(&mut self, key: &str) -> bool {
+        match self.entry_range(key) {
+            Some((start, end)) => {
+                let used = self.used();
+                self.buf.copy_within(end..used, start);
+                self.set_used(used - (end - start));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes all entries.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.set_used(0);
+    }
+
+    #[inline]
+    fn set_used(&mut self, used: usize) {
+        self.len = (used as u16).to_le_bytes();
+    }
+
+    /// Returns the range of the entry of `key` in `buf`.
+    fn entry_range(&self, key: &str) -> Option<(usize, usize)> {
+        let mut iter = self.iter();
+        loop {
+            let start = self.used() - iter.buf.len();
+            let (k, _) = iter.next()?;
+            if k == key {
+                return Some((start, self.used() - iter.buf.len()));
+            }
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a RpcMetadata {
+    type Item = (&'a str, &'a [u8]);
+    type IntoIter = MetadataIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of an [`RpcMetadata`].
+#[derive(Debug, Clone)]
+pub struct MetadataIter<'a> {
+    /// The remaining entries.
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for MetadataIter<'a> {
+    type Item = (&'a str, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < METADATA_ENTRY_HEADER {
+            return None;
+        }
+        let key_len = u16::from_le_bytes([self.buf[0], self.buf[1]]) as usize;
+        let value_len = u16::from_le_bytes([self.buf[2], self.buf[3]]) as usize;
+        let end = METADATA_ENTRY_HEADER + key_len + value_len;
+        // stop at a malformed entry
+        let key = self
+            .buf
+            .get(METADATA_ENTRY_HEADER..METADATA_ENTRY_HEADER + key_len);
+        let key = match key.map(std::str::from_utf8) {
+            Some(Ok(key)) if end <= self.buf.len() => key,
+            _ => {
+                self.buf = &[];
+                return None;
+            }
+        };
+        let value = &self.buf[METADATA_ENTRY_HEADER + key_len..end];
+        self.buf = &self.buf[end..];
+        Some((key, value))
+    }
 }
 
 mod sa {
@@ -141,10 +405,123 @@ mod sa {
     use static_assertions::const_assert_eq;
     use std::mem::size_of;
 
-    const_assert_eq!(size_of::<StatusCode>(), 4);
+    const_assert_eq!(size_of::<RpcMsgType>(), 1);
+    const_assert_eq!(size_of::<StatusCode>(), 1);
     const_assert_eq!(size_of::<Token>(), size_of::<usize>());
     const_assert_eq!(size_of::<TransportStatus>(), 4);
     const_assert_eq!(size_of::<RpcId>(), 16);
     const_assert_eq!(size_of::<MessageMeta>(), 40);
-    const_assert_eq!(size_of::<MessageErased>(), 56);
+    const_assert_eq!(size_of::<MessageErased>(), 72);
+    const_assert_eq!(size_of::<RpcMetadata>(), 1024);
+    const_assert_eq!(std::mem::align_of::<RpcMetadata>(), 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(metadata: &RpcMetadata) -> Vec<(&str, &[u8])> {
+        metadata.iter().collect()
+    }
+
+    #[test]
+    fn test_metadata_capacity() {
+        let mut metadata = RpcMetadata::new();
+        assert!(metadata.is_empty());
+
+        // an entry that takes the whole buffer
+        let value = vec![b'x'; RPC_METADATA_CAPACITY - METADATA_ENTRY_HEADER - 1];
+        metadata.insert("k", &value).unwrap();
+        assert_eq!(metadata.used(), RPC_METADATA_CAPACITY);
+        assert_eq!(metadata.get("k"), Some(&value[..]));
+
+        // no room for one more, the metadata is unchanged
+        assert_eq!(metadata.insert("a", ""), Err(MetadataFull));
+        assert_eq!(entries(&metadata), [("k", &value[..])]);
+
+        // the room of the replaced entry counts
+        let other = vec![b'y'; value.len()];
+        metadata.insert("k", &other).unwrap();
+        assert_eq!(metadata.get("k"), Some(&other[..]));
+        assert_eq!(
+            metadata.insert("k", [&other[..], b"z"].concat()),
+            Err(MetadataFull)
+        );
+        assert_eq!(metadata.get("k"), Some(&other[..]));
+
+        metadata.clear();
+        assert!(metadata.is_empty());
+        assert_eq!(metadata.get("k"), None);
+    }
+
+    #[test]
+    fn test_metadata_replace() {
+        let mut metadata = RpcMetadata::new();
+        metadata.insert("authorization", "Bearer xyz").unwrap();
+        metadata.insert("trace-id", [0xffu8, 0, 1]).unwrap();
+        metadata.insert("authorization", "Bearer abc").unwrap();
+
+        // the replaced entry moves to the end
+        assert_eq!(
+            entries(&metadata),
+            [
+                ("trace-id", &[0xffu8, 0, 1][..]),
+                ("authorization", &b"Bearer abc"[..])
+            ]
+        );
+        assert_eq!(metadata.get_str("authorization"), Some("Bearer abc"));
+        assert_eq!(metadata.get_str("trace-id"), None);
+        assert_eq!(metadata.get("missing"), None);
+    }
+
+    #[test]
+    fn test_metadata_remove() {
+        let mut metadata = RpcMetadata::new();
+        metadata.insert("a", "1").unwrap();
+        metadata.insert("b", "22").unwrap();
+        metadata.insert("c", "333").unwrap();
+        let used = metadata.used();
+
+        assert!(metadata.remove("b"));
+        assert!(!metadata.remove("b"));
+        // the entries behind are moved forward
+        assert_eq!(entries(&metadata), [("a", &b"1"[..]), ("c", &b"333"[..])]);
+        assert_eq!(metadata.used(), used - (METADATA_ENTRY_HEADER + 3));
+
+        assert!(metadata.remove("c"));
+        assert!(metadata.remove("a"));
+        assert!(metadata.is_empty());
+        assert!(!metadata.remove("a"));
+    }
+
+    #[test]
+    fn test_metadata_malformed() {
+        let mut metadata = RpcMetadata::new();
+        metadata.insert("a", "1").unwrap();
+        metadata.insert("b", "2").unwrap();
+
+        // a len beyond the buffer is clamped
+        let mut clamped = metadata;
+        clamped.len = u16::MAX.to_le_bytes();
+        assert_eq!(clamped.used(), RPC_METADATA_CAPACITY);
+        assert_eq!(clamped.get("b"), Some(&b"2"[..]));
+
+        // a len that cuts an entry stops the iteration before it
+        let mut truncated = metadata;
+        truncated.set_used(metadata.used() - 1);
+        assert_eq!(entries(&truncated), [("a", &b"1"[..])]);
+        truncated.set_used(METADATA_ENTRY_HEADER - 1);
+        assert!(entries(&truncated).is_empty());
+
+        // a value len beyond the used bytes
+        let mut overlong = metadata;
+        overlong.buf[2..4].copy_from_slice(&1000u16.to_le_bytes());
+        assert!(entries(&overlong).is_empty());
+        assert_eq!(overlong.get("b"), None);
+
+        // a key that is not UTF-8
+        let mut invalid = metadata;
+        invalid.buf[METADATA_ENTRY_HEADER] = 0xff;
+        assert!(entries(&invalid).is_empty());
+    }
 }
@@ -1,5 +1,6 @@
 //! Socket Control path commands.
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
@@ -7,13 +8,39 @@ use phoenix_api::Handle;
 
 type IResult<T> = Result<T, phoenix_api::Error>;
 
+/// Options that can be set on a connected socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SockOption {
+    /// TCP_NODELAY.
+    NoDelay(bool),
+    /// SO_SNDBUF, in bytes.
+    SendBufferSize(usize),
+    /// SO_RCVBUF, in bytes.
+    RecvBufferSize(usize),
+    /// SO_KEEPALIVE, with the idle time before the first probe. `None` disables keepalive.
+    KeepAlive(Option<Duration>),
+}
+
+/// A memory region registered with the TCP transport.
+///
+/// The backing memfd is sent to the application along with the completion. The application
+/// maps `map_len` bytes of it at `file_off`. The buffer ranges in work requests must be
+/// expressed in terms of `vaddr`, the address of the region in the backend.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MemoryRegion {
+    pub handle: Handle,
+    pub vaddr: usize,
+    pub map_len: u64,
+    pub file_off: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Command {
     Bind(SocketAddr, u32),
     Accept(Handle),
     Connect(SocketAddr),
     RegMr(usize),
-    SetSockOption(Handle),
+    SetSockOption(Handle, SockOption),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,7 +48,7 @@ pub enum CompletionKind {
     Bind(Handle),
     Accept(Handle),
     Connect(Handle),
-    RegMr(Handle),
+    RegMr(MemoryRegion),
     SetSockOption,
 }
 
@@ -51,6 +51,7 @@ pub trait PhoenixAddon: TypeTagged + Send + Sync + 'static {
     /// Restores an addon engine
     /// addon does not share states
     /// it only dumps and restores from local states
+    /// see `PhoenixModule::restore_engine` for the states on failure
     fn restore_engine(
         &mut self,
         ty: EngineType,
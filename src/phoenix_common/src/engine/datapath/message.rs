@@ -1,6 +1,6 @@
-use std::ptr::Unique;
+use std::ptr::{NonNull, Unique};
 
-use phoenix_api::rpc::{CallId, MessageMeta, RpcId, TransportStatus};
+use phoenix_api::rpc::{CallId, MessageMeta, RpcId, RpcMetadata, TransportStatus};
 use phoenix_api::Handle;
 use phoenix_api_mrpc::dp::RECV_RECLAIM_BS;
 
@@ -21,6 +21,8 @@ pub struct RpcMessageTx {
     // Each RPC message is assigned a buffer for meta and optionally for its data
     pub meta_buf_ptr: MetaBufferPtr,
     pub addr_backend: usize,
+    /// The backend address of the [`RpcMetadata`] of the message, or 0 if it has none.
+    pub metadata_backend: usize,
 }
 
 impl RpcMessageTx {
@@ -28,14 +30,25 @@ impl RpcMessageTx {
         RpcMessageTx {
             meta_buf_ptr,
             addr_backend,
+            metadata_backend: 0,
         }
     }
+
+    /// Returns a pointer to the [`RpcMetadata`] of the message on the shared memory heap, which
+    /// policies may read and modify in place.
+    #[inline]
+    pub fn metadata_ptr(&self) -> Option<NonNull<RpcMetadata>> {
+        NonNull::new(self.metadata_backend as *mut RpcMetadata)
+    }
 }
 
 #[derive(Debug)]
 pub enum EngineTxMessage {
     RpcMessage(RpcMessageTx),
     ReclaimRecvBuf(Handle, [CallId; RECV_RECLAIM_BS]),
+    // A meta-only message that cancels an ongoing call. It does not hold a MetaBuffer, and it is
+    // not acknowledged.
+    Cancel(MessageMeta),
 }
 
 #[derive(Debug)]
@@ -43,6 +56,18 @@ pub struct RpcMessageRx {
     pub meta: Unique<MessageMeta>,
     pub addr_app: usize,
     pub addr_backend: usize,
+    /// The addresses of the [`RpcMetadata`] of the message, or 0 if it has none.
+    pub metadata_app: usize,
+    pub metadata_backend: usize,
+}
+
+impl RpcMessageRx {
+    /// Returns a pointer to the [`RpcMetadata`] of the message in the receive buffers, which
+    /// policies may read and modify in place.
+    #[inline]
+    pub fn metadata_ptr(&self) -> Option<NonNull<RpcMetadata>> {
+        NonNull::new(self.metadata_backend as *mut RpcMetadata)
+    }
 }
 
 #[derive(Debug)]
@@ -51,4 +76,6 @@ pub enum EngineRxMessage {
     Ack(RpcId, TransportStatus),
     // (conn_id, status), we cannot know which rpc_id the receive corresponds
     RecvError(Handle, TransportStatus),
+    // The peer cancelled the call identified by the rpc_id.
+    Cancel(RpcId),
 }
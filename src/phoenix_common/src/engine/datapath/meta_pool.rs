@@ -37,6 +37,7 @@ mod sa {
     use std::mem::size_of;
 
     const_assert_eq!(size_of::<MetaBuffer>(), META_BUFFER_SIZE);
+    const_assert_eq!(size_of::<MetaHeader>(), size_of::<MessageMeta>() + 8);
     const_assert_eq!(size_of::<Option<MetaBufferPtr>>(), mem::size_of::<usize>());
 }
 
@@ -107,6 +108,39 @@ impl MetaBuffer {
     }
 }
 
+/// The header of a [`MetaBuffer`] that carries no message body.
+///
+/// Used to send meta-only messages (e.g., cancellations) without occupying a [`MetaBuffer`].
+/// The receiver sees it as a fused message with zero segments.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct MetaHeader {
+    /// The metadata of an RPC message.
+    pub meta: MessageMeta,
+    /// Always 0.
+    pub num_sge: u32,
+    /// Always 0.
+    pub value_len: u32,
+}
+
+impl MetaHeader {
+    /// Constructs a `MetaHeader` for `meta`.
+    #[inline]
+    pub fn new(meta: MessageMeta) -> Self {
+        MetaHeader {
+            meta,
+            num_sge: 0,
+            value_len: 0,
+        }
+    }
+
+    /// Returns the number of bytes of the `MetaHeader`.
+    #[inline]
+    pub const fn len() -> usize {
+        mem::size_of::<Self>()
+    }
+}
+
 /// A `Unique` pointer to the a [`MetaBuffer`].
 // Must be Send
 #[derive(Debug, Clone, Copy)]
@@ -1,3 +1,6 @@
+use std::fmt;
+use std::sync::Mutex;
+
 use super::datapath::node::DataPathNode;
 use crate::storage::{ResourceCollection, SharedStorage};
 
@@ -16,3 +19,44 @@ pub trait Decompose {
         global: &mut ResourceCollection,
     ) -> (ResourceCollection, DataPathNode);
 }
+
+/// An error of restoring an engine that hands back the states of the engine,
+/// so that the engine can still be restored by another version of its module.
+///
+/// `restore_engine` of a module may return it (wrapped in an `anyhow::Error`)
+/// if it fails before taking any state.
+pub struct RestoreError {
+    // the states are not shared, the mutex only makes the error `Sync`
+    states: Mutex<(ResourceCollection, DataPathNode)>,
+    source: anyhow::Error,
+}
+
+impl RestoreError {
+    pub fn new(local: ResourceCollection, node: DataPathNode, source: anyhow::Error) -> Self {
+        RestoreError {
+            states: Mutex::new((local, node)),
+            source,
+        }
+    }
+
+    /// Takes the states of the engine back.
+    pub fn into_states(self) -> (ResourceCollection, DataPathNode) {
+        self.states.into_inner().unwrap()
+    }
+}
+
+impl fmt::Debug for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RestoreError")
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.source)
+    }
+}
+
+impl std::error::Error for RestoreError {}
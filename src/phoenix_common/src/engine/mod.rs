@@ -11,7 +11,7 @@ pub mod datapath;
 pub use datapath::node::Vertex;
 
 pub mod decompose;
-pub use decompose::{Decompose, DecomposeResult};
+pub use decompose::{Decompose, DecomposeResult, RestoreError};
 
 pub type EngineResult = Result<(), Box<dyn std::error::Error>>;
 
@@ -139,6 +139,9 @@ pub trait PhoenixModule: TypeTagged + Send + Sync + 'static {
     /// In case some of the states' types are changed in an upgrade,
     /// engines should dumped their states to atomic components,
     /// so that the new state type can be reassembled from the components.
+    /// If the states are left untouched on failure, return them in a
+    /// [`RestoreError`](crate::engine::RestoreError), so that the engine can be
+    /// restored by the previous version of the module.
     #[allow(clippy::too_many_arguments)]
     fn restore_engine(
         &mut self,
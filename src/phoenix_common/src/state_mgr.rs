@@ -1,4 +1,5 @@
 use std::collections::hash_map;
+use std::hash::Hash;
 use std::sync::{Arc, Weak};
 
 use fnv::FnvHashMap as HashMap;
@@ -11,23 +12,28 @@ pub trait ProcessShared: Sized {
 }
 
 /// Per-user-application-process shared state
-pub struct SharedStateManager<S> {
-    states: HashMap<Pid, Weak<S>>,
+///
+/// The state can also be shared by a group of processes other than a single process, e.g.,
+/// all processes of a uid, by keying it with `K`. The state is dropped once no engine holds it.
+pub struct SharedStateManager<S, K = Pid> {
+    states: HashMap<K, Weak<S>>,
 }
 
-impl<S: ProcessShared> Default for SharedStateManager<S> {
+impl<S, K> Default for SharedStateManager<S, K> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<S: ProcessShared> SharedStateManager<S> {
+impl<S, K> SharedStateManager<S, K> {
     pub fn new() -> Self {
         SharedStateManager {
             states: HashMap::default(),
         }
     }
+}
 
+impl<S: ProcessShared> SharedStateManager<S> {
     pub fn get_or_create(&mut self, pid: Pid) -> Result<Arc<S>, <S as ProcessShared>::Err> {
         match self.states.entry(pid) {
             hash_map::Entry::Occupied(mut entry) => {
@@ -54,29 +60,42 @@ impl<S: ProcessShared> SharedStateManager<S> {
         pid: Pid,
         init: F,
     ) -> Result<Arc<S>, <S as ProcessShared>::Err> {
-        match self.states.entry(pid) {
+        Ok(self.get_or_insert_with(pid, init))
+    }
+
+    #[inline]
+    pub fn contains(&self, pid: Pid) -> bool {
+        self.contains_key(&pid)
+    }
+}
+
+impl<S, K: Hash + Eq> SharedStateManager<S, K> {
+    /// Returns the state of `key`, and creates it with `init` if no one holds it.
+    pub fn get_or_insert_with<F: FnOnce() -> S>(&mut self, key: K, init: F) -> Arc<S> {
+        match self.states.entry(key) {
             hash_map::Entry::Occupied(mut entry) => {
                 if let Some(state) = entry.get().upgrade() {
-                    Ok(state)
+                    state
                 } else {
                     let state = init();
                     let wrapped = Arc::new(state);
                     entry.insert(Arc::downgrade(&wrapped));
-                    Ok(wrapped)
+                    wrapped
                 }
             }
             hash_map::Entry::Vacant(entry) => {
                 let state = init();
                 let wrapped = Arc::new(state);
                 entry.insert(Arc::downgrade(&wrapped));
-                Ok(wrapped)
+                wrapped
             }
         }
     }
 
+    /// Returns true if the state of `key` is held by anyone.
     #[inline]
-    pub fn contains(&self, pid: Pid) -> bool {
-        if let Some(state) = self.states.get(&pid) {
+    pub fn contains_key(&self, key: &K) -> bool {
+        if let Some(state) = self.states.get(key) {
             state.strong_count() > 0
         } else {
             false
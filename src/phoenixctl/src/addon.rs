@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use ipc::control::{pid_t, AddonInsertion, AddonRequest, Request, ResponseKind};
+use phoenix_api::engine::SchedulingMode;
+
+use crate::client::Client;
+use crate::report::check_report;
+
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct Args {
+    /// Path to the addon descriptor
+    #[arg(short, long)]
+    config: PathBuf,
+    #[arg(long)]
+    pid: pid_t,
+    #[arg(long)]
+    sid: u64,
+    /// Seconds to wait for the operation to finish
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum AddonOp {
+    #[serde(alias = "attach")]
+    Attach,
+    #[serde(alias = "detach")]
+    Detach,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    addon_engine: String,
+    #[serde(default)]
+    tx_channels_replacements: Vec<(String, String, usize, usize)>,
+    #[serde(default)]
+    rx_channels_replacements: Vec<(String, String, usize, usize)>,
+    /// Where to attach the addon, instead of spelling out the replacements
+    insertion: Option<AddonInsertion>,
+    #[serde(default)]
+    group: Vec<String>,
+    /// The subcommand decides the operation. Kept for the descriptors written for `addonctl`.
+    op: Option<AddonOp>,
+    config_path: Option<PathBuf>,
+    config_string: Option<String>,
+}
+
+impl Config {
+    fn from_path<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+}
+
+pub(crate) fn run(client: &Client, op: AddonOp, args: Args) -> anyhow::Result<()> {
+    let config = Config::from_path(&args.config)?;
+    if config.op.map_or(false, |x| x != op) {
+        bail!(
+            "{:?} is for {:?}, but {:?} is requested",
+            args.config,
+            config.op.unwrap(),
+            op
+        );
+    }
+
+    let request = AddonRequest {
+        pid: args.pid,
+        sid: args.sid,
+        addon_engine: config.addon_engine,
+        tx_channels_replacements: config.tx_channels_replacements,
+        rx_channels_replacements: config.rx_channels_replacements,
+        insertion: config.insertion,
+        group: config.group,
+        config_path: config.config_path,
+        config_string: config.config_string,
+    };
+    let req = match op {
+        AddonOp::Attach => Request::AttachAddon(SchedulingMode::Dedicate, request),
+        AddonOp::Detach => Request::DetachAddon(request),
+    };
+    client.send(&req)?;
+
+    match client.recv(Some(Duration::from_secs(args.timeout)))? {
+        ResponseKind::AttachAddon(report) | ResponseKind::DetachAddon(report) => {
+            check_report(&report)
+        }
+        res => bail!("invalid response: {:?}", res),
+    }
+}
@@ -0,0 +1,85 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::bail;
+use uuid::Uuid;
+
+use ipc::control::{Request, Response, ResponseKind};
+use ipc::unix::DomainSocket;
+
+const MAX_MSG_LEN: usize = 65536;
+
+const DEFAULT_PHOENIX_PREFIX: &str = "/tmp/phoenix";
+const DEFAULT_PHOENIX_CONTROL: &str = "control.sock";
+
+lazy_static::lazy_static! {
+    static ref PHOENIX_PREFIX: PathBuf = {
+        env::var("PHOENIX_PREFIX").map_or_else(|_| PathBuf::from(DEFAULT_PHOENIX_PREFIX), |p| {
+            let path = PathBuf::from(p);
+            assert!(path.is_dir(), "{path:?} is not a directly");
+            path
+        })
+    };
+
+    static ref PHOENIX_CONTROL_SOCK: PathBuf = {
+        env::var("PHOENIX_CONTROL")
+            .map_or_else(|_| PathBuf::from(DEFAULT_PHOENIX_CONTROL), PathBuf::from)
+    };
+}
+
+/// A connection to the control plane of phoenix.
+pub(crate) struct Client {
+    sock: DomainSocket,
+    service_path: PathBuf,
+}
+
+impl Client {
+    pub(crate) fn new() -> anyhow::Result<Self> {
+        let uuid = Uuid::new_v4();
+        let arg0 = env::args().next().unwrap();
+        let appname = Path::new(&arg0).file_name().unwrap().to_string_lossy();
+
+        let sock_path = PHOENIX_PREFIX.join(format!("phoenix-client-{}_{}.sock", appname, uuid));
+
+        if sock_path.exists() {
+            std::fs::remove_file(&sock_path)?;
+        }
+        let sock = DomainSocket::bind(sock_path)?;
+        let service_path = PHOENIX_PREFIX.join(PHOENIX_CONTROL_SOCK.as_path());
+        Ok(Client { sock, service_path })
+    }
+
+    pub(crate) fn send(&self, req: &Request) -> anyhow::Result<()> {
+        let buf = bincode::serialize(req)?;
+        assert!(buf.len() < MAX_MSG_LEN);
+        self.sock.send_to(&buf, &self.service_path)?;
+        Ok(())
+    }
+
+    /// Waits for the response to the last request. Blocks forever if `timeout` is `None`.
+    pub(crate) fn recv(&self, timeout: Option<Duration>) -> anyhow::Result<ResponseKind> {
+        self.sock.set_read_timeout(timeout)?;
+        let mut buf = vec![0u8; MAX_MSG_LEN];
+        let (_, sender) = match self.sock.recv_from(buf.as_mut_slice()) {
+            Ok(res) => res,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                bail!(
+                    "no response from phoenix after {:?}",
+                    timeout.unwrap_or_default()
+                );
+            }
+            Err(e) => return Err(e.into()),
+        };
+        assert_eq!(sender.as_pathname(), Some(self.service_path.as_ref()));
+
+        let res: Response = bincode::deserialize(&buf)?;
+        Ok(res.0?)
+    }
+}
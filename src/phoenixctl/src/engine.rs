@@ -0,0 +1,89 @@
+use anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use ipc::control::Request;
+
+use crate::client::Client;
+
+/// Converts a JSON value to the bincode encoded `control_plane::Request` of a policy.
+type Encoder = fn(Value) -> anyhow::Result<Vec<u8>>;
+
+fn encode<T: DeserializeOwned + Serialize>(value: Value) -> anyhow::Result<Vec<u8>> {
+    let request: T = serde_json::from_value(value)?;
+    Ok(bincode::serialize(&request)?)
+}
+
+/// The policies (and engines) whose requests can be sent. Supporting a new policy only takes
+/// adding its `control_plane::Request` here.
+const POLICIES: &[(&str, Encoder)] = &[
+    (
+        "rpc-adapter",
+        encode::<phoenix_api_rpc_adapter::control_plane::Request>,
+    ),
+    (
+        "ratelimit",
+        encode::<phoenix_api_policy_ratelimit::control_plane::Request>,
+    ),
+    (
+        "qos",
+        encode::<phoenix_api_policy_qos::control_plane::Request>,
+    ),
+    (
+        "delay",
+        encode::<phoenix_api_policy_delay::control_plane::Request>,
+    ),
+    (
+        "acl",
+        encode::<phoenix_api_policy_acl::control_plane::Request>,
+    ),
+];
+
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct RequestArgs {
+    /// The engine to send the request to
+    #[arg(short, long)]
+    eid: u64,
+    /// The policy that defines the request, e.g., ratelimit, qos, delay or rpc-adapter
+    #[arg(short, long)]
+    policy: String,
+    /// The request in JSON, e.g., '{"NewConfig":[1000,128]}' or '"ListConnection"'
+    request: String,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct ConfigArgs {
+    /// The engine to update
+    #[arg(short, long)]
+    eid: u64,
+    /// The policy that defines the configuration, e.g., ratelimit, qos or delay
+    #[arg(short, long)]
+    policy: String,
+    /// The new configuration in JSON, e.g., '[1000,128]' for ratelimit
+    config: String,
+}
+
+fn send_request(client: &Client, eid: u64, policy: &str, request: Value) -> anyhow::Result<()> {
+    let (_, encoder) = POLICIES
+        .iter()
+        .find(|(name, _)| *name == policy)
+        .ok_or_else(|| {
+            let names: Vec<_> = POLICIES.iter().map(|(name, _)| *name).collect();
+            anyhow!("unknown policy {:?}, expect one of {:?}", policy, names)
+        })?;
+    let request_encoded = encoder(request)?;
+    client.send(&Request::EngineRequest(eid, request_encoded))
+}
+
+pub(crate) fn run_request(client: &Client, args: RequestArgs) -> anyhow::Result<()> {
+    let request = serde_json::from_str(&args.request)?;
+    send_request(client, args.eid, &args.policy, request)
+}
+
+/// Sends the policy's `NewConfig` request.
+pub(crate) fn run_config(client: &Client, args: ConfigArgs) -> anyhow::Result<()> {
+    let config: Value = serde_json::from_str(&args.config)?;
+    let request = serde_json::json!({ "NewConfig": config });
+    send_request(client, args.eid, &args.policy, request)
+}
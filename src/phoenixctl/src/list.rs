@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::bail;
+use prettytable::Table;
+
+use ipc::control::{pid_t, Request, ResponseKind, ServiceSubscriptionInfo, SubscriptionFilter};
+
+use crate::client::Client;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// Human readable tables
+    Table,
+    /// A JSON array of all service subscriptions
+    Json,
+    /// One JSON object per line for each service subscription
+    JsonLines,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct Args {
+    /// Only list the service subscriptions of this process
+    #[arg(long)]
+    pid: Option<pid_t>,
+    /// Only list the subscriptions of this service, e.g., Mrpc
+    #[arg(long)]
+    service: Option<String>,
+    /// How to print the service subscriptions to stdout
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Dump the service subscriptions to a JSON file instead of printing them
+    #[arg(short, long)]
+    dump: Option<PathBuf>,
+}
+
+pub(crate) fn run(client: &Client, args: Args) -> anyhow::Result<()> {
+    let filter = SubscriptionFilter {
+        pid: args.pid,
+        service: args.service,
+    };
+    client.send(&Request::ListSubscription(filter))?;
+    let subscriptions = match client.recv(None)? {
+        ResponseKind::ListSubscription(subscriptions) => subscriptions,
+        res => bail!("invalid response: {:?}", res),
+    };
+
+    if let Some(path) = args.dump {
+        let f = File::create(path)?;
+        let writer = BufWriter::new(f);
+        serde_json::to_writer_pretty(writer, &subscriptions)?;
+        return Ok(());
+    }
+
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+    match args.format {
+        Format::Table => print_table(subscriptions),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, &subscriptions)?;
+            writeln!(writer)?;
+        }
+        Format::JsonLines => {
+            for subscription in subscriptions {
+                serde_json::to_writer(&mut writer, &subscription)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_table(subscriptions: Vec<ServiceSubscriptionInfo>) {
+    let mut table = Table::new();
+    table.add_row(row![bFm => "PID", "SID", "Service", "Addons", "Engines"]);
+    for subscription in subscriptions {
+        let addons = if !subscription.addons.is_empty() {
+            subscription
+                .addons
+                .iter()
+                .map(|addon| addon.engine_type.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            "None".to_string()
+        };
+        if subscription.engines.is_empty() {
+            table.add_row(row![
+                subscription.pid,
+                subscription.sid,
+                subscription.service,
+                Fy->addons,
+                Fb->"None"
+            ]);
+            continue;
+        }
+        let mut engines = Table::new();
+        engines
+            .add_row(row![bFc => "EngineId", "EngineType", "Runtime", "Group", "Mode", "Version"]);
+        for engine in subscription.engines {
+            engines.add_row(row![Fc =>
+                engine.eid,
+                engine.engine_type,
+                engine.rid,
+                engine.gid,
+                format!("{:?}", engine.scheduling_mode),
+                engine.version.as_deref().unwrap_or("-")
+            ]);
+        }
+        table.add_row(row![
+            subscription.pid,
+            subscription.sid,
+            subscription.service,
+            Fy->addons,
+            Fb->engines
+        ]);
+    }
+    table.printstd();
+}
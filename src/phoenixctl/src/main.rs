@@ -0,0 +1,70 @@
+//! The command line utility to manage phoenix.
+use std::process::ExitCode;
+
+#[macro_use]
+extern crate prettytable;
+use clap::{Parser, Subcommand};
+
+mod addon;
+mod client;
+mod engine;
+mod list;
+mod metrics;
+mod report;
+mod unload;
+mod upgrade;
+
+use addon::AddonOp;
+use client::Client;
+
+#[derive(Debug, Clone, Parser)]
+#[command(name = "phoenixctl", about = "Phoenix control utility")]
+struct Opts {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// List service subscriptions
+    List(list::Args),
+    /// Attach an addon to a service subscription
+    Attach(addon::Args),
+    /// Detach an addon from a service subscription
+    Detach(addon::Args),
+    /// Upgrade modules and addons
+    Upgrade(upgrade::Args),
+    /// Unload modules and addons that have no running engines
+    Unload(unload::Args),
+    /// Send a control plane request, written in JSON, to an engine
+    EngineRequest(engine::RequestArgs),
+    /// Update the configuration of a policy engine
+    Config(engine::ConfigArgs),
+    /// Show the metrics of runtimes and engines
+    Metrics(metrics::Args),
+}
+
+fn run(command: Command) -> anyhow::Result<()> {
+    let client = Client::new()?;
+    match command {
+        Command::List(args) => list::run(&client, args),
+        Command::Attach(args) => addon::run(&client, AddonOp::Attach, args),
+        Command::Detach(args) => addon::run(&client, AddonOp::Detach, args),
+        Command::Upgrade(args) => upgrade::run(&client, args),
+        Command::Unload(args) => unload::run(&client, args),
+        Command::EngineRequest(args) => engine::run_request(&client, args),
+        Command::Config(args) => engine::run_config(&client, args),
+        Command::Metrics(args) => metrics::run(&client, args),
+    }
+}
+
+fn main() -> ExitCode {
+    let opts = Opts::parse();
+    match run(opts.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use prettytable::Table;
+
+use ipc::control::{Request, ResponseKind, RuntimeMetrics};
+
+use crate::client::Client;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// Human readable tables
+    Table,
+    /// A JSON array of the metrics of all runtimes
+    Json,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct Args {
+    /// Only show the metrics of this engine
+    #[arg(short, long)]
+    eid: Option<u64>,
+    /// How to print the metrics to stdout
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Seconds to wait for the metrics
+    #[arg(long, default_value_t = 5)]
+    timeout: u64,
+}
+
+pub(crate) fn run(client: &Client, args: Args) -> anyhow::Result<()> {
+    client.send(&Request::Metrics)?;
+    let mut metrics = match client.recv(Some(Duration::from_secs(args.timeout)))? {
+        ResponseKind::Metrics(metrics) => metrics,
+        res => bail!("invalid response: {:?}", res),
+    };
+
+    if let Some(eid) = args.eid {
+        for runtime in metrics.iter_mut() {
+            runtime.engines.retain(|e| e.eid == eid);
+        }
+        metrics.retain(|runtime| !runtime.engines.is_empty());
+    }
+
+    match args.format {
+        Format::Table => print_table(&metrics),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&metrics)?),
+    }
+    Ok(())
+}
+
+fn print_table(metrics: &[RuntimeMetrics]) {
+    let mut table = Table::new();
+    table.add_row(row![bFm => "Runtime", "Iterations", "Busy", "Engines"]);
+    for runtime in metrics {
+        let mut engines = Table::new();
+        engines.add_row(row![bFc =>
+            "EngineId", "EngineType", "Polls", "Busy", "PollTime(ms)", "Queues (messages, depth)"
+        ]);
+        for engine in runtime.engines.iter() {
+            let queues = engine
+                .queues
+                .iter()
+                .map(|q| {
+                    format!(
+                        "{}[{}]: {}, {}",
+                        q.kind.as_str(),
+                        q.index,
+                        q.messages,
+                        q.depth
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            engines.add_row(row![Fc =>
+                engine.eid,
+                engine.engine_type,
+                engine.polls,
+                format!("{:.2}%", engine.busy_ratio() * 100.0),
+                format!("{:.3}", engine.poll_time_ns as f64 / 1e6),
+                queues
+            ]);
+        }
+        table.add_row(row![
+            runtime.rid,
+            runtime.iterations,
+            format!("{:.2}%", runtime.busy_ratio() * 100.0),
+            Fb->engines
+        ]);
+    }
+    table.printstd();
+}
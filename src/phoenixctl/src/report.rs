@@ -0,0 +1,23 @@
+use anyhow::bail;
+
+use ipc::control::UpgradeReport;
+
+/// Prints the report, returns an error if any plugin failed.
+pub(crate) fn check_report(report: &UpgradeReport) -> anyhow::Result<()> {
+    for plugin in &report.plugins {
+        match &plugin.error {
+            None => println!("{}: succeeded", plugin.name),
+            Some(error) => eprintln!("{}: failed: {}", plugin.name, error),
+        }
+    }
+    for (pid, sid, engine_id, engine_type) in &report.engines {
+        println!(
+            "engine {} {} (pid={}, sid={})",
+            engine_id, engine_type, pid, sid
+        );
+    }
+    if !report.is_success() {
+        bail!("some plugins failed");
+    }
+    Ok(())
+}
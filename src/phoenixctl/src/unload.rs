@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use anyhow::bail;
+
+use ipc::control::{PluginType, Request, ResponseKind, UnloadRequest};
+
+use crate::client::Client;
+use crate::report::check_report;
+
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct Args {
+    /// Names of the modules to unload
+    #[arg(long = "module", value_delimiter = ',')]
+    modules: Vec<String>,
+    /// Names of the addons to unload
+    #[arg(long = "addon", value_delimiter = ',')]
+    addons: Vec<String>,
+    /// Seconds to wait for phoenix to respond
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+}
+
+pub(crate) fn run(client: &Client, args: Args) -> anyhow::Result<()> {
+    if args.modules.is_empty() && args.addons.is_empty() {
+        bail!("nothing to unload, specify --module or --addon");
+    }
+    let timeout = Duration::from_secs(args.timeout);
+
+    let unload = |plugins, ty| -> anyhow::Result<()> {
+        client.send(&Request::Unload(UnloadRequest { plugins, ty }))?;
+        match client.recv(Some(timeout))? {
+            ResponseKind::Unload(report) => check_report(&report),
+            res => bail!("invalid response: {:?}", res),
+        }
+    };
+
+    // handle addons, then modules even if the addons failed
+    let mut result = Ok(());
+    if !args.addons.is_empty() {
+        result = unload(args.addons, PluginType::Addon);
+    }
+    if !args.modules.is_empty() {
+        result = result.and(unload(args.modules, PluginType::Module));
+    }
+    result
+}
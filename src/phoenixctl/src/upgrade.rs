@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use ipc::control::{
+    PluginDescriptor, PluginType, Request, ResponseKind, UpgradeRequest, UpgradeScope,
+};
+
+use crate::client::Client;
+use crate::report::check_report;
+
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct Args {
+    /// Phoenix config path
+    #[arg(short, long)]
+    config: PathBuf,
+    /// Seconds to wait for each upgrade to finish
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+    /// Only upgrade the modules for these client processes, the others keep running the old
+    /// version until the modules are upgraded again
+    #[arg(long = "pid", value_delimiter = ',')]
+    pids: Vec<i32>,
+    /// Roll back the staged upgrade of the modules, addons are ignored
+    #[arg(long, conflicts_with = "pids")]
+    rollback: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    modules: Vec<PluginDescriptor>,
+    #[serde(default)]
+    addons: Vec<PluginDescriptor>,
+    flush: Option<bool>,
+    detach_subscription: Option<bool>,
+}
+
+impl Config {
+    fn from_path<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+}
+
+pub(crate) fn run(client: &Client, args: Args) -> anyhow::Result<()> {
+    let config = Config::from_path(args.config)?;
+    let timeout = Duration::from_secs(args.timeout);
+
+    let flush = config.flush.unwrap_or(false);
+    let detach_subscription = config.detach_subscription.unwrap_or(true);
+
+    let upgrade = |plugins, ty, scope, rollback| -> anyhow::Result<()> {
+        let upgrade_request = UpgradeRequest {
+            plugins,
+            ty,
+            flush,
+            detach_subscription,
+            scope,
+            rollback,
+        };
+        client.send(&Request::Upgrade(upgrade_request))?;
+        match client.recv(Some(timeout))? {
+            ResponseKind::Upgrade(report) => check_report(&report),
+            res => bail!("invalid response: {:?}", res),
+        }
+    };
+
+    if args.rollback {
+        return upgrade(config.modules, PluginType::Module, UpgradeScope::All, true);
+    }
+
+    // handle modules
+    let mut result = Ok(());
+    if !config.modules.is_empty() {
+        let scope = if args.pids.is_empty() {
+            UpgradeScope::All
+        } else {
+            UpgradeScope::Pids(args.pids)
+        };
+        result = upgrade(config.modules, PluginType::Module, scope, false);
+    }
+
+    // handle addons, even if the modules failed
+    if !config.addons.is_empty() {
+        result = result.and(upgrade(
+            config.addons,
+            PluginType::Addon,
+            UpgradeScope::All,
+            false,
+        ));
+    }
+    result
+}
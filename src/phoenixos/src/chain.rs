@@ -0,0 +1,98 @@
+//! Addon chains declared in the config, which are put in place when a client subscribes.
+use std::fs;
+use std::os::unix::net::UCred;
+
+use anyhow::bail;
+
+use phoenix_common::engine::datapath::ChannelDescriptor;
+use phoenix_common::engine::EngineType;
+
+use crate::config::ClientSelector;
+
+impl ClientSelector {
+    /// Returns true if the client process matches the selector.
+    pub(crate) fn matches(&self, cred: &UCred) -> bool {
+        if self.uid.map_or(false, |uid| uid != cred.uid) {
+            return false;
+        }
+        let pid = match cred.pid {
+            Some(pid) => pid,
+            None => return self.app.is_none() && self.cgroup.is_none(),
+        };
+        if let Some(app) = &self.app {
+            match fs::read_to_string(format!("/proc/{}/comm", pid)) {
+                Ok(comm) if comm.trim_end() == app => {}
+                _ => return false,
+            }
+        }
+        if let Some(cgroup) = &self.cgroup {
+            let Ok(content) = fs::read_to_string(format!("/proc/{}/cgroup", pid)) else {
+                return false;
+            };
+            // hierarchy-ID:controller-list:cgroup-path
+            let in_cgroup = content
+                .lines()
+                .filter_map(|l| l.splitn(3, ':').nth(2))
+                .any(|path| {
+                    path.strip_prefix(cgroup.as_str()).map_or(false, |rest| {
+                        rest.is_empty() || rest.starts_with('/') || cgroup.ends_with('/')
+                    })
+                });
+            if !in_cgroup {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// An addon chain of the config, with the engine types resolved.
+pub(crate) struct ChainPlan {
+    pub(crate) sender: EngineType,
+    pub(crate) receiver: EngineType,
+    /// Addons on the tx direction, from the sender to the receiver
+    pub(crate) tx: Vec<EngineType>,
+    /// Addons on the rx direction, from the receiver to the sender
+    pub(crate) rx: Vec<EngineType>,
+    /// Each addon and the config string to create it with
+    pub(crate) addons: Vec<(EngineType, Option<String>)>,
+}
+
+impl ChainPlan {
+    /// Insert the addons into the channels of a service.
+    pub(crate) fn splice(
+        &self,
+        tx_channels: &mut Vec<ChannelDescriptor>,
+        rx_channels: &mut Vec<ChannelDescriptor>,
+    ) -> anyhow::Result<()> {
+        splice_channels(tx_channels, self.sender, self.receiver, &self.tx)?;
+        splice_channels(rx_channels, self.receiver, self.sender, &self.rx)
+    }
+}
+
+/// Replace the channel from `sender` to `receiver` in `channels` with a chain of channels
+/// through `addons`, in order. The addons only have one input and one output.
+fn splice_channels(
+    channels: &mut Vec<ChannelDescriptor>,
+    sender: EngineType,
+    receiver: EngineType,
+    addons: &[EngineType],
+) -> anyhow::Result<()> {
+    if addons.is_empty() {
+        return Ok(());
+    }
+    let Some(index) = channels
+        .iter()
+        .position(|c| c.0 == sender && c.1 == receiver)
+    else {
+        bail!("no channel from {:?} to {:?}", sender, receiver);
+    };
+    let channel = channels.remove(index);
+    let mut prev = (channel.0, channel.2);
+    for addon in addons {
+        channels.push(ChannelDescriptor(prev.0, *addon, prev.1, 0));
+        prev = (*addon, 0);
+    }
+    channels.push(ChannelDescriptor(prev.0, channel.1, prev.1, channel.3));
+    Ok(())
+}
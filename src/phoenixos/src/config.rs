@@ -1,4 +1,5 @@
 use std::fs;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
 use phoenix_api::engine::CustomSchedulingSpec;
@@ -43,6 +44,125 @@ pub struct ProfilingConfig {
     pub duration_ms: u64,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Serve the runtime and engine metrics in Prometheus text format on this address.
+    pub prometheus: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BalancerConfig {
+    /// Whether to migrate scheduling groups between compact runtimes according to their load.
+    #[serde(default)]
+    pub enable: bool,
+    /// How often to sample the load of the runtimes.
+    #[serde(default = "BalancerConfig::default_interval_ms")]
+    pub interval_ms: u64,
+    /// A runtime busy in more than this fraction of its iterations sheds a scheduling group.
+    #[serde(default = "BalancerConfig::default_high_watermark")]
+    pub high_watermark: f64,
+    /// Runtimes busy in less than this fraction of their iterations are packed together.
+    #[serde(default = "BalancerConfig::default_low_watermark")]
+    pub low_watermark: f64,
+}
+
+impl BalancerConfig {
+    fn default_interval_ms() -> u64 {
+        1000
+    }
+
+    fn default_high_watermark() -> f64 {
+        0.8
+    }
+
+    fn default_low_watermark() -> f64 {
+        0.2
+    }
+}
+
+impl Default for BalancerConfig {
+    fn default() -> Self {
+        BalancerConfig {
+            enable: false,
+            interval_ms: Self::default_interval_ms(),
+            high_watermark: Self::default_high_watermark(),
+            low_watermark: Self::default_low_watermark(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateConfig {
+    /// Where to save the control plane state, relative to `control.prefix`. The state is saved
+    /// at shutdown and restored at startup. Unset disables persisting the state.
+    pub path: Option<PathBuf>,
+    /// How often to also save the state while running. Zero only saves it at shutdown.
+    #[serde(default)]
+    pub interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpgradeConfig {
+    /// How long to watch the engines after a module upgrade before unloading the old version.
+    /// The upgrade is rolled back if any of them fails to restore or exits with an error
+    /// during this window. Zero disables the window.
+    #[serde(default)]
+    pub health_window_ms: u64,
+}
+
+/// Addons that are put in place when a matching client subscribes to a service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddonChain {
+    /// The service, e.g., Mrpc
+    pub service: String,
+    /// The (sender, receiver) engines of the tx channel the addons are inserted into.
+    /// On the rx direction, they are inserted into the channel from the receiver to the sender.
+    pub between: (String, String),
+    /// Which clients get the addons
+    #[serde(default)]
+    pub selector: ClientSelector,
+    /// Addons, in the order on the tx direction, from the sender to the receiver.
+    /// The order is reversed on the rx direction.
+    pub addons: Vec<ChainedAddon>,
+}
+
+/// Selects clients by their process. An unset field matches all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientSelector {
+    /// Name of the executable, as in /proc/<pid>/comm
+    pub app: Option<String>,
+    pub uid: Option<u32>,
+    /// The client's cgroup is this one or a descendant of it
+    pub cgroup: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainedAddon {
+    /// Engine type of the addon
+    pub engine: String,
+    /// Whether the addon is on the tx direction
+    #[serde(default = "ChainedAddon::default_tx")]
+    pub tx: bool,
+    /// Whether the addon is on the rx direction
+    #[serde(default)]
+    pub rx: bool,
+    pub config_path: Option<PathBuf>,
+    pub config_string: Option<String>,
+}
+
+impl ChainedAddon {
+    fn default_tx() -> bool {
+        true
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Group {
@@ -65,11 +185,21 @@ pub struct Config {
     pub control: Control,
     pub linker: LinkerConfig,
     #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub balancer: BalancerConfig,
+    #[serde(default)]
+    pub upgrade: UpgradeConfig,
+    #[serde(default)]
+    pub state: StateConfig,
+    #[serde(default)]
     pub modules: Vec<PluginDescriptor>,
     #[serde(default)]
     pub addons: Vec<PluginDescriptor>,
     #[serde(default)]
     pub scheduling: Vec<SchedulingPolicy>,
+    #[serde(default)]
+    pub chains: Vec<AddonChain>,
 }
 
 impl Config {
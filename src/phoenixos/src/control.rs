@@ -1,35 +1,45 @@
 //! A Control is the entry of control plane. It forwards commands from the external
 //! world to corresponding module.
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::io;
 use std::os::unix::net::{SocketAddr, UCred};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, bail};
+use futures::channel::oneshot;
 use ipc::control::ResponseKind;
-use ipc::control::{PluginType, Response};
+use ipc::control::{
+    AddonRequest, InsertionPoint, PluginStatus, UnloadRequest, UpgradeReport, UpgradeRequest,
+    UpgradeScope,
+};
+use ipc::control::{PluginDescriptor, PluginType, Response};
 use itertools::Itertools;
 use nix::unistd::Pid;
 
-use ipc::control::ServiceSubscriptionInfo;
+use ipc::control::{AddonInfo, EngineInfo, ServiceSubscriptionInfo};
 use ipc::unix::DomainSocket;
 use phoenix_api::engine::{SchedulingHint, SchedulingMode};
 
 use phoenix_common::engine::datapath::{ChannelDescriptor, DataPathNode};
 use phoenix_common::engine::EngineType;
-use phoenix_common::module::{NewEngineRequest, Service};
+use phoenix_common::module::{NewEngineRequest, PhoenixModule, Service};
 use phoenix_common::storage::{ResourceCollection, SharedStorage, PHOENIX_PREFIX_KEY};
 
+use crate::chain::ChainPlan;
 use crate::config::Config;
+use crate::metrics::{self, MetricsSink, PendingMetrics, PrometheusExporter};
 use crate::plugin::{Plugin, PluginName};
 use crate::plugin_mgr::PluginManager;
-use crate::runtime::graph::create_datapath_channels;
+use crate::runtime::graph::{create_datapath_channels, InsertionPoint as GraphInsertionPoint};
+use crate::runtime::lb::{EngineBalancer, LoadBalancer};
 use crate::runtime::manager::{EngineId, ServiceSubscription, SubscriptionId};
+use crate::runtime::upgrade::UpgradeOutcome;
 use crate::runtime::{EngineContainer, EngineUpgrader, RuntimeManager};
+use crate::state::{AddonAttachment, DaemonState, StateKeeper};
 use crate::{log, tracing};
 
 pub struct Control {
@@ -39,6 +49,13 @@ pub struct Control {
     upgrader: EngineUpgrader,
     scheduling_override: HashMap<String, SchedulingMode>,
     config: Config,
+    pending_responses: Vec<PendingResponse>,
+    prometheus: Option<PrometheusExporter>,
+    pending_metrics: Vec<PendingMetrics>,
+    balancer: Option<LoadBalancer>,
+    state: Option<StateKeeper>,
+    /// Addons to attach again to the subscriptions of reconnected clients
+    pending_attachments: VecDeque<(Pid, SubscriptionId, AddonAttachment)>,
 }
 
 impl Control {
@@ -84,7 +101,7 @@ impl Control {
         scheduling_hint: SchedulingHint,
         cred: &UCred,
         config_string: Option<String>,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<SubscriptionId> {
         let pid = Pid::from_raw(cred.pid.unwrap());
         if self.upgrader.is_upgrading(pid) {
             bail!("client {} still upgrading", pid);
@@ -99,17 +116,22 @@ impl Control {
             .service_registry
             .get(&service)
             .ok_or_else(|| anyhow!("service {:?} not found in the registry", service))?;
-        let tx_channels = service_registry.tx_channels.iter().copied();
-        let rx_channels = service_registry.rx_channels.iter().copied();
+        let mut tx_channels = service_registry.tx_channels.clone();
+        let mut rx_channels = service_registry.rx_channels.clone();
+        let chain = self.select_chain(&service, cred)?;
+        if let Some(chain) = chain.as_ref() {
+            chain.splice(&mut tx_channels, &mut rx_channels)?;
+        }
         let (mut nodes, graph) = create_datapath_channels(
             tx_channels,
             rx_channels,
             &service_registry.scheduling_groups,
         )?;
 
-        let subscription = ServiceSubscription {
+        let mut subscription = ServiceSubscription {
             service,
             addons: Vec::new(),
+            addon_configs: HashMap::new(),
             graph,
         };
 
@@ -182,6 +204,47 @@ impl Control {
             }
         }
 
+        // create the addons declared for the service, each in its own scheduling group
+        for (addon_engine_type, config) in chain.into_iter().flat_map(|c| c.addons) {
+            let plugin = self
+                .plugins
+                .engine_registry
+                .get(&addon_engine_type)
+                .unwrap();
+            let (addon_name, specified_mode) = match plugin.value() {
+                (PluginName::Addon(addon), mode) => (addon, *mode),
+                (PluginName::Module(_), _) => unreachable!(),
+            };
+
+            let mut addon = self.plugins.addons.get_mut(addon_name).unwrap();
+            if let Some(config) = config.as_ref() {
+                addon.update_config(config)?;
+            }
+            let node = nodes
+                .remove(&addon_engine_type)
+                .unwrap_or_else(DataPathNode::new);
+            let engine = addon.create_engine(addon_engine_type, pid, node)?;
+            tracing::info!(
+                "Created addon engine {:?} of service {:?} for client pid={:?}",
+                addon_engine_type,
+                service,
+                pid
+            );
+
+            let container = EngineContainer::new(engine, addon_engine_type, addon.version());
+            containers_to_submit.insert(
+                singleton_id,
+                vec![(container, specified_mode.unwrap_or(service_mode))],
+            );
+            singleton_id += 1;
+            subscription.addons.push(addon_engine_type);
+            if let Some(config) = config {
+                subscription
+                    .addon_configs
+                    .insert(addon_engine_type.0.to_string(), config);
+            }
+        }
+
         // finally, create service engine
         let service_engine_type = service_registry.engines.last().unwrap();
         let plugin = self
@@ -276,11 +339,33 @@ impl Control {
             self.runtime_manager
                 .submit_group(pid, sid, containers, mode, scheduling_hint);
         }
-        Ok(())
+        Ok(sid)
     }
 
     /// Create a `Control` instance.
-    pub fn new(runtime_manager: Arc<RuntimeManager>, config: Config) -> Self {
+    pub fn new(runtime_manager: Arc<RuntimeManager>, mut config: Config) -> Self {
+        // restore the plugins and the subscriptions saved before the last shutdown
+        let state_path = config
+            .state
+            .path
+            .as_ref()
+            .map(|path| config.control.prefix.join(path));
+        let mut restored = Vec::new();
+        if let Some(path) = &state_path {
+            match DaemonState::load(path) {
+                Ok(Some(state)) => {
+                    log::info!("Restoring state from {:?}", path);
+                    config.modules = state.modules;
+                    config.addons = state.addons;
+                    restored = state.subscriptions;
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("Failed to load state from {:?}: {:#}", path, e),
+            }
+        }
+        let state =
+            state_path.map(|path| StateKeeper::new(path, config.state.interval_ms, restored));
+
         let config_clone = config.clone();
 
         // Create phoenix working directory if not existing
@@ -318,9 +403,30 @@ impl Control {
                 .expect("failed to load preset addons");
         }
 
-        let upgrader = EngineUpgrader::new(Arc::clone(&runtime_manager), Arc::clone(&plugins));
+        let upgrader = EngineUpgrader::new(
+            Arc::clone(&runtime_manager),
+            Arc::clone(&plugins),
+            &config.upgrade,
+        );
         tracing::info!("Control plane initialized");
 
+        let prometheus = config.metrics.prometheus.and_then(|addr| {
+            PrometheusExporter::bind(addr)
+                .map_err(|e| {
+                    log::error!(
+                        "Cannot serve Prometheus metrics at {:?}, running without them: {}",
+                        addr,
+                        e
+                    )
+                })
+                .ok()
+        });
+
+        let balancer = config
+            .balancer
+            .enable
+            .then(|| LoadBalancer::new(Arc::clone(&runtime_manager), &config.balancer));
+
         let scheduling_override = config
             .scheduling
             .into_iter()
@@ -334,9 +440,23 @@ impl Control {
             upgrader,
             scheduling_override,
             config: config_clone,
+            pending_responses: Vec::new(),
+            prometheus,
+            pending_metrics: Vec::new(),
+            balancer,
+            state,
+            pending_attachments: VecDeque::new(),
         }
     }
 
+    /// Loads modules that are linked into the process rather than loaded from plugins.
+    pub fn load_static_modules(
+        &self,
+        modules: Vec<(String, Box<dyn PhoenixModule>)>,
+    ) -> anyhow::Result<()> {
+        self.plugins.load_static_modules(modules)
+    }
+
     pub fn mainloop(&mut self, exit_flag: &AtomicBool) -> anyhow::Result<()> {
         let mut buf = vec![0u8; 65536];
         while !exit_flag.load(Ordering::Relaxed) {
@@ -364,11 +484,86 @@ impl Control {
                     log::warn!("recv failed: {:?}", e)
                 }
             }
+            self.check_pending_responses();
+            if let Err(e) = self.check_metrics() {
+                log::warn!("Metrics: {}", e);
+            }
+            if let Some(plugins) = self.upgrader.check_health() {
+                self.forget_modules(&plugins);
+            }
+            if let Some(balancer) = self.balancer.as_mut() {
+                if let Err(e) = balancer.rebalance(&mut self.upgrader) {
+                    log::warn!("Balancer: {:#}", e);
+                }
+            }
+            self.restore_attachments();
+            if self.state.as_ref().map_or(false, |s| s.save_due()) {
+                self.save_state();
+            }
         }
         log::info!("exiting...");
+        self.save_state();
         Ok(())
     }
 
+    fn save_state(&mut self) {
+        if let Some(state) = self.state.as_mut() {
+            if let Err(e) = state.save(
+                &self.config.modules,
+                &self.config.addons,
+                &self.runtime_manager,
+            ) {
+                log::warn!("Failed to save state: {:#}", e);
+            }
+        }
+    }
+
+    /// Attach the addons that reconnected clients used to have, one at a time for each client.
+    fn restore_attachments(&mut self) {
+        while let Some((pid, ..)) = self.pending_attachments.front() {
+            if self.upgrader.is_upgrading(*pid) {
+                break;
+            }
+            let (pid, sid, attachment) = self.pending_attachments.pop_front().unwrap();
+            if !self
+                .runtime_manager
+                .service_subscriptions
+                .contains_key(&(pid, sid))
+            {
+                continue;
+            }
+            let mut request = attachment.request.clone();
+            request.pid = pid.as_raw();
+            request.sid = sid.0;
+            log::info!(
+                "Restoring addon {} for client pid={:?}, sid={:?}",
+                request.addon_engine,
+                pid,
+                sid
+            );
+            let report = new_report([request.addon_engine.clone()]);
+            let change = StateChange::AttachAddon(
+                pid,
+                sid,
+                AddonAttachment {
+                    mode: attachment.mode,
+                    request: request.clone(),
+                },
+            );
+            // nobody waits for the response, the upgrader logs the outcome
+            match self.attach_addon(attachment.mode, request) {
+                Ok(outcome) => self.pending_responses.push(PendingResponse {
+                    client_path: None,
+                    kind: ResponseKind::AttachAddon,
+                    report,
+                    outcomes: vec![outcome],
+                    state_change: Some(change),
+                }),
+                Err(e) => log::warn!("Failed to restore addon: {:#}", e),
+            }
+        }
+    }
+
     fn dispatch(
         &mut self,
         buf: &mut [u8],
@@ -397,7 +592,20 @@ impl Control {
                     .get(&service_name)
                     .copied()
                     .unwrap_or(desired_mode);
-                self.create_service(service, client_path, mode_override, hint, cred, config_str)?;
+                let sid = self.create_service(
+                    service,
+                    client_path,
+                    mode_override,
+                    hint,
+                    cred,
+                    config_str.clone(),
+                )?;
+                if let Some(state) = self.state.as_mut() {
+                    let pid = Pid::from_raw(cred.pid.unwrap());
+                    for attachment in state.add_subscription(pid, sid, &service_name, config_str) {
+                        self.pending_attachments.push_back((pid, sid, attachment));
+                    }
+                }
                 Ok(())
             }
             control::Request::EngineRequest(eid, request) => {
@@ -417,27 +625,15 @@ impl Control {
             }
             control::Request::Upgrade(mut request) => {
                 log::info!("Receive backend upgrade request: {:?}", request);
-                match request.ty {
-                    PluginType::Module => {
-                        let engines_to_upgrade =
-                            self.plugins.load_or_upgrade_modules(&request.plugins)?;
-                        self.upgrader.upgrade(
-                            engines_to_upgrade,
-                            request.flush,
-                            request.detach_subscription,
-                        )?;
-
-                        self.config.modules.append(&mut request.plugins);
-                    }
-                    PluginType::Addon => {
-                        for addon in &request.plugins {
-                            self.plugins.load_or_upgrade_addon(addon)?;
-                        }
-                    }
-                }
-                Ok(())
+                let result = self.upgrade(&mut request);
+                self.submit_pending_response(sender, ResponseKind::Upgrade, result, None)
+            }
+            control::Request::Unload(request) => {
+                log::info!("Receive unload request: {:?}", request);
+                let result = self.unload(&request);
+                self.submit_pending_response(sender, ResponseKind::Unload, result, None)
             }
-            control::Request::ListSubscription => {
+            control::Request::ListSubscription(filter) => {
                 let client_path = sender
                     .as_pathname()
                     .ok_or_else(|| anyhow!("peer is unnamed, something is wrong"))?;
@@ -447,7 +643,14 @@ impl Control {
                     let entry = engine_subscriptions
                         .entry((engine.pid, engine.sid))
                         .or_insert_with(Vec::new);
-                    entry.push((engine.key().0, engine.engine_type.0.to_string()));
+                    entry.push(EngineInfo {
+                        eid: engine.key().0,
+                        engine_type: engine.engine_type.0.to_string(),
+                        rid: engine.rid.0,
+                        gid: engine.gid.0,
+                        scheduling_mode: engine.scheduling_mode,
+                        version: self.engine_version(engine.engine_type),
+                    });
                 }
                 let mut subscriptions_info =
                     Vec::with_capacity(self.runtime_manager.service_subscriptions.len());
@@ -455,13 +658,20 @@ impl Control {
                     let pid = subscription.key().0.as_raw();
                     let sid = subscription.key().1 .0;
                     let service = subscription.0.service.0.to_string();
+                    if !filter.matches(pid, &service) {
+                        continue;
+                    }
                     let mut addons = Vec::with_capacity(subscription.0.addons.len());
                     for addon in subscription.0.addons.iter() {
-                        addons.push(addon.0.to_string());
+                        addons.push(AddonInfo {
+                            engine_type: addon.0.to_string(),
+                            config: subscription.0.addon_configs.get(addon.0).cloned(),
+                        });
                     }
-                    let engines = engine_subscriptions
+                    let mut engines = engine_subscriptions
                         .remove(&(subscription.key().0, subscription.key().1))
                         .unwrap_or_default();
+                    engines.sort_by_key(|e| e.eid);
 
                     let info = ServiceSubscriptionInfo {
                         pid,
@@ -472,96 +682,509 @@ impl Control {
                     };
                     subscriptions_info.push(info);
                 }
+                subscriptions_info.sort_by_key(|s| (s.pid, s.sid));
                 let response = Response(Ok(ResponseKind::ListSubscription(subscriptions_info)));
-                let mut buf = bincode::serialize(&response)?;
-                let nbytes = self.sock.send_to(buf.as_mut_slice(), client_path)?;
-                assert_eq!(
-                    nbytes,
-                    buf.len(),
-                    "expect to send {} bytes, but only {} was sent",
-                    buf.len(),
-                    nbytes
-                );
+                self.send_response(client_path, &response)?;
                 tracing::info!("List subscription request completed");
                 Ok(())
             }
+            control::Request::Metrics => {
+                let client_path = sender
+                    .as_pathname()
+                    .ok_or_else(|| anyhow!("peer is unnamed, something is wrong"))?;
+                let receivers = self.runtime_manager.collect_metrics();
+                let sink = MetricsSink::Client(client_path.to_path_buf());
+                self.pending_metrics
+                    .push(PendingMetrics::new(sink, receivers));
+                Ok(())
+            }
             control::Request::AttachAddon(mode, request) => {
                 log::info!("Receive attach addon request from phoenixctl");
-                let addon_engine =
-                    unsafe { transmute_engine_type_from_str(request.addon_engine.as_str()) };
-                let addon_engine = *self
-                    .plugins
-                    .engine_registry
-                    .get(&addon_engine)
-                    .ok_or_else(|| {
-                        anyhow!("Addon engine type {:?} not found", request.addon_engine)
-                    })?
-                    .key();
+                let report = new_report([request.addon_engine.clone()]);
+                let change = StateChange::AttachAddon(
+                    Pid::from_raw(request.pid),
+                    SubscriptionId(request.sid),
+                    AddonAttachment {
+                        mode,
+                        request: request.clone(),
+                    },
+                );
+                let result = self
+                    .attach_addon(mode, request)
+                    .map(|outcome| (report, vec![outcome]));
+                self.submit_pending_response(
+                    sender,
+                    ResponseKind::AttachAddon,
+                    result,
+                    Some(change),
+                )
+            }
+            control::Request::DetachAddon(request) => {
+                log::info!("Receive detach addon request from phoenixctl");
+                let report = new_report([request.addon_engine.clone()]);
+                let change = StateChange::DetachAddon(
+                    Pid::from_raw(request.pid),
+                    SubscriptionId(request.sid),
+                    request.addon_engine.clone(),
+                );
+                let result = self
+                    .detach_addon(request)
+                    .map(|outcome| (report, vec![outcome]));
+                self.submit_pending_response(
+                    sender,
+                    ResponseKind::DetachAddon,
+                    result,
+                    Some(change),
+                )
+            }
+        }
+    }
 
-                let tx_edges_replacement =
-                    self.refactor_channel_descriptors(request.tx_channels_replacements)?;
-                let rx_edges_replacement =
-                    self.refactor_channel_descriptors(request.rx_channels_replacements)?;
-                let mut group = HashSet::with_capacity(request.group.len());
-                for engine in request.group {
-                    let engine_ty = unsafe { transmute_engine_type_from_str(engine.as_str()) };
-                    let engine_ty = *self
-                        .plugins
-                        .engine_registry
-                        .get(&engine_ty)
-                        .ok_or_else(|| anyhow!("Engine type {:?} not found", engine))?
-                        .key();
-                    group.insert(engine_ty);
+    fn upgrade(&mut self, request: &mut UpgradeRequest) -> anyhow::Result<PendingUpgrade> {
+        match request.ty {
+            PluginType::Module => {
+                if request.rollback {
+                    let staged = self.upgrader.staged_plugins().unwrap_or_default().to_vec();
+                    let report = new_report(staged.iter().map(|p| p.name.clone()));
+                    let outcomes = self.upgrader.rollback(
+                        &request.plugins,
+                        request.flush,
+                        request.detach_subscription,
+                    )?;
+                    self.forget_modules(&staged);
+                    return Ok((report, outcomes));
                 }
 
-                let pid = Pid::from_raw(request.pid);
-                let gid = SubscriptionId(request.sid);
-                let config_string =
-                    Plugin::load_config(request.config_path, request.config_string)?;
-                self.upgrader.attach_addon(
-                    pid,
-                    gid,
-                    addon_engine,
-                    mode,
-                    tx_edges_replacement,
-                    rx_edges_replacement,
-                    group,
-                    config_string,
+                let report = new_report(request.plugins.iter().map(|p| p.name.clone()));
+                let rolling_forward = self.upgrader.staged_plugins().is_some();
+                let outcomes = self.upgrader.upgrade(
+                    &request.plugins,
+                    &request.scope,
+                    request.flush,
+                    request.detach_subscription,
                 )?;
-                Ok(())
+
+                if !rolling_forward {
+                    self.config.modules.append(&mut request.plugins);
+                }
+                Ok((report, outcomes))
             }
-            control::Request::DetachAddon(request) => {
-                log::info!("Receive detach addon request from phoenixctl");
-                let addon_engine =
-                    unsafe { transmute_engine_type_from_str(request.addon_engine.as_str()) };
-                let addon_engine = *self
-                    .plugins
-                    .engine_registry
-                    .get(&addon_engine)
-                    .ok_or_else(|| {
-                        anyhow!("Addon engine type {:?} not found", request.addon_engine)
-                    })?
-                    .key();
+            PluginType::Addon => {
+                if request.rollback || request.scope != UpgradeScope::All {
+                    bail!("staged upgrades and rollbacks only apply to modules");
+                }
+                let mut report = new_report([]);
+                for addon in &request.plugins {
+                    let error = self
+                        .plugins
+                        .load_or_upgrade_addon(addon)
+                        .err()
+                        .map(|e| format!("{:#}", e));
+                    match &error {
+                        Some(error) => {
+                            log::error!("Failed to upgrade addon {}: {}", addon.name, error)
+                        }
+                        None => self.config.addons.push(addon.clone()),
+                    }
+                    report.plugins.push(PluginStatus {
+                        name: addon.name.clone(),
+                        error,
+                    });
+                }
+                Ok((report, Vec::new()))
+            }
+        }
+    }
 
-                let tx_edges_replacement =
-                    self.refactor_channel_descriptors(request.tx_channels_replacements)?;
-                let rx_edges_replacement =
-                    self.refactor_channel_descriptors(request.rx_channels_replacements)?;
-
-                let pid = Pid::from_raw(request.pid);
-                let gid = SubscriptionId(request.sid);
-                self.upgrader.detach_addon(
-                    pid,
-                    gid,
-                    addon_engine,
-                    tx_edges_replacement,
-                    rx_edges_replacement,
-                )?;
+    fn unload(&mut self, request: &UnloadRequest) -> anyhow::Result<PendingUpgrade> {
+        if !self.upgrader.is_idle() {
+            bail!("there is an ongoing upgrade or addon operation");
+        }
+        let mut report = new_report([]);
+        for name in &request.plugins {
+            let error = self
+                .unload_plugin(name, &request.ty)
+                .err()
+                .map(|e| format!("{:#}", e));
+            if let Some(error) = &error {
+                log::error!("Failed to unload {}: {}", name, error);
+            }
+            report.plugins.push(PluginStatus {
+                name: name.clone(),
+                error,
+            });
+        }
+        Ok((report, Vec::new()))
+    }
+
+    fn unload_plugin(&mut self, name: &str, ty: &PluginType) -> anyhow::Result<()> {
+        if let Some(staged) = self.upgrader.staged_plugins() {
+            if staged.iter().any(|p| p.name == name) {
+                bail!(
+                    "{} has a staged upgrade, roll it forward or back first",
+                    name
+                );
+            }
+        }
+
+        let plugin_name = match ty {
+            PluginType::Module => PluginName::Module(name.to_string()),
+            PluginType::Addon => PluginName::Addon(name.to_string()),
+        };
+        let engine_types = self
+            .plugins
+            .engine_registry
+            .iter()
+            .filter(|e| e.value().0 == plugin_name)
+            .map(|e| *e.key())
+            .collect::<HashSet<_>>();
+        let mut running = self
+            .runtime_manager
+            .engine_subscriptions
+            .iter()
+            .filter(|e| engine_types.contains(&e.engine_type))
+            .map(|e| (e.pid.as_raw(), e.sid.0))
+            .collect::<Vec<_>>();
+        if !running.is_empty() {
+            running.sort_unstable();
+            running.dedup();
+            bail!(
+                "engines of {} are running in service subscriptions (pid, sid) {:?}, detach or shut them down first",
+                name,
+                running
+            );
+        }
+
+        match ty {
+            PluginType::Module => {
+                self.plugins.unload_module(name)?;
+                self.config.modules.retain(|p| p.name != name);
+            }
+            PluginType::Addon => {
+                self.plugins.unload_addon(name)?;
+                self.config.addons.retain(|p| p.name != name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget the descriptors appended by an upgrade that has been rolled back.
+    fn forget_modules(&mut self, plugins: &[PluginDescriptor]) {
+        for plugin in plugins {
+            if let Some(i) = self.config.modules.iter().rposition(|m| m == plugin) {
+                self.config.modules.remove(i);
+            }
+        }
+    }
+
+    fn attach_addon(
+        &mut self,
+        mode: SchedulingMode,
+        request: AddonRequest,
+    ) -> anyhow::Result<oneshot::Receiver<UpgradeOutcome>> {
+        let addon_engine = unsafe { transmute_engine_type_from_str(request.addon_engine.as_str()) };
+        let addon_engine = *self
+            .plugins
+            .engine_registry
+            .get(&addon_engine)
+            .ok_or_else(|| anyhow!("Addon engine type {:?} not found", request.addon_engine))?
+            .key();
+
+        let pid = Pid::from_raw(request.pid);
+        let gid = SubscriptionId(request.sid);
+        let (tx_edges_replacement, rx_edges_replacement) = {
+            let subscription = self
+                .runtime_manager
+                .service_subscriptions
+                .get(&(pid, gid))
+                .ok_or_else(|| anyhow!("service subscription ({}, {}) not found", pid, gid.0))?;
+            let graph = &subscription.0.graph;
+            let (tx_edges, rx_edges) = match request.insertion {
+                Some(insertion)
+                    if request.tx_channels_replacements.is_empty()
+                        && request.rx_channels_replacements.is_empty() =>
+                {
+                    let point = match insertion.at {
+                        InsertionPoint::Between(sender, receiver) => GraphInsertionPoint::Between(
+                            self.resolve_engine_type(&sender)?,
+                            self.resolve_engine_type(&receiver)?,
+                        ),
+                        InsertionPoint::Before(engine) => {
+                            GraphInsertionPoint::Before(self.resolve_engine_type(&engine)?)
+                        }
+                        InsertionPoint::After(engine) => {
+                            GraphInsertionPoint::After(self.resolve_engine_type(&engine)?)
+                        }
+                    };
+                    graph.insertion_replacements(addon_engine, point, insertion.tx, insertion.rx)?
+                }
+                _ => (
+                    self.refactor_channel_descriptors(request.tx_channels_replacements)?,
+                    self.refactor_channel_descriptors(request.rx_channels_replacements)?,
+                ),
+            };
+            // reject the replacements before the engines are detached for them
+            graph.check_attach(addon_engine, &tx_edges, &rx_edges)?;
+            (tx_edges, rx_edges)
+        };
+        let mut group = HashSet::with_capacity(request.group.len());
+        for engine in request.group {
+            let engine_ty = unsafe { transmute_engine_type_from_str(engine.as_str()) };
+            let engine_ty = *self
+                .plugins
+                .engine_registry
+                .get(&engine_ty)
+                .ok_or_else(|| anyhow!("Engine type {:?} not found", engine))?
+                .key();
+            group.insert(engine_ty);
+        }
+
+        let config_string = Plugin::load_config(request.config_path, request.config_string)?;
+        self.upgrader.attach_addon(
+            pid,
+            gid,
+            addon_engine,
+            mode,
+            tx_edges_replacement,
+            rx_edges_replacement,
+            group,
+            config_string,
+        )
+    }
+
+    fn detach_addon(
+        &mut self,
+        request: AddonRequest,
+    ) -> anyhow::Result<oneshot::Receiver<UpgradeOutcome>> {
+        let addon_engine = unsafe { transmute_engine_type_from_str(request.addon_engine.as_str()) };
+        let addon_engine = *self
+            .plugins
+            .engine_registry
+            .get(&addon_engine)
+            .ok_or_else(|| anyhow!("Addon engine type {:?} not found", request.addon_engine))?
+            .key();
+
+        let pid = Pid::from_raw(request.pid);
+        let gid = SubscriptionId(request.sid);
+        let (tx_edges_replacement, rx_edges_replacement) = if request
+            .tx_channels_replacements
+            .is_empty()
+            && request.rx_channels_replacements.is_empty()
+        {
+            let subscription = self
+                .runtime_manager
+                .service_subscriptions
+                .get(&(pid, gid))
+                .ok_or_else(|| anyhow!("service subscription ({}, {}) not found", pid, gid.0))?;
+            subscription.0.graph.removal_replacements(addon_engine)?
+        } else {
+            (
+                self.refactor_channel_descriptors(request.tx_channels_replacements)?,
+                self.refactor_channel_descriptors(request.rx_channels_replacements)?,
+            )
+        };
+
+        self.upgrader.detach_addon(
+            pid,
+            gid,
+            addon_engine,
+            tx_edges_replacement,
+            rx_edges_replacement,
+        )
+    }
+
+    /// Returns the version of the module or addon that currently provides `engine_type`.
+    fn engine_version(&self, engine_type: EngineType) -> Option<String> {
+        let entry = self.plugins.engine_registry.get(&engine_type)?;
+        let version = match &entry.value().0 {
+            PluginName::Module(name) => self.plugins.modules.get(name)?.version(),
+            PluginName::Addon(name) => self.plugins.addons.get(name)?.version(),
+        };
+        Some(version.to_string())
+    }
+
+    fn send_response(&self, client_path: &Path, response: &Response) -> anyhow::Result<()> {
+        let mut buf = bincode::serialize(response)?;
+        let nbytes = self.sock.send_to(buf.as_mut_slice(), client_path)?;
+        assert_eq!(
+            nbytes,
+            buf.len(),
+            "expect to send {} bytes, but only {} was sent",
+            buf.len(),
+            nbytes
+        );
+        Ok(())
+    }
+
+    /// Replies to the sender once the operation finishes, and then applies `state_change` if the
+    /// operation succeeded. The reply is sent immediately if the operation failed to start.
+    fn submit_pending_response(
+        &mut self,
+        sender: &SocketAddr,
+        kind: fn(UpgradeReport) -> ResponseKind,
+        result: anyhow::Result<PendingUpgrade>,
+        state_change: Option<StateChange>,
+    ) -> anyhow::Result<()> {
+        match result {
+            Ok((report, outcomes)) => {
+                self.pending_responses.push(PendingResponse {
+                    client_path: sender.as_pathname().map(Path::to_path_buf),
+                    kind,
+                    report,
+                    outcomes,
+                    state_change,
+                });
                 Ok(())
             }
+            Err(e) => {
+                if let Some(client_path) = sender.as_pathname() {
+                    let response = Response(Err(phoenix_api::Error::Generic(format!("{:#}", e))));
+                    self.send_response(client_path, &response)?;
+                }
+                Err(e)
+            }
         }
     }
 
+    /// Sends the responses whose operations have finished on all the affected clients, and
+    /// records the changes of the operations that succeeded in the daemon state.
+    fn check_pending_responses(&mut self) {
+        let mut finished = Vec::new();
+        for (i, pending) in self.pending_responses.iter_mut().enumerate() {
+            let report = &mut pending.report;
+            pending.outcomes.retain_mut(|rx| match rx.try_recv() {
+                Ok(Some(outcome)) => {
+                    merge_outcome(report, outcome);
+                    false
+                }
+                Ok(None) => true,
+                Err(oneshot::Canceled) => {
+                    for plugin in report.plugins.iter_mut() {
+                        plugin
+                            .error
+                            .get_or_insert_with(|| "upgrade task aborted".to_string());
+                    }
+                    false
+                }
+            });
+            if pending.outcomes.is_empty() {
+                finished.push(i);
+            }
+        }
+
+        // a failed send must not lose the other finished responses
+        for i in finished.into_iter().rev() {
+            let pending = self.pending_responses.swap_remove(i);
+            let succeeded = pending.report.plugins.iter().all(|p| p.error.is_none());
+            if let (true, Some(change), Some(state)) =
+                (succeeded, pending.state_change, self.state.as_mut())
+            {
+                match change {
+                    StateChange::AttachAddon(pid, sid, attachment) => {
+                        state.add_addon(pid, sid, attachment)
+                    }
+                    StateChange::DetachAddon(pid, sid, addon) => {
+                        state.remove_addon(pid, sid, &addon)
+                    }
+                }
+            }
+            if let Some(client_path) = pending.client_path {
+                let response = Response(Ok((pending.kind)(pending.report)));
+                if let Err(e) = self.send_response(&client_path, &response) {
+                    log::warn!("Failed to send response to {:?}: {}", client_path, e);
+                }
+            }
+        }
+    }
+
+    /// Starts collecting metrics for new Prometheus scrapes, and delivers the metrics that have
+    /// been collected from all runtimes.
+    fn check_metrics(&mut self) -> anyhow::Result<()> {
+        if let Some(prometheus) = self.prometheus.as_ref() {
+            if let Some(stream) = prometheus.accept()? {
+                let receivers = self.runtime_manager.collect_metrics();
+                self.pending_metrics
+                    .push(PendingMetrics::new(MetricsSink::Scrape(stream), receivers));
+            }
+        }
+
+        let mut finished = Vec::new();
+        for (i, pending) in self.pending_metrics.iter_mut().enumerate() {
+            if pending.poll() {
+                finished.push(i);
+            }
+        }
+        // a failed delivery must not lose the other finished metrics
+        for i in finished.into_iter().rev() {
+            let pending = self.pending_metrics.swap_remove(i);
+            match pending.sink {
+                MetricsSink::Client(client_path) => {
+                    let response = Response(Ok(ResponseKind::Metrics(pending.metrics)));
+                    if let Err(e) = self.send_response(&client_path, &response) {
+                        log::warn!("Failed to send metrics to {:?}: {}", client_path, e);
+                    }
+                }
+                MetricsSink::Scrape(stream) => {
+                    if let Err(e) = metrics::respond_scrape(stream, &pending.metrics) {
+                        log::warn!("Failed to respond to a Prometheus scrape: {}", e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the first addon chain in the config for `service` that selects the client.
+    fn select_chain(&self, service: &Service, cred: &UCred) -> anyhow::Result<Option<ChainPlan>> {
+        let chain = match self
+            .config
+            .chains
+            .iter()
+            .find(|c| c.service == service.0 && c.selector.matches(cred))
+        {
+            Some(chain) => chain,
+            None => return Ok(None),
+        };
+
+        let mut plan = ChainPlan {
+            sender: self.resolve_engine_type(&chain.between.0)?,
+            receiver: self.resolve_engine_type(&chain.between.1)?,
+            tx: Vec::new(),
+            rx: Vec::new(),
+            addons: Vec::with_capacity(chain.addons.len()),
+        };
+        for addon in chain.addons.iter() {
+            let engine_ty = self.resolve_engine_type(&addon.engine)?;
+            if !matches!(
+                self.plugins.engine_registry.get(&engine_ty).unwrap().0,
+                PluginName::Addon(_)
+            ) {
+                bail!("Engine type {:?} is not an addon", addon.engine);
+            }
+            if addon.tx {
+                plan.tx.push(engine_ty);
+            }
+            if addon.rx {
+                plan.rx.push(engine_ty);
+            }
+            let config_string =
+                Plugin::load_config(addon.config_path.as_ref(), addon.config_string.as_ref())?;
+            plan.addons.push((engine_ty, config_string));
+        }
+        plan.rx.reverse();
+        Ok(Some(plan))
+    }
+
+    fn resolve_engine_type(&self, engine: &str) -> anyhow::Result<EngineType> {
+        let engine_ty = unsafe { transmute_engine_type_from_str(engine) };
+        Ok(*self
+            .plugins
+            .engine_registry
+            .get(&engine_ty)
+            .ok_or_else(|| anyhow!("Engine type {:?} not found", engine))?
+            .key())
+    }
+
     fn refactor_channel_descriptors(
         &self,
         channels: Vec<(String, String, usize, usize)>,
@@ -593,6 +1216,59 @@ impl Control {
     }
 }
 
+/// The report to fill in, and the outcomes of the operation to wait for.
+type PendingUpgrade = (UpgradeReport, Vec<oneshot::Receiver<UpgradeOutcome>>);
+
+/// A response to an upgrade or addon request, waiting for the operation to finish.
+struct PendingResponse {
+    /// The client to reply to, `None` if nobody waits for the response.
+    client_path: Option<PathBuf>,
+    kind: fn(UpgradeReport) -> ResponseKind,
+    report: UpgradeReport,
+    outcomes: Vec<oneshot::Receiver<UpgradeOutcome>>,
+    state_change: Option<StateChange>,
+}
+
+/// A change of the daemon state, made once the operation that causes it succeeds.
+enum StateChange {
+    AttachAddon(Pid, SubscriptionId, AddonAttachment),
+    DetachAddon(Pid, SubscriptionId, String),
+}
+
+fn new_report<I: IntoIterator<Item = String>>(plugins: I) -> UpgradeReport {
+    UpgradeReport {
+        plugins: plugins
+            .into_iter()
+            .map(|name| PluginStatus { name, error: None })
+            .collect(),
+        engines: Vec::new(),
+    }
+}
+
+fn merge_outcome(report: &mut UpgradeReport, outcome: UpgradeOutcome) {
+    for (name, reason) in outcome.failures {
+        match report.plugins.iter_mut().find(|p| p.name == name) {
+            Some(PluginStatus {
+                error: Some(error), ..
+            }) => {
+                error.push_str("; ");
+                error.push_str(&reason);
+            }
+            Some(plugin) => plugin.error = Some(reason),
+            None => report.plugins.push(PluginStatus {
+                name,
+                error: Some(reason),
+            }),
+        }
+    }
+    report.engines.extend(
+        outcome
+            .engines
+            .into_iter()
+            .map(|(pid, sid, eid, ty)| (pid.as_raw(), sid.0, eid.0, ty)),
+    );
+}
+
 unsafe fn transmute_engine_type_from_str(engine: &str) -> EngineType {
     let bytes = engine.as_bytes();
     let (ptr, len) = (bytes.as_ptr(), bytes.len());
@@ -3,7 +3,7 @@ use std::iter::Iterator;
 
 use petgraph::graph::NodeIndex;
 use petgraph::visit::{DfsPostOrder, Walker};
-use petgraph::Graph;
+use petgraph::{Direction, Graph};
 use thiserror::Error;
 
 use phoenix_common::engine::{EnginePair, EngineType};
@@ -84,6 +84,39 @@ impl EngineGraph {
         Ok(())
     }
 
+    /// Remove the engines and all their dependencies from the graph.
+    pub(crate) fn remove_engines<I>(&mut self, engines: I)
+    where
+        I: IntoIterator<Item = EngineType>,
+    {
+        for engine in engines.into_iter() {
+            if let Some(index) = self.index.remove(&engine) {
+                self.graph.remove_node(index);
+                // the last node is moved to the index of the removed node
+                if let Some(moved) = self.graph.node_weight(index) {
+                    self.index.insert(*moved, index);
+                }
+            }
+        }
+    }
+
+    /// Returns the engines that depend on `engine`.
+    pub(crate) fn get_engine_dependents(
+        &self,
+        engine: &EngineType,
+    ) -> Result<Vec<EngineType>, Error> {
+        let index = *self
+            .index
+            .get(engine)
+            .ok_or(Error::EngineNotFound(*engine))?;
+        let dependents = self
+            .graph
+            .neighbors_directed(index, Direction::Incoming)
+            .map(|node| self.graph[node])
+            .collect();
+        Ok(dependents)
+    }
+
     pub(crate) fn get_engine_dependencies(
         &self,
         service: &EngineType,
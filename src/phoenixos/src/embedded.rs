@@ -0,0 +1,120 @@
+//! Runs phoenix on a thread inside another process, e.g., to test applications without a
+//! phoenix daemon. The modules are linked into the process rather than loaded from plugins.
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Context};
+
+use phoenix_common::InitModuleFn;
+
+use crate::config::{self, Config, LinkerConfig, ProfilingConfig, TracingConfig};
+use crate::control::Control;
+use crate::runtime::manager::RuntimeManager;
+
+/// A module linked into the process.
+#[derive(Debug, Clone)]
+pub struct StaticModule {
+    /// Name of the module, e.g., Mrpc
+    pub name: String,
+    /// The `init_module` of the plugin
+    pub init: InitModuleFn,
+    /// The config of the module, as the `config_string` of a plugin
+    pub config_string: Option<String>,
+}
+
+/// Returns the config of an embedded phoenix that keeps its sockets and files in `prefix`.
+pub fn config<P: Into<PathBuf>>(prefix: P) -> Config {
+    let prefix = prefix.into();
+    Config {
+        log_level: "info".to_owned(),
+        log_file: None,
+        tracing: TracingConfig {
+            enable: false,
+            min_event_level: "trace".to_owned(),
+            max_event_level: "trace".to_owned(),
+            span_level: "info".to_owned(),
+            output_dir: prefix.join("trace").to_string_lossy().into_owned(),
+        },
+        profiling: ProfilingConfig {
+            enable_on_new_client: false,
+            duration_ms: 0,
+        },
+        control: config::Control {
+            prefix,
+            path: "control.sock".into(),
+        },
+        linker: LinkerConfig {
+            workdir: "linker".into(),
+        },
+        metrics: Default::default(),
+        balancer: Default::default(),
+        upgrade: Default::default(),
+        state: Default::default(),
+        modules: Vec::new(),
+        addons: Vec::new(),
+        scheduling: Vec::new(),
+        chains: Vec::new(),
+    }
+}
+
+/// A phoenix running on a thread of the process.
+#[derive(Debug)]
+pub struct EmbeddedPhoenix {
+    exit_flag: Arc<AtomicBool>,
+    thread: JoinHandle<anyhow::Result<()>>,
+}
+
+impl EmbeddedPhoenix {
+    /// Starts phoenix with the `modules`. Clients can register at the control socket of `config`
+    /// once this returns.
+    pub fn start(config: Config, modules: Vec<StaticModule>) -> anyhow::Result<Self> {
+        let exit_flag = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let flag = Arc::clone(&exit_flag);
+        let thread = thread::Builder::new()
+            .name("phoenix-control".to_owned())
+            .spawn(move || match launch(config, modules) {
+                Ok(mut control) => {
+                    let _ = ready_tx.send(Ok(()));
+                    control.mainloop(&flag)
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    Ok(())
+                }
+            })?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(EmbeddedPhoenix { exit_flag, thread }),
+            Ok(Err(e)) => Err(e),
+            // the control plane panicked
+            Err(_) => Err(anyhow!("phoenix exited during startup")),
+        }
+    }
+
+    /// Stops the control plane and waits for its thread to exit.
+    pub fn shutdown(self) -> anyhow::Result<()> {
+        self.exit_flag.store(true, Ordering::Relaxed);
+        self.thread
+            .join()
+            .map_err(|_| anyhow!("phoenix control plane panicked"))?
+    }
+}
+
+fn launch(config: Config, modules: Vec<StaticModule>) -> anyhow::Result<Control> {
+    let runtime_manager = Arc::new(RuntimeManager::new(&config));
+    let control = Control::new(runtime_manager, config);
+    let modules = modules
+        .into_iter()
+        .map(|module| {
+            let init = module.init;
+            init(module.config_string.as_deref())
+                .with_context(|| format!("Fail to init_module for {}", module.name))
+                .map(|loaded| (module.name, loaded))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    control.load_static_modules(modules)?;
+    Ok(control)
+}
@@ -0,0 +1,30 @@
+//! The phoenix daemon, also usable as a library to run phoenix inside another process (see
+//! [`embedded`]).
+#![feature(peer_credentials_unix_socket)]
+#![feature(drain_filter)]
+#![feature(strict_provenance)]
+#![feature(int_roundings)]
+#![feature(local_key_cell_methods)]
+
+pub use phoenix_common::tracing;
+pub use phoenix_common::tracing as log;
+
+pub(crate) mod chain;
+pub mod config;
+pub(crate) mod control;
+pub(crate) mod linker;
+pub(crate) mod logging;
+pub(crate) mod metrics;
+pub(crate) mod plugin;
+pub(crate) mod plugin_mgr;
+pub(crate) mod runtime;
+pub(crate) mod state;
+
+pub(crate) mod dependency;
+
+pub mod embedded;
+
+pub use config::Config;
+pub use control::Control;
+pub use logging::init_log;
+pub use runtime::manager::RuntimeManager;
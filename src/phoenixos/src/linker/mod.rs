@@ -98,6 +98,11 @@ impl LoadedModules {
         inner.push(linked);
     }
 
+    fn remove(&self, mod_id: usize) {
+        let mut inner = self.0.lock().unwrap();
+        inner.retain(|linked| linked.mod_id() != mod_id);
+    }
+
     pub(crate) fn find_module_by_name<P: AsRef<Path>>(&self, name: P) -> Option<LinkedModule> {
         let inner = self.0.lock().unwrap();
         let canonicalized_path = name
@@ -298,6 +303,14 @@ impl Linker {
         self.load_archive_inner(archive_path.as_ref(), dep_path.as_ref())
     }
 
+    /// Unloads a module loaded by `load_archive`. Its memory is freed once all references
+    /// to the module are dropped. The dependencies loaded along with it are kept, since other
+    /// modules may have been linked against them.
+    pub(crate) fn unload_archive(&mut self, linked: &LinkedModule) {
+        linked.remove_from_global_symbol_table(&mut self.global_sym_table);
+        LOADED_MODULES.remove(linked.mod_id());
+    }
+
     /// Loads a single `rlib`.
     ///
     /// # Safety
@@ -260,6 +260,24 @@ impl LinkedModuleInner {
         eprintln!("TODO: Run finitializers");
     }
 
+    /// Remove the symbols defined by this module from the global symbol table.
+    pub(crate) fn remove_from_global_symbol_table(&self, sym_lookup_table: &mut SymbolLookupTable) {
+        for (_, sym) in self.symtab.iter() {
+            if sym.is_global
+                && (sym.is_definition
+                    || sym.is_common
+                    || (sym.kind == SymbolKind::Tls && !sym.is_undefined))
+            {
+                // the symbol may be defined by another module, which is loaded first
+                if let Some(global) = sym_lookup_table.table.get(&sym.name) {
+                    if global.address == sym.address && global.mod_id == sym.mod_id {
+                        sym_lookup_table.table.remove(&sym.name);
+                    }
+                }
+            }
+        }
+    }
+
     pub(crate) fn lookup_symbol_addr(&self, name: &str) -> Option<usize> {
         self.symtab.symbol_by_name(name).map(|s| s.address as _)
     }
@@ -1,9 +1,3 @@
-#![feature(peer_credentials_unix_socket)]
-#![feature(drain_filter)]
-#![feature(strict_provenance)]
-#![feature(int_roundings)]
-#![feature(local_key_cell_methods)]
-
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -13,22 +7,7 @@ use nix::sys::signal;
 use anyhow::Result;
 use clap::Parser;
 
-pub use phoenix_common::tracing;
-pub use phoenix_common::tracing as log;
-
-pub(crate) mod config;
-pub(crate) mod control;
-pub(crate) mod linker;
-pub(crate) mod logging;
-pub(crate) mod plugin;
-pub(crate) mod plugin_mgr;
-pub(crate) mod runtime;
-
-pub(crate) mod dependency;
-
-use config::Config;
-use control::Control;
-use runtime::manager::RuntimeManager;
+use phoenixos::{init_log, Config, Control, RuntimeManager};
 
 #[derive(Debug, Clone, Parser)]
 #[command(name = "Phoenix Service")]
@@ -55,7 +34,7 @@ fn main() -> Result<()> {
     // init log setting from "PHOENIX_LOG", print messages with level lower than specified to stdout
     // print messages with level higher than PHOENIX_TRACING_EVENT to file
     // collect traces to tracing.json and save to output_dir.
-    let _guards = logging::init_log(&config, !opts.no_ansi);
+    let _guards = init_log(&config, !opts.no_ansi);
 
     // create runtime manager
     let runtime_manager = Arc::new(RuntimeManager::new(&config));
@@ -0,0 +1,223 @@
+//! Collecting and exporting the metrics of runtimes and engines.
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+
+use ipc::control::RuntimeMetrics;
+
+/// Where the collected metrics go.
+pub(crate) enum MetricsSink {
+    /// A control plane client, which gets a `ResponseKind::Metrics`.
+    Client(PathBuf),
+    /// A Prometheus scrape, which gets the metrics in text format.
+    Scrape(TcpStream),
+}
+
+/// Metrics being collected from the runtimes.
+pub(crate) struct PendingMetrics {
+    pub(crate) sink: MetricsSink,
+    pub(crate) metrics: Vec<RuntimeMetrics>,
+    receivers: Vec<oneshot::Receiver<RuntimeMetrics>>,
+}
+
+impl PendingMetrics {
+    pub(crate) fn new(
+        sink: MetricsSink,
+        receivers: Vec<oneshot::Receiver<RuntimeMetrics>>,
+    ) -> Self {
+        PendingMetrics {
+            sink,
+            metrics: Vec::with_capacity(receivers.len()),
+            receivers,
+        }
+    }
+
+    /// Returns true if every runtime has replied. Runtimes that are gone are skipped.
+    pub(crate) fn poll(&mut self) -> bool {
+        let metrics = &mut self.metrics;
+        self.receivers.retain_mut(|rx| match rx.try_recv() {
+            Ok(Some(m)) => {
+                metrics.push(m);
+                false
+            }
+            Ok(None) => true,
+            Err(oneshot::Canceled) => false,
+        });
+        if self.receivers.is_empty() {
+            self.metrics.sort_by_key(|m| m.rid);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Serves Prometheus scrapes on a local TCP address.
+pub(crate) struct PrometheusExporter {
+    listener: TcpListener,
+}
+
+impl PrometheusExporter {
+    pub(crate) fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(PrometheusExporter { listener })
+    }
+
+    /// Accepts a pending scrape, if any.
+    pub(crate) fn accept(&self) -> io::Result<Option<TcpStream>> {
+        match self.listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+                stream.set_write_timeout(Some(Duration::from_millis(100)))?;
+                // The content of the HTTP request does not matter, every path returns the
+                // metrics. Consume it so that closing the stream does not reset the connection.
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf);
+                Ok(Some(stream))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Replies to a scrape with the metrics.
+pub(crate) fn respond_scrape(mut stream: TcpStream, metrics: &[RuntimeMetrics]) -> io::Result<()> {
+    let body = encode_prometheus(metrics);
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body.as_bytes())
+}
+
+/// Writes the header of a metric family.
+fn family(out: &mut String, name: &str, ty: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, ty);
+}
+
+/// Encodes the metrics in the Prometheus text exposition format.
+pub(crate) fn encode_prometheus(metrics: &[RuntimeMetrics]) -> String {
+    let mut out = String::new();
+
+    family(
+        &mut out,
+        "phoenix_runtime_iterations_total",
+        "counter",
+        "Iterations of the runtime mainloop.",
+    );
+    for rt in metrics {
+        let _ = writeln!(
+            out,
+            "phoenix_runtime_iterations_total{{rid=\"{}\"}} {}",
+            rt.rid, rt.iterations
+        );
+    }
+    family(
+        &mut out,
+        "phoenix_runtime_busy_iterations_total",
+        "counter",
+        "Iterations of the runtime mainloop in which any engine made progress.",
+    );
+    for rt in metrics {
+        let _ = writeln!(
+            out,
+            "phoenix_runtime_busy_iterations_total{{rid=\"{}\"}} {}",
+            rt.rid, rt.busy_iterations
+        );
+    }
+
+    let engines = || {
+        metrics.iter().flat_map(|rt| {
+            rt.engines.iter().map(move |e| {
+                let labels = format!(
+                    "rid=\"{}\",eid=\"{}\",engine_type=\"{}\"",
+                    rt.rid, e.eid, e.engine_type
+                );
+                (labels, e)
+            })
+        })
+    };
+
+    family(
+        &mut out,
+        "phoenix_engine_polls_total",
+        "counter",
+        "Times the engine has been polled.",
+    );
+    for (labels, e) in engines() {
+        let _ = writeln!(out, "phoenix_engine_polls_total{{{}}} {}", labels, e.polls);
+    }
+    family(
+        &mut out,
+        "phoenix_engine_busy_polls_total",
+        "counter",
+        "Polls in which the engine made progress.",
+    );
+    for (labels, e) in engines() {
+        let _ = writeln!(
+            out,
+            "phoenix_engine_busy_polls_total{{{}}} {}",
+            labels, e.busy_polls
+        );
+    }
+    family(
+        &mut out,
+        "phoenix_engine_poll_seconds_total",
+        "counter",
+        "Time spent in polling the engine.",
+    );
+    for (labels, e) in engines() {
+        let _ = writeln!(
+            out,
+            "phoenix_engine_poll_seconds_total{{{}}} {}",
+            labels,
+            e.poll_time_ns as f64 / 1e9
+        );
+    }
+    family(
+        &mut out,
+        "phoenix_engine_queue_messages_total",
+        "counter",
+        "Messages the engine received from an input queue or sent to an output queue.",
+    );
+    for (labels, e) in engines() {
+        for q in e.queues.iter() {
+            let _ = writeln!(
+                out,
+                "phoenix_engine_queue_messages_total{{{},queue=\"{}\",index=\"{}\"}} {}",
+                labels,
+                q.kind.as_str(),
+                q.index,
+                q.messages
+            );
+        }
+    }
+    family(
+        &mut out,
+        "phoenix_engine_queue_depth",
+        "gauge",
+        "Messages waiting in the queue.",
+    );
+    for (labels, e) in engines() {
+        for q in e.queues.iter() {
+            let _ = writeln!(
+                out,
+                "phoenix_engine_queue_depth{{{},queue=\"{}\",index=\"{}\"}} {}",
+                labels,
+                q.kind.as_str(),
+                q.index,
+                q.depth
+            );
+        }
+    }
+    out
+}
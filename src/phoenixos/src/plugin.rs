@@ -91,6 +91,24 @@ impl Plugin {
         }
     }
 
+    #[inline]
+    pub(crate) fn linked(&self) -> &LinkedModule {
+        &self.linked
+    }
+
+    #[inline]
+    pub(crate) fn has_old(&self) -> bool {
+        self._old.is_some()
+    }
+
+    /// Switch back to the old library, keeping the current one as the old library.
+    #[inline]
+    pub(crate) fn revert(&mut self) {
+        if let Some(old) = self._old.as_mut() {
+            std::mem::swap(&mut self.linked, old);
+        }
+    }
+
     /// Load config string from either inline multiline string or a separate path.
     pub(crate) fn load_config<P: AsRef<Path>, S: AsRef<str>>(
         config_path: Option<P>,
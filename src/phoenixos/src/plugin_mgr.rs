@@ -21,7 +21,7 @@ use phoenix_common::module::Service;
 
 use crate::config::LinkerConfig;
 use crate::dependency::EngineGraph;
-use crate::linker::Linker;
+use crate::linker::{LinkedModule, Linker};
 use crate::plugin::{Plugin, PluginName};
 use crate::runtime::group::GroupUnionFind;
 use crate::{log, tracing};
@@ -154,6 +154,56 @@ impl PluginManager {
         &self,
         descriptors: &[PluginDescriptor],
     ) -> anyhow::Result<HashSet<EngineType>> {
+        self.replace_modules(
+            descriptors,
+            |descriptor| {
+                // Get the library path and its dep file path
+                let (lib_path, dep_path) = self.get_plugin_path(descriptor);
+                log::debug!(
+                    "loading plugin from path: lib_path: {}, dep_path: {}",
+                    lib_path.display(),
+                    dep_path.display()
+                );
+
+                // RT linker load the rlib and its all transitive dependencies
+                let mut linker = self.rt_linker.lock().unwrap();
+                Ok(Some(linker.load_archive(lib_path, dep_path)?))
+            },
+            Plugin::rollback,
+        )
+    }
+
+    /// Roll back upgraded plugins to the libraries they were upgraded from.
+    /// The old libraries must not have been unloaded by `upgrade_cleanup`.
+    /// Returns a set of affected engine types.
+    pub(crate) fn rollback_modules(
+        &self,
+        descriptors: &[PluginDescriptor],
+    ) -> anyhow::Result<HashSet<EngineType>> {
+        for descriptor in descriptors.iter() {
+            match self.plugins.get(descriptor) {
+                Some(plugin) if plugin.has_old() => {}
+                _ => bail!(
+                    "plugin {} has no old version to roll back to",
+                    descriptor.name
+                ),
+            }
+        }
+        self.replace_modules(descriptors, |_| Ok(None), Plugin::revert)
+    }
+
+    /// Replace the modules of `descriptors` and migrate the states from the current modules.
+    /// `link` returns the new library of a plugin, or `None` to revert to its old library.
+    /// `undo` restores a plugin if the new modules are not compatible.
+    fn replace_modules<F>(
+        &self,
+        descriptors: &[PluginDescriptor],
+        link: F,
+        undo: fn(&mut Plugin),
+    ) -> anyhow::Result<HashSet<EngineType>>
+    where
+        F: Fn(&PluginDescriptor) -> anyhow::Result<Option<LinkedModule>>,
+    {
         let mut new_modules = HashMap::with_capacity(descriptors.len());
         let mut old_verions = HashMap::with_capacity(descriptors.len());
         let mut new_versions = HashMap::with_capacity(descriptors.len() + self.modules.len());
@@ -164,31 +214,25 @@ impl PluginManager {
 
         // load new moduels (plugins)
         for descriptor in descriptors.iter() {
-            // Get the library path and its dep file path
-            let (lib_path, dep_path) = self.get_plugin_path(&descriptor);
-            log::debug!(
-                "loading plugin from path: lib_path: {}, dep_path: {}",
-                lib_path.display(),
-                dep_path.display()
-            );
-
-            // RT linker load the rlib and its all transitive dependencies
-            let linked = {
-                let mut linker = self.rt_linker.lock().unwrap();
-                linker.load_archive(lib_path, dep_path)?
-            };
+            let linked = link(descriptor)?;
 
             // Replace the old plugin or create the new plugin
-            let new_plug = match self.plugins.remove(&descriptor) {
-                Some((_, old_plug)) => {
-                    // upgrade from the old library
+            let new_plug = match self.plugins.remove(descriptor) {
+                Some((_, mut old_plug)) => {
                     let old_ver = new_versions.remove(&descriptor.name[..]).unwrap();
                     old_verions.insert(&descriptor.name, old_ver);
-                    old_plug.upgrade(linked)
+                    match linked {
+                        // upgrade from the old library
+                        Some(linked) => old_plug.upgrade(linked),
+                        None => {
+                            old_plug.revert();
+                            old_plug
+                        }
+                    }
                 }
                 None => {
                     // directly load the new library since it's the new
-                    Plugin::new(linked)
+                    Plugin::new(linked.expect("reverting a plugin that is not loaded"))
                 }
             };
 
@@ -217,18 +261,60 @@ impl PluginManager {
         if !compatible {
             // not compatible, rollback
             for desc in descriptors.iter() {
-                self.plugins.get_mut(&desc).unwrap().rollback();
+                undo(self.plugins.get_mut(desc).unwrap().value_mut());
             }
             bail!("new modules are not compatible with existing ones");
         }
 
         drop(modules_guard);
         // if compatible, finish upgrade
+        let new_modules = new_modules
+            .into_iter()
+            .map(|(name, module)| (name.clone(), module))
+            .collect();
+        self.install_modules(new_modules)
+    }
+
+    /// Loads modules that are linked into the process rather than loaded from plugins, e.g., by
+    /// an embedded phoenix. They cannot be unloaded.
+    pub fn load_static_modules(
+        &self,
+        modules: Vec<(String, Box<dyn PhoenixModule>)>,
+    ) -> anyhow::Result<()> {
+        let modules_guard = self.modules.iter().collect::<Vec<_>>();
+        let mut versions = HashMap::with_capacity(modules.len() + modules_guard.len());
+        for module in modules_guard.iter() {
+            versions.insert(&module.key()[..], module.version());
+        }
+        for (name, module) in modules.iter() {
+            if versions.insert(&name[..], module.version()).is_some() {
+                bail!("module {} is already loaded", name);
+            }
+        }
+        for (_, module) in modules.iter() {
+            if !module.check_compatibility(None, &versions) {
+                bail!("static modules are not compatible with existing ones");
+            }
+        }
+
+        drop(versions);
+        drop(modules_guard);
+        self.install_modules(modules)?;
+        Ok(())
+    }
+
+    /// Puts new modules in place of the old ones with the same names, and registers their
+    /// engines and services.
+    /// Returns a set of affected engine types.
+    fn install_modules(
+        &self,
+        mut new_modules: Vec<(String, Box<dyn PhoenixModule>)>,
+    ) -> anyhow::Result<HashSet<EngineType>> {
         let mut graph_guard = self.dependency_graph.lock().unwrap();
         let mut upgraded_engine_types = HashSet::new();
         for (name, module) in new_modules.iter_mut() {
-            let plugin_name = name.to_string();
-            if let Some((_, old_module)) = self.modules.remove(*name) {
+            let plugin_name = name.clone();
+            if let Some((_, old_module)) = self.modules.remove(name.as_str()) {
                 // remove dependencies of the old module from the dependency graph
                 let old_edges = old_module.dependencies();
                 graph_guard.remove_dependency(old_edges.iter().copied())?;
@@ -250,14 +336,18 @@ impl PluginManager {
             }
         }
 
+        let names = new_modules
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
         for (name, module) in new_modules.into_iter() {
             let edges = module.dependencies();
             graph_guard.add_dependency(edges.iter().copied())?;
-            self.modules.insert(name.to_string(), module);
+            self.modules.insert(name, module);
         }
 
-        for descriptor in descriptors.iter() {
-            let module = self.modules.get(&descriptor.name).unwrap();
+        for name in names.iter() {
+            let module = self.modules.get(name).unwrap();
             if let Some(service_info) = module.service() {
                 let dependencies = graph_guard.get_engine_dependencies(&service_info.engine)?;
                 let subscription_engines = dependencies.iter().copied().collect::<HashSet<_>>();
@@ -341,6 +431,74 @@ impl PluginManager {
         Ok(upgraded_engine_types)
     }
 
+    /// Unload a module. Fails if engines of other modules depend on the engines of this module.
+    /// The caller must ensure none of the engines of the module is running.
+    pub(crate) fn unload_module(&self, name: &str) -> anyhow::Result<()> {
+        let descriptor = self.find_descriptor(name)?;
+        let Some(module) = self.modules.get(name) else {
+            bail!("{} is not a module", name);
+        };
+        let engines = module.engines().to_vec();
+        let mut graph_guard = self.dependency_graph.lock().unwrap();
+        for engine in engines.iter() {
+            for dependent in graph_guard.get_engine_dependents(engine)? {
+                if !engines.contains(&dependent) {
+                    bail!("engine {:?} depends on engine {:?}", dependent, engine);
+                }
+            }
+        }
+        drop(module);
+
+        let (_, module) = self.modules.remove(name).unwrap();
+        graph_guard.remove_dependency(module.dependencies().iter().copied())?;
+        graph_guard.remove_engines(engines.iter().copied());
+        drop(graph_guard);
+        if let Some(service_info) = module.service() {
+            self.service_registry.remove(&service_info.service);
+        }
+        for engine in engines.iter() {
+            self.engine_registry.remove(engine);
+        }
+        // the module's code is in the library, drop it first
+        drop(module);
+        self.unlink(&descriptor);
+        tracing::info!("Unloaded module {}", name);
+        Ok(())
+    }
+
+    /// Unload an addon.
+    /// The caller must ensure none of the engines of the addon is running.
+    pub(crate) fn unload_addon(&self, name: &str) -> anyhow::Result<()> {
+        let descriptor = self.find_descriptor(name)?;
+        let Some((_, addon)) = self.addons.remove(name) else {
+            bail!("{} is not an addon", name);
+        };
+        for engine in addon.engines() {
+            self.engine_registry.remove(engine);
+        }
+        // the addon's code is in the library, drop it first
+        drop(addon);
+        self.unlink(&descriptor);
+        tracing::info!("Unloaded addon {}", name);
+        Ok(())
+    }
+
+    fn find_descriptor(&self, name: &str) -> anyhow::Result<PluginDescriptor> {
+        let Some(plugin) = self.plugins.iter().find(|p| p.key().name == name) else {
+            bail!("plugin {} not found", name);
+        };
+        if plugin.value().has_old() {
+            bail!("plugin {} is being upgraded", name);
+        }
+        Ok(plugin.key().clone())
+    }
+
+    fn unlink(&self, descriptor: &PluginDescriptor) {
+        let (_, plugin) = self.plugins.remove(descriptor).unwrap();
+        let mut linker = self.rt_linker.lock().unwrap();
+        linker.unload_archive(plugin.linked());
+    }
+
     /// Finish upgrade of all engines, unload old plugins
     pub(crate) fn upgrade_cleanup(&self) {
         // NOTE, we drop the old library here. To work around the issue mentioned earlier in
@@ -39,6 +39,13 @@ impl CoreMask {
         use libnuma::masks::Mask;
         self.0.is_set(CpuIndex::new(i))
     }
+
+    /// Returns the number of cores in the mask.
+    pub(crate) fn count(&self) -> usize {
+        use libnuma::masks::indices::CpuIndex;
+        let all_cpus = CpuIndex::number_of_permitted_cpus();
+        (0..all_cpus).filter(|&i| self.is_set(i as u16)).count()
+    }
 }
 
 impl fmt::Display for CoreMask {
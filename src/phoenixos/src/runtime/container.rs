@@ -5,6 +5,7 @@ use std::pin::Pin;
 use futures::future::BoxFuture;
 use semver::Version;
 
+use ipc::control::{QueueKind, QueueMetrics};
 use phoenix_common::engine::{Engine, EngineResult, EngineType};
 
 /// A container that bundles a `Box<dyn Engine>` and its `Future` object so that the caller of this
@@ -37,6 +38,20 @@ pub(crate) struct EngineContainer {
 
     /// The verion of the phoenix module that the engine belongs to.
     version: Version,
+
+    /// Counters updated by the runtime that polls this engine.
+    pub(crate) stats: EngineStats,
+}
+
+/// Per-engine counters maintained by the runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct EngineStats {
+    /// Number of times the future has been polled.
+    pub(crate) polls: u64,
+    /// Number of polls in which the engine reported progress.
+    pub(crate) busy_polls: u64,
+    /// Total time spent in polling the future, in nanoseconds.
+    pub(crate) poll_time_ns: u64,
 }
 
 /// Extending the future's lifetime from 'a to 'static.
@@ -68,6 +83,7 @@ impl EngineContainer {
             engine: pinned,
             version,
             ty,
+            stats: EngineStats::default(),
         }
     }
 
@@ -96,6 +112,46 @@ impl EngineContainer {
         self.version.clone()
     }
 
+    /// Returns the counters and the depth of each queue in the engine's `DataPathNode`.
+    pub(crate) fn queue_metrics(&mut self) -> Vec<QueueMetrics> {
+        // the engine is Unpin, and only the references to its queues are taken here
+        let engine = &mut *self.engine;
+        let mut queues = Vec::new();
+        for (index, queue) in engine.tx_inputs().iter().enumerate() {
+            queues.push(QueueMetrics {
+                kind: QueueKind::TxInput,
+                index,
+                messages: queue.received(),
+                depth: queue.len(),
+            });
+        }
+        for (index, queue) in engine.tx_outputs().iter().enumerate() {
+            queues.push(QueueMetrics {
+                kind: QueueKind::TxOutput,
+                index,
+                messages: queue.sent(),
+                depth: queue.len(),
+            });
+        }
+        for (index, queue) in engine.rx_inputs().iter().enumerate() {
+            queues.push(QueueMetrics {
+                kind: QueueKind::RxInput,
+                index,
+                messages: queue.received(),
+                depth: queue.len(),
+            });
+        }
+        for (index, queue) in engine.rx_outputs().iter().enumerate() {
+            queues.push(QueueMetrics {
+                kind: QueueKind::RxOutput,
+                index,
+                messages: queue.sent(),
+                depth: queue.len(),
+            });
+        }
+        queues
+    }
+
     pub(crate) fn handle_request(&mut self, request: Vec<u8>, cred: UCred) -> anyhow::Result<()> {
         self.engine.handle_request(request, cred)
     }
@@ -9,10 +9,12 @@ use std::thread;
 use std::time::Duration;
 
 use dashmap::DashMap;
+use futures::channel::oneshot;
 use minstant::Instant;
 use spin::Mutex;
 use thiserror::Error;
 
+use ipc::control::{EngineMetrics, RuntimeMetrics};
 use phoenix_common::engine::EngineResult;
 
 use super::affinity::CoreMask;
@@ -93,6 +95,9 @@ pub(crate) struct Runtime {
     pub(crate) new_ctrl_request: AtomicBool,
     pub(crate) control_requests: Mutex<Vec<(EngineId, Vec<u8>, UCred)>>,
 
+    new_metrics_request: AtomicBool,
+    metrics_requests: Mutex<Vec<oneshot::Sender<RuntimeMetrics>>>,
+
     pub(crate) runtime_manager: Weak<RuntimeManager>,
 }
 
@@ -117,10 +122,29 @@ impl Runtime {
             new_ctrl_request: AtomicBool::new(false),
             control_requests: Mutex::new(Vec::new()),
 
+            new_metrics_request: AtomicBool::new(false),
+            metrics_requests: Mutex::new(Vec::new()),
+
             runtime_manager: rm,
         }
     }
 
+    /// Returns the mode of the runtime, `None` if no group has ever been submitted.
+    #[inline]
+    pub(crate) fn mode(&self) -> Option<RuntimeMode> {
+        match self.mode.load(Ordering::Relaxed) {
+            1 => Some(RuntimeMode::Compact),
+            2 => Some(RuntimeMode::Dedicated),
+            3 => Some(RuntimeMode::GroupShared),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn cores(&self) -> &CoreMask {
+        &self.cores
+    }
+
     /// Returns true if there is no runnable engine or pending engine.
     #[inline]
     pub(crate) fn is_empty(&self) -> bool {
@@ -224,6 +248,15 @@ impl Runtime {
         self.new_ctrl_request.store(true, Ordering::Release);
     }
 
+    /// Request a snapshot of the runtime's metrics. The snapshot is taken in the mainloop, so the
+    /// caller may need to unpark the runtime thread.
+    pub(crate) fn request_metrics(&self) -> oneshot::Receiver<RuntimeMetrics> {
+        let (sender, receiver) = oneshot::channel();
+        self.metrics_requests.lock().push(sender);
+        self.new_metrics_request.store(true, Ordering::Release);
+        receiver
+    }
+
     pub(crate) fn request_suspend(&self, eid: EngineId) {
         self.suspend_requests.lock().push(eid);
         self.new_suspend.store(true, Ordering::Release);
@@ -270,12 +303,18 @@ impl Runtime {
 
         let mut last_event_ts = Instant::now();
 
+        // mainloop iterations and the iterations in which any engine made progress
+        let mut iterations = 0u64;
+        let mut busy_iterations = 0u64;
+
         loop {
             // TODO(cjr): if there's no active engine on this runtime, call `mwait` to put the CPU
             // into an optimized state. (the wakeup latency and whether it can be used in user mode
             // are two concerns)
             self.save_energy_or_shutdown(last_event_ts);
 
+            let mut has_work = false;
+
             // drive each engine
             for (group_index, group) in self.running.borrow().iter().enumerate() {
                 let mut group = group.borrow_mut();
@@ -285,33 +324,44 @@ impl Runtime {
                     engine.engine_mut().set_els();
 
                     // bind to a variable first (otherwise engine is borrowed in the match expression)
+                    let poll_start = Instant::now();
                     let ret = engine.future().poll(&mut cx);
+                    let poll_end = Instant::now();
+                    engine.stats.polls += 1;
+                    engine.stats.poll_time_ns += (poll_end - poll_start).as_nanos() as u64;
                     match ret {
                         Poll::Pending => {
                             let tracker = engine.engine_mut().tracker();
-                            // has_work += tracker.nwork();
-                            if tracker.nwork() > 0 {
-                                last_event_ts = Instant::now();
-                            }
+                            let nwork = tracker.nwork();
                             tracker.set_nwork(0);
+                            if nwork > 0 {
+                                last_event_ts = poll_end;
+                                has_work = true;
+                                engine.stats.busy_polls += 1;
+                            }
                         }
                         Poll::Ready(EngineResult::Ok(())) => {
                             log::info!(
                                 "Engine [{}] completed, shutting down...",
                                 engine.engine().description()
                             );
-                            shutdown.push((group_index, engine_index));
+                            shutdown.push((group_index, engine_index, None));
                         }
                         Poll::Ready(EngineResult::Err(e)) => {
                             log::error!("Engine [{}] error: {}", engine.engine().description(), e);
-                            shutdown.push((group_index, engine_index));
+                            shutdown.push((group_index, engine_index, Some(e.to_string())));
                         }
                     }
                 }
             }
 
+            iterations += 1;
+            if has_work {
+                busy_iterations += 1;
+            }
+
             // garbage collect every several rounds, maybe move to another thread.
-            for (group_index, engine_index) in shutdown.drain(..).rev() {
+            for (group_index, engine_index, error) in shutdown.drain(..).rev() {
                 let mut running = self.running.borrow_mut();
                 let (eid, mut engine) = running[group_index]
                     .borrow_mut()
@@ -329,10 +379,11 @@ impl Runtime {
                     running.swap_remove(group_index);
                 }
                 // This should be fine because runtime will be dropped later than RuntimeManager.
-                self.runtime_manager
-                    .upgrade()
-                    .unwrap()
-                    .register_engine_shutdown(eid);
+                let rm = self.runtime_manager.upgrade().unwrap();
+                if let Some(error) = error {
+                    rm.register_engine_error(eid, error);
+                }
+                rm.register_engine_shutdown(eid);
             }
 
             // move newly added runtime to the scheduling queue
@@ -358,8 +409,10 @@ impl Runtime {
                                     group.borrow_mut().engines.extend(engines);
                                 }
                                 None => {
-                                    // handle the case that all engines within the group already shutdowns
+                                    // handle the case that all engines within the group already
+                                    // shutdowns or are suspended
                                     let group = SchedulingGroup::new(group_id, engines);
+                                    self.active_cnt.fetch_add(1, Ordering::Relaxed);
                                     running.push(RefCell::new(group));
                                 }
                             }
@@ -390,6 +443,12 @@ impl Runtime {
                         .drain_filter(|e| engine_ids.contains(&e.0));
                     engines.extend(group_engines_suspend);
                 }
+                // groups whose engines are all suspended no longer count as active, they are
+                // re-created when the engines are attached back
+                let ngroups = running.len();
+                running.retain(|group| !group.borrow().engines.is_empty());
+                self.active_cnt
+                    .fetch_sub(ngroups - running.len(), Ordering::Relaxed);
                 for (engine_id, mut engine) in engines {
                     if let Err(err) = engine.engine_mut().pre_detach() {
                         tracing::error!(
@@ -443,6 +502,40 @@ impl Runtime {
                 }
             }
 
+            if Ok(true)
+                == self.new_metrics_request.compare_exchange(
+                    true,
+                    false,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+            {
+                let mut engines = Vec::new();
+                for group in self.running.borrow().iter() {
+                    let mut group = group.borrow_mut();
+                    for (eid, engine) in group.engines.iter_mut() {
+                        engines.push(EngineMetrics {
+                            eid: eid.0,
+                            engine_type: engine.engine_type().0.to_string(),
+                            polls: engine.stats.polls,
+                            busy_polls: engine.stats.busy_polls,
+                            poll_time_ns: engine.stats.poll_time_ns,
+                            queues: engine.queue_metrics(),
+                        });
+                    }
+                }
+                let metrics = RuntimeMetrics {
+                    rid: self.id.0,
+                    iterations,
+                    busy_iterations,
+                    engines,
+                };
+                for sender in self.metrics_requests.lock().drain(..) {
+                    // the requester may have given up waiting
+                    let _ = sender.send(metrics.clone());
+                }
+            }
+
             // loop scope ends here
         }
     }
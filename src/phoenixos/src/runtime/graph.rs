@@ -28,6 +28,26 @@ pub enum Error {
     NodeTampered(EngineType, EndpointType),
     #[error("Dangling endpoints left after replacement")]
     DanglingEndpoint,
+    #[error("Data path graph contains a directed cycle")]
+    Cycle,
+    #[error("Engine {0:?} not found in the data path")]
+    EngineNotFound(EngineType),
+    #[error("Engine {0:?} is already in the data path")]
+    EngineExists(EngineType),
+    #[error("No channel from {0:?} to {1:?}")]
+    ChannelNotFound(EngineType, EngineType),
+    #[error("Engine {0:?} does not have exactly one channel of {1:?}")]
+    AmbiguousInsertion(EngineType, EndpointType),
+    #[error("Addon must be inserted on the tx or the rx direction")]
+    NoDirection,
+}
+
+/// Where to insert an addon, named by the engines on the tx direction.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum InsertionPoint {
+    Between(EngineType, EngineType),
+    Before(EngineType),
+    After(EngineType),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -68,7 +88,7 @@ impl FlowDependencyGraph {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct DataPathGraph {
     // the engines on the sender end for `tx_inputs` on each engine's DataPathNode
     // type of the engine, and the index in the sender engine's `tx_outputs`
@@ -115,7 +135,190 @@ impl DataPathGraph {
         self.rx_outputs.remove(engine);
     }
 
-    pub(crate) fn topological_order(&self) -> Vec<(EngineType, FlowDirection)> {
+    /// Computes the tx and rx channel replacements to insert `addon` at `point`.
+    /// On each direction, the addon gets a single input and a single output.
+    pub(crate) fn insertion_replacements(
+        &self,
+        addon: EngineType,
+        point: InsertionPoint,
+        tx: bool,
+        rx: bool,
+    ) -> Result<(Vec<ChannelDescriptor>, Vec<ChannelDescriptor>), Error> {
+        if !tx && !rx {
+            return Err(Error::NoDirection);
+        }
+        if self.tx_inputs.contains_key(&addon) {
+            return Err(Error::EngineExists(addon));
+        }
+        let (sender, receiver) = match point {
+            InsertionPoint::Between(sender, receiver) => (sender, receiver),
+            InsertionPoint::Before(receiver) => {
+                match self
+                    .tx_inputs
+                    .get(&receiver)
+                    .ok_or(Error::EngineNotFound(receiver))?
+                    .as_slice()
+                {
+                    [(sender, _)] => (*sender, receiver),
+                    _ => return Err(Error::AmbiguousInsertion(receiver, EndpointType::TxInput)),
+                }
+            }
+            InsertionPoint::After(sender) => {
+                match self
+                    .tx_outputs
+                    .get(&sender)
+                    .ok_or(Error::EngineNotFound(sender))?
+                    .as_slice()
+                {
+                    [(receiver, _)] => (sender, *receiver),
+                    _ => return Err(Error::AmbiguousInsertion(sender, EndpointType::TxOutput)),
+                }
+            }
+        };
+
+        let split = |outputs: &HashMap<EngineType, Vec<(EngineType, usize)>>,
+                     from: EngineType,
+                     to: EngineType|
+         -> Result<Vec<ChannelDescriptor>, Error> {
+            let index = outputs
+                .get(&from)
+                .ok_or(Error::EngineNotFound(from))?
+                .iter()
+                .position(|(engine, _)| *engine == to)
+                .ok_or(Error::ChannelNotFound(from, to))?;
+            let peer_index = outputs[&from][index].1;
+            Ok(vec![
+                ChannelDescriptor(from, addon, index, 0),
+                ChannelDescriptor(addon, to, 0, peer_index),
+            ])
+        };
+        let tx_edges = if tx {
+            split(&self.tx_outputs, sender, receiver)?
+        } else {
+            Vec::new()
+        };
+        let rx_edges = if rx {
+            split(&self.rx_outputs, receiver, sender)?
+        } else {
+            Vec::new()
+        };
+        Ok((tx_edges, rx_edges))
+    }
+
+    /// Computes the tx and rx channel replacements to detach `addon`, connecting each input
+    /// of the addon to its output. Only applies to addons with at most one input and one
+    /// output on each direction.
+    pub(crate) fn removal_replacements(
+        &self,
+        addon: EngineType,
+    ) -> Result<(Vec<ChannelDescriptor>, Vec<ChannelDescriptor>), Error> {
+        let bypass = |inputs: &HashMap<EngineType, Vec<(EngineType, usize)>>,
+                      outputs: &HashMap<EngineType, Vec<(EngineType, usize)>>,
+                      input_ty: EndpointType,
+                      output_ty: EndpointType|
+         -> Result<Vec<ChannelDescriptor>, Error> {
+            let inputs = inputs.get(&addon).ok_or(Error::EngineNotFound(addon))?;
+            let outputs = outputs.get(&addon).ok_or(Error::EngineNotFound(addon))?;
+            match (inputs.as_slice(), outputs.as_slice()) {
+                ([], []) => Ok(Vec::new()),
+                ([(sender, sender_index)], [(receiver, receiver_index)]) => {
+                    Ok(vec![ChannelDescriptor(
+                        *sender,
+                        *receiver,
+                        *sender_index,
+                        *receiver_index,
+                    )])
+                }
+                ([], _) | ([_], _) => Err(Error::AmbiguousInsertion(addon, output_ty)),
+                _ => Err(Error::AmbiguousInsertion(addon, input_ty)),
+            }
+        };
+        let tx_edges = bypass(
+            &self.tx_inputs,
+            &self.tx_outputs,
+            EndpointType::TxInput,
+            EndpointType::TxOutput,
+        )?;
+        let rx_edges = bypass(
+            &self.rx_inputs,
+            &self.rx_outputs,
+            EndpointType::RxInput,
+            EndpointType::RxOutput,
+        )?;
+        Ok((tx_edges, rx_edges))
+    }
+
+    /// Checks that attaching `addon` with the replacements leaves a valid graph:
+    /// every channel is connected on both ends, and there is no directed cycle.
+    pub(crate) fn check_attach(
+        &self,
+        addon: EngineType,
+        tx_edges: &[ChannelDescriptor],
+        rx_edges: &[ChannelDescriptor],
+    ) -> Result<(), Error> {
+        if self.tx_inputs.contains_key(&addon) {
+            return Err(Error::EngineExists(addon));
+        }
+        let mut graph = self.clone();
+        graph.insert_node(addon, Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (edges, inputs, outputs) in [
+            (tx_edges, &mut graph.tx_inputs, &mut graph.tx_outputs),
+            (rx_edges, &mut graph.rx_inputs, &mut graph.rx_outputs),
+        ] {
+            for edge in edges {
+                if edge.0 != addon && edge.1 != addon {
+                    return Err(Error::InvalidReplacement(*edge));
+                }
+                for (endpoints, engine, index, peer) in [
+                    (&mut *outputs, edge.0, edge.2, (edge.1, edge.3)),
+                    (&mut *inputs, edge.1, edge.3, (edge.0, edge.2)),
+                ] {
+                    let endpoints = endpoints
+                        .get_mut(&engine)
+                        .ok_or(Error::InvalidReplacement(*edge))?;
+                    if engine == addon && index >= endpoints.len() {
+                        // holes left by missing indices fail the consistency check
+                        endpoints.resize(index + 1, (addon, usize::MAX));
+                    } else if index >= endpoints.len() {
+                        return Err(Error::InvalidReplacement(*edge));
+                    }
+                    endpoints[index] = peer;
+                }
+            }
+        }
+        if !graph.is_consistent() {
+            return Err(Error::DanglingEndpoint);
+        }
+        if petgraph::algo::is_cyclic_directed(&graph.flow_dependency().graph) {
+            return Err(Error::Cycle);
+        }
+        Ok(())
+    }
+
+    /// Returns true if both ends of every channel agree on each other.
+    fn is_consistent(&self) -> bool {
+        let matched =
+            |outputs: &HashMap<EngineType, Vec<(EngineType, usize)>>,
+             inputs: &HashMap<EngineType, Vec<(EngineType, usize)>>| {
+                outputs.iter().all(|(engine, endpoints)| {
+                    endpoints
+                        .iter()
+                        .enumerate()
+                        .all(|(index, (peer, peer_index))| {
+                            inputs
+                                .get(peer)
+                                .and_then(|x| x.get(*peer_index))
+                                .map_or(false, |x| *x == (*engine, index))
+                        })
+                })
+            };
+        matched(&self.tx_outputs, &self.tx_inputs)
+            && matched(&self.tx_inputs, &self.tx_outputs)
+            && matched(&self.rx_outputs, &self.rx_inputs)
+            && matched(&self.rx_inputs, &self.rx_outputs)
+    }
+
+    fn flow_dependency(&self) -> FlowDependencyGraph {
         let mut graph = FlowDependencyGraph::new();
         for (engine_type, endpoints) in self.tx_outputs.iter() {
             let sender = graph.get_or_insert_index(*engine_type, FlowDirection::Tx);
@@ -131,7 +334,11 @@ impl DataPathGraph {
                 graph.graph.add_edge(sender, receiver, ());
             }
         }
+        graph
+    }
 
+    pub(crate) fn topological_order(&self) -> Vec<(EngineType, FlowDirection)> {
+        let graph = self.flow_dependency();
         if petgraph::algo::is_cyclic_directed(&graph.graph) {
             panic!("Data path graph contains a directed cycle")
         }
@@ -311,3 +518,234 @@ impl EndpointCollection {
         Ok((node, endpoint_info))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MRPC: EngineType = EngineType("MrpcEngine");
+    const ADAPTER: EngineType = EngineType("TcpRpcAdapterEngine");
+    const RATELIMIT: EngineType = EngineType("RateLimitEngine");
+    const ACL: EngineType = EngineType("AclEngine");
+
+    /// Builds the graph of the channels, whose endpoints must leave no holes.
+    fn new_graph(tx_edges: &[ChannelDescriptor], rx_edges: &[ChannelDescriptor]) -> DataPathGraph {
+        let mut graph = DataPathGraph::new();
+        for edge in tx_edges.iter().chain(rx_edges) {
+            for engine in [edge.0, edge.1] {
+                if !graph.tx_inputs.contains_key(&engine) {
+                    graph.insert_node(engine, Vec::new(), Vec::new(), Vec::new(), Vec::new());
+                }
+            }
+        }
+        let set = |endpoints: &mut Vec<(EngineType, usize)>, index, peer| {
+            if index >= endpoints.len() {
+                endpoints.resize(index + 1, peer);
+            }
+            endpoints[index] = peer;
+        };
+        for (edges, inputs, outputs) in [
+            (tx_edges, &mut graph.tx_inputs, &mut graph.tx_outputs),
+            (rx_edges, &mut graph.rx_inputs, &mut graph.rx_outputs),
+        ] {
+            for edge in edges {
+                set(outputs.get_mut(&edge.0).unwrap(), edge.2, (edge.1, edge.3));
+                set(inputs.get_mut(&edge.1).unwrap(), edge.3, (edge.0, edge.2));
+            }
+        }
+        assert!(graph.is_consistent());
+        graph
+    }
+
+    fn edges(edges: &[ChannelDescriptor]) -> Vec<(&'static str, &'static str, usize, usize)> {
+        edges
+            .iter()
+            .map(|edge| (edge.0 .0, edge.1 .0, edge.2, edge.3))
+            .collect()
+    }
+
+    /// mRPC and the adapter.
+    fn service_graph() -> DataPathGraph {
+        new_graph(
+            &[ChannelDescriptor(MRPC, ADAPTER, 0, 0)],
+            &[ChannelDescriptor(ADAPTER, MRPC, 0, 0)],
+        )
+    }
+
+    /// The rate limiter between mRPC and the adapter.
+    fn attached_graph() -> DataPathGraph {
+        new_graph(
+            &[
+                ChannelDescriptor(MRPC, RATELIMIT, 0, 0),
+                ChannelDescriptor(RATELIMIT, ADAPTER, 0, 0),
+            ],
+            &[
+                ChannelDescriptor(ADAPTER, RATELIMIT, 0, 0),
+                ChannelDescriptor(RATELIMIT, MRPC, 0, 0),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_insert() {
+        let graph = service_graph();
+        for point in [
+            InsertionPoint::Between(MRPC, ADAPTER),
+            InsertionPoint::Before(ADAPTER),
+            InsertionPoint::After(MRPC),
+        ] {
+            let (tx_edges, rx_edges) = graph
+                .insertion_replacements(RATELIMIT, point, true, true)
+                .unwrap();
+            assert_eq!(
+                edges(&tx_edges),
+                [
+                    ("MrpcEngine", "RateLimitEngine", 0, 0),
+                    ("RateLimitEngine", "TcpRpcAdapterEngine", 0, 0)
+                ]
+            );
+            assert_eq!(
+                edges(&rx_edges),
+                [
+                    ("TcpRpcAdapterEngine", "RateLimitEngine", 0, 0),
+                    ("RateLimitEngine", "MrpcEngine", 0, 0)
+                ]
+            );
+            graph.check_attach(RATELIMIT, &tx_edges, &rx_edges).unwrap();
+        }
+
+        // on the tx direction only
+        let point = InsertionPoint::Between(MRPC, ADAPTER);
+        let (tx_edges, rx_edges) = graph
+            .insertion_replacements(RATELIMIT, point, true, false)
+            .unwrap();
+        assert_eq!(tx_edges.len(), 2);
+        assert!(rx_edges.is_empty());
+        graph.check_attach(RATELIMIT, &tx_edges, &rx_edges).unwrap();
+    }
+
+    #[test]
+    fn test_insert_errors() {
+        let graph = service_graph();
+        let point = InsertionPoint::Between(MRPC, ADAPTER);
+        assert!(matches!(
+            graph.insertion_replacements(RATELIMIT, point, false, false),
+            Err(Error::NoDirection)
+        ));
+        assert!(matches!(
+            graph.insertion_replacements(MRPC, point, true, true),
+            Err(Error::EngineExists(MRPC))
+        ));
+        assert!(matches!(
+            graph.insertion_replacements(RATELIMIT, InsertionPoint::Before(ACL), true, true),
+            Err(Error::EngineNotFound(ACL))
+        ));
+        // the channels go the other way
+        assert!(matches!(
+            graph.insertion_replacements(
+                RATELIMIT,
+                InsertionPoint::Between(ADAPTER, MRPC),
+                true,
+                false
+            ),
+            Err(Error::ChannelNotFound(ADAPTER, MRPC))
+        ));
+        // mRPC has no tx input to insert before
+        assert!(matches!(
+            graph.insertion_replacements(RATELIMIT, InsertionPoint::Before(MRPC), true, true),
+            Err(Error::AmbiguousInsertion(MRPC, EndpointType::TxInput))
+        ));
+    }
+
+    #[test]
+    fn test_check_attach_cycle() {
+        // the rate limiter sends back to the acl engine it receives from
+        let graph = attached_graph();
+        let tx_edges = [
+            ChannelDescriptor(MRPC, ACL, 0, 0),
+            ChannelDescriptor(ACL, RATELIMIT, 0, 0),
+            ChannelDescriptor(RATELIMIT, ACL, 0, 1),
+            ChannelDescriptor(ACL, ADAPTER, 1, 0),
+        ];
+        assert!(matches!(
+            graph.check_attach(ACL, &tx_edges, &[]),
+            Err(Error::Cycle)
+        ));
+    }
+
+    #[test]
+    fn test_check_attach_dangling() {
+        let graph = service_graph();
+        // the adapter still receives from mRPC, which sends to the acl engine
+        let tx_edges = [ChannelDescriptor(MRPC, ACL, 0, 0)];
+        assert!(matches!(
+            graph.check_attach(ACL, &tx_edges, &[]),
+            Err(Error::DanglingEndpoint)
+        ));
+
+        // the first tx input of the acl engine is left unconnected
+        let tx_edges = [
+            ChannelDescriptor(MRPC, ACL, 0, 1),
+            ChannelDescriptor(ACL, ADAPTER, 0, 0),
+        ];
+        assert!(matches!(
+            graph.check_attach(ACL, &tx_edges, &[]),
+            Err(Error::DanglingEndpoint)
+        ));
+
+        // a channel that bypasses the addon
+        let tx_edges = [ChannelDescriptor(MRPC, ADAPTER, 0, 0)];
+        assert!(matches!(
+            graph.check_attach(ACL, &tx_edges, &[]),
+            Err(Error::InvalidReplacement(_))
+        ));
+        // an endpoint that mRPC does not have
+        let tx_edges = [
+            ChannelDescriptor(MRPC, ACL, 1, 0),
+            ChannelDescriptor(ACL, ADAPTER, 0, 0),
+        ];
+        assert!(matches!(
+            graph.check_attach(ACL, &tx_edges, &[]),
+            Err(Error::InvalidReplacement(_))
+        ));
+    }
+
+    #[test]
+    fn test_remove() {
+        let graph = attached_graph();
+        let (tx_edges, rx_edges) = graph.removal_replacements(RATELIMIT).unwrap();
+        assert_eq!(
+            edges(&tx_edges),
+            [("MrpcEngine", "TcpRpcAdapterEngine", 0, 0)]
+        );
+        assert_eq!(
+            edges(&rx_edges),
+            [("TcpRpcAdapterEngine", "MrpcEngine", 0, 0)]
+        );
+
+        // an addon on the tx direction only
+        let graph = new_graph(
+            &[
+                ChannelDescriptor(MRPC, ACL, 0, 0),
+                ChannelDescriptor(ACL, ADAPTER, 0, 0),
+            ],
+            &[ChannelDescriptor(ADAPTER, MRPC, 0, 0)],
+        );
+        let (tx_edges, rx_edges) = graph.removal_replacements(ACL).unwrap();
+        assert_eq!(
+            edges(&tx_edges),
+            [("MrpcEngine", "TcpRpcAdapterEngine", 0, 0)]
+        );
+        assert!(rx_edges.is_empty());
+
+        assert!(matches!(
+            graph.removal_replacements(RATELIMIT),
+            Err(Error::EngineNotFound(RATELIMIT))
+        ));
+        // mRPC has a tx output but no tx input
+        assert!(matches!(
+            graph.removal_replacements(MRPC),
+            Err(Error::AmbiguousInsertion(MRPC, EndpointType::TxOutput))
+        ));
+    }
+}
@@ -1,8 +1,338 @@
 //! Engine load balancer assigns engines to runtimes.
+//!
+//! The scheduling groups are placed once when they are submitted. [`LoadBalancer`] samples the
+//! metrics of the runtimes periodically, and moves whole scheduling groups between compact
+//! runtimes: a hot runtime sheds a group to a cooler (or a new) runtime, and groups on lightly
+//! loaded runtimes are packed together so that the emptied runtime parks itself.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use phoenix_common::engine::Engine;
+use anyhow::{anyhow, bail};
+use futures::channel::oneshot;
+use nix::unistd::Pid;
+
+use ipc::control::RuntimeMetrics;
+
+use super::affinity::CoreMask;
+use super::executor::{RuntimeMode, SuspendResult};
+use super::group::GroupId;
+use super::manager::{EngineId, RuntimeId, RuntimeManager};
+use super::EngineUpgrader;
+use crate::config::BalancerConfig;
+use crate::{log, tracing};
 
 pub(crate) trait EngineBalancer {
-    /// Schedule a runtime for this engine.
-    fn submit<E: Engine>(&self, engine: E);
+    /// Observes the load of the runtimes and moves scheduling groups between them.
+    /// It is called periodically by the control plane, and must not block.
+    fn rebalance(&mut self, upgrader: &mut EngineUpgrader) -> anyhow::Result<()>;
+}
+
+/// Counters of a runtime when it was last sampled.
+#[derive(Debug, Default)]
+struct Sample {
+    iterations: u64,
+    busy_iterations: u64,
+    /// busy polls of each engine
+    busy_polls: HashMap<u64, u64>,
+}
+
+#[derive(Debug)]
+struct GroupLoad {
+    gid: GroupId,
+    pid: Pid,
+    engines: Vec<EngineId>,
+    /// Busy polls of the group's engines per runtime iteration
+    load: f64,
+}
+
+#[derive(Debug)]
+struct RuntimeLoad {
+    rid: RuntimeId,
+    cores: CoreMask,
+    /// Fraction of iterations in which any engine made progress
+    load: f64,
+    groups: Vec<GroupLoad>,
+}
+
+/// A scheduling group to move.
+struct Migration<'a> {
+    from: &'a RuntimeLoad,
+    group: &'a GroupLoad,
+    /// The runtime to move to, `None` for a new runtime
+    to: Option<RuntimeId>,
+}
+
+/// Balances the load of compact runtimes by migrating scheduling groups.
+pub(crate) struct LoadBalancer {
+    runtime_manager: Arc<RuntimeManager>,
+    interval: Duration,
+    high_watermark: f64,
+    low_watermark: f64,
+    last_sample: Instant,
+    samples: HashMap<u64, Sample>,
+    /// Metrics requested from the runtimes that have not arrived
+    collecting: Vec<oneshot::Receiver<RuntimeMetrics>>,
+    collected: Vec<RuntimeMetrics>,
+    /// The ongoing migration
+    migrating: Option<oneshot::Receiver<anyhow::Result<RuntimeId>>>,
+}
+
+impl LoadBalancer {
+    pub(crate) fn new(rm: Arc<RuntimeManager>, config: &BalancerConfig) -> Self {
+        LoadBalancer {
+            runtime_manager: rm,
+            interval: Duration::from_millis(config.interval_ms),
+            high_watermark: config.high_watermark,
+            low_watermark: config.low_watermark,
+            last_sample: Instant::now(),
+            samples: HashMap::new(),
+            collecting: Vec::new(),
+            collected: Vec::new(),
+            migrating: None,
+        }
+    }
+
+    /// Computes the load of each compact runtime since the last sample.
+    fn update_loads(&mut self, metrics: Vec<RuntimeMetrics>) -> Vec<RuntimeLoad> {
+        let mut loads = Vec::with_capacity(metrics.len());
+        let mut samples = HashMap::with_capacity(metrics.len());
+        for runtime in metrics {
+            let prev = self.samples.remove(&runtime.rid).unwrap_or_default();
+            let iterations = runtime.iterations.saturating_sub(prev.iterations);
+            let busy_iterations = runtime.busy_iterations.saturating_sub(prev.busy_iterations);
+            let ratio = |busy: u64| {
+                if iterations == 0 {
+                    0.0
+                } else {
+                    busy as f64 / iterations as f64
+                }
+            };
+
+            let rid = RuntimeId(runtime.rid);
+            let cores = {
+                let inner = self.runtime_manager.inner.lock().unwrap();
+                match inner.runtimes.get(&rid) {
+                    Some(rt) if rt.mode() == Some(RuntimeMode::Compact) => Some(rt.cores().clone()),
+                    _ => None,
+                }
+            };
+
+            let mut groups: HashMap<GroupId, GroupLoad> = HashMap::new();
+            let mut busy_polls = HashMap::with_capacity(runtime.engines.len());
+            for engine in runtime.engines {
+                let busy = engine
+                    .busy_polls
+                    .saturating_sub(prev.busy_polls.get(&engine.eid).copied().unwrap_or(0));
+                busy_polls.insert(engine.eid, engine.busy_polls);
+                let eid = EngineId(engine.eid);
+                if let Some(info) = self.runtime_manager.engine_subscriptions.get(&eid) {
+                    if info.rid != rid {
+                        continue;
+                    }
+                    let group = groups.entry(info.gid).or_insert_with(|| GroupLoad {
+                        gid: info.gid,
+                        pid: info.pid,
+                        engines: Vec::new(),
+                        load: 0.0,
+                    });
+                    group.engines.push(eid);
+                    group.load += ratio(busy);
+                }
+            }
+
+            samples.insert(
+                runtime.rid,
+                Sample {
+                    iterations: runtime.iterations,
+                    busy_iterations: runtime.busy_iterations,
+                    busy_polls,
+                },
+            );
+            if let Some(cores) = cores {
+                loads.push(RuntimeLoad {
+                    rid,
+                    cores,
+                    load: ratio(busy_iterations),
+                    groups: groups.into_values().collect(),
+                });
+            }
+        }
+        // runtimes that are gone are forgotten
+        self.samples = samples;
+        loads
+    }
+
+    /// Decides which group to move, if any.
+    fn plan<'a>(&self, loads: &'a [RuntimeLoad]) -> Option<Migration<'a>> {
+        // shed a group from the hottest runtime
+        let hot = loads
+            .iter()
+            .filter(|r| r.groups.len() > 1 && r.load > self.high_watermark)
+            .max_by(|a, b| a.load.total_cmp(&b.load));
+        if let Some(hot) = hot {
+            let target = loads
+                .iter()
+                .filter(|r| r.rid != hot.rid && r.cores == hot.cores)
+                .filter(|r| r.load < self.low_watermark)
+                .min_by(|a, b| a.load.total_cmp(&b.load));
+            let (to, target_load) = match target {
+                Some(target) => (Some(target.rid), target.load),
+                None => {
+                    let siblings = loads.iter().filter(|r| r.cores == hot.cores).count();
+                    if siblings >= hot.cores.count() {
+                        return None;
+                    }
+                    (None, 0.0)
+                }
+            };
+            // move the group that evens out the load of the two runtimes the most
+            let excess = (hot.load - target_load) / 2.0;
+            let group = hot
+                .groups
+                .iter()
+                .min_by(|a, b| (a.load - excess).abs().total_cmp(&(b.load - excess).abs()))?;
+            return Some(Migration {
+                from: hot,
+                group,
+                to,
+            });
+        }
+
+        // pack the coldest runtime into the busiest runtime that stays lightly loaded
+        let mut cold = loads
+            .iter()
+            .filter(|r| !r.groups.is_empty() && r.load < self.low_watermark)
+            .collect::<Vec<_>>();
+        cold.sort_by(|a, b| a.load.total_cmp(&b.load));
+        for from in cold.iter() {
+            let target = cold
+                .iter()
+                .filter(|r| r.rid != from.rid && r.cores == from.cores)
+                .filter(|r| r.groups.len() >= from.groups.len())
+                .filter(|r| r.load + from.load < self.low_watermark)
+                .max_by(|a, b| a.load.total_cmp(&b.load));
+            if let Some(target) = target {
+                let group = from
+                    .groups
+                    .iter()
+                    .min_by(|a, b| a.load.total_cmp(&b.load))?;
+                return Some(Migration {
+                    from,
+                    group,
+                    to: Some(target.rid),
+                });
+            }
+        }
+        None
+    }
+}
+
+impl EngineBalancer for LoadBalancer {
+    fn rebalance(&mut self, upgrader: &mut EngineUpgrader) -> anyhow::Result<()> {
+        if let Some(rx) = self.migrating.as_mut() {
+            match rx.try_recv() {
+                Ok(None) => return Ok(()),
+                Ok(Some(result)) => {
+                    self.migrating = None;
+                    // the load observed during the migration is not representative
+                    self.samples.clear();
+                    let rid = result?;
+                    tracing::info!("Scheduling group migrated to runtime (rid={:?})", rid);
+                }
+                Err(oneshot::Canceled) => {
+                    self.migrating = None;
+                    bail!("migration of scheduling group aborted");
+                }
+            }
+        }
+
+        if self.collecting.is_empty() {
+            if self.last_sample.elapsed() >= self.interval {
+                self.last_sample = Instant::now();
+                self.collecting = self.runtime_manager.collect_metrics();
+            }
+            return Ok(());
+        }
+
+        let collected = &mut self.collected;
+        self.collecting.retain_mut(|rx| match rx.try_recv() {
+            Ok(Some(metrics)) => {
+                collected.push(metrics);
+                false
+            }
+            Ok(None) => true,
+            Err(oneshot::Canceled) => false,
+        });
+        if !self.collecting.is_empty() {
+            return Ok(());
+        }
+
+        let metrics = std::mem::take(&mut self.collected);
+        let loads = self.update_loads(metrics);
+        if let Some(migration) = self.plan(&loads) {
+            log::info!(
+                "Moving scheduling group {:?} (pid={:?}, load={:.2}) from runtime {:?} (load={:.2}) to {}",
+                migration.group.gid,
+                migration.group.pid,
+                migration.group.load,
+                migration.from.rid,
+                migration.from.load,
+                migration
+                    .to
+                    .map_or_else(|| "a new runtime".to_string(), |rid| format!("runtime {:?}", rid)),
+            );
+            let fut = migrate_group(
+                Arc::clone(&self.runtime_manager),
+                migration.from.rid,
+                migration.group.gid,
+                migration.group.engines.clone(),
+                migration.to,
+                migration.from.cores.clone(),
+            );
+            self.migrating = Some(upgrader.spawn_for_client(migration.group.pid, fut)?);
+        }
+        Ok(())
+    }
+}
+
+/// Suspends the engines of a scheduling group on runtime `from`, and submits them to runtime `to`.
+async fn migrate_group(
+    rm: Arc<RuntimeManager>,
+    from: RuntimeId,
+    gid: GroupId,
+    mut engines: Vec<EngineId>,
+    to: Option<RuntimeId>,
+    cores: CoreMask,
+) -> anyhow::Result<RuntimeId> {
+    {
+        let guard = rm.inner.lock().unwrap();
+        let runtime = guard
+            .runtimes
+            .get(&from)
+            .ok_or_else(|| anyhow!("runtime {:?} not found", from))?;
+        for eid in engines.iter() {
+            runtime.request_suspend(*eid);
+        }
+    }
+
+    let mut containers = Vec::with_capacity(engines.len());
+    while !engines.is_empty() {
+        let guard = rm.inner.lock().unwrap();
+        let runtime = guard.runtimes.get(&from).unwrap();
+        engines.retain(|eid| match runtime.suspended.remove(eid) {
+            Some((_, SuspendResult::Engine(container))) => {
+                containers.push((*eid, container));
+                false
+            }
+            // the engine has shutdown
+            Some((_, SuspendResult::NotFound)) => false,
+            None => true,
+        });
+    }
+
+    if containers.is_empty() {
+        bail!("all engines in scheduling group {:?} have shutdown", gid);
+    }
+    Ok(rm.resubmit_group(gid, to, cores, containers))
 }
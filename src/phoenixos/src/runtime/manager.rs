@@ -11,8 +11,10 @@ use std::thread::{self, JoinHandle};
 
 use crc32fast::Hasher as Crc32Hasher;
 use dashmap::DashMap;
+use futures::channel::oneshot;
 use nix::unistd::Pid;
 
+use ipc::control::RuntimeMetrics;
 use phoenix_api::engine::{SchedulingHint, SchedulingMode};
 use phoenix_common::engine::EngineType;
 use phoenix_common::module::Service;
@@ -58,6 +60,8 @@ pub(crate) struct EngineInfo {
 pub(crate) struct ServiceSubscription {
     pub(crate) service: Service,
     pub(crate) addons: Vec<EngineType>,
+    /// The config string each addon was attached with, keyed by the addon engine name
+    pub(crate) addon_configs: HashMap<String, String>,
     pub(crate) graph: DataPathGraph,
 }
 
@@ -103,6 +107,9 @@ pub struct RuntimeManager {
     /// and the number of active engines in that group
     pub(crate) service_subscriptions: DashMap<(Pid, SubscriptionId), (ServiceSubscription, usize)>,
     pub(crate) global_resource_mgr: GlobalResourceManager,
+    /// Engines restored by a live upgrade that is in its health window,
+    /// and the error if the engine has failed
+    pub(crate) watched_engines: DashMap<EngineId, Option<String>>,
 }
 
 pub struct Inner {
@@ -198,6 +205,7 @@ impl RuntimeManager {
             engine_subscriptions: DashMap::new(),
             service_subscriptions: DashMap::new(),
             global_resource_mgr: GlobalResourceManager::new(),
+            watched_engines: DashMap::new(),
         }
     }
 
@@ -209,9 +217,10 @@ impl RuntimeManager {
         rid: RuntimeId,
         engines: Vec<EngineContainer>,
         mode: SchedulingMode,
-    ) {
+    ) -> Vec<(EngineId, EngineType)> {
         let inner = self.inner.lock().unwrap();
         let mut submission = Vec::with_capacity(engines.len());
+        let mut attached = Vec::with_capacity(engines.len());
         for engine in engines {
             let eid = EngineId(self.engine_counter.fetch_add(1, Ordering::Relaxed));
             let engine_type = engine.engine_type();
@@ -234,8 +243,10 @@ impl RuntimeManager {
             let prev = self.engine_subscriptions.insert(eid, engine_info);
             assert!(prev.is_none(), "eid={:?} is already used", eid);
             submission.push((eid, engine));
+            attached.push((eid, engine_type));
         }
         inner.runtimes[&rid].attach_engines_to_group(gid, submission);
+        attached
     }
 
     pub(crate) fn submit_group(
@@ -245,11 +256,13 @@ impl RuntimeManager {
         engines: Vec<EngineContainer>,
         mode: SchedulingMode,
         hint: SchedulingHint,
-    ) {
+    ) -> Vec<(EngineId, EngineType)> {
         let mut inner = self.inner.lock().unwrap();
         let mut submission = Vec::with_capacity(engines.len());
+        let mut submitted = Vec::with_capacity(engines.len());
         for engine in engines {
             let eid = EngineId(self.engine_counter.fetch_add(1, Ordering::Relaxed));
+            submitted.push((eid, engine.engine_type()));
             submission.push((eid, engine));
         }
         let gid = GroupId(
@@ -259,6 +272,46 @@ impl RuntimeManager {
         let group = SchedulingGroup::new(gid, submission);
 
         inner.schedule(pid, sid, group, self, mode, hint);
+        submitted
+    }
+
+    /// Submits a suspended scheduling group to runtime `target`, keeping the group and engine IDs.
+    /// A new runtime is started if `target` is `None` or can no longer take a compact group.
+    /// Returns the runtime the group is submitted to.
+    pub(crate) fn resubmit_group(
+        self: &Arc<Self>,
+        gid: GroupId,
+        target: Option<RuntimeId>,
+        cores: CoreMask,
+        engines: Vec<(EngineId, EngineContainer)>,
+    ) -> RuntimeId {
+        let mut inner = self.inner.lock().unwrap();
+        let target = target.filter(|rid| {
+            inner.runtimes.get(rid).map_or(false, |r| {
+                r.try_acquire(RuntimeMode::Compact, None, cores.clone(), None)
+            })
+        });
+        let rid = match target {
+            Some(rid) => rid,
+            None => inner.start_runtime(cores, RuntimeMode::Compact, None, Arc::clone(self)),
+        };
+        for (eid, _) in engines.iter() {
+            if let Some(mut info) = self.engine_subscriptions.get_mut(eid) {
+                tracing::info!(
+                    "Moving engine {:?} (pid={:?}, sid={:?}, gid={:?}) from runtime (rid={:?}) to runtime (rid={:?})",
+                    info.engine_type,
+                    info.pid,
+                    info.sid,
+                    gid,
+                    info.rid,
+                    rid,
+                );
+                info.rid = rid;
+            }
+        }
+        inner.runtimes[&rid].attach_engines_to_group(gid, engines);
+        inner.handles[&rid].thread().unpark();
+        rid
     }
 
     /// Create a new engine group for service subscription
@@ -276,6 +329,24 @@ impl RuntimeManager {
         sid
     }
 
+    /// Requests a metrics snapshot from every runtime.
+    pub(crate) fn collect_metrics(&self) -> Vec<oneshot::Receiver<RuntimeMetrics>> {
+        let inner = self.inner.lock().unwrap();
+        let mut receivers = Vec::with_capacity(inner.runtimes.len());
+        for (rid, runtime) in inner.runtimes.iter() {
+            receivers.push(runtime.request_metrics());
+            // an idle runtime may be parked
+            inner.handles[rid].thread().unpark();
+        }
+        receivers
+    }
+
+    pub(crate) fn register_engine_error(&self, engine_id: EngineId, error: String) {
+        if let Some(mut watched) = self.watched_engines.get_mut(&engine_id) {
+            *watched = Some(error);
+        }
+    }
+
     pub(crate) fn register_engine_shutdown(&self, engine_id: EngineId) {
         let info = self.engine_subscriptions.remove(&engine_id).unwrap().1;
         let removed =
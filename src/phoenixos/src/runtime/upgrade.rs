@@ -1,20 +1,24 @@
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::pin::Pin;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::bail;
 use dashmap::DashSet;
+use futures::channel::oneshot;
 use futures::executor::{ThreadPool, ThreadPoolBuilder};
 use nix::unistd::Pid;
 use semver::Version;
 
+use ipc::control::{PluginDescriptor, UpgradeScope};
 use phoenix_api::engine::{SchedulingHint, SchedulingMode};
 
 use phoenix_common::engine::datapath::{
     create_channel, ChannelDescriptor, ChannelFlavor, DataPathNode,
 };
-use phoenix_common::engine::{Engine, EngineType};
+use phoenix_common::engine::{Engine, EngineType, RestoreError};
 use phoenix_common::storage::{ResourceCollection, SharedStorage};
 
 use super::executor::SuspendResult;
@@ -24,6 +28,7 @@ use super::group::GroupId;
 use super::manager::{EngineId, EngineInfo, RuntimeId, RuntimeManager, SubscriptionId};
 use super::EngineContainer;
 
+use crate::config::UpgradeConfig;
 use crate::plugin::PluginName;
 use crate::plugin_mgr::PluginManager;
 use crate::{log, tracing};
@@ -33,8 +38,44 @@ pub(crate) struct EngineUpgrader {
     plugins: Arc<PluginManager>,
     executor: ThreadPool,
     upgrade_indicator: Arc<DashSet<Pid>>,
+    staged: Option<StagedUpgrade>,
+    health_window: Duration,
 }
 
+/// A module upgrade that has only been rolled out to some of the clients.
+/// Both the old and the new libraries stay loaded until it is rolled forward or back.
+struct StagedUpgrade {
+    plugins: Vec<PluginDescriptor>,
+    /// engine types of the new modules
+    engine_types: HashSet<EngineType>,
+    /// service subscriptions still running the old version
+    pending: HashSet<(Pid, SubscriptionId)>,
+    flush: bool,
+    detach_subscription: bool,
+    /// whether the engines upgraded by the last round are in the health window
+    watching: bool,
+    /// when the last round finished
+    watch_since: Option<Instant>,
+    /// subscriptions that failed to restore, kept until the upgrade is rolled back
+    failed: FailedSubscriptions,
+}
+
+/// A service subscription that failed to restore in the health window of an upgrade.
+/// Its engines are kept decomposed, so that it can be restored by the previous modules.
+struct FailedSubscription {
+    pid: Pid,
+    sid: SubscriptionId,
+    engines: HashMap<EngineType, EngineDumped>,
+    shared: SharedStorage,
+    /// engines that are not upgraded
+    containers: GroupContainers,
+}
+
+type FailedSubscriptions = Arc<Mutex<Vec<FailedSubscription>>>;
+
+/// Engine containers of a subscription in each scheduling group
+type GroupContainers = HashMap<GroupId, (Vec<EngineContainer>, SchedulingMode, RuntimeId)>;
+
 struct EngineDumped {
     local_states: ResourceCollection,
     node: DataPathNode,
@@ -58,8 +99,8 @@ async fn attach_addon<I>(
     rx_edges_replacement: I,
     group: HashSet<EngineType>,
     config_string: Option<String>,
-    indicator: Arc<DashSet<Pid>>,
-) where
+) -> anyhow::Result<Vec<(EngineId, EngineType)>>
+where
     I: IntoIterator<Item = ChannelDescriptor>,
 {
     let mut subscription_engines = rm
@@ -70,13 +111,11 @@ async fn attach_addon<I>(
         .collect::<Vec<_>>();
 
     if subscription_engines.is_empty() {
-        log::warn!(
+        bail!(
             "No engines exist for subscription (pid={:?}, sid={:?})",
             pid,
             sid,
         );
-        indicator.remove(&pid);
-        return;
     }
 
     let guard = rm.inner.lock().unwrap();
@@ -88,15 +127,13 @@ async fn attach_addon<I>(
 
     let (mut subscription, _) = rm.service_subscriptions.remove(&(pid, sid)).unwrap().1;
     if subscription.addons.contains(&addon) {
-        log::error!(
+        rm.global_resource_mgr.register_subscription_shutdown(pid);
+        bail!(
             "Addon engine {:?} already exists in service subscription (pid={:?}, sid={:?})",
             addon,
             pid,
             sid,
         );
-        rm.global_resource_mgr.register_subscription_shutdown(pid);
-        indicator.remove(&pid);
-        return;
     }
     let mut engine_containers = Vec::with_capacity(subscription_engines.len());
     while !subscription_engines.is_empty() {
@@ -171,18 +208,16 @@ async fn attach_addon<I>(
     ) {
         Ok(node) => node,
         Err(err) => {
-            log::error!(
+            // discard the service subscription
+            // do not resubmit the engines
+            rm.global_resource_mgr.register_subscription_shutdown(pid);
+            bail!(
                 "Fail to refactor data path channels in installing addon {:?} on subscription (pid={:?}, sid={:?}): {:?}",
                 addon,
                 pid,
                 sid,
                 err,
             );
-            // discard the service subscription
-            // do not resubmit the engines
-            rm.global_resource_mgr.register_subscription_shutdown(pid);
-            indicator.remove(&pid);
-            return;
         }
     };
 
@@ -190,29 +225,25 @@ async fn attach_addon<I>(
     let mut plugin = match plugins.engine_registry.get_mut(&addon) {
         Some(plugin) => match &plugin.value().0 {
             PluginName::Module(_) => {
-                log::error!("Engine type {:?} is not an addon", addon);
                 rm.global_resource_mgr.register_subscription_shutdown(pid);
-                return;
+                bail!("Engine type {:?} is not an addon", addon);
             }
             PluginName::Addon(addon_name) => plugins.addons.get_mut(addon_name).unwrap(),
         },
         None => {
-            log::error!("Addon for engine type {:?} not found", addon);
             rm.global_resource_mgr.register_subscription_shutdown(pid);
-            indicator.remove(&pid);
-            return;
+            bail!("Addon for engine type {:?} not found", addon);
         }
     };
 
     // update config if found necessary
-    if let Some(config) = config_string {
-        if let Err(err) = plugin.update_config(&config) {
-            log::error!(
+    if let Some(config) = config_string.as_ref() {
+        if let Err(err) = plugin.update_config(config) {
+            bail!(
                 "Failed to update config for addon: {:?}, err: {:?}, attach aborted",
                 addon,
                 err
             );
-            return;
         }
     }
 
@@ -220,16 +251,14 @@ async fn attach_addon<I>(
     let addon_engine = match plugin.value_mut().create_engine(addon, pid, node) {
         Ok(engine) => engine,
         Err(err) => {
-            log::error!(
+            rm.global_resource_mgr.register_subscription_shutdown(pid);
+            bail!(
                 "Failed to create addon engine {:?} for subscription (pid={:?}, sid={:?}), error: {:?}",
                 addon,
                 pid,
                 sid,
                 err,
             );
-            rm.global_resource_mgr.register_subscription_shutdown(pid);
-            indicator.remove(&pid);
-            return;
         }
     };
 
@@ -275,20 +304,23 @@ async fn attach_addon<I>(
     for engine in addon_group_engines {
         let engine_type = engine.engine_type();
         if (engine_type != addon) && !group.contains(&engine_type) {
-            log::error!(
+            rm.global_resource_mgr.register_subscription_shutdown(pid);
+            bail!(
                 "Scheduling group {:?} to attach addon {:?} to subscription (pid={:?}, sid={:?}) does not contain all engines in the group",
                 group,
                 addon,
                 pid,
                 sid,
             );
-            rm.global_resource_mgr.register_subscription_shutdown(pid);
-            indicator.remove(&pid);
-            return;
         }
     }
 
     subscription.addons.push(addon);
+    if let Some(config) = config_string {
+        subscription
+            .addon_configs
+            .insert(addon.0.to_string(), config);
+    }
 
     let engines_count = containers_resubmit
         .iter()
@@ -296,11 +328,12 @@ async fn attach_addon<I>(
         .sum();
     rm.service_subscriptions
         .insert((pid, sid), (subscription, engines_count));
+    let mut engines = Vec::with_capacity(engines_count);
     for (group_id, (containers, mode, rid)) in containers_resubmit {
         if let Some(rid) = rid {
-            rm.attach_to_group(pid, sid, group_id, rid, containers, mode);
+            engines.extend(rm.attach_to_group(pid, sid, group_id, rid, containers, mode));
         } else {
-            rm.submit_group(
+            engines.extend(rm.submit_group(
                 pid,
                 sid,
                 containers,
@@ -309,10 +342,10 @@ async fn attach_addon<I>(
                     mode,
                     numa_node_affinity: None,
                 },
-            );
+            ));
         }
     }
-    indicator.remove(&pid);
+    Ok(engines)
 }
 
 /// Detach an addon from a service subscription
@@ -323,8 +356,8 @@ async fn detach_addon<I>(
     addon: EngineType,
     tx_edges_replacement: I,
     rx_edges_replacement: I,
-    indicator: Arc<DashSet<Pid>>,
-) where
+) -> anyhow::Result<Vec<(EngineId, EngineType)>>
+where
     I: IntoIterator<Item = ChannelDescriptor>,
 {
     let mut subscription_engines = rm
@@ -335,13 +368,11 @@ async fn detach_addon<I>(
         .collect::<Vec<_>>();
 
     if subscription_engines.is_empty() {
-        log::warn!(
+        bail!(
             "No engines exist for subscription (pid={:?}, sid={:?})",
             pid,
             sid,
         );
-        indicator.remove(&pid);
-        return;
     }
 
     let guard = rm.inner.lock().unwrap();
@@ -355,16 +386,15 @@ async fn detach_addon<I>(
 
     if let Some(index) = subscription.addons.iter().position(|x| *x == addon) {
         subscription.addons.remove(index);
+        subscription.addon_configs.remove(addon.0);
     } else {
-        log::error!(
+        rm.global_resource_mgr.register_subscription_shutdown(pid);
+        bail!(
             "Addon engine {:?} not found in subscription (pid={:?}, gid={:?})",
             addon,
             pid,
             sid,
         );
-        rm.global_resource_mgr.register_subscription_shutdown(pid);
-        indicator.remove(&pid);
-        return;
     }
     let mut engine_containers = Vec::with_capacity(subscription_engines.len());
     while !subscription_engines.is_empty() {
@@ -438,7 +468,8 @@ async fn detach_addon<I>(
         rx_edges_replacement,
     );
     if let Err(err) = result {
-        log::error!(
+        rm.global_resource_mgr.register_subscription_shutdown(pid);
+        bail!(
             "Failed to refactor data path channels in uninstall addon {:?} \
             for subscription (pid={:?}, sid={:?}), error: {:?}",
             addon,
@@ -446,9 +477,6 @@ async fn detach_addon<I>(
             sid,
             err,
         );
-        rm.global_resource_mgr.register_subscription_shutdown(pid);
-        indicator.remove(&pid);
-        return;
     }
 
     let mut containers_resubmit = HashMap::new();
@@ -470,10 +498,265 @@ async fn detach_addon<I>(
         .sum();
     rm.service_subscriptions
         .insert((pid, sid), (subscription, engines_count));
+    let mut engines = Vec::with_capacity(engines_count);
     for (group_id, (containers, mode, rid)) in containers_resubmit {
-        rm.attach_to_group(pid, sid, group_id, rid, containers, mode);
+        engines.extend(rm.attach_to_group(pid, sid, group_id, rid, containers, mode));
+    }
+    Ok(engines)
+}
+
+/// Restores the decomposed engines of a service subscription with the current modules, and
+/// submits them to the runtimes together with the engines that are not upgraded.
+/// * watch: whether the upgrade is in a health window. The restored engines are then watched
+///     for errors. If an engine fails to restore and hands back its states, the subscription
+///     is not shut down, but returned with the states of its engines, so that it can be restored
+///     by the previous modules.
+#[allow(clippy::too_many_arguments)]
+fn restore_subscription(
+    rm: &RuntimeManager,
+    plugins: &PluginManager,
+    pid: Pid,
+    sid: SubscriptionId,
+    mut engine_group: HashMap<EngineType, EngineDumped>,
+    mut shared: SharedStorage,
+    mut containers_resubmit: GroupContainers,
+    global_resource: &mut ResourceCollection,
+    watch: bool,
+    outcome: &mut UpgradeOutcome,
+) -> Option<FailedSubscription> {
+    let mut subscription_guard = rm.service_subscriptions.get_mut(&(pid, sid)).unwrap();
+    let (subscription, _) = subscription_guard.value_mut();
+    let mut service = plugins
+        .service_registry
+        .get_mut(&subscription.service)
+        .unwrap();
+    // redirects all `ServiceType` in `subscription`
+    // to point to the new &'static str
+    subscription.service = *service.key();
+
+    let resubmit_count = engine_group.len()
+        + containers_resubmit
+            .values()
+            .map(|(containers, ..)| containers.len())
+            .sum::<usize>();
+    let mut resubmit = true;
+    // whether the engine that failed to restore handed back its states
+    let mut recoverable = false;
+    // engines restored by the current modules
+    let mut restored = HashSet::new();
+    for subscribed_engine_ty in service
+        .engines
+        .iter_mut()
+        .chain(subscription.addons.iter_mut())
+    {
+        if let Some(dumped) = engine_group.remove(subscribed_engine_ty) {
+            let plugin = plugins.engine_registry.get(subscribed_engine_ty).unwrap();
+
+            // redirects all `EngineType` in `subscription`
+            // to point to &'static str in the new shared library
+            // otherwise, these `EngineType` become invalid after old library is unloaded
+            let engine_ty_relocated = *plugin.key();
+            if let PluginName::Addon(_) = &plugin.value().0 {
+                // `EngineType` in `ServiceRegistry` should already been relocated
+                // but not for the addons in `ServiceSubscription`
+                *subscribed_engine_ty = engine_ty_relocated;
+            }
+            if let Some(tx_inputs) = subscription.graph.tx_inputs.remove(&engine_ty_relocated) {
+                for (peer, index) in tx_inputs.iter() {
+                    subscription.graph.tx_outputs.get_mut(peer).unwrap()[*index].0 =
+                        engine_ty_relocated;
+                }
+                subscription
+                    .graph
+                    .tx_inputs
+                    .insert(engine_ty_relocated, tx_inputs);
+            }
+            if let Some(tx_outputs) = subscription.graph.tx_outputs.remove(&engine_ty_relocated) {
+                for (peer, index) in tx_outputs.iter() {
+                    subscription.graph.tx_inputs.get_mut(peer).unwrap()[*index].0 =
+                        engine_ty_relocated;
+                }
+                subscription
+                    .graph
+                    .tx_outputs
+                    .insert(engine_ty_relocated, tx_outputs);
+            }
+            if let Some(rx_inputs) = subscription.graph.rx_inputs.remove(&engine_ty_relocated) {
+                for (peer, index) in rx_inputs.iter() {
+                    subscription.graph.rx_outputs.get_mut(peer).unwrap()[*index].0 =
+                        engine_ty_relocated;
+                }
+                subscription
+                    .graph
+                    .rx_inputs
+                    .insert(engine_ty_relocated, rx_inputs);
+            }
+            if let Some(rx_outputs) = subscription.graph.rx_outputs.remove(&engine_ty_relocated) {
+                for (peer, index) in rx_outputs.iter() {
+                    subscription.graph.rx_inputs.get_mut(peer).unwrap()[*index].0 =
+                        engine_ty_relocated;
+                }
+                subscription
+                    .graph
+                    .rx_outputs
+                    .insert(engine_ty_relocated, rx_outputs);
+            }
+
+            let EngineDumped {
+                local_states,
+                node,
+                prev_version,
+                rid,
+                gid,
+                mode,
+            } = dumped;
+            let (engine, new_version) = match &plugin.value().0 {
+                PluginName::Module(module_name) => {
+                    let mut module = plugins.modules.get_mut(module_name).unwrap();
+                    let new_version = module.version();
+                    // resotre engine
+                    let engine = module.restore_engine(
+                        *subscribed_engine_ty,
+                        local_states,
+                        &mut shared,
+                        global_resource,
+                        node,
+                        &plugins.modules,
+                        prev_version.clone(),
+                    );
+                    (engine, new_version)
+                }
+                PluginName::Addon(addon_name) => {
+                    let mut addon = plugins.addons.get_mut(addon_name).unwrap();
+                    let new_version = addon.version();
+                    let engine = addon.restore_engine(
+                        *subscribed_engine_ty,
+                        local_states,
+                        node,
+                        prev_version.clone(),
+                    );
+                    (engine, new_version)
+                }
+            };
+            match engine {
+                Ok(engine) => {
+                    let container =
+                        EngineContainer::new(engine, *subscribed_engine_ty, new_version);
+                    let entry = containers_resubmit
+                        .entry(gid)
+                        .or_insert((Vec::new(), mode, rid));
+                    entry.0.push(container);
+                    restored.insert(*subscribed_engine_ty);
+                    log::info!(
+                        "Engine (pid={:?}, sid={:?}, type={:?}) restored",
+                        pid,
+                        sid,
+                        subscribed_engine_ty,
+                    );
+                }
+                Err(err) => {
+                    log::error!(
+                        "Failed to restore engine (pid={:?}, sid={:?}, type={:?}), error: {:?}",
+                        pid,
+                        sid,
+                        subscribed_engine_ty,
+                        err,
+                    );
+                    let (PluginName::Module(name) | PluginName::Addon(name)) = &plugin.value().0;
+                    outcome.failures.push((
+                        name.clone(),
+                        format!(
+                            "failed to restore engine {:?} (pid={:?}, sid={:?}): {:?}",
+                            subscribed_engine_ty, pid, sid, err,
+                        ),
+                    ));
+                    if watch {
+                        if let Ok(err) = err.downcast::<RestoreError>() {
+                            let (local_states, node) = err.into_states();
+                            let dumped = EngineDumped {
+                                local_states,
+                                node,
+                                prev_version,
+                                rid,
+                                gid,
+                                mode,
+                            };
+                            engine_group.insert(*subscribed_engine_ty, dumped);
+                            recoverable = true;
+                        }
+                    }
+                    resubmit = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    drop(subscription_guard);
+    if resubmit {
+        for (group_id, (containers, mode, rid)) in containers_resubmit {
+            let engines = rm.attach_to_group(pid, sid, group_id, rid, containers, mode);
+            if watch {
+                for (eid, _) in engines.iter() {
+                    rm.watched_engines.insert(*eid, None);
+                }
+            }
+            outcome.add_engines(pid, sid, engines);
+        }
+        None
+    } else if recoverable {
+        // decompose the engines restored by the current modules again,
+        // the subscription is restored by the previous modules as a whole
+        for (gid, (containers, mode, rid)) in containers_resubmit.iter_mut() {
+            let mut suspended = Vec::with_capacity(containers.len());
+            for container in containers.drain(..) {
+                let engine_type = container.engine_type();
+                if !restored.contains(&engine_type) {
+                    suspended.push(container);
+                    continue;
+                }
+                let prev_version = container.version();
+                let mut engine = container.detach();
+                Pin::new(engine.as_mut()).set_els();
+                let (local_states, node) = engine.decompose(&mut shared, global_resource);
+                let dumped = EngineDumped {
+                    local_states,
+                    node,
+                    prev_version,
+                    rid: *rid,
+                    gid: *gid,
+                    mode: *mode,
+                };
+                engine_group.insert(engine_type, dumped);
+            }
+            *containers = suspended;
+        }
+        log::warn!(
+            "Service subscription (pid={:?}, sid={:?}) is suspended until the upgrade is rolled back",
+            pid,
+            sid,
+        );
+        Some(FailedSubscription {
+            pid,
+            sid,
+            engines: engine_group,
+            shared,
+            containers: containers_resubmit,
+        })
+    } else {
+        // error has occurred, rollback
+        // cancel all pending submission
+        let removed = rm
+            .service_subscriptions
+            .remove_if_mut(&(pid, sid), |_, (_, cnt)| {
+                *cnt -= resubmit_count;
+                *cnt == 0
+            });
+        if removed.is_some() {
+            rm.global_resource_mgr.register_subscription_shutdown(pid);
+        }
+        None
     }
-    indicator.remove(&pid);
 }
 
 /// Upgrade the engines of a client process
@@ -489,6 +772,11 @@ async fn detach_addon<I>(
 /// is shutdown, to be upgraded, or to be suspended,
 /// then the new engines will submit in a new subscription.
 /// Otherwise, they will submit to the original subscription
+/// * cleanup: whether to unload the old libraries after the last client is upgraded
+/// * watch: if set, the restored engines are watched for errors, and the subscriptions that
+///     fail to restore are kept in it, see `restore_subscription`
+/// * restore: subscriptions kept by a failed upgrade, to restore with the current modules
+#[allow(clippy::too_many_arguments)]
 async fn upgrade_client(
     rm: Arc<RuntimeManager>,
     plugins: Arc<PluginManager>,
@@ -497,7 +785,11 @@ async fn upgrade_client(
     mut to_suspend: Vec<(EngineId, EngineInfo)>,
     flush: bool,
     indicator: Arc<DashSet<Pid>>,
-) {
+    cleanup: bool,
+    watch: Option<FailedSubscriptions>,
+    restore: Vec<FailedSubscription>,
+) -> UpgradeOutcome {
+    let mut outcome = UpgradeOutcome::default();
     let guard = rm.inner.lock().unwrap();
     for (engine_id, info) in to_upgrade.iter().chain(to_suspend.iter()) {
         let runtime = guard.runtimes.get(&info.rid).unwrap();
@@ -557,7 +849,7 @@ async fn upgrade_client(
         .keys()
         .chain(containers_suspended.keys())
         .copied()
-        .collect::<Vec<_>>();
+        .collect::<HashSet<_>>();
 
     // Each engine to upgrade's local state
     let mut local_states = HashMap::new();
@@ -641,199 +933,124 @@ async fn upgrade_client(
     }
 
     for sid in subscribed {
-        let mut subscription_guard = rm.service_subscriptions.get_mut(&(pid, sid)).unwrap();
-        let (subscription, _) = subscription_guard.value_mut();
-        let mut service = plugins
-            .service_registry
-            .get_mut(&subscription.service)
-            .unwrap();
-        // redirects all `ServiceType` in `subscription`
-        // to point to the new &'static str
-        subscription.service = *service.key();
-
-        let mut resubmit = true;
+        let engine_group = local_states.remove(&sid).unwrap_or_default();
+        let shared = shared_storage
+            .remove(&sid)
+            .unwrap_or_else(SharedStorage::new);
         let mut containers_resubmit = HashMap::new();
-        let mut resubmit_count = 0;
         if let Some(containers) = containers_suspended.remove(&sid) {
             for (container, info) in containers {
-                let gid = info.gid;
-                let rid = info.rid;
-                let entry = containers_resubmit.entry(gid).or_insert((
+                let entry = containers_resubmit.entry(info.gid).or_insert((
                     Vec::new(),
                     info.scheduling_mode,
-                    rid,
+                    info.rid,
                 ));
                 entry.0.push(container);
-                resubmit_count += 1;
             }
         }
-
-        if let Some(mut engine_group) = local_states.remove(&sid) {
-            resubmit_count += engine_group.len();
-            let mut shared = shared_storage.remove(&sid).unwrap();
-            for subscribed_engine_ty in service
-                .engines
-                .iter_mut()
-                .chain(subscription.addons.iter_mut())
-            {
-                if let Some(dumped) = engine_group.remove(subscribed_engine_ty) {
-                    let plugin = plugins.engine_registry.get(subscribed_engine_ty).unwrap();
-
-                    // redirects all `EngineType` in `subscription`
-                    // to point to &'static str in the new shared library
-                    // otherwise, these `EngineType` become invalid after old library is unloaded
-                    let engine_ty_relocated = *plugin.key();
-                    if let PluginName::Addon(_) = &plugin.value().0 {
-                        // `EngineType` in `ServiceRegistry` should already been relocated
-                        // but not for the addons in `ServiceSubscription`
-                        *subscribed_engine_ty = engine_ty_relocated;
-                    }
-                    if let Some(tx_inputs) =
-                        subscription.graph.tx_inputs.remove(&engine_ty_relocated)
-                    {
-                        for (peer, index) in tx_inputs.iter() {
-                            subscription.graph.tx_outputs.get_mut(peer).unwrap()[*index].0 =
-                                engine_ty_relocated;
-                        }
-                        subscription
-                            .graph
-                            .tx_inputs
-                            .insert(engine_ty_relocated, tx_inputs);
-                    }
-                    if let Some(tx_outputs) =
-                        subscription.graph.tx_outputs.remove(&engine_ty_relocated)
-                    {
-                        for (peer, index) in tx_outputs.iter() {
-                            subscription.graph.tx_inputs.get_mut(peer).unwrap()[*index].0 =
-                                engine_ty_relocated;
-                        }
-                        subscription
-                            .graph
-                            .tx_outputs
-                            .insert(engine_ty_relocated, tx_outputs);
-                    }
-                    if let Some(rx_inputs) =
-                        subscription.graph.rx_inputs.remove(&engine_ty_relocated)
-                    {
-                        for (peer, index) in rx_inputs.iter() {
-                            subscription.graph.rx_outputs.get_mut(peer).unwrap()[*index].0 =
-                                engine_ty_relocated;
-                        }
-                        subscription
-                            .graph
-                            .rx_inputs
-                            .insert(engine_ty_relocated, rx_inputs);
-                    }
-                    if let Some(rx_outputs) =
-                        subscription.graph.rx_outputs.remove(&engine_ty_relocated)
-                    {
-                        for (peer, index) in rx_outputs.iter() {
-                            subscription.graph.rx_inputs.get_mut(peer).unwrap()[*index].0 =
-                                engine_ty_relocated;
-                        }
-                        subscription
-                            .graph
-                            .rx_outputs
-                            .insert(engine_ty_relocated, rx_outputs);
-                    }
-
-                    let (engine, new_version) = match &plugin.value().0 {
-                        PluginName::Module(module_name) => {
-                            let mut module = plugins.modules.get_mut(module_name).unwrap();
-                            let new_version = module.version();
-                            // resotre engine
-                            let engine = module.restore_engine(
-                                *subscribed_engine_ty,
-                                dumped.local_states,
-                                &mut shared,
-                                global_resource.value_mut(),
-                                dumped.node,
-                                &plugins.modules,
-                                dumped.prev_version,
-                            );
-                            (engine, new_version)
-                        }
-                        PluginName::Addon(addon_name) => {
-                            let mut addon = plugins.addons.get_mut(addon_name).unwrap();
-                            let new_version = addon.version();
-                            let engine = addon.restore_engine(
-                                *subscribed_engine_ty,
-                                dumped.local_states,
-                                dumped.node,
-                                dumped.prev_version,
-                            );
-                            (engine, new_version)
-                        }
-                    };
-                    match engine {
-                        Ok(engine) => {
-                            let container =
-                                EngineContainer::new(engine, *subscribed_engine_ty, new_version);
-                            let gid = dumped.gid;
-                            let rid = dumped.rid;
-                            let entry = containers_resubmit.entry(gid).or_insert((
-                                Vec::new(),
-                                dumped.mode,
-                                rid,
-                            ));
-                            entry.0.push(container);
-                            log::info!(
-                                "Engine (pid={:?}, sid={:?}, type={:?}) restored",
-                                pid,
-                                sid,
-                                subscribed_engine_ty,
-                            );
-                        }
-                        Err(err) => {
-                            log::error!(
-                                "Failed to restore engine (pid={:?}, sid={:?}, type={:?}), error: {:?}",
-                                pid,
-                                sid,
-                                subscribed_engine_ty,
-                                err,
-                            );
-                            resubmit = false;
-                            break;
-                        }
-                    }
-                }
-            }
+        let failed = restore_subscription(
+            &rm,
+            &plugins,
+            pid,
+            sid,
+            engine_group,
+            shared,
+            containers_resubmit,
+            global_resource.value_mut(),
+            watch.is_some(),
+            &mut outcome,
+        );
+        if let (Some(failed), Some(kept)) = (failed, &watch) {
+            kept.lock().unwrap().push(failed);
         }
+    }
 
-        drop(subscription_guard);
-        if resubmit {
-            for (group_id, (containers, mode, rid)) in containers_resubmit {
-                rm.attach_to_group(pid, sid, group_id, rid, containers, mode);
-            }
-        } else {
-            // error has occurred, rollback
-            // cancel all pending submission
-            let removed = rm
-                .service_subscriptions
-                .remove_if_mut(&(pid, sid), |_, (_, cnt)| {
-                    *cnt -= resubmit_count;
-                    *cnt == 0
-                });
-            if removed.is_some() {
-                rm.global_resource_mgr.register_subscription_shutdown(pid);
-            }
-        }
+    // subscriptions kept by an upgrade that failed to restore them
+    for failed in restore {
+        restore_subscription(
+            &rm,
+            &plugins,
+            pid,
+            failed.sid,
+            failed.engines,
+            failed.shared,
+            failed.containers,
+            global_resource.value_mut(),
+            false,
+            &mut outcome,
+        );
     }
 
     indicator.remove(&pid);
-    if indicator.is_empty() {
+    if cleanup && indicator.is_empty() {
         plugins.upgrade_cleanup();
     }
+    outcome
+}
+
+/// What an upgrade or an addon operation ended up with for a client process.
+#[derive(Debug, Default)]
+pub(crate) struct UpgradeOutcome {
+    /// Engines submitted to the runtimes, in (pid, sid, engine_id, engine_type).
+    pub(crate) engines: Vec<(Pid, SubscriptionId, EngineId, String)>,
+    /// Names of the plugins that failed, and the reasons.
+    pub(crate) failures: Vec<(String, String)>,
+}
+
+impl UpgradeOutcome {
+    fn add_engines(&mut self, pid: Pid, sid: SubscriptionId, engines: Vec<(EngineId, EngineType)>) {
+        self.engines.extend(
+            engines
+                .into_iter()
+                .map(|(eid, ty)| (pid, sid, eid, ty.0.to_string())),
+        );
+    }
+
+    fn from_addon_result(
+        pid: Pid,
+        sid: SubscriptionId,
+        addon: String,
+        result: anyhow::Result<Vec<(EngineId, EngineType)>>,
+    ) -> Self {
+        let mut outcome = UpgradeOutcome::default();
+        match result {
+            Ok(engines) => outcome.add_engines(pid, sid, engines),
+            Err(err) => {
+                log::error!("{:#}", err);
+                outcome.failures.push((addon, format!("{:#}", err)));
+            }
+        }
+        outcome
+    }
+}
+
+/// Spawns `fut` on the executor. Returns a receiver for its outcome.
+fn spawn_with_outcome<F>(executor: &ThreadPool, fut: F) -> oneshot::Receiver<UpgradeOutcome>
+where
+    F: Future<Output = UpgradeOutcome> + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    executor.spawn_ok(async move {
+        // the receiver may have been dropped, nobody is waiting for the outcome then
+        let _ = tx.send(fut.await);
+    });
+    rx
 }
 
 impl EngineUpgrader {
-    pub(crate) fn new(rm: Arc<RuntimeManager>, plugins: Arc<PluginManager>) -> Self {
+    pub(crate) fn new(
+        rm: Arc<RuntimeManager>,
+        plugins: Arc<PluginManager>,
+        config: &UpgradeConfig,
+    ) -> Self {
         let pool = ThreadPoolBuilder::new().pool_size(1).create().unwrap();
         EngineUpgrader {
             runtime_manager: rm,
             plugins,
             executor: pool,
             upgrade_indicator: Arc::new(DashSet::new()),
+            staged: None,
+            health_window: Duration::from_millis(config.health_window_ms),
         }
     }
 
@@ -849,7 +1066,7 @@ impl EngineUpgrader {
         rx_edges_replacement: I,
         group: HashSet<EngineType>,
         config_string: Option<String>,
-    ) -> anyhow::Result<()>
+    ) -> anyhow::Result<oneshot::Receiver<UpgradeOutcome>>
     where
         I: IntoIterator<Item = ChannelDescriptor> + Send + 'static,
     {
@@ -860,21 +1077,27 @@ impl EngineUpgrader {
             )
         }
         self.upgrade_indicator.insert(pid);
-        let fut = attach_addon(
-            self.runtime_manager.clone(),
-            self.plugins.clone(),
-            pid,
-            gid,
-            addon,
-            mode,
-            tx_edges_replacement,
-            rx_edges_replacement,
-            group,
-            config_string,
-            Arc::clone(&self.upgrade_indicator),
-        );
-        self.executor.spawn_ok(fut);
-        Ok(())
+        let rm = self.runtime_manager.clone();
+        let plugins = self.plugins.clone();
+        let indicator = Arc::clone(&self.upgrade_indicator);
+        let fut = async move {
+            let result = attach_addon(
+                rm,
+                plugins,
+                pid,
+                gid,
+                addon,
+                mode,
+                tx_edges_replacement,
+                rx_edges_replacement,
+                group,
+                config_string,
+            )
+            .await;
+            indicator.remove(&pid);
+            UpgradeOutcome::from_addon_result(pid, gid, addon.0.to_string(), result)
+        };
+        Ok(spawn_with_outcome(&self.executor, fut))
     }
 
     /// Detach an addon from a service subscription
@@ -885,7 +1108,7 @@ impl EngineUpgrader {
         addon: EngineType,
         tx_edges_replacement: I,
         rx_edges_replacement: I,
-    ) -> anyhow::Result<()>
+    ) -> anyhow::Result<oneshot::Receiver<UpgradeOutcome>>
     where
         I: IntoIterator<Item = ChannelDescriptor> + Send + 'static,
     {
@@ -896,30 +1119,220 @@ impl EngineUpgrader {
             )
         }
         self.upgrade_indicator.insert(pid);
-        let fut = detach_addon(
-            self.runtime_manager.clone(),
-            pid,
-            gid,
-            addon,
-            tx_edges_replacement,
-            rx_edges_replacement,
-            Arc::clone(&self.upgrade_indicator),
-        );
-        self.executor.spawn_ok(fut);
-        Ok(())
+        let rm = self.runtime_manager.clone();
+        let indicator = Arc::clone(&self.upgrade_indicator);
+        let fut = async move {
+            let result = detach_addon(
+                rm,
+                pid,
+                gid,
+                addon,
+                tx_edges_replacement,
+                rx_edges_replacement,
+            )
+            .await;
+            indicator.remove(&pid);
+            UpgradeOutcome::from_addon_result(pid, gid, addon.0.to_string(), result)
+        };
+        Ok(spawn_with_outcome(&self.executor, fut))
     }
     /// Live upgrade existing clients
     /// Arguments:
-    /// * engine_types: engines that need to be upgraded
+    /// * plugins: modules to upgrade
+    /// * scope: which clients to upgrade, the others keep running the old version
     /// * flush: whether to flush the queues for the engines to be upgraded
     /// * detach_subscription: whether to suspend/detach all engines in each service subscription,
     ///     even the engine does not need upgrade, this is generally required to flush queues
+    ///
+    /// If only some of the clients are upgraded, the upgrade is staged. Upgrading the same
+    /// modules again rolls it forward to the next clients, without loading them again.
+    ///
+    /// If a health window is configured, the old modules stay loaded until the window passes,
+    /// see `check_health`.
+    ///
+    /// Returns a receiver for the outcome of each client process being upgraded.
     pub(crate) fn upgrade(
         &mut self,
-        engine_types: HashSet<EngineType>,
+        plugins: &[PluginDescriptor],
+        scope: &UpgradeScope,
+        flush: bool,
+        detach_subscription: bool,
+    ) -> anyhow::Result<Vec<oneshot::Receiver<UpgradeOutcome>>> {
+        self.check_upgrade(flush, detach_subscription)?;
+
+        let mut staged = match self.staged.take() {
+            Some(staged) if staged.plugins[..] == *plugins => staged,
+            Some(staged) => {
+                let names = staged.plugins.iter().map(|p| &p.name).collect::<Vec<_>>();
+                self.staged = Some(staged);
+                bail!(
+                    "upgrade of {:?} is staged or in its health window, roll it forward or back first",
+                    names
+                )
+            }
+            None => {
+                let engine_types = self.plugins.load_or_upgrade_modules(plugins)?;
+                // every existing client runs the old version
+                let pending = self
+                    .runtime_manager
+                    .engine_subscriptions
+                    .iter()
+                    .filter(|e| engine_types.contains(&e.engine_type))
+                    .map(|e| (e.pid, e.sid))
+                    .collect();
+                StagedUpgrade {
+                    plugins: plugins.to_vec(),
+                    engine_types,
+                    pending,
+                    flush,
+                    detach_subscription,
+                    watching: false,
+                    watch_since: None,
+                    failed: Arc::new(Mutex::new(Vec::new())),
+                }
+            }
+        };
+
+        let subscriptions = staged
+            .pending
+            .iter()
+            .copied()
+            .filter(|(pid, sid)| scope.contains(pid.as_raw(), sid.0))
+            .collect::<HashSet<_>>();
+        staged.pending.retain(|s| {
+            !subscriptions.contains(s) && self.runtime_manager.service_subscriptions.contains_key(s)
+        });
+
+        let watch = (!self.health_window.is_zero() && !subscriptions.is_empty())
+            .then(|| Arc::clone(&staged.failed));
+        if watch.is_some() {
+            staged.watching = true;
+            staged.watch_since = None;
+        }
+
+        let engine_types = staged.engine_types.clone();
+        if !staged.pending.is_empty() || staged.watching {
+            self.staged = Some(staged);
+        }
+        let cleanup = self.staged.is_none();
+        Ok(self.upgrade_subscriptions(
+            &engine_types,
+            &subscriptions,
+            flush,
+            detach_subscription,
+            cleanup,
+            watch,
+            Vec::new(),
+        ))
+    }
+
+    /// Roll back a staged upgrade. All the clients that run the new version,
+    /// including those that connected after the upgrade, are restored to the old version,
+    /// and the new modules are unloaded. The subscriptions that failed to restore in the
+    /// health window are restored by the old modules.
+    pub(crate) fn rollback(
+        &mut self,
+        plugins: &[PluginDescriptor],
         flush: bool,
         detach_subscription: bool,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<Vec<oneshot::Receiver<UpgradeOutcome>>> {
+        self.check_upgrade(flush, detach_subscription)?;
+
+        let staged = match &self.staged {
+            Some(staged) if plugins.is_empty() || staged.plugins[..] == *plugins => staged,
+            Some(_) => bail!("the plugins to roll back do not match the staged upgrade"),
+            None => bail!("there is no staged upgrade to roll back"),
+        };
+
+        let engine_types = self.plugins.rollback_modules(&staged.plugins)?;
+        let subscriptions = self
+            .runtime_manager
+            .engine_subscriptions
+            .iter()
+            .filter(|e| {
+                staged.engine_types.contains(&e.engine_type)
+                    && !staged.pending.contains(&(e.pid, e.sid))
+            })
+            .map(|e| (e.pid, e.sid))
+            .collect();
+        let failed = std::mem::take(&mut *staged.failed.lock().unwrap());
+        self.staged = None;
+        self.runtime_manager.watched_engines.clear();
+
+        Ok(self.upgrade_subscriptions(
+            &engine_types,
+            &subscriptions,
+            flush,
+            detach_subscription,
+            true,
+            None,
+            failed,
+        ))
+    }
+
+    /// Checks the engines upgraded by the last round of a module upgrade, after all clients
+    /// of the round have been upgraded. If any of them failed to restore or exited with
+    /// an error within the health window, the upgrade is rolled back. Otherwise, the old
+    /// modules are unloaded once the window passes, unless the upgrade is still staged.
+    ///
+    /// The service subscriptions that failed to restore stay suspended until the rollback,
+    /// which restores them from their decomposed states by the old modules.
+    ///
+    /// Returns the plugins that have been rolled back.
+    pub(crate) fn check_health(&mut self) -> Option<Vec<PluginDescriptor>> {
+        if !self.upgrade_indicator.is_empty() {
+            return None;
+        }
+        let staged = self.staged.as_mut().filter(|staged| staged.watching)?;
+        let watch_since = *staged.watch_since.get_or_insert_with(Instant::now);
+
+        let failure = if !staged.failed.lock().unwrap().is_empty() {
+            Some("engines failed to restore".to_string())
+        } else {
+            self.runtime_manager.watched_engines.iter().find_map(|e| {
+                e.value()
+                    .as_ref()
+                    .map(|err| format!("engine {:?} failed: {}", e.key(), err))
+            })
+        };
+
+        if let Some(failure) = failure {
+            // do not retry if the rollback fails
+            staged.watching = false;
+            let plugins = staged.plugins.clone();
+            let (flush, detach_subscription) = (staged.flush, staged.detach_subscription);
+            let names = plugins.iter().map(|p| &p.name).collect::<Vec<_>>();
+            log::error!(
+                "Upgrade of {:?} is unhealthy, {}, rolling back",
+                names,
+                failure
+            );
+            match self.rollback(&plugins, flush, detach_subscription) {
+                Ok(_) => Some(plugins),
+                Err(err) => {
+                    log::error!("Failed to roll back upgrade of {:?}: {:#}", names, err);
+                    None
+                }
+            }
+        } else if watch_since.elapsed() >= self.health_window {
+            staged.watching = false;
+            self.runtime_manager.watched_engines.clear();
+            if staged.pending.is_empty() {
+                self.staged = None;
+                self.plugins.upgrade_cleanup();
+            }
+            None
+        } else {
+            None
+        }
+    }
+
+    /// Returns the plugins of the staged upgrade, if any.
+    pub(crate) fn staged_plugins(&self) -> Option<&[PluginDescriptor]> {
+        self.staged.as_ref().map(|staged| &staged.plugins[..])
+    }
+
+    fn check_upgrade(&self, flush: bool, detach_subscription: bool) -> anyhow::Result<()> {
         if !self.upgrade_indicator.is_empty() {
             bail!("there is already an ongoing upgrade")
         }
@@ -927,25 +1340,40 @@ impl EngineUpgrader {
         if flush && !detach_subscription {
             bail!("Flush queues but not detaching all engines within each group during upgrade");
         }
+        Ok(())
+    }
 
+    /// Upgrade engines of `engine_types` in `subscriptions`.
+    /// * cleanup: whether to unload the old libraries once done
+    /// * watch, restore: see `upgrade_client`
+    fn upgrade_subscriptions(
+        &mut self,
+        engine_types: &HashSet<EngineType>,
+        subscriptions: &HashSet<(Pid, SubscriptionId)>,
+        flush: bool,
+        detach_subscription: bool,
+        cleanup: bool,
+        watch: Option<FailedSubscriptions>,
+        restore: Vec<FailedSubscription>,
+    ) -> Vec<oneshot::Receiver<UpgradeOutcome>> {
         // engines that need to be upgraded
         let mut engines_to_upgrade = HashMap::new();
         // other engines that are in the same group
         // as the engines to be upgraded
         let mut engines_to_detach = HashMap::new();
 
-        let mut subscriptions_to_upgrade = HashSet::new();
         for engine in self
             .runtime_manager
             .engine_subscriptions
             .iter()
-            .filter(|e| engine_types.contains(&e.engine_type))
+            .filter(|e| {
+                engine_types.contains(&e.engine_type) && subscriptions.contains(&(e.pid, e.sid))
+            })
         {
             let client = engines_to_upgrade
                 .entry(engine.pid)
                 .or_insert_with(Vec::new);
             client.push((*engine.key(), *engine.value()));
-            subscriptions_to_upgrade.insert((engine.pid, engine.sid));
         }
 
         if detach_subscription {
@@ -955,7 +1383,7 @@ impl EngineUpgrader {
                 .iter()
                 .filter(|e| {
                     !engine_types.contains(&e.engine_type)
-                        && subscriptions_to_upgrade.contains(&(e.pid, e.sid))
+                        && subscriptions.contains(&(e.pid, e.sid))
                 })
             {
                 let client = engines_to_detach.entry(engine.pid).or_insert_with(Vec::new);
@@ -963,16 +1391,34 @@ impl EngineUpgrader {
             }
         }
 
+        // the kept subscriptions are restored along with the other engines of their clients
+        let mut engines_to_restore = HashMap::new();
+        for failed in restore {
+            engines_to_upgrade
+                .entry(failed.pid)
+                .or_insert_with(Vec::new);
+            let client = engines_to_restore
+                .entry(failed.pid)
+                .or_insert_with(Vec::new);
+            client.push(failed);
+        }
+
+        if engines_to_upgrade.is_empty() && cleanup {
+            self.plugins.upgrade_cleanup();
+        }
+
         for (pid, _) in engines_to_upgrade.iter() {
             self.upgrade_indicator.insert(*pid);
         }
 
+        let mut outcomes = Vec::with_capacity(engines_to_upgrade.len());
         for (pid, to_upgrade) in engines_to_upgrade {
             let to_detach = if let Some(engines) = engines_to_detach.remove(&pid) {
                 engines
             } else {
                 Vec::new()
             };
+            let to_restore = engines_to_restore.remove(&pid).unwrap_or_default();
             let rm = Arc::clone(&self.runtime_manager);
             let plugins = Arc::clone(&self.plugins);
             let fut = upgrade_client(
@@ -983,11 +1429,48 @@ impl EngineUpgrader {
                 to_detach,
                 flush,
                 Arc::clone(&self.upgrade_indicator),
+                cleanup,
+                watch.clone(),
+                to_restore,
             );
-            self.executor.spawn_ok(fut);
+            outcomes.push(spawn_with_outcome(&self.executor, fut));
         }
 
-        Ok(())
+        outcomes
+    }
+
+    /// Runs `fut` on the upgrade executor as an operation on client `pid`, so that it does not
+    /// interleave with upgrades or addon requests of the same client.
+    pub(crate) fn spawn_for_client<F>(
+        &mut self,
+        pid: Pid,
+        fut: F,
+    ) -> anyhow::Result<oneshot::Receiver<F::Output>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        if self.upgrade_indicator.contains(&pid) {
+            bail!(
+                "there is already an ongoing upgrade for client pid={:?}",
+                pid
+            )
+        }
+        self.upgrade_indicator.insert(pid);
+        let indicator = Arc::clone(&self.upgrade_indicator);
+        let (tx, rx) = oneshot::channel();
+        self.executor.spawn_ok(async move {
+            let output = fut.await;
+            indicator.remove(&pid);
+            // the receiver may have been dropped, nobody is waiting for the output then
+            let _ = tx.send(output);
+        });
+        Ok(rx)
+    }
+
+    /// Returns true if there is no ongoing upgrade or addon operation.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.upgrade_indicator.is_empty()
     }
 
     /// Check whether engines for an application process is still upgrading,
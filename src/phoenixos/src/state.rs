@@ -0,0 +1,188 @@
+//! Persisting the control plane state across restarts of phoenixos.
+//!
+//! The state covers the loaded modules and addons, and for each service subscription, the
+//! service, its config string and the addons attached to it. The engines themselves cannot
+//! outlive the process. When a client process reconnects to a restarted phoenixos and subscribes
+//! to the same service, the addons it used to have are attached again.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use nix::sys::signal;
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use ipc::control::{AddonRequest, PluginDescriptor};
+use phoenix_api::engine::SchedulingMode;
+
+use crate::runtime::manager::SubscriptionId;
+use crate::runtime::RuntimeManager;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct DaemonState {
+    pub(crate) modules: Vec<PluginDescriptor>,
+    pub(crate) addons: Vec<PluginDescriptor>,
+    pub(crate) subscriptions: Vec<SubscriptionState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SubscriptionState {
+    pub(crate) pid: libc::pid_t,
+    pub(crate) service: String,
+    pub(crate) config_string: Option<String>,
+    /// Addons attached to the subscription, in the order they were attached
+    pub(crate) addons: Vec<AddonAttachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AddonAttachment {
+    pub(crate) mode: SchedulingMode,
+    pub(crate) request: AddonRequest,
+}
+
+impl DaemonState {
+    /// Reads the state saved at `path`, returns `None` if there is none.
+    pub(crate) fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Writes the state to `path`. The file is replaced atomically, so a crash while saving
+    /// leaves the previous state intact.
+    pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Returns the descriptors with only the last one for each plugin name,
+/// which is the version currently loaded.
+pub(crate) fn latest_descriptors(descriptors: &[PluginDescriptor]) -> Vec<PluginDescriptor> {
+    let mut seen = HashSet::new();
+    let mut latest = descriptors
+        .iter()
+        .rev()
+        .filter(|d| seen.insert(d.name.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    latest.reverse();
+    latest
+}
+
+/// Keeps track of the service subscriptions and their addons, and saves the state.
+pub(crate) struct StateKeeper {
+    path: PathBuf,
+    interval: Option<Duration>,
+    last_save: Instant,
+    subscriptions: HashMap<(Pid, SubscriptionId), SubscriptionState>,
+    /// Subscriptions in the restored state whose clients have not reconnected yet
+    restored: Vec<SubscriptionState>,
+}
+
+impl StateKeeper {
+    pub(crate) fn new(path: PathBuf, interval_ms: u64, restored: Vec<SubscriptionState>) -> Self {
+        StateKeeper {
+            path,
+            interval: (interval_ms > 0).then(|| Duration::from_millis(interval_ms)),
+            last_save: Instant::now(),
+            subscriptions: HashMap::new(),
+            restored,
+        }
+    }
+
+    /// Records a new service subscription. If the client had subscribed to the same service
+    /// before the restart, returns the addons that were attached to that subscription.
+    pub(crate) fn add_subscription(
+        &mut self,
+        pid: Pid,
+        sid: SubscriptionId,
+        service: &str,
+        config_string: Option<String>,
+    ) -> Vec<AddonAttachment> {
+        let addons = match self
+            .restored
+            .iter()
+            .position(|s| s.pid == pid.as_raw() && s.service == service)
+        {
+            Some(index) => self.restored.remove(index).addons,
+            None => Vec::new(),
+        };
+        let subscription = SubscriptionState {
+            pid: pid.as_raw(),
+            service: service.to_string(),
+            config_string,
+            addons: Vec::new(),
+        };
+        self.subscriptions.insert((pid, sid), subscription);
+        addons
+    }
+
+    pub(crate) fn add_addon(&mut self, pid: Pid, sid: SubscriptionId, attachment: AddonAttachment) {
+        if let Some(subscription) = self.subscriptions.get_mut(&(pid, sid)) {
+            let addon = &attachment.request.addon_engine;
+            subscription
+                .addons
+                .retain(|a| a.request.addon_engine != *addon);
+            subscription.addons.push(attachment);
+        }
+    }
+
+    pub(crate) fn remove_addon(&mut self, pid: Pid, sid: SubscriptionId, addon: &str) {
+        if let Some(subscription) = self.subscriptions.get_mut(&(pid, sid)) {
+            subscription
+                .addons
+                .retain(|a| a.request.addon_engine != addon);
+        }
+    }
+
+    /// Returns true if it is time for a periodic save.
+    pub(crate) fn save_due(&self) -> bool {
+        self.interval
+            .map_or(false, |interval| self.last_save.elapsed() >= interval)
+    }
+
+    /// Saves the plugins, the live subscriptions, and the restored subscriptions whose
+    /// clients are still alive.
+    pub(crate) fn save(
+        &mut self,
+        modules: &[PluginDescriptor],
+        addons: &[PluginDescriptor],
+        rm: &RuntimeManager,
+    ) -> anyhow::Result<()> {
+        self.last_save = Instant::now();
+        self.subscriptions
+            .retain(|key, _| rm.service_subscriptions.contains_key(key));
+        self.restored
+            .retain(|s| signal::kill(Pid::from_raw(s.pid), None).is_ok());
+
+        let mut subscriptions = Vec::with_capacity(self.subscriptions.len() + self.restored.len());
+        for (key, subscription) in self.subscriptions.iter() {
+            let Some(live) = rm.service_subscriptions.get(key) else {
+                continue;
+            };
+            // skip the addons that failed to attach
+            let attached = live.0.addons.iter().map(|a| a.0).collect::<HashSet<_>>();
+            let mut subscription = subscription.clone();
+            subscription
+                .addons
+                .retain(|a| attached.contains(a.request.addon_engine.as_str()));
+            subscriptions.push(subscription);
+        }
+        subscriptions.extend(self.restored.iter().cloned());
+
+        let state = DaemonState {
+            modules: latest_descriptors(modules),
+            addons: latest_descriptors(addons),
+            subscriptions,
+        };
+        state.save(&self.path)
+    }
+}
@@ -50,7 +50,7 @@ impl<T> From<SendError<T>> for ControlPathError {
 use crate::config::SallocConfig;
 use crate::module::SallocModule;
 
-#[no_mangle]
+#[cfg_attr(not(feature = "embedded"), no_mangle)]
 pub fn init_module(config_string: Option<&str>) -> InitFnResult<Box<dyn PhoenixModule>> {
     let config = SallocConfig::new(config_string)?;
     let module = SallocModule::new(config);
@@ -659,11 +659,14 @@ impl TransportEngine {
                 Ok(CompletionKind::Listen)
             }
             Command::GetRequest(listener_handle) => {
-                let ret_cmid = self.ops.get_request(*listener_handle).await?;
+                let (ret_cmid, _private_data) = self.ops.get_request(*listener_handle).await?;
                 Ok(CompletionKind::GetRequest(ret_cmid))
             }
             Command::TryGetRequest(listener_handle) => {
-                let ret_cmid = self.ops.try_get_request(*listener_handle)?;
+                let ret_cmid = self
+                    .ops
+                    .try_get_request(*listener_handle)?
+                    .map(|(ret_cmid, _private_data)| ret_cmid);
                 Ok(CompletionKind::TryGetRequest(ret_cmid))
             }
             Command::Accept(cmid_handle, conn_param) => {
@@ -671,7 +674,7 @@ impl TransportEngine {
                 Ok(CompletionKind::Accept)
             }
             Command::Connect(cmid_handle, conn_param) => {
-                self.ops.connect(*cmid_handle, conn_param.as_ref()).await?;
+                let _private_data = self.ops.connect(*cmid_handle, conn_param.as_ref()).await?;
                 Ok(CompletionKind::Connect)
             }
             Command::BindAddr(cmid_handle, sockaddr) => {
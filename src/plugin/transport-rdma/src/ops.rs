@@ -395,10 +395,11 @@ impl Ops {
         Ok(())
     }
 
-    // Helper function.
-    fn handle_connect_request(&self, event: rdmacm::CmEvent) -> Result<returned::CmId> {
+    // Helper function. Returns the new CmId and the private data of the request.
+    fn handle_connect_request(&self, event: rdmacm::CmEvent) -> Result<(returned::CmId, Vec<u8>)> {
         log::debug!("handle_connect_request");
         let (new_cmid, new_qp) = event.get_request();
+        let private_data = event.private_data().to_vec();
 
         // Create event channel for the new_cmid and migrate
         let (channel_handle, channel) = self.create_and_register_event_channel()?;
@@ -426,13 +427,16 @@ impl Ops {
             channel_handle
         );
 
-        Ok(returned::CmId {
+        let ret_cmid = returned::CmId {
             handle: net::CmId(new_cmid_handle),
             qp: ret_qp,
-        })
+        };
+        Ok((ret_cmid, private_data))
     }
 
-    pub async fn get_request(&self, listener_handle: Handle) -> Result<returned::CmId> {
+    /// Waits for a connect request on the listener. Returns the new CmId and the private data
+    /// of the request.
+    pub async fn get_request(&self, listener_handle: Handle) -> Result<(returned::CmId, Vec<u8>)> {
         log::debug!("GetRequest, listener_handle: {:?}", listener_handle);
 
         let event_type = rdma::ffi::rdma_cm_event_type::RDMA_CM_EVENT_CONNECT_REQUEST;
@@ -444,7 +448,11 @@ impl Ops {
         self.handle_connect_request(event)
     }
 
-    pub fn try_get_request(&self, listener_handle: Handle) -> Result<Option<returned::CmId>> {
+    /// Returns the new CmId and the private data of a pending connect request on the listener.
+    pub fn try_get_request(
+        &self,
+        listener_handle: Handle,
+    ) -> Result<Option<(returned::CmId, Vec<u8>)>> {
         // log::trace!("TryGetRequest, listener_handle: {:?}", listener_handle);
 
         let event_type = rdma::ffi::rdma_cm_event_type::RDMA_CM_EVENT_CONNECT_REQUEST;
@@ -501,11 +509,13 @@ impl Ops {
         Ok(())
     }
 
+    /// Connects to the peer. Returns the private data that the peer accepts the connection
+    /// with.
     pub async fn connect(
         &self,
         cmid_handle: Handle,
         conn_param: Option<&net::ConnParam>,
-    ) -> Result<()> {
+    ) -> Result<Vec<u8>> {
         log::debug!(
             "Connect, cmid_handle: {:?}, conn_param: {:?}",
             cmid_handle,
@@ -519,9 +529,9 @@ impl Ops {
         // wait until the accept is done
         let event_type = rdma::ffi::rdma_cm_event_type::RDMA_CM_EVENT_ESTABLISHED;
         let ec_handle = cmid.event_channel().as_handle();
-        let _event = self.wait_cm_event(&ec_handle, event_type).await?;
+        let event = self.wait_cm_event(&ec_handle, event_type).await?;
 
-        Ok(())
+        Ok(event.private_data().to_vec())
     }
 
     pub fn bind_addr(&self, cmid_handle: Handle, sockaddr: &SocketAddr) -> Result<()> {
@@ -1,6 +1,8 @@
 use std::collections::VecDeque;
+use std::io;
 use std::mem;
 use std::num::NonZeroU32;
+use std::os::unix::io::AsRawFd;
 use std::pin::Pin;
 
 use anyhow::{anyhow, Result};
@@ -9,10 +11,12 @@ use futures::future::BoxFuture;
 use phoenix_api::engine::SchedulingMode;
 use phoenix_api::net::{WcOpcode, WcStatus};
 use phoenix_api::transport::tcp::{cmd, dp};
+use phoenix_api::Handle;
 
 use super::module::CustomerType;
+use super::mr::MemoryRegion;
 use super::ops::Ops;
-use super::{Error, TransportError};
+use super::{ApiError, Error, TransportError};
 
 use phoenix_common::engine::datapath::node::DataPathNode;
 use phoenix_common::engine::{future, Decompose, Engine, EngineResult, Indicator};
@@ -29,6 +33,8 @@ pub(crate) struct TransportEngine {
     pub(crate) node: DataPathNode,
     pub(crate) ops: Ops,
     pub(crate) cq_err_buffer: VecDeque<dp::Completion>,
+    // Listeners of the accepts that wait for a connection, in the order they were issued.
+    pub(crate) pending_accepts: VecDeque<Handle>,
     pub(crate) _mode: SchedulingMode,
 }
 
@@ -52,6 +58,10 @@ impl Decompose for TransportEngine {
         collections.insert("mode".to_string(), Box::new(engine._mode));
         collections.insert("ops".to_string(), Box::new(engine.ops));
         collections.insert("cq_err_buffer".to_string(), Box::new(engine.cq_err_buffer));
+        collections.insert(
+            "pending_accepts".to_string(),
+            Box::new(engine.pending_accepts),
+        );
         (collections, engine.node)
     }
 }
@@ -86,6 +96,13 @@ impl TransportEngine {
             .unwrap()
             .downcast::<VecDeque<dp::Completion>>()
             .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?;
+        // absent when upgrading from a version that accepted connections in place
+        let pending_accepts = match local.remove("pending_accepts") {
+            Some(pending) => *pending
+                .downcast::<VecDeque<Handle>>()
+                .map_err(|x| anyhow!("fail to downcast, type_name={:?}", x.type_name()))?,
+            None => VecDeque::new(),
+        };
 
         let engine = TransportEngine {
             customer,
@@ -94,6 +111,7 @@ impl TransportEngine {
             node,
             ops,
             cq_err_buffer,
+            pending_accepts,
         };
         Ok(engine)
     }
@@ -137,7 +155,13 @@ impl TransportEngine {
             }
             // timer.tick();
 
-            self.check_comp();
+            if let Progress(n) = self.check_accepts()? {
+                nwork += n;
+            }
+
+            if let Progress(n) = self.check_comp()? {
+                nwork += n;
+            }
             // timer.tick();
 
             // log::info!("TcpTransport mainloop: {}", timer);
@@ -231,6 +255,12 @@ impl TransportEngine {
             Ok(req) => {
                 // Flush datapath!
                 self.flush_dp()?;
+                if let cmd::Command::Accept(listener) = req {
+                    // completed by `check_accepts` once a connection arrives, so that the
+                    // datapath and the other commands are not held up in the meantime
+                    self.pending_accepts.push_back(listener);
+                    return Ok(Progress(1));
+                }
                 let result = self.process_cmd(&req).await;
                 match result {
                     Ok(res) => self.customer.send_comp(cmd::Completion(Ok(res)))?,
@@ -334,8 +364,20 @@ impl TransportEngine {
                 self.ops.post_recv(*sock_handle, *wr_id, *range)?;
                 Ok(())
             }
-            WorkRequest::PollCq(_sock_handle) => {
-                unimplemented!("PollCq");
+            WorkRequest::PollCq(sock_handle) => {
+                self.ops.poll_cq(*sock_handle, &mut self.cq_err_buffer)?;
+                // Tell the user that there are no more completions for now.
+                self.cq_err_buffer.push_back(dp::Completion {
+                    wr_id: 0,
+                    conn_id: sock_handle.0,
+                    opcode: WcOpcode::Invalid,
+                    status: WcStatus::AGAIN,
+                    buf: phoenix_api::buf::Range { offset: 0, len: 0 },
+                    byte_len: 0,
+                    imm: 0,
+                });
+                self.try_flush_cq_err_buffer()?;
+                Ok(())
             }
         }
     }
@@ -347,25 +389,56 @@ impl TransportEngine {
                 let handle = self.ops.bind(addr)?;
                 Ok(CompletionKind::Bind(handle))
             }
-            Command::Accept(listener) => {
-                let handle = self.ops.accept(*listener)?;
-                Ok(CompletionKind::Accept(handle))
-            }
+            // accepts are queued by `check_cmd` and completed by `check_accepts`
+            Command::Accept(_) => Err(ApiError::UnexpectedCommand("Accept").into()),
             Command::Connect(addr) => {
                 let handle = self.ops.connect(addr)?;
                 Ok(CompletionKind::Connect(handle))
             }
-            Command::RegMr(_nbytes) => {
-                unimplemented!("RegMr");
+            Command::RegMr(nbytes) => {
+                let mr = MemoryRegion::new(*nbytes).map_err(ApiError::MemoryRegion)?;
+                // Send the memfd before the completion, the user maps it upon receiving the
+                // completion.
+                let fd = mr.memfd().as_raw_fd();
+                self.customer.send_fd(&[fd][..]).map_err(ApiError::SendFd)?;
+                let region = self.ops.register_mr(mr);
+                Ok(CompletionKind::RegMr(region))
             }
-            Command::SetSockOption(_handle) => {
-                unimplemented!("SetSockOption");
-                // Ok(CompletionKind::SetSockOption)
+            Command::SetSockOption(handle, opt) => {
+                self.ops.set_sock_option(*handle, opt)?;
+                Ok(CompletionKind::SetSockOption)
             }
         }
     }
 
-    fn check_comp(&self) {
-        unimplemented!("check_comp");
+    /// Completes the pending accepts whose listener has a new connection. The completion of an
+    /// accept may thus come after the completions of commands issued later.
+    fn check_accepts(&mut self) -> Result<Status, Error> {
+        let mut completed = 0;
+        let mut i = 0;
+        while i < self.pending_accepts.len() {
+            let listener = self.pending_accepts[i];
+            let result = match self.ops.accept(listener) {
+                Ok(handle) => Ok(cmd::CompletionKind::Accept(handle)),
+                Err(ApiError::Socket(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    i += 1;
+                    continue;
+                }
+                Err(e) => {
+                    let e = Error::from(e);
+                    log::error!("accept error: {}", e);
+                    Err(e.into())
+                }
+            };
+            self.pending_accepts.remove(i);
+            self.customer.send_comp(cmd::Completion(result))?;
+            completed += 1;
+        }
+        Ok(Progress(completed))
+    }
+
+    fn check_comp(&self) -> Result<Status, TransportError> {
+        let n = self.ops.progress()?;
+        Ok(Progress(n))
     }
 }
@@ -1,3 +1,4 @@
+#![feature(int_roundings)]
 #![feature(strict_provenance)]
 #![feature(peer_credentials_unix_socket)]
 
@@ -10,9 +11,9 @@ pub use phoenix_common::{InitFnResult, PhoenixModule};
 pub mod config;
 pub mod engine;
 pub mod module;
+pub(crate) mod mr;
 pub mod ops;
 pub(crate) mod state;
-// pub(crate) mod mr;
 
 #[derive(Debug, Error)]
 pub enum ApiError {
@@ -20,8 +21,12 @@ pub enum ApiError {
     Socket(#[from] io::Error),
     #[error("Resource not found in table")]
     NotFound,
-    // #[error("Fail to create MemoryRegion: {0}")]
-    // MemoryRegion(mr::Error),
+    #[error("Fail to create MemoryRegion: {0}")]
+    MemoryRegion(mr::Error),
+    #[error("Failed to send file descriptors: {0}")]
+    SendFd(ipc::Error),
+    #[error("Unexpected command: {0}")]
+    UnexpectedCommand(&'static str),
 }
 
 #[derive(Debug, Error)]
@@ -104,7 +109,7 @@ impl TransportError {
 
 use crate::config::TcpTransportConfig;
 use crate::module::TcpTransportModule;
-#[no_mangle]
+#[cfg_attr(not(feature = "embedded"), no_mangle)]
 pub fn init_module(config_string: Option<&str>) -> InitFnResult<Box<dyn PhoenixModule>> {
     let config = TcpTransportConfig::new(config_string)?;
     let module = TcpTransportModule::new(config);
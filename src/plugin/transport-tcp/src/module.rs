@@ -50,6 +50,7 @@ impl TransportEngineBuilder {
             customer: self.customer,
             node: self.node,
             cq_err_buffer: VecDeque::new(),
+            pending_accepts: VecDeque::new(),
             _mode: self.mode,
             ops: self.ops,
             indicator: Default::default(),
@@ -1,12 +1,11 @@
-use std::cmp::{max, min};
+use std::alloc::Layout;
 use std::io;
 use std::sync::atomic::{AtomicU32, Ordering};
 
 use memfd::{Memfd, MemfdOptions};
-use memmap_fixed::MmapFixed;
+use mmap::MmapAligned;
 use thiserror::Error;
 
-use phoenix_api::buf::Range;
 use phoenix_api::{AsHandle, Handle};
 
 static GLOBAL_ID_COUNTER: AtomicU32 = AtomicU32::new(0);
@@ -19,9 +18,11 @@ pub enum Error {
     Io(#[from] io::Error),
 }
 
+/// A memfd-backed buffer shared between the transport and the application.
 pub(crate) struct MemoryRegion {
     handle: Handle,
-    mmap: MmapFixed,
+    mmap: MmapAligned,
+    file_off: usize,
     memfd: Memfd,
 }
 
@@ -33,7 +34,7 @@ impl AsHandle for MemoryRegion {
 }
 
 impl MemoryRegion {
-    pub(crate) fn new<'ctx>(nbytes: usize) -> Result<Self, Error> {
+    pub(crate) fn new(nbytes: usize) -> Result<Self, Error> {
         let hugetlb_size = None;
 
         let opts = MemfdOptions::default()
@@ -42,40 +43,56 @@ impl MemoryRegion {
             .hugetlb(hugetlb_size);
         let name = format!("shared-mr-{}", nbytes);
         let memfd = opts.create(name)?;
-        memfd.as_file().set_len(nbytes as u64)?;
 
-        // let align = nbytes
-        //     .checked_next_power_of_two()
-        //     .expect("next_power_of_two: {len}")
-        //     .max(page_size());
-        // let layout = Layout::from_size_align(nbytes, align).unwrap();
-        let mmap = MmapFixed::new(0, nbytes, 0, memfd.as_file())?;
+        let align = page_size();
+        let len = nbytes.max(1).next_multiple_of(align);
+        let layout = Layout::from_size_align(len, align).unwrap();
+        let (mmap, file_off) = MmapAligned::map_aligned(memfd.as_file(), layout)?;
 
         let handle = Handle(GLOBAL_ID_COUNTER.fetch_add(1, Ordering::Relaxed));
 
         Ok(Self {
             handle,
             mmap,
+            file_off,
             memfd,
         })
     }
 
-    pub(crate) fn as_slice(&self, mut range: Range) -> &[u8] {
-        range.offset = min(max(0, range.offset), self.mmap.len() as _); //Should not use mmap.len()-1
-        range.len = min(self.mmap.len() as u64 - range.offset, range.len);
-        unsafe {
-            std::slice::from_raw_parts(self.mmap.as_ptr().offset(range.offset as _), range.len as _)
-        }
+    #[inline]
+    pub(crate) fn memfd(&self) -> &Memfd {
+        &self.memfd
+    }
+
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.mmap.as_ptr()
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.mmap.len()
     }
 
-    pub(crate) fn as_mut_slice(&self, mut range: Range) -> &mut [u8] {
-        range.offset = min(max(0, range.offset), self.mmap.len() as _); //Should not use mmap.len()-1
-        range.len = min(self.mmap.len() as u64 - range.offset, range.len);
-        unsafe {
-            std::slice::from_raw_parts_mut(
-                self.mmap.as_mut_ptr().offset(range.offset as _),
-                range.len as _,
-            )
+    /// The offset in the memfd where the mapping starts.
+    #[inline]
+    pub(crate) fn file_off(&self) -> usize {
+        self.file_off
+    }
+}
+
+fn page_size() -> usize {
+    use std::sync::atomic::AtomicUsize;
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+    match PAGE_SIZE.load(Ordering::Relaxed) {
+        0 => {
+            let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+
+            PAGE_SIZE.store(page_size, Ordering::Relaxed);
+
+            page_size
         }
+        page_size => page_size,
     }
 }
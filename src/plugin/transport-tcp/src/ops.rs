@@ -8,11 +8,15 @@ use std::time::Duration;
 
 use mio::net::{TcpListener, TcpStream};
 use mio::{Events, Interest, Poll, Token};
+use socket2::{SockRef, TcpKeepalive};
+
 use phoenix_api::buf::Range;
 use phoenix_api::net::{MappedAddrStatus, WcOpcode, WcStatus};
-use phoenix_api::transport::tcp::dp;
+use phoenix_api::transport::tcp::cmd::SockOption;
+use phoenix_api::transport::tcp::{cmd, dp};
 use phoenix_api::{AsHandle, Handle};
 
+use super::mr::MemoryRegion;
 use super::state::State;
 use super::{ApiError, TransportError};
 
@@ -106,8 +110,64 @@ impl Ops {
         Ok(sock_handle)
     }
 
-    pub fn accept(&self, _handle: Handle) -> Result<Handle, ApiError> {
-        unimplemented!("accept");
+    /// Accepts a connection on the listener. Connections that were accepted while polling for
+    /// IO are returned first. Returns an `io::ErrorKind::WouldBlock` error if there is no
+    /// pending connection.
+    pub fn accept(&self, listener_handle: Handle) -> Result<Handle, ApiError> {
+        if !self
+            .state
+            .listener_table
+            .borrow()
+            .contains_key(&listener_handle)
+        {
+            return Err(ApiError::NotFound);
+        }
+
+        let pending = self
+            .state
+            .backlog
+            .borrow_mut()
+            .get_mut(&listener_handle)
+            .and_then(|backlog| backlog.pop_front());
+        let sock_handle = match pending {
+            Some(sock_handle) => sock_handle,
+            None => self.try_accept(listener_handle)?,
+        };
+
+        // The user addresses its buffers directly, there is nothing to map for this connection.
+        let mut sock_table = self.state.sock_table.borrow_mut();
+        let (_sock, status) = sock_table.get_mut(&sock_handle).ok_or(ApiError::NotFound)?;
+        *status = MappedAddrStatus::Mapped;
+        Ok(sock_handle)
+    }
+
+    pub fn set_sock_option(&self, sock_handle: Handle, opt: &SockOption) -> Result<(), ApiError> {
+        let table = self.state.sock_table.borrow();
+        let (sock, _status) = table.get(&sock_handle).ok_or(ApiError::NotFound)?;
+        let sock_ref = SockRef::from(sock);
+        match *opt {
+            SockOption::NoDelay(nodelay) => sock.set_nodelay(nodelay)?,
+            SockOption::SendBufferSize(size) => sock_ref.set_send_buffer_size(size)?,
+            SockOption::RecvBufferSize(size) => sock_ref.set_recv_buffer_size(size)?,
+            SockOption::KeepAlive(None) => sock_ref.set_keepalive(false)?,
+            SockOption::KeepAlive(Some(time)) => {
+                sock_ref.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the memory region alive as long as the transport and returns its description for
+    /// the user.
+    pub(crate) fn register_mr(&self, mr: MemoryRegion) -> cmd::MemoryRegion {
+        let region = cmd::MemoryRegion {
+            handle: mr.as_handle(),
+            vaddr: mr.as_ptr().addr(),
+            map_len: mr.len() as u64,
+            file_off: mr.file_off() as u64,
+        };
+        self.state.mr_table.borrow_mut().insert(region.handle, mr);
+        region
     }
 }
 
@@ -178,19 +238,58 @@ impl Ops {
         Ok(())
     }
 
-    #[allow(dead_code)]
+    /// Moves the completions of the socket that have been collected by `progress` to `wcs`.
     pub fn poll_cq(
         &self,
-        _sock_handle: Handle,
-        _wcs: &mut [dp::Completion],
+        sock_handle: Handle,
+        wcs: &mut VecDeque<dp::Completion>,
     ) -> Result<(), TransportError> {
-        unimplemented!("poll cq");
+        let mut table = self.state.cq_table.borrow_mut();
+        let cq = table
+            .get_mut(&sock_handle)
+            .ok_or(TransportError::NotFound)?;
+        wcs.extend(cq.completions.drain(..));
+        Ok(())
+    }
+
+    /// Polls for IO without blocking. Accepted connections are queued to the backlog of their
+    /// listener and completions are queued to the completion queue of their socket, waiting
+    /// for `accept` and `poll_cq`. Returns the number of connections and completions.
+    pub fn progress(&self) -> Result<usize, TransportError> {
+        let (conns, wcs) = self.poll_events(Duration::from_micros(0))?;
+        let nwork = conns.len() + wcs.len();
+
+        let mut backlog = self.state.backlog.borrow_mut();
+        for (listener_handle, sock_handle) in conns {
+            backlog
+                .entry(listener_handle)
+                .or_default()
+                .push_back(sock_handle);
+        }
+
+        let mut cq_table = self.state.cq_table.borrow_mut();
+        for wc in wcs {
+            if let Some(cq) = cq_table.get_mut(&Handle(wc.conn_id)) {
+                cq.completions.push_back(wc);
+            }
+        }
+        Ok(nwork)
     }
 
     pub fn poll_io(
         &self,
         duration: Duration,
     ) -> Result<(Vec<Handle>, Vec<dp::Completion>), TransportError> {
+        let (conns, wcs) = self.poll_events(duration)?;
+        let conns = conns.into_iter().map(|(_listener, conn)| conn).collect();
+        Ok((conns, wcs))
+    }
+
+    /// Returns the newly accepted connections along with their listeners, and the completions.
+    fn poll_events(
+        &self,
+        duration: Duration,
+    ) -> Result<(Vec<(Handle, Handle)>, Vec<dp::Completion>), TransportError> {
         let mut conns = Vec::new();
         let mut wcs = Vec::new();
         // let mut io_res = Vec::new();
@@ -205,9 +304,10 @@ impl Ops {
                 .borrow()
                 .contains_key(&Handle(handle as _))
             {
-                let listener_handle = handle;
-                if let Ok(handle) = self.try_accept(Handle(listener_handle as _)) {
-                    conns.push(handle);
+                // The listener is edge-triggered, drain all pending connections.
+                let listener_handle = Handle(handle as _);
+                while let Ok(handle) = self.try_accept(listener_handle) {
+                    conns.push((listener_handle, handle));
                 }
             } else {
                 let _res = (|| -> Result<(), TransportError> {
@@ -350,6 +450,8 @@ impl Task {
 pub struct CompletionQueue {
     send_tasks: VecDeque<Task>,
     recv_tasks: VecDeque<Task>,
+    // finished work requests waiting for poll_cq
+    completions: VecDeque<dp::Completion>,
 }
 
 impl Default for CompletionQueue {
@@ -363,6 +465,7 @@ impl CompletionQueue {
         CompletionQueue {
             send_tasks: VecDeque::with_capacity(128),
             recv_tasks: VecDeque::with_capacity(128),
+            completions: VecDeque::with_capacity(128),
         }
     }
 
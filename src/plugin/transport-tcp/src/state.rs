@@ -1,5 +1,6 @@
 //! Per-process state that is shared among multiple transport engines.
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::io;
 use std::sync::Arc;
 
@@ -12,6 +13,7 @@ use phoenix_api::net::MappedAddrStatus;
 use phoenix_api::Handle;
 use phoenix_common::state_mgr::ProcessShared;
 
+use super::mr::MemoryRegion;
 use super::ops::CompletionQueue;
 
 // TODO(cjr): Make this global lock more fine-grained.
@@ -23,6 +25,9 @@ pub struct State {
     pub sock_table: RefCell<HashMap<Handle, (TcpStream, MappedAddrStatus)>>,
     // conn_handle -> completion queue
     pub cq_table: RefCell<HashMap<Handle, CompletionQueue>>,
+    // listener_handle -> connections accepted but not yet returned to the user
+    pub(crate) backlog: RefCell<HashMap<Handle, VecDeque<Handle>>>,
+    pub(crate) mr_table: RefCell<HashMap<Handle, MemoryRegion>>,
 }
 
 // SAFETY: State in tcp will not be shared by multiple threads
@@ -38,6 +43,8 @@ impl State {
             listener_table: RefCell::new(HashMap::default()),
             sock_table: RefCell::new(HashMap::default()),
             cq_table: RefCell::new(HashMap::default()),
+            backlog: RefCell::new(HashMap::default()),
+            mr_table: RefCell::new(HashMap::default()),
         }
     }
 }
@@ -51,6 +58,8 @@ impl Clone for State {
             listener_table: RefCell::new(HashMap::default()),
             sock_table: RefCell::new(HashMap::default()),
             cq_table: RefCell::new(HashMap::default()),
+            backlog: RefCell::new(HashMap::default()),
+            mr_table: RefCell::new(HashMap::default()),
         }
     }
 }
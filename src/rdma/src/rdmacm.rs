@@ -367,6 +367,24 @@ impl CmEvent {
         }
     }
 
+    /// Returns the private data of a connect request or an established connection, sent by the
+    /// peer in `rdma_connect` or `rdma_accept`. The data may be padded by the transport.
+    #[inline]
+    pub fn private_data(&self) -> &[u8] {
+        assert!(!self.0.is_null());
+        let conn = unsafe { &(*self.0).param.conn };
+        if conn.private_data.is_null() {
+            &[]
+        } else {
+            unsafe {
+                std::slice::from_raw_parts(
+                    conn.private_data as *const u8,
+                    conn.private_data_len as usize,
+                )
+            }
+        }
+    }
+
     /// Returns a reference to the assocated rdma_cm_id.
     #[inline]
     pub fn id<'a>(&self) -> &'a CmId<'a> {
@@ -1,4 +1,5 @@
 use std::io;
+use std::path::PathBuf;
 
 use thiserror::Error;
 
@@ -14,6 +15,16 @@ thread_local! {
     pub static SA_CTX: SAContext = SAContext::register().expect("phoenix salloc register failed");
 }
 
+static CONTROL_PATH: spin::Once<(PathBuf, PathBuf)> = spin::Once::new();
+
+/// Registers the allocator at the phoenix whose control socket is `control` under `prefix`,
+/// instead of the one given by `PHOENIX_PREFIX` and `PHOENIX_CONTROL`.
+///
+/// It must be called before the first allocation. Only the first call takes effect.
+pub fn set_control_path(prefix: PathBuf, control: PathBuf) {
+    CONTROL_PATH.call_once(|| (prefix, control));
+}
+
 pub struct SAContext {
     pub(crate) service:
         ShmService<cmd::Command, cmd::Completion, dp::WorkRequestSlot, dp::CompletionSlot>,
@@ -21,9 +32,13 @@ pub struct SAContext {
 
 impl SAContext {
     fn register() -> Result<SAContext, Error> {
+        let (prefix, control) = match CONTROL_PATH.get() {
+            Some((prefix, control)) => (prefix.as_path(), control.as_path()),
+            None => (PHOENIX_PREFIX.as_path(), PHOENIX_CONTROL_SOCK.as_path()),
+        };
         let service = ShmService::register(
-            &*PHOENIX_PREFIX,
-            &*PHOENIX_CONTROL_SOCK,
+            prefix,
+            control,
             "Salloc".to_string(),
             SchedulingHint::default(),
             None,
@@ -0,0 +1,384 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::slice;
+
+use crate::alloc::{ShmAllocator, System};
+use crate::vec::{IntoIter as VecIntoIter, Vec};
+
+/// A shared-memory ordered map, used to hold protobuf `map` fields.
+///
+/// Unlike [`std::collections::BTreeMap`], the entries are kept in a single [`Vec`] sorted by key,
+/// so the whole map lives in one contiguous buffer that can be handed over to another address
+/// space without walking a tree of nodes. Lookups are `O(log n)`, and insertions and removals are
+/// `O(n)`, which is fine for the small maps that usually appear in RPC messages.
+pub struct BTreeMap<K, V, A: ShmAllocator = System> {
+    entries: Vec<(K, V), A>,
+}
+
+impl<K, V, A: ShmAllocator + Default> BTreeMap<K, V, A> {
+    /// Makes a new, empty `BTreeMap`.
+    #[inline]
+    pub fn new() -> Self {
+        BTreeMap {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<K, V, A: ShmAllocator> BTreeMap<K, V, A> {
+    /// Makes a new, empty `BTreeMap` with the provided allocator.
+    #[inline]
+    pub const fn new_in(alloc: A) -> Self {
+        BTreeMap {
+            entries: Vec::new_in(alloc),
+        }
+    }
+
+    /// Returns the number of elements in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Clears the map, removing all elements.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    /// Gets an iterator over the entries of the map, sorted by key.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    /// Gets a mutable iterator over the entries of the map, sorted by key.
+    #[inline]
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            inner: self.entries.iter_mut(),
+        }
+    }
+
+    /// Gets an iterator over the keys of the map, in sorted order.
+    #[inline]
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    /// Gets an iterator over the values of the map, in order by key.
+    #[inline]
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    /// Returns the underlying buffer of the entries.
+    #[inline]
+    pub fn as_entries(&self) -> &Vec<(K, V), A> {
+        &self.entries
+    }
+
+    /// Returns a mutable reference to the underlying buffer of the entries.
+    ///
+    /// # Safety
+    ///
+    /// The caller must keep the entries sorted by key without duplicates.
+    #[inline]
+    pub unsafe fn as_mut_entries(&mut self) -> &mut Vec<(K, V), A> {
+        &mut self.entries
+    }
+}
+
+impl<K: Ord, V, A: ShmAllocator> BTreeMap<K, V, A> {
+    #[inline]
+    fn search<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.binary_search_by(|(k, _)| k.borrow().cmp(key))
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    #[inline]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).ok().map(|i| &self.entries[i].1)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    #[inline]
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).ok().map(|i| &mut self.entries[i].1)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    #[inline]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).is_ok()
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// If the map already had this key present, the value is updated, and the old value is
+    /// returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.search(&key) {
+            Ok(i) => Some(std::mem::replace(&mut self.entries[i].1, value)),
+            Err(i) => {
+                self.entries.insert(i, (key, value));
+                None
+            }
+        }
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was previously in
+    /// the map.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).ok().map(|i| self.entries.remove(i).1)
+    }
+}
+
+impl<K, V, A: ShmAllocator + Default> Default for BTreeMap<K, V, A> {
+    #[inline]
+    fn default() -> Self {
+        BTreeMap::new()
+    }
+}
+
+impl<K: Clone, V: Clone, A: ShmAllocator + Clone> Clone for BTreeMap<K, V, A> {
+    fn clone(&self) -> Self {
+        BTreeMap {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<K: PartialEq, V: PartialEq, A: ShmAllocator> PartialEq for BTreeMap<K, V, A> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.entries[..] == other.entries[..]
+    }
+}
+
+impl<K: Eq, V: Eq, A: ShmAllocator> Eq for BTreeMap<K, V, A> {}
+
+impl<K: fmt::Debug, V: fmt::Debug, A: ShmAllocator> fmt::Debug for BTreeMap<K, V, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord, V, A: ShmAllocator> Extend<(K, V)> for BTreeMap<K, V, A> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: Ord, V, A: ShmAllocator + Default> FromIterator<(K, V)> for BTreeMap<K, V, A> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = BTreeMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, A: ShmAllocator> IntoIterator for BTreeMap<K, V, A> {
+    type Item = (K, V);
+    type IntoIter = VecIntoIter<(K, V), A>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a, K, V, A: ShmAllocator> IntoIterator for &'a BTreeMap<K, V, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, A: ShmAllocator> IntoIterator for &'a mut BTreeMap<K, V, A> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// An iterator over the entries of a [`BTreeMap`].
+#[derive(Clone)]
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k, v))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+/// A mutable iterator over the entries of a [`BTreeMap`].
+pub struct IterMut<'a, K, V> {
+    inner: slice::IterMut<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (&*k, v))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, v)| (&*k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{AllocError, Allocator, Global, Layout};
+    use std::ptr::NonNull;
+
+    use super::*;
+    use crate::ptr::ShmNonNull;
+
+    /// Allocates on the private heap, as [`System`] needs a shared memory device.
+    #[derive(Debug, Default, Clone, Copy)]
+    struct Heap;
+
+    unsafe impl ShmAllocator for Heap {
+        fn allocate(&self, layout: Layout) -> Result<ShmNonNull<[u8]>, AllocError> {
+            let block = Global.allocate(layout)?;
+            let ptr = block.as_non_null_ptr();
+            Ok(ShmNonNull::slice_from_raw_parts(ptr, ptr, block.len()))
+        }
+
+        fn deallocate(&self, ptr: ShmNonNull<u8>, layout: Layout) {
+            // SAFETY: `ptr` was allocated by `Global` with `layout`
+            unsafe {
+                Global.deallocate(NonNull::new_unchecked(ptr.as_ptr_backend()), layout);
+            }
+        }
+    }
+
+    type Map<V> = BTreeMap<u32, V, Heap>;
+
+    fn keys<V>(map: &Map<V>) -> std::vec::Vec<u32> {
+        map.keys().copied().collect()
+    }
+
+    #[test]
+    fn test_insert_keeps_keys_sorted() {
+        let mut map = Map::<&str>::new();
+        assert!(map.is_empty());
+        for (k, v) in [(3, "c"), (1, "a"), (4, "d"), (2, "b")] {
+            assert_eq!(map.insert(k, v), None);
+        }
+        assert_eq!(map.len(), 4);
+        assert_eq!(keys(&map), [1, 2, 3, 4]);
+        assert_eq!(
+            map.iter().map(|(_, v)| *v).collect::<std::vec::Vec<_>>(),
+            ["a", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_insert_replaces_value() {
+        let mut map: Map<&str> = [(1, "a"), (2, "b")].into_iter().collect();
+        assert_eq!(map.insert(2, "B"), Some("b"));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&2), Some(&"B"));
+
+        *map.get_mut(&1).unwrap() = "A";
+        assert_eq!(map.get(&1), Some(&"A"));
+        assert_eq!(keys(&map), [1, 2]);
+    }
+
+    #[test]
+    fn test_get_missing_key() {
+        let map: Map<&str> = [(1, "a"), (3, "c")].into_iter().collect();
+        assert_eq!(map.get(&0), None);
+        assert_eq!(map.get(&2), None);
+        assert_eq!(map.get(&4), None);
+        assert!(map.contains_key(&3));
+        assert!(!map.contains_key(&2));
+    }
+
+    #[test]
+    fn test_remove_keeps_order() {
+        let mut map: Map<&str> = (1..=5).zip(["a", "b", "c", "d", "e"]).collect();
+        assert_eq!(map.remove(&3), Some("c"));
+        assert_eq!(map.remove(&3), None);
+        assert_eq!(map.remove(&1), Some("a"));
+        assert_eq!(keys(&map), [2, 4, 5]);
+
+        // a removed key can be inserted again at its place
+        assert_eq!(map.insert(3, "C"), None);
+        assert_eq!(keys(&map), [2, 3, 4, 5]);
+
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.remove(&2), None);
+    }
+}
@@ -1 +1,4 @@
+pub mod btree_map;
 
+#[doc(inline)]
+pub use btree_map::BTreeMap;
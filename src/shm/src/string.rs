@@ -42,6 +42,7 @@
 
 #[cfg(not(no_global_oom_handling))]
 use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use core::cmp;
 use core::fmt;
 use core::hash;
 #[cfg(not(no_global_oom_handling))]
@@ -60,7 +61,7 @@ use core::str::pattern::Pattern;
 use core::str::Utf8Chunks;
 #[cfg(not(no_global_oom_handling))]
 #[cfg(not(no_global_oom_handling))]
-use std::borrow::Cow; // TODO(cjr): Consider to ShmCow and ShmBorrow in the future?
+use std::borrow::{Borrow, Cow}; // TODO(cjr): Consider to ShmCow and ShmBorrow in the future?
 use std::collections::TryReserveError;
 #[cfg(not(no_global_oom_handling))]
 use std::str::FromStr;
@@ -362,7 +363,6 @@ use crate::vec::Vec;
 /// [`Deref`]: core::ops::Deref "ops::Deref"
 /// [`as_str()`]: String::as_str
 #[repr(C)]
-#[derive(PartialOrd, Ord)]
 pub struct String<A: ShmAllocator = System> {
     vec: Vec<u8, A>,
 }
@@ -1911,6 +1911,27 @@ impl<'a, 'b, A: ShmAllocator> Pattern<'a> for &'b String<A> {
 
 impl<A: ShmAllocator> Eq for String<A> {}
 
+impl<A: ShmAllocator> PartialOrd for String<A> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        PartialOrd::partial_cmp(&self[..], &other[..])
+    }
+}
+
+impl<A: ShmAllocator> Ord for String<A> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        Ord::cmp(&self[..], &other[..])
+    }
+}
+
+impl<A: ShmAllocator> Borrow<str> for String<A> {
+    #[inline]
+    fn borrow(&self) -> &str {
+        &self[..]
+    }
+}
+
 impl<A1: ShmAllocator, A2: ShmAllocator> PartialEq<String<A2>> for String<A1> {
     #[inline]
     fn eq(&self, other: &String<A2>) -> bool {